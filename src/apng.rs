@@ -0,0 +1,213 @@
+use std::str::FromStr;
+
+use anyhow::{bail, ensure, Context, Error, Result};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+
+/// Time each frame is shown, as fraction of a second stored in fcTL
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Delay {
+    pub numerator: u16,
+    pub denominator: u16,
+}
+
+impl FromStr for Delay {
+    type Err = Error;
+
+    /// Accepts `100ms`, `2s` or fraction of a second like `1/30`
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || format!("Invalid delay '{}', expected like 100ms, 2s or 1/30", s);
+        let (numerator, denominator) = if let Some((n, d)) = s.split_once('/') {
+            (
+                n.parse().with_context(invalid)?,
+                d.parse().with_context(invalid)?,
+            )
+        } else if let Some(ms) = s.strip_suffix("ms") {
+            let ms: u32 = ms.parse().with_context(invalid)?;
+            match u16::try_from(ms) {
+                Ok(ms) => (ms, 1000),
+                Err(_) if ms.is_multiple_of(1000) && ms / 1000 <= u16::MAX as u32 => {
+                    ((ms / 1000) as u16, 1)
+                }
+                Err(_) => bail!("Delay {} is too long", s),
+            }
+        } else if let Some(secs) = s.strip_suffix('s') {
+            (secs.parse().with_context(invalid)?, 1)
+        } else {
+            bail!("{}", invalid());
+        };
+        ensure!(denominator != 0, "Delay {} divides by zero", s);
+        Ok(Delay {
+            numerator,
+            denominator,
+        })
+    }
+}
+
+/// Builds animated PNG showing given images one after another.
+/// First image becomes the default one shown by viewers without APNG support,
+/// its other chunks are kept. `plays` of 0 repeats animation forever
+pub fn assemble(mut frames: Vec<Png>, delay: Delay, plays: u32) -> Result<Png> {
+    ensure!(!frames.is_empty(), "No frames given");
+    let mut first = frames.remove(0);
+    let count = frames.len() as u32 + 1;
+    let header = first
+        .chunk_by_type("IHDR")
+        .context("First frame has no IHDR")?
+        .data()
+        .to_vec();
+    ensure!(header.len() == 13, "First frame has invalid IHDR");
+    let palette = first.chunk_by_type("PLTE").map(|c| c.data());
+    for (i, frame) in frames.iter().enumerate() {
+        // frames share IHDR and PLTE of the first one, so pixels must be encoded the same way
+        ensure!(
+            frame.chunk_by_type("IHDR").map(|c| c.data()) == Some(header.as_slice()),
+            "Frame {} differs from the first one in size or pixel format",
+            i + 2
+        );
+        ensure!(
+            frame.chunk_by_type("PLTE").map(|c| c.data()) == palette,
+            "Frame {} has different palette",
+            i + 2
+        );
+    }
+    ensure!(
+        first.chunks().iter().all(|c| !is_animation(c)),
+        "First frame is already animated"
+    );
+
+    // width and height, as every frame covers whole canvas
+    let size = &header[..8];
+    let mut sequence = 0;
+
+    let first_idat = first
+        .chunks()
+        .iter()
+        .position(is_image_data)
+        .context("First frame has no IDAT")?;
+    let last_idat = first.chunks().iter().rposition(is_image_data).unwrap();
+    let mut control = count.to_be_bytes().to_vec();
+    control.extend_from_slice(&plays.to_be_bytes());
+
+    // other frames go right after image data of the first one, keeping its chunks around
+    let animation = vec![
+        chunk("acTL", control),
+        frame_control(&mut sequence, size, delay),
+    ];
+    let mut frame_data = vec![];
+    for (i, frame) in frames.iter().enumerate() {
+        let data: Vec<_> = frame.chunks().iter().filter(|c| is_image_data(c)).collect();
+        ensure!(!data.is_empty(), "Frame {} has no IDAT", i + 2);
+        frame_data.push(frame_control(&mut sequence, size, delay));
+        for idat in data {
+            let mut fdat = sequence.to_be_bytes().to_vec();
+            fdat.extend_from_slice(idat.data());
+            sequence += 1;
+            frame_data.push(chunk("fdAT", fdat));
+        }
+    }
+    for (i, chunk) in frame_data.into_iter().enumerate() {
+        first.insert_chunk(last_idat + 1 + i, chunk);
+    }
+    for chunk in animation.into_iter().rev() {
+        first.insert_chunk(first_idat, chunk);
+    }
+    Ok(first)
+}
+
+fn frame_control(sequence: &mut u32, size: &[u8], delay: Delay) -> Chunk {
+    let mut data = sequence.to_be_bytes().to_vec();
+    data.extend_from_slice(size);
+    data.extend_from_slice(&[0; 8]); // x and y offsets
+    data.extend_from_slice(&delay.numerator.to_be_bytes());
+    data.extend_from_slice(&delay.denominator.to_be_bytes());
+    // nothing is left of previous frame to dispose or blend with
+    data.extend_from_slice(&[0, 0]);
+    *sequence += 1;
+    chunk("fcTL", data)
+}
+
+fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+    Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+}
+
+fn is_image_data(chunk: &Chunk) -> bool {
+    &chunk.chunk_type().bytes() == b"IDAT"
+}
+
+fn is_animation(chunk: &Chunk) -> bool {
+    matches!(&chunk.chunk_type().bytes(), b"acTL" | b"fcTL" | b"fdAT")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ordering;
+
+    fn png(chunks: &[(&str, &[u8])]) -> Png {
+        Png::from_chunks(chunks.iter().map(|(t, d)| chunk(t, d.to_vec())).collect())
+    }
+
+    fn header(width: u8) -> [u8; 13] {
+        [0, 0, 0, width, 0, 0, 0, 2, 8, 6, 0, 0, 0]
+    }
+
+    #[test]
+    fn test_parse_delay() {
+        let delay = |n, d| Delay {
+            numerator: n,
+            denominator: d,
+        };
+        assert_eq!("100ms".parse::<Delay>().unwrap(), delay(100, 1000));
+        assert_eq!("70000ms".parse::<Delay>().unwrap(), delay(70, 1));
+        assert_eq!("2s".parse::<Delay>().unwrap(), delay(2, 1));
+        assert_eq!("1/30".parse::<Delay>().unwrap(), delay(1, 30));
+        assert!("70001ms".parse::<Delay>().is_err());
+        assert!("1/0".parse::<Delay>().is_err());
+        assert!("100".parse::<Delay>().is_err());
+    }
+
+    #[test]
+    fn test_assemble() {
+        let first = png(&[
+            ("IHDR", &header(4)),
+            ("gAMA", b"gama"),
+            ("IDAT", b"one"),
+            ("IDAT", b"two"),
+            ("tEXt", b"Title\0Dice"),
+            ("IEND", b""),
+        ]);
+        let second = png(&[("IHDR", &header(4)), ("IDAT", b"three"), ("IEND", b"")]);
+        let delay = "1/10".parse().unwrap();
+        let apng = assemble(vec![first, second], delay, 3).unwrap();
+
+        let types: Vec<_> = apng
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        let expected = "IHDR gAMA acTL fcTL IDAT IDAT fcTL fdAT tEXt IEND";
+        assert_eq!(types.join(" "), expected);
+        ordering::check(apng.chunks()).unwrap();
+
+        assert_eq!(apng.chunks()[2].data(), [0, 0, 0, 2, 0, 0, 0, 3]);
+        let control = apng.chunks()[6].data();
+        assert_eq!(control[..4], [0, 0, 0, 1]);
+        assert_eq!(control[4..12], header(4)[..8]);
+        assert_eq!(control[20..24], [0, 1, 0, 10]);
+        assert_eq!(apng.chunks()[7].data(), b"\0\0\0\x02three");
+    }
+
+    #[test]
+    fn test_assemble_mismatched() {
+        let first = || png(&[("IHDR", &header(4)), ("IDAT", b"one"), ("IEND", b"")]);
+        let wider = png(&[("IHDR", &header(5)), ("IDAT", b"two"), ("IEND", b"")]);
+        let delay = "100ms".parse().unwrap();
+        assert!(assemble(vec![first(), wider], delay, 0).is_err());
+        let empty = png(&[("IHDR", &header(4)), ("IEND", b"")]);
+        assert!(assemble(vec![first(), empty], delay, 0).is_err());
+        assert!(assemble(vec![], delay, 0).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+
+use ::tar::Archive;
+use anyhow::{bail, ensure, Context, Result};
+use flate2::read::GzDecoder;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+/// Separates archive from path of the entry inside it, as in `assets.zip!images/logo.png`
+pub const SEPARATOR: char = '!';
+/// Entries are decompressed up to this size, so archive bombs don't exhaust memory
+const MAX_ENTRY_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Splits `ARCHIVE!ENTRY` path at the first separator that follows an existing file
+pub fn split(path: &Path) -> Option<(PathBuf, String)> {
+    let path = path.to_str()?;
+    path.match_indices(SEPARATOR).find_map(|(i, _)| {
+        let archive = Path::new(&path[..i]);
+        let entry = path[i + 1..].trim_start_matches('/');
+        (archive.is_file() && !entry.is_empty()).then(|| (archive.into(), entry.to_string()))
+    })
+}
+
+/// Content of file `name` in zip, tar or gzipped tar archive
+pub fn read_entry(archive: &[u8], name: &str) -> Result<Vec<u8>> {
+    if archive.starts_with(b"PK\x03\x04") || archive.starts_with(b"PK\x05\x06") {
+        zip_entry(archive, name)
+    } else if archive.starts_with(&[0x1f, 0x8b]) {
+        tar_entry(GzDecoder::new(archive), name)
+    } else if archive.get(257..262) == Some(b"ustar") {
+        tar_entry(archive, name)
+    } else {
+        bail!("Not a zip or tar archive")
+    }
+}
+
+fn zip_entry(archive: &[u8], name: &str) -> Result<Vec<u8>> {
+    let mut zip = ZipArchive::new(Cursor::new(archive)).context("Invalid zip archive")?;
+    let entry = match zip.by_name(name) {
+        Ok(entry) => entry,
+        Err(ZipError::FileNotFound) => bail!("No {} in archive", name),
+        Err(err) => return Err(err).with_context(|| format!("Zip entry {} is corrupted", name)),
+    };
+    let size = entry.size();
+    // reading whole entry also checks its CRC
+    read_limited(entry, size, name)
+}
+
+fn tar_entry(archive: impl Read, name: &str) -> Result<Vec<u8>> {
+    let mut tar = Archive::new(archive);
+    for entry in tar.entries().context("Invalid tar archive")? {
+        let entry = entry.context("Tar archive is corrupted")?;
+        let path = entry.path().context("Tar archive is corrupted")?;
+        let path = path.strip_prefix("./").unwrap_or(&path);
+        if entry.header().entry_type().is_file() && path == Path::new(name) {
+            let size = entry.size();
+            return read_limited(entry, size, name);
+        }
+    }
+    bail!("No {} in archive", name)
+}
+
+/// Reads entry of declared `size`, which is checked against [`MAX_ENTRY_SIZE`] before
+/// anything is allocated. Reading stops past the limit even if entry lies about its size
+fn read_limited(entry: impl Read, size: u64, name: &str) -> Result<Vec<u8>> {
+    ensure!(
+        size <= MAX_ENTRY_SIZE,
+        "Entry {} is larger than {} bytes",
+        name,
+        MAX_ENTRY_SIZE
+    );
+    let mut content = Vec::with_capacity(size as usize);
+    entry
+        .take(MAX_ENTRY_SIZE + 1)
+        .read_to_end(&mut content)
+        .with_context(|| format!("Entry {} is corrupted", name))?;
+    ensure!(
+        content.len() as u64 <= MAX_ENTRY_SIZE,
+        "Entry {} is larger than {} bytes",
+        name,
+        MAX_ENTRY_SIZE
+    );
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use crc::Crc;
+
+    use super::*;
+    use crate::deflate;
+
+    /// Zip with stored or deflated files, laid out as common tools write them
+    fn zip(files: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        let crc = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+        let (mut out, mut directory) = (vec![], vec![]);
+        for (name, content, compress) in files {
+            let data = if *compress {
+                let zlib = deflate::zlib_compress(content);
+                zlib[2..zlib.len() - 4].to_vec()
+            } else {
+                content.to_vec()
+            };
+            let method: u16 = if *compress { 8 } else { 0 };
+            let mut fields = vec![];
+            fields.extend_from_slice(&[20, 0, 0, 0]);
+            fields.extend_from_slice(&method.to_le_bytes());
+            fields.extend_from_slice(&[0; 4]);
+            fields.extend_from_slice(&crc.checksum(content).to_le_bytes());
+            fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            fields.extend_from_slice(&(content.len() as u32).to_le_bytes());
+            fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            fields.extend_from_slice(&[0, 0]);
+
+            directory.extend_from_slice(b"PK\x01\x02\x14\x00");
+            directory.extend_from_slice(&fields);
+            directory.extend_from_slice(&[0; 10]);
+            directory.extend_from_slice(&(out.len() as u32).to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+
+            out.extend_from_slice(b"PK\x03\x04");
+            out.extend_from_slice(&fields);
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(&data);
+        }
+        let offset = out.len() as u32;
+        out.extend_from_slice(&directory);
+        out.extend_from_slice(b"PK\x05\x06\0\0\0\0");
+        out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        out.extend_from_slice(&offset.to_le_bytes());
+        out.extend_from_slice(&[0, 0]);
+        out
+    }
+
+    #[test]
+    fn test_zip_entry() {
+        let logo = b"not really a png, but good enough".repeat(20);
+        let archive = zip(&[("README", b"hello", false), ("res/logo.png", &logo, true)]);
+        assert_eq!(read_entry(&archive, "README").unwrap(), b"hello");
+        assert_eq!(read_entry(&archive, "res/logo.png").unwrap(), logo);
+        assert!(read_entry(&archive, "res/missing.png").is_err());
+
+        let mut corrupted = archive.clone();
+        corrupted[40] ^= 1;
+        assert!(read_entry(&corrupted, "README").is_err());
+
+        // declared size is checked before anything is read
+        let mut bomb = archive.clone();
+        let directory = bomb.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        bomb[directory + 24..directory + 28].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = read_entry(&bomb, "README").unwrap_err();
+        assert!(err.to_string().contains("larger than"), "{:#}", err);
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_tar_entry() {
+        let archive = crate::tar::archive(&[("a.txt", b"a"), ("img/b.png", b"png")], 0).unwrap();
+        assert_eq!(read_entry(&archive, "img/b.png").unwrap(), b"png");
+        assert_eq!(read_entry(&archive, "a.txt").unwrap(), b"a");
+        assert!(read_entry(&archive, "c.png").is_err());
+        assert!(read_entry(b"plain file", "a.txt").is_err());
+    }
+}
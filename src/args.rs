@@ -0,0 +1,3496 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+#[cfg(feature = "write")]
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, ensure, Context, Error, Result};
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+
+#[cfg(feature = "write")]
+use crate::apng::Delay;
+use crate::blame::ChunkFilter;
+use crate::chunk_type::ChunkType;
+#[cfg(feature = "write")]
+use crate::color::{Chromaticities, Gamma, RenderingIntent};
+#[cfg(feature = "write")]
+use crate::config::Backup;
+use crate::config::Config;
+#[cfg(feature = "write")]
+use crate::config::ProvenanceNames;
+#[cfg(feature = "write")]
+use crate::date;
+use crate::hex;
+use crate::registry;
+use crate::search::Pattern;
+use crate::stealth;
+use crate::text;
+#[cfg(feature = "write")]
+use crate::text::{International, TextEntry, TextFormat};
+
+pub const USAGE: &str = "\
+Usage:
+    pngme encode <FILE> <TYPE> <MESSAGE> [OUTPUT] [--hmac-key <KEY>] [--ecc <RATIO>] [--obfuscate <KEY>]
+    pngme encode <FILE> <TYPE> --message-file <PATH> [OUTPUT] [...]
+    pngme encode <FILE> --random-type <MESSAGE> [OUTPUT] [...]
+    pngme encode <FILE> <TYPE> --from-clipboard [OUTPUT] [...]
+    pngme encode <FILE> <TYPE> <MESSAGE> --recipient <AGE_PUBLIC_KEY>... [...]
+    pngme encode <FILE> <TYPE> <MESSAGE> --filter <COMMAND> [...]
+    pngme encode <FILE> <TYPE> <MESSAGE> --expires <YYYY-MM-DD[Thh:mm:ss]> [...]
+    pngme encode <FILE> <TYPE> <MESSAGE> --input-encoding <base64|hex> [...]
+    pngme encode <FILE> <TYPE> <MESSAGE> --provenance [...]
+    pngme encode <FILE> <TYPE> <MESSAGE> --max-growth <BYTES|PERCENT%> [...]
+    pngme encode <FILE> --mode <chunk|lsb|trailing|text> <MESSAGE> [OUTPUT] [...]
+    pngme batch <MANIFEST.yaml>
+    pngme decode <FILE|-> <TYPE[,TYPE...]> [--raw | --encoding <base64|hex>] [--copy] [--hmac-key <KEY>]
+                               [--obfuscate <KEY>] [--output-dir <DIR> | --output <FILE>]
+                               [--identity <AGE_KEY_FILE>] [--filter <COMMAND> | --no-filter] [--strict]
+                               [--max-size <BYTES>]
+    pngme decode <FILE|-> <TYPE> --stream --output <FILE> [--max-size <BYTES>]
+    pngme decode <FILE> --mode <chunk|lsb|trailing|text> [...]
+    pngme verify-message <FILE> <TYPE> (<EXPECTED> | --expected-file <PATH>) [--hmac-key <KEY>] [--obfuscate <KEY>]
+    pngme edit <FILE> <TYPE> [--hmac-key <KEY>] [--obfuscate <KEY>] [--identity <AGE_KEY_FILE> --recipient <AGE_PUBLIC_KEY>...]
+                             [--decode-filter <COMMAND>] [--encode-filter <COMMAND> | --no-filter]
+    pngme remove <FILE> <TYPE> [--dump <FILE> [--raw]]
+    pngme move <FILE> <TYPE|INDEX> --to <INDEX|before-<TYPE>|after-<TYPE>>
+    pngme print <FILE> [--decompress] [--full] [--hash sha256] [--no-color] [--type <TYPE>]...
+                       [--critical-only | --ancillary-only] [--unknown-only]
+    pngme dump <FILE> <TYPE> [--inflate]
+    pngme export <FILE> [STRUCTURE.json]
+    pngme carve <FILE> [--output-dir <DIR>]
+    pngme build <STRUCTURE.json> <OUTPUT> [--force]
+    pngme cat <RECORDS | TYPE:PAYLOAD>... --out <FILE> [--force]
+    pngme animate <FRAME>... --delay <DELAY> --out <FILE> [--plays <N>]
+    pngme optimize <FILE> --recompress
+    pngme meta <FILE> [--get <KEYWORD>]... [--set-<KEYWORD> <TEXT>]...
+    pngme text convert <FILE> --to <text|ztxt|itxt|itxt-compressed> [--keyword <KEYWORD>]...
+    pngme text list <FILE> [--lang <TAG>] [--keyword <KEYWORD>]... [--json]
+    pngme info <FILE> [--hash sha256]
+    pngme info <FILE> [--set-srgb <INTENT>] [--set-gamma <GAMMA>] [--set-chromaticities <VALUES>]
+    pngme palette <FILE> [--export <FILE.gpl|FILE.pal>]
+    pngme stats <FILE>
+    pngme survey <DIR> [--json] [--headers-only]
+    pngme diff <OLD> <NEW> [--explain]
+    pngme same-image <A> <B> [--decompress]
+    pngme dpi get <FILE>
+    pngme dpi set <FILE> <DPI>
+    pngme trailing get <FILE> [--raw | --output <FILE>]
+    pngme trailing set <FILE> <PAYLOAD>
+    pngme trailing remove <FILE>
+    pngme watch <DIR> --on-add <OPERATION>
+    pngme blame <FILE> --versions <DIR> [--chunk <TYPE[:KEYWORD]>]
+    pngme blame <FILE> --git [--chunk <TYPE[:KEYWORD]>]
+    pngme grep <FILE> <PATTERN> [--hex | --regex] [--inflate]
+    pngme scan <FILE> [--visual] [--scanner <COMMAND>] [--yara <RULES>]
+    pngme scan <FILE> --headers-only
+    pngme doctor <FILE> [--fix]
+    pngme inject <FILE> <TYPE> <PAYLOAD> [--position <end|before-iend>] [--keep-name] [--keep-mtime]
+    pngme wizard
+    pngme init-type
+    pngme undo [DIR | FILE.png] [--steps <N>]
+    pngme merge <BASE> <OURS> <THEIRS> (-o | --output) <FILE>
+    pngme minimize <FILE> --command <COMMAND> [--output <FILE>]
+    pngme bug-report <FILE> [--type <TYPE>]... [--redact] [--command <COMMAND>] [--output <TAR>]
+    pngme split-encode <MESSAGE> <FILE>... [--threshold <N>] [--type <TYPE>]
+    pngme join-decode <FILE>... [--type <TYPE>]
+    pngme spread-encode <FILE> (<MESSAGE> | --message-file <PATH>) --password <PASSWORD> [OUTPUT]
+    pngme spread-decode <FILE> --password <PASSWORD>
+    pngme protect <FILE> --types <TYPE[,TYPE...]> --password <PASSWORD>
+    pngme unprotect <FILE> --password <PASSWORD>
+    pngme self-update [--check]
+    pngme serve [--listen <ADDR:PORT>]
+    pngme keygen <NAME> [--force]
+    pngme sign <FILE> <SECRET_KEY> (--type <TYPE> | --critical)
+    pngme verify <FILE> <PUBLIC_KEY>
+    pngme receipt verify <RECEIPT> <PUBLIC_KEY>
+    pngme seal <FILE> [--key <SECRET_KEY>]
+    pngme verify-seal <FILE> [--key <PUBLIC_KEY>]
+
+decode reads image from standard input when FILE is -, parsing chunks as they
+arrive and stopping at the first one of TYPE. --output-dir saves payload under
+its embedded file name or as TYPE, --output under given name. Name without
+extension gets one by content (PNG, JPEG, ZIP, PDF, gzip...), when detected.
+Several types, given as comma-separated list or repeated --type, are decoded
+from one read of the file, each payload printed after its type as TYPE: ...
+--stream copies data of every chunk of TYPE, joined in file order, straight
+to --output while reading, so payloads of any size take little memory. Data is
+written as stored, ECC and envelope aren't unwrapped. --max-size fails decode
+when payload is larger, partially written output is removed.
+
+same-image compares only IHDR and IDAT data, ignoring all other chunks, and
+fails when images differ. --decompress compares inflated pixel data,
+so recompressed images are the same too.
+
+Every command exits with one of:
+    0  success
+    1  chunk, keyword, share or message asked for is not found
+    2  file is not a valid PNG or has corrupted chunks
+    3  check failed: message or images differ, bad signature, MAC or receipt,
+       expired message with --strict, file would exceed --max-growth,
+       payload exceeds --max-size
+    4  invalid command line, or refusal to overwrite key or write into
+       standard chunk type without --force
+    5  any other error, like failure to read or write a file
+
+pngme --help (or -h, or pngme help) prints this text to standard output.
+Any command accepts --quiet to print nothing but errors, leaving exit code
+as the result, and -v or -vv to log what pngme is doing. Progress bars are
+hidden by --quiet and when output is not a terminal. Payloads written to
+standard output with --raw are still written, byte for byte with no newline
+or line ending translation on any platform. Windows console only shows text,
+so binary payloads there must be redirected to a file or pipe.
+--strict-png rejects images without single IHDR, with missing or repeated IEND,
+repeated PLTE, chunks after IEND or critical chunks unknown to PNG
+specification. Without it repeated IHDR, PLTE and IEND are kept with a warning.
+
+Defaults can be set in $XDG_CONFIG_HOME/pngme/config.toml (usually
+~/.config/pngme/config.toml) or in file given by --config <FILE>:
+    type = \"ruSt\"         chunk type for encode, decode and verify-message,
+                          which then don't take positional TYPE
+    decompress = true     print content of compressed chunks
+    color = false         same as --no-color
+    backup = \"journal\"    same as --journal, or \"trash\" for --trash, or \"none\"
+    history = true        same as --history
+    encode-filter = \"gpg --encrypt -r alice\"
+    decode-filter = \"gpg --decrypt\"
+                          --filter of encode and decode, --no-filter disables
+Options given on command line take precedence.
+
+print colors critical chunks blue, other known ones green, unknown ones yellow
+and corrupted ones red. Colors are off with --no-color, when NO_COLOR is set
+or when output is not a terminal. --type (repeated or comma-separated),
+--critical-only, --ancillary-only and --unknown-only (types without long-form
+name) leave out other chunks, their numbers and offsets stay the same.
+PREVIEW column shows start of chunk data, quoted if it looks like text and in
+hex otherwise. --full drops the column and dumps whole data of every chunk.
+
+dump --inflate shows decompressed content of zlib or gzip stream in chunk data,
+like body of zTXt and compressed iTXt, ICC profile of iCCP or custom compressed
+payload. grep --inflate searches such chunks decompressed, offsets of matches
+in them are then offsets in decompressed data. Image data is never inflated.
+
+stats sizes include whole chunks: length, type, data and CRC. Ancillary share
+counts chunks with lowercase first letter, bytes after IEND include any data
+appended to the file.
+
+survey reads every .png file under DIR, subdirectories included, and sums
+chunks of each type: in how many files, how many chunks and bytes in total.
+Files with types not defined by PNG or APNG specifications are listed after
+the table. --json prints the same report as JSON, for further processing.
+
+survey --headers-only and scan --headers-only read only length and type of
+every chunk and seek past its data, so huge files and directories are walked
+at the speed of the disk with little memory. CRCs aren't checked then, scan
+only reports chunk types, sizes and positions, not what data looks like.
+
+meta --set-* options exist for registered tEXt keywords: title, author,
+description, copyright, creation-time, software, disclaimer, warning,
+source and comment. --get also accepts any other keyword as is.
+Values are written to tEXt, which only allows Latin-1. Use --itxt to write
+UTF-8 into iTXt instead, which is implied by its other options:
+    --lang <TAG>                 language of the text, like en-US
+    --translated-keyword <TEXT>  keyword in that language, needs single --set-*
+    --compress                   compress text with zlib
+text convert rewrites existing text chunks (all, or only given keywords) as
+tEXt, zTXt or iTXt. Converting from iTXt drops language and translated keyword
+and fails if text is not Latin-1.
+text list shows every text entry with its language tag and translated keyword.
+--lang keeps only iTXt entries in that language, a tag like de also matches
+regional variants (de-DE, de-CH). --json prints entries for further export.
+
+init-type asks whether the chunk must be understood to show the image, is
+registered by PNG specification and may be kept by editors changing pixels,
+then proposes types whose letter case says so, named after a word you give.
+Types that only differ from standard ones in case are never proposed.
+
+edit opens message of TYPE chunk in $VISUAL or $EDITOR and writes it back
+when editor exits, wrapped the same way: same ECC ratio, HMAC and obfuscation
+keys, filters and age recipients must be given again. Expiry and name of
+embedded file are kept. File is not touched if message wasn't changed.
+
+Commands that modify files (encode, batch, edit, remove, move, inject, sign, split-encode,
+meta, text convert, info, dpi set, trailing set, trailing remove, protect, unprotect, seal,
+doctor --fix) accept:
+    --dry-run    print chunks that would be added and removed and resulting
+                 file size, without writing anything
+    --[no-]preserve-times
+                 keep modification time and permissions of original file,
+                 on by default when file is modified in place
+    --journal    record operation in DIR/.pngme-journal so it can be undone
+    --history    record operation, changed chunk types and their hashes in
+                 hiSt chunk of the image itself, so `pngme undo FILE.png`
+                 can revert it (removed chunks up to 64 KiB are kept there)
+    --trash      put overwritten original into OS trash (or $PNGME_TRASH_DIR)
+    --receipt <FILE> --receipt-key <SECRET_KEY>
+                 write JSON receipt with hashes of files before and after,
+                 signed with key from `pngme keygen`
+    --emit-changelog <FILE>
+                 write JSON list of chunks added, removed and modified in
+                 every written image, with offsets, lengths and SHA-256 of
+                 whole chunks before and after
+    --no-lock    don't lock files while they are modified. By default another
+                 pngme changing the same file waits until this one is done
+                 (on Unix, where locks are advisory)
+    --force      write file even if modification breaks PNG chunk ordering:
+                 IHDR first and IEND last, PLTE and ancillary chunks before
+                 IDAT as required, single-instance chunks, APNG sequence.
+                 encode and split-encode also need it to write message into
+                 standard (e.g. tRNS) or critical (uppercase first letter) type,
+                 or type with reserved lowercase third letter
+
+doctor checks image for damaged chunks (CRC, truncation), missing IEND, data
+after IEND, chunk ordering, bulky metadata and everything scan looks for, and
+suggests a command for every problem. It fails if serious ones remain. --fix
+computes broken CRCs anew, drops truncated and invalid chunks, all but the first
+IHDR, PLTE and IEND and data after IEND, and appends missing IEND, leaving other
+intact chunks as they are.
+
+trailing works with data appended after IEND that isn't chunks, which decoders
+ignore. Other commands keep it in place. get prints hex dump of it, set
+replaces it with content of PAYLOAD file and remove strips it. Changes of
+trailing data are not recorded by --journal and --history.
+
+carve lists complete PNG images found inside chunk data or after IEND, also
+ones nested deeper, and saves them into --output-dir as FILE-1.png, FILE-2.png...
+
+spread-encode scatters message over many small chunks of types that image
+editors leave behind, placed at random between existing chunks. Only the
+password tells which chunks hold the message and in what order. Message is
+encrypted with a key derived from password and a random nonce, so decoding
+fails if any fragment is missing or altered. One more chunk, encrypted the
+same way, keeps positions of fragments for spread-decode, so fragments must
+not be moved afterwards.
+
+seal stores SHA-256 digest of IHDR, PLTE and IDAT chunks in seAl chunk, signed
+with --key when given, replacing earlier seal. verify-seal fails if pixels
+have changed since then, metadata chunks may be edited freely.
+
+protect encrypts every chunk of given ancillary types (e.g. tEXt,eXIf) with
+ChaCha20-Poly1305 under a key derived from password with PBKDF2-HMAC-SHA256
+and puts it in prOt chunk at the same place,
+so metadata stays in the image, but only those who know the password can read
+it. unprotect restores all prOt chunks it can decrypt.
+
+cat writes chunks in given order: every chunk record from RECORDS files (as
+saved by `remove --dump --raw`), or one chunk of TYPE with content of PAYLOAD
+file. CRCs are computed anew. Chunk ordering is checked unless --force is given.
+
+animate builds animated PNG from frames of the same size and pixel format,
+each shown for --delay given as 100ms, 2s or fraction of a second like 1/30.
+First frame is also the image shown where animation isn't supported. --plays
+limits how many times it is played, forever by default.
+
+optimize --recompress inflates image data and deflates it again as tightly as
+pngme can (with zopfli, when built with that feature), merging all IDAT chunks
+into one. Pixels stay the same, and original data is kept if it was smaller.
+
+info --set-srgb accepts perceptual, relative-colorimetric, saturation or
+absolute-colorimetric intent and also writes matching gAMA and cHRM chunks,
+unless they are set explicitly. --set-chromaticities takes 8 comma-separated
+values: x,y of white point, red, green and blue.
+
+batch encodes messages as listed in YAML manifest, one entry per embedding:
+    - input: dice.png         required, paths are relative to manifest
+      output: out/dice.png    input is overwritten if not set
+      type: ruSt              required
+      message: hello          or message-file: <PATH>
+      hmac-key: <KEY>         optional, same for ecc and obfuscate
+It keeps going when an entry fails and reports all failures at the end.
+
+move places chunk at given index, right before the first chunk of TYPE
+or right after the last one, e.g. --to before-IDAT. The result must keep
+PNG ordering rules: IHDR first, PLTE before IDAT and so on.
+
+export describes every chunk as JSON with base64-encoded data (printed if no
+STRUCTURE.json is given), build makes PNG out of such description, computing
+CRCs anew. Together they allow editing image structure as text.
+
+watch runs OPERATION, e.g. \"decode ruSt\", on every PNG that is added to
+or modified in DIR. File path goes right after command name or in place of
+`{}`, e.g. \"dpi get {}\".
+
+serve answers HTTP on --listen (default 127.0.0.1:8080): POST /verify with
+PNG of up to 8 MiB as body reports whether it parses and its chunks, GET /healthz answers ok
+and GET /metrics gives request, parse failure and chunk counts in Prometheus
+text format. Available when built with the serve feature.
+
+blame tells when each chunk of FILE was added and last changed, looking
+through --versions DIR (ordered by file name, e.g. 2024-05-01.png) or
+commits that touched FILE with --git.
+
+merge takes image data from the side that changed it and merges other
+chunks one by one. Chunks changed differently on both sides are reported
+as conflicts and our version of them is kept.
+
+--obfuscate XORs the message with a stream generated from KEY. This is
+obfuscation against casual inspection, NOT encryption: do not rely on it
+to keep secrets.
+
+--recipient encrypts the message with age to the public key (age1...), it can
+be repeated so that any of the recipients can decrypt. decode --identity takes
+file with secret keys, as written by age-keygen. Needs build with age feature.
+
+--filter pipes the message through shell COMMAND, binary-safe, before encode
+embeds it or after decode extracts it. This reuses existing tools and keyrings:
+    pngme encode dice.png ruSt secret.txt --filter \"gpg --encrypt -r alice\"
+    pngme decode dice.png ruSt --raw --filter \"gpg --decrypt\"
+
+--expires stores expiry time (UTC) along with the message, a date alone means
+end of that day. decode warns about expired message, or fails with --strict.
+
+--provenance also writes prVn chunk with pngme version, payload chunk type,
+time, user and host name, so others can tell who embedded what. Nothing is
+recorded without it. Config can set names to record instead of detected ones,
+or leave them out:
+    provenance-user = \"ci-bot\"
+    provenance-host = false
+
+--max-growth fails encode, leaving file untouched, when it would grow by more
+than given number of bytes or percentage of original size (e.g. 5%). Sizes
+before and after are printed.
+
+--mode picks how encode hides the message and where decode looks for it:
+chunk (default) is a chunk of its own TYPE, lsb is the lowest bit of pixel
+samples (8-bit non-indexed, non-interlaced images only), trailing is data after
+IEND and text is hex in a tEXt chunk with Comment keyword. Modes other than
+chunk take no TYPE, trailing mode isn't recorded by --journal.
+
+FILE of commands that only read it can be inside zip (also apk, jar, ipa)
+or tar archive, optionally gzipped, given as ARCHIVE!PATH:
+    pngme scan app.apk!res/drawable/icon.png
+
+Builds with cloud feature also take s3://BUCKET/KEY and gs://BUCKET/KEY
+for any FILE, read and written in place. Credentials and region come from
+AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY, AWS_SESSION_TOKEN and AWS_REGION,
+AWS_ENDPOINT_URL points to other S3-compatible services. For gs:// these
+hold Cloud Storage HMAC keys.
+
+MESSAGE `-` (or `--message-file -`) reads message from stdin, which is
+handy for multi-line or binary content.
+
+TYPE is either a raw 4-character code (e.g. ruSt) or a long-form name
+of a well-known chunk (e.g. text, exif, physical-dimensions).
+It can also be passed as `--type <TYPE>` instead of positionally, or replaced
+with `--type-password <PASSWORD>` to use a private type derived from password.";
+
+/// Commands and options that write files or run external commands, left out
+/// of usage of read-only viewer together with paragraphs about them
+#[cfg(not(feature = "write"))]
+const WRITE_ONLY: &[&str] = &[
+    "encode",
+    "batch",
+    "edit",
+    "remove",
+    "move",
+    "inject",
+    "wizard",
+    "init-type",
+    "build",
+    "cat",
+    "animate",
+    "optimize",
+    "undo",
+    "keygen",
+    "sign",
+    "seal",
+    "watch",
+    "text convert",
+    "bug-report",
+    "minimize",
+    "merge",
+    "dpi set",
+    "trailing set",
+    "trailing remove",
+    "split-encode",
+    "spread-encode",
+    "protect",
+    "unprotect",
+    "self-update",
+    "Commands that modify files",
+    "--filter",
+    "--scanner",
+    "--git",
+    "--set-",
+    "--fix",
+    "--output",
+    "--export",
+    "--stream",
+    "--provenance",
+    "--max-growth",
+];
+
+/// Usage of this build
+#[cfg(feature = "write")]
+pub fn usage() -> &'static str {
+    USAGE
+}
+
+/// Usage of read-only viewer, without commands and options it doesn't have
+#[cfg(not(feature = "write"))]
+pub fn usage() -> &'static str {
+    static VIEWER_USAGE: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+    VIEWER_USAGE.get_or_init(|| {
+        let starts_entry = |text: &str| {
+            WRITE_ONLY.iter().any(|entry| {
+                text.strip_prefix(entry)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+            })
+        };
+        let mut paragraphs = vec![];
+        for paragraph in USAGE.split("\n\n").filter(|p| !starts_entry(p)) {
+            let mut lines: Vec<String> = vec![];
+            // whether line continues synopsis of a command, and that one was left out
+            let mut synopsis = None;
+            for line in paragraph.lines() {
+                let indent = line.len() - line.trim_start().len();
+                if let Some(command) = line.trim_start().strip_prefix("pngme ") {
+                    synopsis = Some(starts_entry(command));
+                } else if indent <= 4 {
+                    synopsis = None;
+                }
+                match synopsis {
+                    None => lines.push(line.to_string()),
+                    Some(true) => {}
+                    Some(false) => match strip_write_only(line) {
+                        None => synopsis = Some(true),
+                        Some(kept) if kept.trim().is_empty() => {}
+                        // what is left of the line may duplicate other synopsis
+                        Some(kept) if lines.iter().any(|l| l.starts_with(&kept)) => {}
+                        Some(kept) => lines.push(kept),
+                    },
+                }
+            }
+            paragraphs.push(lines.join("\n"));
+        }
+        paragraphs.join("\n\n")
+    })
+}
+
+/// Line of synopsis without optional `[...]` groups that have write-only options,
+/// `None` if such option is required
+#[cfg(not(feature = "write"))]
+fn strip_write_only(line: &str) -> Option<String> {
+    let is_write_only = |word: &str| {
+        let word = word.trim_start_matches(['[', '(']);
+        WRITE_ONLY
+            .iter()
+            .any(|entry| entry.starts_with("--") && word.starts_with(entry))
+    };
+    let mut kept = vec![];
+    let mut group = vec![];
+    let mut depth = 0;
+    for word in line.split_whitespace() {
+        depth += word.matches('[').count() as i32 - word.matches(']').count() as i32;
+        if group.is_empty() && !word.starts_with('[') {
+            if is_write_only(word) {
+                return None;
+            }
+            kept.push(word);
+            continue;
+        }
+        group.push(word);
+        if depth == 0 {
+            if !group.iter().any(|word| is_write_only(word)) {
+                kept.extend(&group);
+            }
+            group.clear();
+        }
+    }
+    let indent = &line[..line.len() - line.trim_start().len()];
+    Some(format!("{}{}", indent, kept.join(" ")))
+}
+
+/// Default chunk type for shares of split messages
+const SHARE_CHUNK: &str = "shRd";
+
+/// Options accepted by every command
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct GlobalOptions {
+    /// Hide progress bars
+    pub quiet: bool,
+    /// 1 logs main steps, 2 and more also details of every chunk
+    pub verbosity: u8,
+    /// Reject images with missing or repeated IHDR, missing IEND, chunks
+    /// after IEND or unknown critical chunks
+    pub strict_png: bool,
+    /// Lock files while they are read and modified, set by commands
+    /// that modify files unless `--no-lock` is given
+    #[cfg(feature = "write")]
+    pub lock: bool,
+}
+
+pub enum Command {
+    Help,
+    #[cfg(feature = "write")]
+    Encode(EncodeArgs),
+    #[cfg(feature = "write")]
+    Batch(BatchArgs),
+    Decode(DecodeArgs),
+    VerifyMessage(VerifyMessageArgs),
+    #[cfg(feature = "write")]
+    Edit(EditArgs),
+    #[cfg(feature = "write")]
+    Remove(RemoveArgs),
+    #[cfg(feature = "write")]
+    Move(MoveArgs),
+    Print(PrintArgs),
+    #[cfg(feature = "write")]
+    Inject(InjectArgs),
+    #[cfg(feature = "write")]
+    Wizard,
+    #[cfg(feature = "write")]
+    InitType,
+    Dump(DumpArgs),
+    Export(ExportArgs),
+    Carve(CarveArgs),
+    #[cfg(feature = "write")]
+    Build(BuildArgs),
+    #[cfg(feature = "write")]
+    Cat(CatArgs),
+    #[cfg(feature = "write")]
+    Animate(AnimateArgs),
+    #[cfg(feature = "write")]
+    Optimize(OptimizeArgs),
+    #[cfg(feature = "write")]
+    Undo(UndoArgs),
+    Scan(ScanArgs),
+    Doctor(DoctorArgs),
+    #[cfg(feature = "write")]
+    Keygen(KeygenArgs),
+    #[cfg(feature = "write")]
+    Sign(SignArgs),
+    Verify(VerifyArgs),
+    #[cfg(feature = "write")]
+    Seal(SealArgs),
+    VerifySeal(VerifySealArgs),
+    Grep(GrepArgs),
+    Diff(DiffArgs),
+    SameImage(SameImageArgs),
+    Blame(BlameArgs),
+    #[cfg(feature = "write")]
+    Watch(WatchArgs),
+    Meta(MetaArgs),
+    #[cfg(feature = "write")]
+    TextConvert(TextConvertArgs),
+    TextList(TextListArgs),
+    Info(InfoArgs),
+    Palette(PaletteArgs),
+    Stats(StatsArgs),
+    Survey(SurveyArgs),
+    #[cfg(feature = "write")]
+    BugReport(BugReportArgs),
+    #[cfg(feature = "write")]
+    Minimize(MinimizeArgs),
+    #[cfg(feature = "write")]
+    Merge(MergeArgs),
+    ReceiptVerify(ReceiptVerifyArgs),
+    DpiGet(DpiGetArgs),
+    #[cfg(feature = "write")]
+    DpiSet(DpiSetArgs),
+    TrailingGet(TrailingGetArgs),
+    #[cfg(feature = "write")]
+    TrailingSet(TrailingSetArgs),
+    #[cfg(feature = "write")]
+    TrailingRemove(TrailingRemoveArgs),
+    #[cfg(feature = "write")]
+    SplitEncode(SplitEncodeArgs),
+    JoinDecode(JoinDecodeArgs),
+    #[cfg(feature = "write")]
+    SpreadEncode(SpreadEncodeArgs),
+    SpreadDecode(SpreadDecodeArgs),
+    #[cfg(feature = "write")]
+    Protect(ProtectArgs),
+    #[cfg(feature = "write")]
+    Unprotect(UnprotectArgs),
+    #[cfg(feature = "self-update")]
+    SelfUpdate(SelfUpdateArgs),
+    #[cfg(feature = "serve")]
+    Serve(ServeArgs),
+}
+
+#[cfg(feature = "write")]
+pub struct EncodeArgs {
+    pub file_path: PathBuf,
+    /// Random private type is picked if not set
+    pub chunk_type: Option<ChunkType>,
+    pub message: Message,
+    /// Message is base64 or hex text of binary payload
+    pub input_encoding: Option<Encoding>,
+    pub output: Option<PathBuf>,
+    pub hmac_key: Option<String>,
+    /// Amount of Reed-Solomon parity relative to payload
+    pub ecc: Option<f64>,
+    /// Key for XOR obfuscation, not encryption
+    pub obfuscate: Option<String>,
+    /// Message is wrapped in envelope with this expiry time
+    pub expires: Option<SystemTime>,
+    /// Shell command to pipe message through before embedding
+    pub filter: Option<String>,
+    /// age public keys to encrypt message to
+    #[cfg(feature = "age")]
+    pub recipients: Vec<String>,
+    /// Names to record in provenance chunk written next to payload, opt-in
+    pub provenance: Option<ProvenanceNames>,
+    /// Encode fails if file would grow by more than this
+    pub max_growth: Option<Growth>,
+    /// Embedding backend other than chunk, `chunk_type` is not set then
+    pub mode: Option<String>,
+    pub write: WriteOptions,
+}
+
+/// Allowed increase of file size, relative to original size or in bytes
+#[cfg(feature = "write")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Growth {
+    Percent(f64),
+    Bytes(u64),
+}
+
+#[cfg(feature = "write")]
+impl FromStr for Growth {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let growth = match s.strip_suffix('%') {
+            Some(percent) => Growth::Percent(
+                percent
+                    .parse()
+                    .ok()
+                    .filter(|p: &f64| *p >= 0.0)
+                    .with_context(|| format!("Invalid percentage '{}'", s))?,
+            ),
+            None => Growth::Bytes(s.parse().with_context(|| {
+                format!(
+                    "Invalid growth '{}', expected bytes or percentage like 5%",
+                    s
+                )
+            })?),
+        };
+        Ok(growth)
+    }
+}
+
+#[cfg(feature = "write")]
+impl Growth {
+    /// Largest allowed size of file that is `size` bytes now
+    pub fn limit(self, size: usize) -> usize {
+        match self {
+            Growth::Percent(percent) => size + (size as f64 * percent / 100.0) as usize,
+            Growth::Bytes(bytes) => size.saturating_add(bytes as usize),
+        }
+    }
+}
+
+#[cfg(feature = "write")]
+pub struct BatchArgs {
+    pub manifest: PathBuf,
+    pub write: WriteOptions,
+}
+
+pub struct DecodeArgs {
+    pub file_path: PathBuf,
+    /// Payload of each type is printed labeled by it when there are several
+    pub chunk_types: Vec<ChunkType>,
+    pub hmac_key: Option<String>,
+    pub obfuscate: Option<String>,
+    /// Write payload bytes to stdout as is, without newline or UTF-8 conversion
+    pub raw: bool,
+    /// Print payload as base64 or hex instead of text
+    pub encoding: Option<Encoding>,
+    /// Directory to save payload into instead of printing it
+    #[cfg(feature = "write")]
+    pub output_dir: Option<PathBuf>,
+    /// File to save payload into, same as `output_dir` if it is a directory
+    #[cfg(feature = "write")]
+    pub output: Option<PathBuf>,
+    /// Put message on clipboard instead of printing it
+    #[cfg(feature = "clipboard")]
+    pub copy: bool,
+    /// age identity file to decrypt message with
+    #[cfg(feature = "age")]
+    pub identity: Option<PathBuf>,
+    /// Shell command to pipe extracted message through
+    #[cfg(feature = "write")]
+    pub filter: Option<String>,
+    /// Fail instead of warning when message has expired
+    pub strict: bool,
+    /// Embedding backend other than chunk, `chunk_types` is empty then
+    pub mode: Option<String>,
+    /// Copy data of all chunks of the type straight to `output`, without holding it in memory
+    #[cfg(feature = "write")]
+    pub stream: bool,
+    /// Decoding fails if payload is larger than this
+    pub max_size: Option<u64>,
+}
+
+pub struct VerifyMessageArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: ChunkType,
+    pub expected: Expected,
+    pub hmac_key: Option<String>,
+    pub obfuscate: Option<String>,
+}
+
+#[cfg(feature = "write")]
+pub struct EditArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: ChunkType,
+    pub hmac_key: Option<String>,
+    pub obfuscate: Option<String>,
+    /// age key file to decrypt message with before editing
+    #[cfg(feature = "age")]
+    pub identity: Option<PathBuf>,
+    /// age public keys to encrypt edited message to
+    #[cfg(feature = "age")]
+    pub recipients: Vec<String>,
+    /// Shell command to pipe extracted message through before editing
+    pub decode_filter: Option<String>,
+    /// Shell command to pipe edited message through before embedding
+    pub encode_filter: Option<String>,
+    pub write: WriteOptions,
+}
+
+/// Value that decoded message is compared against
+#[derive(Debug, Eq, PartialEq)]
+pub enum Expected {
+    Text(String),
+    File(PathBuf),
+}
+
+/// Text representation of binary payload
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Encoding {
+    Base64,
+    Hex,
+}
+
+impl FromStr for Encoding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "base64" => Ok(Encoding::Base64),
+            "hex" => Ok(Encoding::Hex),
+            _ => bail!("Invalid encoding '{}', expected 'base64' or 'hex'", s),
+        }
+    }
+}
+
+impl Encoding {
+    pub fn encode(self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Base64 => BASE64_STANDARD.encode(bytes),
+            Encoding::Hex => hex::encode(bytes),
+        }
+    }
+
+    /// Decodes text, surrounding whitespace is ignored
+    #[cfg(feature = "write")]
+    pub fn decode(self, text: &str) -> Result<Vec<u8>> {
+        match self {
+            Encoding::Base64 => BASE64_STANDARD
+                .decode(text.trim())
+                .context("Invalid base64"),
+            Encoding::Hex => hex::decode(text),
+        }
+    }
+}
+
+#[cfg(feature = "write")]
+pub struct RemoveArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: ChunkType,
+    /// File to save removed chunk data to
+    pub dump: Option<PathBuf>,
+    /// Dump whole chunk with length, type and CRC instead of only data
+    pub raw: bool,
+    pub write: WriteOptions,
+}
+
+#[cfg(feature = "write")]
+pub struct MoveArgs {
+    pub file_path: PathBuf,
+    pub chunk: ChunkRef,
+    pub to: Destination,
+    pub write: WriteOptions,
+}
+
+pub struct ExportArgs {
+    pub file_path: PathBuf,
+    /// Structure is printed if not set
+    #[cfg(feature = "write")]
+    pub output: Option<PathBuf>,
+}
+
+pub struct CarveArgs {
+    pub file_path: PathBuf,
+    /// Nested images are only listed if not set
+    #[cfg(feature = "write")]
+    pub output_dir: Option<PathBuf>,
+}
+
+#[cfg(feature = "write")]
+pub struct BuildArgs {
+    pub structure: PathBuf,
+    pub output: PathBuf,
+    /// Write image even if it breaks chunk ordering rules
+    pub force: bool,
+}
+
+#[cfg(feature = "write")]
+pub struct CatArgs {
+    pub parts: Vec<Part>,
+    pub output: PathBuf,
+    /// Write image even if it breaks chunk ordering rules
+    pub force: bool,
+}
+
+#[cfg(feature = "write")]
+pub struct AnimateArgs {
+    pub frames: Vec<PathBuf>,
+    pub delay: Delay,
+    /// How many times animation is played, 0 for forever
+    pub plays: u32,
+    pub output: PathBuf,
+}
+
+#[cfg(feature = "write")]
+pub struct OptimizeArgs {
+    pub file_path: PathBuf,
+    /// Compress image data again and merge it into a single IDAT
+    pub recompress: bool,
+    pub write: WriteOptions,
+}
+
+/// File that `cat` takes chunks from
+#[cfg(feature = "write")]
+#[derive(Debug, Eq, PartialEq)]
+pub enum Part {
+    /// Raw chunk records: length, type, data and CRC
+    Records(PathBuf),
+    /// Data of single chunk of given type
+    Payload(ChunkType, PathBuf),
+}
+
+#[cfg(feature = "write")]
+impl FromStr for Part {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // single letter before colon is a Windows drive
+        match s.split_once(':') {
+            Some((chunk_type, path)) if chunk_type.len() > 1 => {
+                Ok(Part::Payload(registry::resolve(chunk_type)?, path.into()))
+            }
+            _ => Ok(Part::Records(s.into())),
+        }
+    }
+}
+
+pub struct PrintArgs {
+    pub file_path: PathBuf,
+    /// Show content of compressed chunks
+    pub decompress: bool,
+    /// Show SHA-256 of file and of every chunk data
+    pub hash: bool,
+    pub no_color: bool,
+    /// Dump whole data of every chunk instead of preview column
+    pub full: bool,
+    pub selection: ChunkSelection,
+}
+
+/// Chunks shown by listing commands, all of them by default
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct ChunkSelection {
+    /// Only these types if not empty
+    pub types: Vec<ChunkType>,
+    pub critical_only: bool,
+    pub ancillary_only: bool,
+    /// Only types missing from the registry of well-known chunks
+    pub unknown_only: bool,
+}
+
+impl ChunkSelection {
+    /// Takes raw type bytes, so chunks with invalid type can be listed too
+    pub fn matches(&self, chunk_type: [u8; 4]) -> bool {
+        let known = ChunkType::try_from(chunk_type)
+            .ok()
+            .and_then(|t| registry::name_of(&t))
+            .is_some();
+        let critical = chunk_type[0].is_ascii_uppercase();
+        (self.types.is_empty() || self.types.iter().any(|t| t.bytes() == chunk_type))
+            && (!self.critical_only || critical)
+            && (!self.ancillary_only || !critical)
+            && (!self.unknown_only || !known)
+    }
+}
+
+#[cfg(feature = "write")]
+pub struct InjectArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: ChunkType,
+    pub payload_path: PathBuf,
+    pub position: Position,
+    /// Store file name in the chunk so `decode --output-dir` can restore it
+    pub keep_name: bool,
+    /// Store modification time too, implies `keep_name`
+    pub keep_mtime: bool,
+    pub write: WriteOptions,
+}
+
+pub struct DumpArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: ChunkType,
+    /// Dump decompressed content of zlib or gzip stream found in data
+    pub inflate: bool,
+}
+
+pub struct MetaArgs {
+    pub file_path: PathBuf,
+    /// Keywords to print, all text entries are listed if empty and nothing is set
+    pub get: Vec<String>,
+    /// Entries replacing existing ones with the same keyword
+    #[cfg(feature = "write")]
+    pub set: Vec<TextEntry>,
+    #[cfg(feature = "write")]
+    pub write: WriteOptions,
+}
+
+#[cfg(feature = "write")]
+pub struct TextConvertArgs {
+    pub file_path: PathBuf,
+    pub format: TextFormat,
+    /// Keywords to convert, all text chunks are converted if empty
+    pub keywords: Vec<String>,
+    pub write: WriteOptions,
+}
+
+pub struct TextListArgs {
+    pub file_path: PathBuf,
+    /// Only `iTXt` entries in this language or its variants are listed
+    pub language: Option<String>,
+    /// Keywords to list, all entries are listed if empty
+    pub keywords: Vec<String>,
+    pub json: bool,
+}
+
+pub struct InfoArgs {
+    pub file_path: PathBuf,
+    /// Show SHA-256 of file and of every chunk data
+    pub hash: bool,
+    #[cfg(feature = "write")]
+    pub gamma: Option<Gamma>,
+    #[cfg(feature = "write")]
+    pub chromaticities: Option<Chromaticities>,
+    #[cfg(feature = "write")]
+    pub srgb: Option<RenderingIntent>,
+    #[cfg(feature = "write")]
+    pub write: WriteOptions,
+}
+
+pub struct PaletteArgs {
+    pub file_path: PathBuf,
+    /// GIMP (.gpl) or JASC (.pal) palette file to write, picked by extension
+    #[cfg(feature = "write")]
+    pub export: Option<PathBuf>,
+}
+
+pub struct StatsArgs {
+    pub file_path: PathBuf,
+}
+
+pub struct SurveyArgs {
+    pub dir: PathBuf,
+    pub json: bool,
+    /// Read only chunk headers, skipping data
+    pub headers_only: bool,
+}
+
+pub struct DiffArgs {
+    pub old_path: PathBuf,
+    pub new_path: PathBuf,
+    /// Describe changes in terms of chunk meaning instead of raw chunks
+    pub explain: bool,
+}
+
+pub struct SameImageArgs {
+    pub a_path: PathBuf,
+    pub b_path: PathBuf,
+    /// Compare inflated image data instead of compressed stream
+    pub decompress: bool,
+}
+
+#[cfg(feature = "write")]
+pub struct WatchArgs {
+    pub dir: PathBuf,
+    /// pngme command line without file path
+    pub on_add: String,
+    /// Config that operation is parsed with
+    pub config: Config,
+}
+
+pub struct BlameArgs {
+    pub file_path: PathBuf,
+    pub chunk: Option<ChunkFilter>,
+    pub history: History,
+}
+
+/// Where earlier versions of a file are taken from
+#[derive(Debug, Eq, PartialEq)]
+pub enum History {
+    /// Directory with versions that sort chronologically by file name
+    Versions(PathBuf),
+    /// Runs `git`, so not in read-only viewer
+    #[cfg(feature = "write")]
+    Git,
+}
+
+pub struct GrepArgs {
+    pub file_path: PathBuf,
+    pub pattern: Pattern,
+    /// Search compressed ancillary chunks in their decompressed form
+    pub inflate: bool,
+}
+
+pub struct ScanArgs {
+    pub file_path: PathBuf,
+    /// Show entropy of every chunk as a sparkline
+    pub visual: bool,
+    /// Shell command that suspicious payloads are piped into, e.g. `clamdscan -`
+    #[cfg(feature = "write")]
+    pub scanner: Option<String>,
+    /// YARA rules file matched against raw and decoded chunk data
+    #[cfg(feature = "yara")]
+    pub yara: Option<PathBuf>,
+    /// Read only chunk headers, skipping data
+    pub headers_only: bool,
+}
+
+pub struct DoctorArgs {
+    pub file_path: PathBuf,
+    /// Apply fixes that leave intact chunks as they are
+    #[cfg(feature = "write")]
+    pub fix: bool,
+    #[cfg(feature = "write")]
+    pub write: WriteOptions,
+}
+
+#[cfg(feature = "write")]
+pub struct BugReportArgs {
+    pub file_path: PathBuf,
+    /// Chunks to keep when image itself parses fine
+    pub chunk_types: Vec<ChunkType>,
+    /// Zero data of kept chunks
+    pub redact: bool,
+    /// Command that fails on the image, recorded in report
+    pub command: Option<String>,
+    pub output: Option<PathBuf>,
+}
+
+#[cfg(feature = "write")]
+pub struct MinimizeArgs {
+    pub file_path: PathBuf,
+    /// Shell command with `{}` in place of image path, image reproduces problem while it fails
+    pub command: String,
+    pub output: Option<PathBuf>,
+}
+
+#[cfg(feature = "write")]
+pub struct MergeArgs {
+    pub base_path: PathBuf,
+    pub ours_path: PathBuf,
+    pub theirs_path: PathBuf,
+    pub output: PathBuf,
+}
+
+#[cfg(feature = "write")]
+pub struct SplitEncodeArgs {
+    pub message: Message,
+    pub file_paths: Vec<PathBuf>,
+    /// Number of files needed to recover the message, all of them if not set
+    pub threshold: Option<usize>,
+    pub chunk_type: ChunkType,
+    pub write: WriteOptions,
+}
+
+#[cfg(feature = "write")]
+pub struct SpreadEncodeArgs {
+    pub file_path: PathBuf,
+    pub message: Message,
+    pub password: String,
+    pub output: Option<PathBuf>,
+    pub write: WriteOptions,
+}
+
+pub struct SpreadDecodeArgs {
+    pub file_path: PathBuf,
+    pub password: String,
+}
+
+#[cfg(feature = "write")]
+pub struct ProtectArgs {
+    pub file_path: PathBuf,
+    pub chunk_types: Vec<ChunkType>,
+    pub password: String,
+    pub write: WriteOptions,
+}
+
+#[cfg(feature = "write")]
+pub struct UnprotectArgs {
+    pub file_path: PathBuf,
+    pub password: String,
+    pub write: WriteOptions,
+}
+
+pub struct JoinDecodeArgs {
+    pub file_paths: Vec<PathBuf>,
+    pub chunk_type: ChunkType,
+}
+
+#[cfg(feature = "self-update")]
+pub struct SelfUpdateArgs {
+    /// Only report whether update is available
+    pub check: bool,
+}
+
+#[cfg(feature = "serve")]
+pub struct ServeArgs {
+    /// Address and port to listen on
+    pub listen: String,
+}
+
+#[cfg(feature = "write")]
+pub struct KeygenArgs {
+    pub name: PathBuf,
+    /// Replace existing key files
+    pub force: bool,
+}
+
+#[cfg(feature = "write")]
+pub struct SignArgs {
+    pub file_path: PathBuf,
+    pub key_path: PathBuf,
+    /// Chunk to sign, all critical chunks are signed if not set
+    pub chunk_type: Option<ChunkType>,
+    pub write: WriteOptions,
+}
+
+pub struct VerifyArgs {
+    pub file_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+#[cfg(feature = "write")]
+pub struct SealArgs {
+    pub file_path: PathBuf,
+    /// Secret key to sign the seal with
+    pub key_path: Option<PathBuf>,
+    pub write: WriteOptions,
+}
+
+pub struct VerifySealArgs {
+    pub file_path: PathBuf,
+    /// Public key to check seal signature with
+    pub key_path: Option<PathBuf>,
+}
+
+pub struct ReceiptVerifyArgs {
+    pub receipt_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+pub struct DpiGetArgs {
+    pub file_path: PathBuf,
+}
+
+#[cfg(feature = "write")]
+pub struct DpiSetArgs {
+    pub file_path: PathBuf,
+    pub dpi: f64,
+    pub write: WriteOptions,
+}
+
+pub struct TrailingGetArgs {
+    pub file_path: PathBuf,
+    /// Write bytes to stdout as is instead of hex dump
+    pub raw: bool,
+    /// File to save data into instead of printing it
+    #[cfg(feature = "write")]
+    pub output: Option<PathBuf>,
+}
+
+#[cfg(feature = "write")]
+pub struct TrailingSetArgs {
+    pub file_path: PathBuf,
+    pub payload_path: PathBuf,
+    pub write: WriteOptions,
+}
+
+#[cfg(feature = "write")]
+pub struct TrailingRemoveArgs {
+    pub file_path: PathBuf,
+    pub write: WriteOptions,
+}
+
+#[cfg(feature = "write")]
+pub struct UndoArgs {
+    /// Directory with journal, or image with history chunk
+    pub path: PathBuf,
+    pub steps: usize,
+}
+
+/// Where message to hide is taken from
+#[cfg(feature = "write")]
+#[derive(Debug, Eq, PartialEq)]
+pub enum Message {
+    Text(String),
+    File(PathBuf),
+    Stdin,
+    #[cfg(feature = "clipboard")]
+    Clipboard,
+}
+
+/// Options shared by all commands that modify files
+#[cfg(feature = "write")]
+#[derive(Clone, Debug, Default)]
+pub struct WriteOptions {
+    /// Only report what would change
+    pub dry_run: bool,
+    /// Keep modification time and permissions of original file,
+    /// by default only when it is modified in place
+    pub preserve_times: Option<bool>,
+    pub journal: bool,
+    pub trash: bool,
+    /// Where to write signed receipt and secret key to sign it with
+    pub receipt: Option<(PathBuf, PathBuf)>,
+    /// Where to write JSON list of chunks added, removed and modified
+    pub changelog: Option<PathBuf>,
+    /// Record operation in history chunk of the image
+    pub history: bool,
+    /// Write image even if modification breaks chunk ordering rules
+    pub force: bool,
+}
+
+/// Where a new chunk is placed in the chunk list
+#[cfg(feature = "write")]
+#[derive(Debug, Eq, PartialEq)]
+pub enum Position {
+    End,
+    BeforeIend,
+}
+
+#[cfg(feature = "write")]
+impl FromStr for Position {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "end" => Ok(Position::End),
+            "before-iend" => Ok(Position::BeforeIend),
+            _ => bail!("Invalid position '{}', expected 'end' or 'before-iend'", s),
+        }
+    }
+}
+
+/// Chunk given either by index or by type, which must be unique then
+#[cfg(feature = "write")]
+#[derive(Debug, Eq, PartialEq)]
+pub enum ChunkRef {
+    Index(usize),
+    Type(ChunkType),
+}
+
+#[cfg(feature = "write")]
+impl FromStr for ChunkRef {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.parse() {
+            Ok(index) => Ok(ChunkRef::Index(index)),
+            Err(_) => Ok(ChunkRef::Type(registry::resolve(s)?)),
+        }
+    }
+}
+
+/// Where chunk is moved to
+#[cfg(feature = "write")]
+#[derive(Debug, Eq, PartialEq)]
+pub enum Destination {
+    /// Index of chunk after the move
+    Index(usize),
+    /// Right before the first chunk of type
+    Before(ChunkType),
+    /// Right after the last chunk of type
+    After(ChunkType),
+}
+
+#[cfg(feature = "write")]
+impl FromStr for Destination {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(name) = s.strip_prefix("before-") {
+            return Ok(Destination::Before(registry::resolve(name)?));
+        }
+        if let Some(name) = s.strip_prefix("after-") {
+            return Ok(Destination::After(registry::resolve(name)?));
+        }
+        match s.parse() {
+            Ok(index) => Ok(Destination::Index(index)),
+            Err(_) => bail!(
+                "Invalid destination '{}', expected index, before-<TYPE> or after-<TYPE>",
+                s
+            ),
+        }
+    }
+}
+
+impl Command {
+    /// Parses command along with options accepted by every command,
+    /// options missing from command line are taken from config
+    pub fn parse<I: IntoIterator<Item = String>>(
+        args: I,
+        config: Config,
+    ) -> Result<(Command, GlobalOptions)> {
+        let mut args = Args::new(args, config);
+        let mut options = GlobalOptions {
+            quiet: args.flag("--quiet"),
+            verbosity: 0,
+            strict_png: args.flag("--strict-png"),
+            #[cfg(feature = "write")]
+            lock: false,
+        };
+        while args.flag("-vv") {
+            options.verbosity += 2;
+        }
+        while args.flag("-v") || args.flag("--verbose") {
+            options.verbosity += 1;
+        }
+        if args.flag("--help") || args.flag("-h") {
+            return Ok((Command::Help, options));
+        }
+        let name = args.positional().context("Missing command")?;
+
+        let command = match name.as_str() {
+            "help" => Command::Help,
+            #[cfg(feature = "write")]
+            "encode" => {
+                let write = args.write_options()?;
+                let hmac_key = args.option("--hmac-key")?;
+                let ecc = match args.option("--ecc")? {
+                    Some(ratio) => Some(ratio.parse().context("Invalid ECC ratio")?),
+                    None => None,
+                };
+                let obfuscate = args.option("--obfuscate")?;
+                let filter = args.filter(args.config.encode_filter.clone())?;
+                let expires = match args.option("--expires")? {
+                    // date alone means message is valid through that day
+                    Some(time) if !time.contains('T') => {
+                        Some(date::parse_timestamp(&time)? + Duration::from_secs(24 * 60 * 60))
+                    }
+                    Some(time) => Some(date::parse_timestamp(&time)?),
+                    None => None,
+                };
+                #[cfg(feature = "age")]
+                let recipients = {
+                    let mut recipients = vec![];
+                    while let Some(recipient) = args.option("--recipient")? {
+                        recipients.push(recipient);
+                    }
+                    recipients
+                };
+                let input_encoding = match args.option("--input-encoding")? {
+                    Some(encoding) => Some(encoding.parse()?),
+                    None => None,
+                };
+                let message_file = args.option("--message-file")?;
+                let provenance = args
+                    .flag("--provenance")
+                    .then(|| args.config.provenance.clone());
+                let max_growth = match args.option("--max-growth")? {
+                    Some(growth) => Some(growth.parse()?),
+                    None => None,
+                };
+                let mode = args.mode()?;
+                let random_type = args.flag("--random-type");
+                let chunk_type = args.type_option()?;
+                let file_path = args.required("FILE")?.into();
+                let chunk_type = match (random_type, chunk_type) {
+                    _ if mode.is_some() => {
+                        ensure!(
+                            !random_type && chunk_type.is_none() && provenance.is_none(),
+                            "--mode doesn't take chunk type, --random-type or --provenance"
+                        );
+                        None
+                    }
+                    (true, Some(_)) => bail!("--random-type can't be used with explicit type"),
+                    (true, None) => None,
+                    (false, chunk_type) => Some(args.message_type(chunk_type)?),
+                };
+                Command::Encode(EncodeArgs {
+                    file_path,
+                    chunk_type,
+                    message: args.message(message_file)?,
+                    input_encoding,
+                    output: args.positional().map(PathBuf::from),
+                    hmac_key,
+                    ecc,
+                    obfuscate,
+                    expires,
+                    filter,
+                    #[cfg(feature = "age")]
+                    recipients,
+                    provenance,
+                    max_growth,
+                    mode,
+                    write,
+                })
+            }
+            #[cfg(feature = "write")]
+            "batch" => {
+                let write = args.write_options()?;
+                ensure!(
+                    write.receipt.is_none(),
+                    "batch doesn't support --receipt, use a separate encode per receipt"
+                );
+                Command::Batch(BatchArgs {
+                    manifest: args.required("MANIFEST")?.into(),
+                    write,
+                })
+            }
+            "decode" => {
+                let hmac_key = args.option("--hmac-key")?;
+                let obfuscate = args.option("--obfuscate")?;
+                let raw = args.flag("--raw");
+                let encoding = match args.option("--encoding")? {
+                    Some(encoding) => Some(encoding.parse()?),
+                    None => None,
+                };
+                ensure!(
+                    !raw || encoding.is_none(),
+                    "--raw and --encoding can't be used together"
+                );
+                #[cfg(feature = "write")]
+                let output_dir = args.option("--output-dir")?.map(PathBuf::from);
+                #[cfg(feature = "write")]
+                let output = args.option("--output")?.map(PathBuf::from);
+                #[cfg(feature = "write")]
+                ensure!(
+                    output_dir.is_none() || output.is_none(),
+                    "--output-dir and --output can't be used together"
+                );
+                #[cfg(feature = "clipboard")]
+                let copy = args.flag("--copy");
+                #[cfg(feature = "age")]
+                let identity = args.option("--identity")?.map(PathBuf::from);
+                #[cfg(feature = "write")]
+                let filter = args.filter(args.config.decode_filter.clone())?;
+                let strict = args.flag("--strict");
+                let max_size = match args.option("--max-size")? {
+                    Some(size) => Some(size.parse().context("Invalid --max-size")?),
+                    None => None,
+                };
+                #[cfg(feature = "write")]
+                let stream = args.flag("--stream");
+                #[cfg(feature = "write")]
+                ensure!(
+                    !stream
+                        || output.is_some()
+                            && hmac_key.is_none()
+                            && obfuscate.is_none()
+                            && filter.is_none()
+                            && !raw
+                            && encoding.is_none(),
+                    "--stream needs --output and can't be used with --hmac-key, \
+                     --obfuscate, --filter, --raw or --encoding"
+                );
+                let mode = args.mode()?;
+                let chunk_types = args.type_list()?;
+                let file_path: PathBuf = args.required("FILE")?.into();
+                let chunk_types = match (&mode, chunk_types.is_empty()) {
+                    (Some(_), empty) => {
+                        ensure!(empty, "--mode doesn't take chunk type");
+                        ensure!(
+                            file_path.as_os_str() != "-",
+                            "--mode can't decode from stdin"
+                        );
+                        vec![]
+                    }
+                    (None, true) => args.message_types()?,
+                    (None, false) => chunk_types,
+                };
+                let single = chunk_types.len() == 1 || mode.is_some();
+                ensure!(
+                    single || !raw,
+                    "--raw can't be used with several chunk types"
+                );
+                #[cfg(feature = "clipboard")]
+                ensure!(
+                    single || !copy,
+                    "--copy can't be used with several chunk types"
+                );
+                #[cfg(feature = "write")]
+                ensure!(
+                    !stream || chunk_types.len() == 1 && mode.is_none(),
+                    "--stream needs single chunk type"
+                );
+                Command::Decode(DecodeArgs {
+                    file_path,
+                    chunk_types,
+                    hmac_key,
+                    obfuscate,
+                    raw,
+                    encoding,
+                    #[cfg(feature = "write")]
+                    output_dir,
+                    #[cfg(feature = "write")]
+                    output,
+                    #[cfg(feature = "clipboard")]
+                    copy,
+                    #[cfg(feature = "age")]
+                    identity,
+                    #[cfg(feature = "write")]
+                    filter,
+                    strict,
+                    mode,
+                    #[cfg(feature = "write")]
+                    stream,
+                    max_size,
+                })
+            }
+            "verify-message" => {
+                let hmac_key = args.option("--hmac-key")?;
+                let obfuscate = args.option("--obfuscate")?;
+                let expected_file = args.option("--expected-file")?;
+                let chunk_type = args.type_option()?;
+                let file_path = args.required("FILE")?.into();
+                let chunk_type = args.message_type(chunk_type)?;
+                let expected = match expected_file {
+                    Some(path) => Expected::File(path.into()),
+                    None => Expected::Text(args.required("EXPECTED")?),
+                };
+                Command::VerifyMessage(VerifyMessageArgs {
+                    file_path,
+                    chunk_type,
+                    expected,
+                    hmac_key,
+                    obfuscate,
+                })
+            }
+            #[cfg(feature = "write")]
+            "edit" => {
+                let write = args.write_options()?;
+                let hmac_key = args.option("--hmac-key")?;
+                let obfuscate = args.option("--obfuscate")?;
+                #[cfg(feature = "age")]
+                let identity = args.option("--identity")?.map(PathBuf::from);
+                #[cfg(feature = "age")]
+                let recipients = {
+                    let mut recipients = vec![];
+                    while let Some(recipient) = args.option("--recipient")? {
+                        recipients.push(recipient);
+                    }
+                    recipients
+                };
+                #[cfg(feature = "age")]
+                ensure!(
+                    identity.is_none() == recipients.is_empty(),
+                    "--identity and --recipient must be given together"
+                );
+                let decode_filter = args.option("--decode-filter")?;
+                let encode_filter = args.option("--encode-filter")?;
+                let (decode_filter, encode_filter) = if args.flag("--no-filter") {
+                    ensure!(
+                        decode_filter.is_none() && encode_filter.is_none(),
+                        "--decode-filter and --encode-filter can't be used with --no-filter"
+                    );
+                    (None, None)
+                } else {
+                    (
+                        decode_filter.or(args.config.decode_filter.clone()),
+                        encode_filter.or(args.config.encode_filter.clone()),
+                    )
+                };
+                ensure!(
+                    decode_filter.is_some() == encode_filter.is_some(),
+                    "Both --decode-filter and --encode-filter are needed to edit filtered message"
+                );
+                let chunk_type = args.type_option()?;
+                let file_path = args.required("FILE")?.into();
+                Command::Edit(EditArgs {
+                    file_path,
+                    chunk_type: args.message_type(chunk_type)?,
+                    hmac_key,
+                    obfuscate,
+                    #[cfg(feature = "age")]
+                    identity,
+                    #[cfg(feature = "age")]
+                    recipients,
+                    decode_filter,
+                    encode_filter,
+                    write,
+                })
+            }
+            #[cfg(feature = "write")]
+            "remove" => {
+                let write = args.write_options()?;
+                let dump = args.option("--dump")?.map(PathBuf::from);
+                let raw = args.flag("--raw");
+                ensure!(!raw || dump.is_some(), "--raw can only be used with --dump");
+                let chunk_type = args.type_option()?;
+                let file_path = args.required("FILE")?.into();
+                Command::Remove(RemoveArgs {
+                    file_path,
+                    chunk_type: args.chunk_type(chunk_type)?,
+                    dump,
+                    raw,
+                    write,
+                })
+            }
+            #[cfg(feature = "write")]
+            "move" => {
+                let write = args.write_options()?;
+                let to = args
+                    .option("--to")?
+                    .context("Missing required option --to")?;
+                Command::Move(MoveArgs {
+                    file_path: args.required("FILE")?.into(),
+                    chunk: args.required("TYPE|INDEX")?.parse()?,
+                    to: to.parse()?,
+                    write,
+                })
+            }
+            "print" => {
+                let decompress = args.flag("--decompress") || args.config.decompress;
+                let hash = args.hash_option()?;
+                let no_color = args.flag("--no-color") || !args.config.color;
+                let full = args.flag("--full");
+                let selection = args.selection()?;
+                Command::Print(PrintArgs {
+                    file_path: args.required("FILE")?.into(),
+                    decompress,
+                    hash,
+                    no_color,
+                    full,
+                    selection,
+                })
+            }
+            #[cfg(feature = "write")]
+            "inject" => {
+                let write = args.write_options()?;
+                let chunk_type = args.type_option()?;
+                let position = args.option("--position")?;
+                let keep_mtime = args.flag("--keep-mtime");
+                let keep_name = args.flag("--keep-name") || keep_mtime;
+                let file_path = args.required("FILE")?.into();
+                Command::Inject(InjectArgs {
+                    file_path,
+                    chunk_type: args.chunk_type(chunk_type)?,
+                    payload_path: args.required("PAYLOAD")?.into(),
+                    position: position.as_deref().unwrap_or("end").parse()?,
+                    keep_name,
+                    keep_mtime,
+                    write,
+                })
+            }
+            #[cfg(feature = "write")]
+            "wizard" => Command::Wizard,
+            #[cfg(feature = "write")]
+            "init-type" => Command::InitType,
+            "export" => {
+                let file_path = args.required("FILE")?.into();
+                #[cfg(feature = "write")]
+                let output = args.positional().map(PathBuf::from);
+                Command::Export(ExportArgs {
+                    file_path,
+                    #[cfg(feature = "write")]
+                    output,
+                })
+            }
+            "carve" => {
+                #[cfg(feature = "write")]
+                let output_dir = args.option("--output-dir")?.map(PathBuf::from);
+                Command::Carve(CarveArgs {
+                    file_path: args.required("FILE")?.into(),
+                    #[cfg(feature = "write")]
+                    output_dir,
+                })
+            }
+            #[cfg(feature = "write")]
+            "build" => Command::Build(BuildArgs {
+                force: args.flag("--force"),
+                structure: args.required("STRUCTURE")?.into(),
+                output: args.required("OUTPUT")?.into(),
+            }),
+            #[cfg(feature = "write")]
+            "cat" => {
+                let force = args.flag("--force");
+                let output = args.option("--out")?.context("--out <FILE> is required")?;
+                let parts = args
+                    .remaining("PART")?
+                    .iter()
+                    .map(|part| part.to_string_lossy().parse())
+                    .collect::<Result<_>>()?;
+                Command::Cat(CatArgs {
+                    parts,
+                    output: output.into(),
+                    force,
+                })
+            }
+            #[cfg(feature = "write")]
+            "animate" => {
+                let delay = args
+                    .option("--delay")?
+                    .context("--delay <DELAY> is required")?
+                    .parse()?;
+                let plays = match args.option("--plays")? {
+                    Some(plays) => plays.parse().context("Invalid number of plays")?,
+                    None => 0,
+                };
+                let output = args.option("--out")?.context("--out <FILE> is required")?;
+                Command::Animate(AnimateArgs {
+                    frames: args.remaining("FRAME")?,
+                    delay,
+                    plays,
+                    output: output.into(),
+                })
+            }
+            #[cfg(feature = "write")]
+            "optimize" => {
+                let recompress = args.flag("--recompress");
+                let write = args.write_options()?;
+                Command::Optimize(OptimizeArgs {
+                    file_path: args.required("FILE")?.into(),
+                    recompress,
+                    write,
+                })
+            }
+            "dump" => {
+                let inflate = args.flag("--inflate");
+                let chunk_type = args.type_option()?;
+                let file_path = args.required("FILE")?.into();
+                Command::Dump(DumpArgs {
+                    file_path,
+                    chunk_type: args.chunk_type(chunk_type)?,
+                    inflate,
+                })
+            }
+            "scan" => {
+                let visual = args.flag("--visual");
+                #[cfg(feature = "write")]
+                let scanner = args.option("--scanner")?;
+                #[cfg(feature = "yara")]
+                let yara = args.option("--yara")?.map(PathBuf::from);
+                let headers_only = args.flag("--headers-only");
+                #[cfg_attr(not(any(feature = "write", feature = "yara")), allow(unused_mut))]
+                let mut needs_data = visual;
+                #[cfg(feature = "write")]
+                {
+                    needs_data |= scanner.is_some();
+                }
+                #[cfg(feature = "yara")]
+                {
+                    needs_data |= yara.is_some();
+                }
+                ensure!(
+                    !(headers_only && needs_data),
+                    "--headers-only can't be used with --visual, --scanner or --yara, they need chunk data"
+                );
+                Command::Scan(ScanArgs {
+                    file_path: args.required("FILE")?.into(),
+                    visual,
+                    #[cfg(feature = "write")]
+                    scanner,
+                    #[cfg(feature = "yara")]
+                    yara,
+                    headers_only,
+                })
+            }
+            "doctor" => {
+                #[cfg(feature = "write")]
+                let write = args.write_options()?;
+                #[cfg(feature = "write")]
+                let fix = args.flag("--fix");
+                Command::Doctor(DoctorArgs {
+                    file_path: args.required("FILE")?.into(),
+                    #[cfg(feature = "write")]
+                    fix,
+                    #[cfg(feature = "write")]
+                    write,
+                })
+            }
+            "meta" => {
+                #[cfg(feature = "write")]
+                let write = args.write_options()?;
+                #[cfg(feature = "write")]
+                let set = {
+                    let mut values = vec![];
+                    for (option, keyword) in text::STANDARD_KEYWORDS {
+                        while let Some(value) = args.option(&format!("--set-{}", option))? {
+                            values.push((keyword, value));
+                        }
+                    }
+                    let itxt = args.flag("--itxt");
+                    let language = args.option("--lang")?;
+                    let translated_keyword = args.option("--translated-keyword")?;
+                    let compressed = args.flag("--compress");
+                    ensure!(
+                        translated_keyword.is_none() || values.len() == 1,
+                        "--translated-keyword requires exactly one --set-* option"
+                    );
+                    // any of iTXt fields means text goes into iTXt chunk
+                    let international =
+                        (itxt || language.is_some() || translated_keyword.is_some() || compressed)
+                            .then(|| International {
+                                language: language.unwrap_or_default(),
+                                translated_keyword: translated_keyword.unwrap_or_default(),
+                            });
+                    values
+                        .into_iter()
+                        .map(|(keyword, value)| match &international {
+                            Some(international) => {
+                                let mut entry = TextEntry::international(
+                                    keyword,
+                                    &value,
+                                    international.clone(),
+                                )?;
+                                entry.compressed = compressed;
+                                Ok(entry)
+                            }
+                            None => TextEntry::new(keyword, &value),
+                        })
+                        .collect::<Result<Vec<_>>>()?
+                };
+                let mut get = vec![];
+                while let Some(name) = args.option("--get")? {
+                    get.push(text::resolve_keyword(&name)?);
+                }
+                Command::Meta(MetaArgs {
+                    file_path: args.required("FILE")?.into(),
+                    get,
+                    #[cfg(feature = "write")]
+                    set,
+                    #[cfg(feature = "write")]
+                    write,
+                })
+            }
+            "text" => match args.required("SUBCOMMAND")?.as_str() {
+                "list" => {
+                    let language = args.option("--lang")?;
+                    if let Some(tag) = &language {
+                        text::validate_language(tag)?;
+                    }
+                    let mut keywords = vec![];
+                    while let Some(name) = args.option("--keyword")? {
+                        keywords.push(text::resolve_keyword(&name)?);
+                    }
+                    Command::TextList(TextListArgs {
+                        file_path: args.required("FILE")?.into(),
+                        language,
+                        keywords,
+                        json: args.flag("--json"),
+                    })
+                }
+                #[cfg(feature = "write")]
+                "convert" => {
+                    let write = args.write_options()?;
+                    let format = args
+                        .option("--to")?
+                        .context("Missing required option --to")?
+                        .parse()?;
+                    let mut keywords = vec![];
+                    while let Some(name) = args.option("--keyword")? {
+                        keywords.push(text::resolve_keyword(&name)?);
+                    }
+                    Command::TextConvert(TextConvertArgs {
+                        file_path: args.required("FILE")?.into(),
+                        format,
+                        keywords,
+                        write,
+                    })
+                }
+                other => bail!("Unknown text subcommand '{}'", other),
+            },
+            "info" => {
+                #[cfg(feature = "write")]
+                let write = args.write_options()?;
+                #[cfg(feature = "write")]
+                let srgb: Option<RenderingIntent> = match args.option("--set-srgb")? {
+                    Some(intent) => Some(intent.parse()?),
+                    None => None,
+                };
+                #[cfg(feature = "write")]
+                let gamma = match args.option("--set-gamma")? {
+                    Some(gamma) => {
+                        Some(Gamma::from_value(gamma.parse().context("Invalid gamma")?)?)
+                    }
+                    // sRGB images should also declare matching gamma and chromaticities
+                    None => srgb.map(|_| Gamma::SRGB),
+                };
+                #[cfg(feature = "write")]
+                let chromaticities = match args.option("--set-chromaticities")? {
+                    Some(values) => Some(values.parse()?),
+                    None => srgb.map(|_| Chromaticities::SRGB),
+                };
+                let hash = args.hash_option()?;
+                Command::Info(InfoArgs {
+                    file_path: args.required("FILE")?.into(),
+                    hash,
+                    #[cfg(feature = "write")]
+                    gamma,
+                    #[cfg(feature = "write")]
+                    chromaticities,
+                    #[cfg(feature = "write")]
+                    srgb,
+                    #[cfg(feature = "write")]
+                    write,
+                })
+            }
+            "palette" => {
+                #[cfg(feature = "write")]
+                let export = args.option("--export")?.map(PathBuf::from);
+                #[cfg(feature = "write")]
+                if let Some(export) = &export {
+                    let extension = export.extension().and_then(|ext| ext.to_str());
+                    ensure!(
+                        matches!(extension, Some("gpl" | "pal")),
+                        "Palette can be exported only to .gpl or .pal file"
+                    );
+                }
+                Command::Palette(PaletteArgs {
+                    file_path: args.required("FILE")?.into(),
+                    #[cfg(feature = "write")]
+                    export,
+                })
+            }
+            "stats" => Command::Stats(StatsArgs {
+                file_path: args.required("FILE")?.into(),
+            }),
+            "survey" => {
+                let json = args.flag("--json");
+                let headers_only = args.flag("--headers-only");
+                Command::Survey(SurveyArgs {
+                    dir: args.required("DIR")?.into(),
+                    json,
+                    headers_only,
+                })
+            }
+            "diff" => {
+                let explain = args.flag("--explain");
+                Command::Diff(DiffArgs {
+                    old_path: args.required("OLD")?.into(),
+                    new_path: args.required("NEW")?.into(),
+                    explain,
+                })
+            }
+            "same-image" => {
+                let decompress = args.flag("--decompress");
+                Command::SameImage(SameImageArgs {
+                    a_path: args.required("A")?.into(),
+                    b_path: args.required("B")?.into(),
+                    decompress,
+                })
+            }
+            "blame" => {
+                let chunk = match args.option("--chunk")? {
+                    Some(filter) => Some(filter.parse()?),
+                    None => None,
+                };
+                let versions = args.option("--versions")?;
+                #[cfg(feature = "write")]
+                let git = args.flag("--git");
+                #[cfg(not(feature = "write"))]
+                let git = false;
+                let history = match (versions, git) {
+                    (Some(dir), false) => History::Versions(dir.into()),
+                    #[cfg(feature = "write")]
+                    (None, true) => History::Git,
+                    _ => bail!("Exactly one of --versions and --git must be set"),
+                };
+                Command::Blame(BlameArgs {
+                    file_path: args.required("FILE")?.into(),
+                    chunk,
+                    history,
+                })
+            }
+            #[cfg(feature = "write")]
+            "watch" => {
+                let on_add = args
+                    .option("--on-add")?
+                    .context("Missing required option --on-add")?;
+                Command::Watch(WatchArgs {
+                    dir: args.required("DIR")?.into(),
+                    on_add,
+                    config: args.config.clone(),
+                })
+            }
+            "grep" => {
+                let hex = args.flag("--hex");
+                let regex = args.flag("--regex");
+                let inflate = args.flag("--inflate");
+                let file_path = args.required("FILE")?.into();
+                let pattern = args.required("PATTERN")?;
+                ensure!(!pattern.is_empty(), "Pattern can't be empty");
+                let pattern = match (hex, regex) {
+                    (true, true) => bail!("--hex and --regex can't be used together"),
+                    (true, false) => {
+                        Pattern::Bytes(hex::decode(&pattern).context("Invalid hex pattern")?)
+                    }
+                    (false, true) => Pattern::regex(&pattern)?,
+                    (false, false) => Pattern::Bytes(pattern.into_bytes()),
+                };
+                Command::Grep(GrepArgs {
+                    file_path,
+                    pattern,
+                    inflate,
+                })
+            }
+            #[cfg(feature = "write")]
+            "bug-report" => {
+                let mut chunk_types = vec![];
+                while let Some(name) = args.option("--type")? {
+                    chunk_types.push(registry::resolve(&name)?);
+                }
+                let redact = args.flag("--redact");
+                let command = args.option("--command")?;
+                let output = args.option("--output")?.map(PathBuf::from);
+                Command::BugReport(BugReportArgs {
+                    file_path: args.required("FILE")?.into(),
+                    chunk_types,
+                    redact,
+                    command,
+                    output,
+                })
+            }
+            #[cfg(feature = "write")]
+            "minimize" => {
+                let command = args
+                    .option("--command")?
+                    .context("Missing required option --command")?;
+                ensure!(
+                    command.contains("{}"),
+                    "--command must contain {{}} to be replaced with image path"
+                );
+                let output = args.option("--output")?.map(PathBuf::from);
+                Command::Minimize(MinimizeArgs {
+                    file_path: args.required("FILE")?.into(),
+                    command,
+                    output,
+                })
+            }
+            #[cfg(feature = "write")]
+            "merge" => {
+                let output = match args.option("-o")? {
+                    Some(output) => Some(output),
+                    None => args.option("--output")?,
+                };
+                let output = output.context("Missing required option --output")?;
+                Command::Merge(MergeArgs {
+                    base_path: args.required("BASE")?.into(),
+                    ours_path: args.required("OURS")?.into(),
+                    theirs_path: args.required("THEIRS")?.into(),
+                    output: output.into(),
+                })
+            }
+            #[cfg(feature = "write")]
+            "split-encode" => {
+                let write = args.write_options()?;
+                let threshold = match args.option("--threshold")? {
+                    Some(threshold) => Some(threshold.parse().context("Invalid threshold")?),
+                    None => None,
+                };
+                let chunk_type = args.option("--type")?;
+                let message_file = args.option("--message-file")?;
+                Command::SplitEncode(SplitEncodeArgs {
+                    message: args.message(message_file)?,
+                    file_paths: args.remaining("FILE")?,
+                    threshold,
+                    chunk_type: registry::resolve(chunk_type.as_deref().unwrap_or(SHARE_CHUNK))?,
+                    write,
+                })
+            }
+            #[cfg(feature = "write")]
+            "spread-encode" => {
+                let write = args.write_options()?;
+                let password = args.option("--password")?;
+                let message_file = args.option("--message-file")?;
+                Command::SpreadEncode(SpreadEncodeArgs {
+                    file_path: args.required("FILE")?.into(),
+                    message: args.message(message_file)?,
+                    password: password.context("--password <PASSWORD> is required")?,
+                    output: args.positional().map(PathBuf::from),
+                    write,
+                })
+            }
+            "spread-decode" => {
+                let password = args.option("--password")?;
+                Command::SpreadDecode(SpreadDecodeArgs {
+                    file_path: args.required("FILE")?.into(),
+                    password: password.context("--password <PASSWORD> is required")?,
+                })
+            }
+            #[cfg(feature = "write")]
+            "protect" => {
+                let write = args.write_options()?;
+                let password = args.option("--password")?;
+                let types = args.option("--types")?;
+                let types = types.context("--types <TYPE[,TYPE...]> is required")?;
+                Command::Protect(ProtectArgs {
+                    file_path: args.required("FILE")?.into(),
+                    chunk_types: types
+                        .split(',')
+                        .map(registry::resolve)
+                        .collect::<Result<_>>()?,
+                    password: password.context("--password <PASSWORD> is required")?,
+                    write,
+                })
+            }
+            #[cfg(feature = "write")]
+            "unprotect" => {
+                let write = args.write_options()?;
+                let password = args.option("--password")?;
+                Command::Unprotect(UnprotectArgs {
+                    file_path: args.required("FILE")?.into(),
+                    password: password.context("--password <PASSWORD> is required")?,
+                    write,
+                })
+            }
+            "join-decode" => {
+                let chunk_type = args.option("--type")?;
+                Command::JoinDecode(JoinDecodeArgs {
+                    file_paths: args.remaining("FILE")?,
+                    chunk_type: registry::resolve(chunk_type.as_deref().unwrap_or(SHARE_CHUNK))?,
+                })
+            }
+            #[cfg(feature = "self-update")]
+            "self-update" => Command::SelfUpdate(SelfUpdateArgs {
+                check: args.flag("--check"),
+            }),
+            #[cfg(feature = "serve")]
+            "serve" => Command::Serve(ServeArgs {
+                listen: args
+                    .option("--listen")?
+                    .unwrap_or_else(|| "127.0.0.1:8080".to_string()),
+            }),
+            #[cfg(feature = "write")]
+            "keygen" => Command::Keygen(KeygenArgs {
+                force: args.flag("--force"),
+                name: args.required("NAME")?.into(),
+            }),
+            #[cfg(feature = "write")]
+            "sign" => {
+                let write = args.write_options()?;
+                let chunk_type = match (args.option("--type")?, args.flag("--critical")) {
+                    (Some(chunk_type), false) => Some(registry::resolve(&chunk_type)?),
+                    (None, true) => None,
+                    _ => bail!("Exactly one of --type or --critical must be given"),
+                };
+                Command::Sign(SignArgs {
+                    file_path: args.required("FILE")?.into(),
+                    key_path: args.required("SECRET_KEY")?.into(),
+                    chunk_type,
+                    write,
+                })
+            }
+            "verify" => Command::Verify(VerifyArgs {
+                file_path: args.required("FILE")?.into(),
+                key_path: args.required("PUBLIC_KEY")?.into(),
+            }),
+            #[cfg(feature = "write")]
+            "seal" => {
+                let write = args.write_options()?;
+                let key_path = args.option("--key")?.map(PathBuf::from);
+                Command::Seal(SealArgs {
+                    file_path: args.required("FILE")?.into(),
+                    key_path,
+                    write,
+                })
+            }
+            "verify-seal" => {
+                let key_path = args.option("--key")?.map(PathBuf::from);
+                Command::VerifySeal(VerifySealArgs {
+                    file_path: args.required("FILE")?.into(),
+                    key_path,
+                })
+            }
+            "receipt" => match args.required("SUBCOMMAND")?.as_str() {
+                "verify" => Command::ReceiptVerify(ReceiptVerifyArgs {
+                    receipt_path: args.required("RECEIPT")?.into(),
+                    key_path: args.required("PUBLIC_KEY")?.into(),
+                }),
+                other => bail!("Unknown receipt subcommand '{}'", other),
+            },
+            "dpi" => match args.required("SUBCOMMAND")?.as_str() {
+                "get" => Command::DpiGet(DpiGetArgs {
+                    file_path: args.required("FILE")?.into(),
+                }),
+                #[cfg(feature = "write")]
+                "set" => {
+                    let write = args.write_options()?;
+                    Command::DpiSet(DpiSetArgs {
+                        file_path: args.required("FILE")?.into(),
+                        dpi: args.required("DPI")?.parse().context("Invalid DPI")?,
+                        write,
+                    })
+                }
+                other => bail!("Unknown dpi subcommand '{}'", other),
+            },
+            "trailing" => match args.required("SUBCOMMAND")?.as_str() {
+                "get" => {
+                    let raw = args.flag("--raw");
+                    #[cfg(feature = "write")]
+                    let output = args.option("--output")?.map(PathBuf::from);
+                    #[cfg(feature = "write")]
+                    ensure!(
+                        !(raw && output.is_some()),
+                        "--raw and --output can't be used together"
+                    );
+                    Command::TrailingGet(TrailingGetArgs {
+                        file_path: args.required("FILE")?.into(),
+                        raw,
+                        #[cfg(feature = "write")]
+                        output,
+                    })
+                }
+                #[cfg(feature = "write")]
+                "set" => {
+                    let write = args.write_options()?;
+                    Command::TrailingSet(TrailingSetArgs {
+                        file_path: args.required("FILE")?.into(),
+                        payload_path: args.required("PAYLOAD")?.into(),
+                        write,
+                    })
+                }
+                #[cfg(feature = "write")]
+                "remove" => {
+                    let write = args.write_options()?;
+                    Command::TrailingRemove(TrailingRemoveArgs {
+                        file_path: args.required("FILE")?.into(),
+                        write,
+                    })
+                }
+                other => bail!("Unknown trailing subcommand '{}'", other),
+            },
+            #[cfg(feature = "write")]
+            "undo" => {
+                let steps = args.option("--steps")?;
+                Command::Undo(UndoArgs {
+                    path: args.positional().unwrap_or_else(|| ".".to_string()).into(),
+                    steps: match steps {
+                        Some(steps) => steps.parse().context("Invalid number of steps")?,
+                        None => 1,
+                    },
+                })
+            }
+            _ => bail!("Unknown command '{}'", name),
+        };
+
+        #[cfg(feature = "write")]
+        {
+            options.lock = args.lock;
+        }
+        args.finish()?;
+        Ok((command, options))
+    }
+}
+
+/// Raw command line arguments that are consumed piece by piece.
+/// Options must be taken before positional arguments, so their values
+/// are not mistaken for positionals
+struct Args {
+    raw: Vec<String>,
+    config: Config,
+    /// Set once write options are parsed
+    #[cfg(feature = "write")]
+    lock: bool,
+}
+
+impl Args {
+    fn new<I: IntoIterator<Item = String>>(args: I, config: Config) -> Self {
+        Args {
+            raw: args.into_iter().collect(),
+            config,
+            #[cfg(feature = "write")]
+            lock: false,
+        }
+    }
+
+    /// Takes value of option given either as `--name value` or `--name=value`
+    fn option(&mut self, name: &str) -> Result<Option<String>> {
+        let prefix = format!("{}=", name);
+        if let Some(pos) = self.raw.iter().position(|a| a.starts_with(&prefix)) {
+            let arg = self.raw.remove(pos);
+            return Ok(Some(arg[prefix.len()..].to_string()));
+        }
+
+        match self.raw.iter().position(|a| a == name) {
+            Some(pos) if pos + 1 < self.raw.len() => {
+                self.raw.remove(pos);
+                Ok(Some(self.raw.remove(pos)))
+            }
+            Some(_) => bail!("Option {} requires a value", name),
+            None => Ok(None),
+        }
+    }
+
+    fn flag(&mut self, name: &str) -> bool {
+        match self.raw.iter().position(|a| a == name) {
+            Some(pos) => {
+                self.raw.remove(pos);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn positional(&mut self) -> Option<String> {
+        let pos = self.raw.iter().position(|a| !a.starts_with("--"))?;
+        Some(self.raw.remove(pos))
+    }
+
+    /// Takes all remaining positional arguments, at least one is required
+    fn remaining(&mut self, name: &str) -> Result<Vec<PathBuf>> {
+        let mut values = vec![self.required(name)?.into()];
+        while let Some(value) = self.positional() {
+            values.push(value.into());
+        }
+        Ok(values)
+    }
+
+    fn required(&mut self, name: &str) -> Result<String> {
+        self.positional()
+            .with_context(|| format!("Missing required argument <{}>", name))
+    }
+
+    /// Resolves chunk type from `--type` value or next positional argument
+    fn chunk_type(&mut self, option: Option<ChunkType>) -> Result<ChunkType> {
+        match option {
+            Some(chunk_type) => Ok(chunk_type),
+            None => registry::resolve(&self.required("TYPE")?),
+        }
+    }
+
+    /// Like `chunk_type`, but type from config is used instead of positional argument
+    fn message_type(&mut self, option: Option<ChunkType>) -> Result<ChunkType> {
+        match (option, &self.config.chunk_type) {
+            (None, Some(name)) => registry::resolve(name),
+            (option, _) => self.chunk_type(option),
+        }
+    }
+
+    /// Like `type_option`, but `--type` may be repeated and hold a comma-separated list
+    fn type_list(&mut self) -> Result<Vec<ChunkType>> {
+        let mut chunk_types = vec![];
+        while let Some(names) = self.option("--type")? {
+            for name in names.split(',') {
+                chunk_types.push(registry::resolve(name)?);
+            }
+        }
+        if let Some(password) = self.option("--type-password")? {
+            ensure!(
+                chunk_types.is_empty(),
+                "--type and --type-password can't be used together"
+            );
+            chunk_types.push(stealth::chunk_type(&password));
+        }
+        Ok(chunk_types)
+    }
+
+    /// Like `message_type`, but positional TYPE may be a comma-separated list
+    fn message_types(&mut self) -> Result<Vec<ChunkType>> {
+        match &self.config.chunk_type {
+            Some(name) => Ok(vec![registry::resolve(name)?]),
+            None => self
+                .required("TYPE")?
+                .split(',')
+                .map(registry::resolve)
+                .collect(),
+        }
+    }
+
+    /// Embedding backend given by `--mode`, `None` for default chunk mode
+    fn mode(&mut self) -> Result<Option<String>> {
+        Ok(self.option("--mode")?.filter(|mode| mode != "chunk"))
+    }
+
+    /// Command given by `--filter`, or configured one unless `--no-filter` is set
+    #[cfg(feature = "write")]
+    fn filter(&mut self, configured: Option<String>) -> Result<Option<String>> {
+        let filter = self.option("--filter")?;
+        if self.flag("--no-filter") {
+            ensure!(filter.is_none(), "--filter can't be used with --no-filter");
+            return Ok(None);
+        }
+        Ok(filter.or(configured))
+    }
+
+    /// Takes chunk type given by either `--type` or `--type-password`
+    fn type_option(&mut self) -> Result<Option<ChunkType>> {
+        match (self.option("--type")?, self.option("--type-password")?) {
+            (Some(_), Some(_)) => bail!("--type and --type-password can't be used together"),
+            (Some(name), None) => Ok(Some(registry::resolve(&name)?)),
+            (None, Some(password)) => Ok(Some(stealth::chunk_type(&password))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Takes `--type` (repeated or comma-separated) and `--*-only` filters of listing commands
+    fn selection(&mut self) -> Result<ChunkSelection> {
+        let selection = ChunkSelection {
+            types: self.type_list()?,
+            critical_only: self.flag("--critical-only"),
+            ancillary_only: self.flag("--ancillary-only"),
+            unknown_only: self.flag("--unknown-only"),
+        };
+        ensure!(
+            !(selection.critical_only && selection.ancillary_only),
+            "--critical-only and --ancillary-only can't be used together"
+        );
+        Ok(selection)
+    }
+
+    /// Takes `--hash <ALGORITHM>`, only SHA-256 is supported
+    fn hash_option(&mut self) -> Result<bool> {
+        match self.option("--hash")? {
+            Some(algorithm) if algorithm.eq_ignore_ascii_case("sha256") => Ok(true),
+            Some(algorithm) => bail!("Unsupported hash algorithm '{}', use sha256", algorithm),
+            None => Ok(false),
+        }
+    }
+
+    /// Takes message from `--message-file` value or next positional argument, `-` means stdin
+    #[cfg(feature = "write")]
+    fn message(&mut self, file: Option<String>) -> Result<Message> {
+        #[cfg(feature = "clipboard")]
+        if self.flag("--from-clipboard") {
+            ensure!(
+                file.is_none(),
+                "--from-clipboard can't be used with --message-file"
+            );
+            return Ok(Message::Clipboard);
+        }
+        Ok(match file {
+            Some(path) if path == "-" => Message::Stdin,
+            Some(path) => Message::File(path.into()),
+            None => match self.required("MESSAGE")?.as_str() {
+                "-" => Message::Stdin,
+                text => Message::Text(text.to_string()),
+            },
+        })
+    }
+
+    #[cfg(feature = "write")]
+    fn write_options(&mut self) -> Result<WriteOptions> {
+        let receipt = match (self.option("--receipt")?, self.option("--receipt-key")?) {
+            (Some(path), Some(key)) => Some((path.into(), key.into())),
+            (None, None) => None,
+            _ => bail!("--receipt and --receipt-key must be given together"),
+        };
+        let preserve_times = match (
+            self.flag("--preserve-times"),
+            self.flag("--no-preserve-times"),
+        ) {
+            (true, true) => {
+                bail!("--preserve-times and --no-preserve-times can't be used together")
+            }
+            (true, false) => Some(true),
+            (false, true) => Some(false),
+            (false, false) => None,
+        };
+        let dry_run = self.flag("--dry-run");
+        self.lock = !self.flag("--no-lock") && !dry_run;
+        Ok(WriteOptions {
+            dry_run,
+            preserve_times,
+            journal: self.flag("--journal") || self.config.backup == Backup::Journal,
+            trash: self.flag("--trash") || self.config.backup == Backup::Trash,
+            receipt,
+            changelog: self.option("--emit-changelog")?.map(PathBuf::from),
+            history: self.flag("--history") || self.config.history,
+            force: self.flag("--force"),
+        })
+    }
+
+    fn finish(self) -> Result<()> {
+        if let Some(arg) = self.raw.first() {
+            bail!("Unexpected argument '{}'", arg);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Command> {
+        Command::parse(args.split_whitespace().map(String::from), Config::default())
+            .map(|(command, _)| command)
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_decode_encoding() {
+        let Command::Decode(args) = parse("decode dice.png ruSt --encoding hex").unwrap() else {
+            panic!("Expected decode command");
+        };
+        assert_eq!(args.encoding, Some(Encoding::Hex));
+        assert!(parse("decode dice.png ruSt --encoding base32").is_err());
+        assert!(parse("decode dice.png ruSt --encoding hex --raw").is_err());
+        assert_eq!(Encoding::Base64.encode(b"\xffhi"), "/2hp");
+        assert_eq!(Encoding::Hex.encode(b"\xffhi"), "ff6869");
+        assert_eq!(Encoding::Base64.decode("/2hp\n").unwrap(), b"\xffhi");
+        assert_eq!(Encoding::Hex.decode("FF6869").unwrap(), b"\xffhi");
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        let Command::Decode(args) = parse("decode dice.png --mode lsb --raw").unwrap() else {
+            panic!("Expected decode command");
+        };
+        assert_eq!(args.mode.as_deref(), Some("lsb"));
+        assert!(args.chunk_types.is_empty());
+        let Command::Decode(args) = parse("decode dice.png ruSt --mode chunk").unwrap() else {
+            panic!("Expected decode command");
+        };
+        assert_eq!(args.mode, None);
+        assert!(parse("decode dice.png --mode lsb --type ruSt").is_err());
+        assert!(parse("decode - --mode lsb").is_err());
+        #[cfg(feature = "write")]
+        {
+            let Command::Decode(args) =
+                parse("decode - ruSt --stream --output out.bin --max-size 1024").unwrap()
+            else {
+                panic!("Expected decode command");
+            };
+            assert!(args.stream);
+            assert_eq!(args.max_size, Some(1024));
+            assert!(parse("decode dice.png ruSt --stream").is_err());
+            assert!(parse("decode dice.png ruSt,abCd --stream --output out").is_err());
+            assert!(parse("decode dice.png ruSt --stream --output out --raw").is_err());
+        }
+
+        #[cfg(feature = "write")]
+        {
+            let Command::Encode(args) = parse("encode dice.png --mode text hello").unwrap() else {
+                panic!("Expected encode command");
+            };
+            assert_eq!(args.chunk_type, None);
+            assert_eq!(args.message, Message::Text("hello".to_string()));
+            assert!(parse("encode dice.png --mode lsb --random-type hello").is_err());
+        }
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_input_encoding() {
+        let Command::Encode(args) =
+            parse("encode dice.png ruSt /2hp --input-encoding base64").unwrap()
+        else {
+            panic!("Expected encode command");
+        };
+        assert_eq!(args.input_encoding, Some(Encoding::Base64));
+        assert!(parse("encode dice.png ruSt ff --input-encoding binary").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_filter() {
+        let config = Config {
+            decode_filter: Some("gpg --decrypt".to_string()),
+            ..Config::default()
+        };
+        let decode = |args: &str| {
+            let args = args.split_whitespace().map(String::from);
+            match Command::parse(args, config.clone()).unwrap().0 {
+                Command::Decode(args) => args.filter,
+                _ => panic!("Expected decode command"),
+            }
+        };
+        assert_eq!(
+            decode("decode dice.png ruSt").as_deref(),
+            Some("gpg --decrypt")
+        );
+        assert_eq!(
+            decode("decode dice.png ruSt --filter rev").as_deref(),
+            Some("rev")
+        );
+        assert_eq!(decode("decode dice.png ruSt --no-filter"), None);
+        assert!(parse("decode dice.png ruSt --filter rev --no-filter").is_err());
+    }
+
+    #[cfg(feature = "age")]
+    #[test]
+    fn test_parse_age() {
+        let Command::Encode(args) =
+            parse("encode dice.png ruSt hi --recipient age1abc --recipient age1def").unwrap()
+        else {
+            panic!("Expected encode command");
+        };
+        assert_eq!(args.recipients, ["age1abc", "age1def"]);
+
+        let Command::Decode(args) = parse("decode dice.png ruSt --identity key.txt").unwrap()
+        else {
+            panic!("Expected decode command");
+        };
+        assert_eq!(args.identity, Some(PathBuf::from("key.txt")));
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_encode() {
+        let Command::Encode(args) = parse("encode dice.png ruSt hello out.png").unwrap() else {
+            panic!("Expected encode command");
+        };
+        assert_eq!(args.file_path, PathBuf::from("dice.png"));
+        assert_eq!(args.chunk_type.unwrap().to_string(), "ruSt");
+        assert_eq!(args.message, Message::Text("hello".to_string()));
+        assert_eq!(args.output, Some(PathBuf::from("out.png")));
+        assert_eq!(args.hmac_key, None);
+
+        let Command::Encode(args) = parse("encode dice.png ruSt hello --hmac-key k3y").unwrap()
+        else {
+            panic!("Expected encode command");
+        };
+        assert_eq!(args.hmac_key.as_deref(), Some("k3y"));
+        assert_eq!(args.ecc, None);
+        assert_eq!(args.obfuscate, None);
+
+        let Command::Encode(args) = parse("encode dice.png ruSt hi --expires 2025-12-31").unwrap()
+        else {
+            panic!("Expected encode command");
+        };
+        let expires = date::parse_timestamp("2026-01-01").unwrap();
+        assert_eq!(args.expires, Some(expires));
+        assert!(parse("encode dice.png ruSt hi --expires 2025-12-32").is_err());
+
+        let Command::Encode(args) = parse("encode dice.png ruSt hello --ecc 0.25").unwrap() else {
+            panic!("Expected encode command");
+        };
+        assert_eq!(args.ecc, Some(0.25));
+        assert_eq!(args.output, None);
+        assert_eq!(args.max_growth, None);
+
+        let Command::Encode(args) = parse("encode dice.png ruSt hi --max-growth 5%").unwrap()
+        else {
+            panic!("Expected encode command");
+        };
+        let growth = args.max_growth.unwrap();
+        assert_eq!(growth, Growth::Percent(5.0));
+        assert_eq!(growth.limit(1000), 1050);
+        assert_eq!(Growth::from_str("4096").unwrap().limit(1000), 5096);
+        assert!(parse("encode dice.png ruSt hi --max-growth -5%").is_err());
+        assert!(parse("encode dice.png ruSt hi --max-growth 5KB").is_err());
+
+        let Command::Encode(args) = parse("encode dice.png ruSt - out.png").unwrap() else {
+            panic!("Expected encode command");
+        };
+        assert_eq!(args.message, Message::Stdin);
+        assert_eq!(args.output, Some(PathBuf::from("out.png")));
+
+        let Command::Encode(args) = parse("encode dice.png ruSt --message-file msg.txt").unwrap()
+        else {
+            panic!("Expected encode command");
+        };
+        assert_eq!(args.message, Message::File("msg.txt".into()));
+        assert_eq!(args.output, None);
+
+        let Command::Encode(args) = parse("encode dice.png --random-type hello").unwrap() else {
+            panic!("Expected encode command");
+        };
+        assert!(args.chunk_type.is_none());
+        assert_eq!(args.message, Message::Text("hello".to_string()));
+        assert!(parse("encode dice.png --random-type --type ruSt hello").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_long_type_option() {
+        let Command::Encode(args) = parse("encode --type text dice.png hello").unwrap() else {
+            panic!("Expected encode command");
+        };
+        assert_eq!(args.chunk_type.unwrap().to_string(), "tEXt");
+        assert_eq!(args.message, Message::Text("hello".to_string()));
+        assert_eq!(args.output, None);
+
+        let Command::Decode(args) = parse("decode dice.png --type=physical-dimensions").unwrap()
+        else {
+            panic!("Expected decode command");
+        };
+        assert_eq!(args.chunk_types, [ChunkType::from_str("pHYs").unwrap()]);
+
+        let Command::Decode(args) = parse("decode dice.png --type-password s3cret").unwrap() else {
+            panic!("Expected decode command");
+        };
+        assert_eq!(args.chunk_types, [stealth::chunk_type("s3cret")]);
+
+        assert!(parse("decode dice.png --type text --type-password s3cret").is_err());
+    }
+
+    #[test]
+    fn test_parse_decode_several_types() {
+        let types = |s: &str| {
+            s.split(',')
+                .map(|t| ChunkType::from_str(t).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let Command::Decode(args) = parse("decode dice.png ruSt,text,seCr").unwrap() else {
+            panic!("Expected decode command");
+        };
+        assert_eq!(args.chunk_types, types("ruSt,tEXt,seCr"));
+        let Command::Decode(args) = parse("decode dice.png --type ruSt --type abCd,seCr").unwrap()
+        else {
+            panic!("Expected decode command");
+        };
+        assert_eq!(args.chunk_types, types("ruSt,abCd,seCr"));
+        assert!(parse("decode dice.png ruSt,teXt --raw").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_inject() {
+        let Command::Inject(args) =
+            parse("inject dice.png ruSt payload.bin --position before-iend").unwrap()
+        else {
+            panic!("Expected inject command");
+        };
+        assert_eq!(args.chunk_type.to_string(), "ruSt");
+        assert_eq!(args.payload_path, PathBuf::from("payload.bin"));
+        assert_eq!(args.position, Position::BeforeIend);
+
+        let Command::Inject(args) = parse("inject dice.png ruSt payload.bin").unwrap() else {
+            panic!("Expected inject command");
+        };
+        assert_eq!(args.position, Position::End);
+        assert!(!args.keep_name);
+
+        let Command::Inject(args) = parse("inject dice.png ruSt payload.bin --keep-mtime").unwrap()
+        else {
+            panic!("Expected inject command");
+        };
+        assert!(args.keep_name && args.keep_mtime);
+
+        assert!(parse("inject dice.png ruSt payload.bin --position middle").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_undo() {
+        let Command::Undo(args) = parse("undo --steps 2 images").unwrap() else {
+            panic!("Expected undo command");
+        };
+        assert_eq!(args.path, PathBuf::from("images"));
+        assert_eq!(args.steps, 2);
+
+        let Command::Remove(args) = parse("remove --journal dice.png ruSt").unwrap() else {
+            panic!("Expected remove command");
+        };
+        assert!(args.write.journal);
+        assert!(!args.write.dry_run);
+
+        let Command::Remove(args) = parse("remove dice.png ruSt --dry-run").unwrap() else {
+            panic!("Expected remove command");
+        };
+        assert!(args.write.dry_run);
+        assert_eq!(args.write.preserve_times, None);
+
+        let args = "remove dice.png ruSt --no-preserve-times";
+        assert!(
+            matches!(parse(args), Ok(Command::Remove(args)) if args.write.preserve_times == Some(false))
+        );
+        assert!(parse("remove dice.png ruSt --preserve-times --no-preserve-times").is_err());
+
+        let Command::Remove(args) = parse("remove dice.png ruSt --dump ruSt.bin --raw").unwrap()
+        else {
+            panic!("Expected remove command");
+        };
+        assert_eq!(args.dump, Some(PathBuf::from("ruSt.bin")));
+        assert!(args.raw);
+        assert!(parse("remove dice.png ruSt --raw").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_keygen() {
+        let Command::Keygen(args) = parse("keygen me").unwrap() else {
+            panic!("Expected keygen command");
+        };
+        assert_eq!(args.name, PathBuf::from("me"));
+        assert!(!args.force);
+        let Command::Keygen(args) = parse("keygen me --force").unwrap() else {
+            panic!("Expected keygen command");
+        };
+        assert!(args.force);
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_sign() {
+        let Command::Sign(args) = parse("sign dice.png me.key --type text").unwrap() else {
+            panic!("Expected sign command");
+        };
+        assert_eq!(args.key_path, PathBuf::from("me.key"));
+        assert_eq!(args.chunk_type.unwrap().to_string(), "tEXt");
+
+        let Command::Sign(args) = parse("sign dice.png me.key --critical").unwrap() else {
+            panic!("Expected sign command");
+        };
+        assert!(args.chunk_type.is_none());
+
+        assert!(parse("sign dice.png me.key").is_err());
+        assert!(parse("sign dice.png me.key --critical --type text").is_err());
+
+        let Command::Sign(args) =
+            parse("sign dice.png me.key --critical --receipt r.json --receipt-key me.key").unwrap()
+        else {
+            panic!("Expected sign command");
+        };
+        let (receipt, key) = args.write.receipt.unwrap();
+        assert_eq!(receipt, PathBuf::from("r.json"));
+        assert_eq!(key, PathBuf::from("me.key"));
+        assert!(parse("sign dice.png me.key --critical --receipt r.json").is_err());
+    }
+
+    #[test]
+    fn test_parse_verify_seal() {
+        let Command::VerifySeal(args) = parse("verify-seal dice.png --key me.pub").unwrap() else {
+            panic!("Expected verify-seal command");
+        };
+        assert_eq!(args.key_path, Some(PathBuf::from("me.pub")));
+        let Command::VerifySeal(args) = parse("verify-seal dice.png").unwrap() else {
+            panic!("Expected verify-seal command");
+        };
+        assert_eq!(args.key_path, None);
+        assert!(parse("verify-seal").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_split_encode() {
+        let Command::SplitEncode(args) =
+            parse("split-encode hello a.png b.png c.png --threshold 2").unwrap()
+        else {
+            panic!("Expected split-encode command");
+        };
+        assert_eq!(args.message, Message::Text("hello".to_string()));
+        assert_eq!(args.file_paths.len(), 3);
+        assert_eq!(args.threshold, Some(2));
+        assert_eq!(args.chunk_type.to_string(), "shRd");
+
+        assert!(parse("split-encode hello").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_edit() {
+        let Command::Edit(args) = parse("edit dice.png ruSt --hmac-key k").unwrap() else {
+            panic!("Expected edit command");
+        };
+        assert_eq!(args.chunk_type.to_string(), "ruSt");
+        assert_eq!(args.hmac_key.as_deref(), Some("k"));
+        assert_eq!(args.decode_filter, None);
+
+        let args = parse("edit dice.png ruSt --decode-filter rev --encode-filter rev");
+        let Ok(Command::Edit(args)) = args else {
+            panic!("Expected edit command");
+        };
+        assert_eq!(args.encode_filter.as_deref(), Some("rev"));
+        assert!(parse("edit dice.png ruSt --decode-filter rev").is_err());
+        assert!(parse("edit dice.png ruSt --encode-filter rev --no-filter").is_err());
+        assert!(parse("edit dice.png").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_protect() {
+        let Command::Protect(args) =
+            parse("protect dice.png --types tEXt,exif --password s3cret").unwrap()
+        else {
+            panic!("Expected protect command");
+        };
+        let types: Vec<_> = args.chunk_types.iter().map(|t| t.to_string()).collect();
+        assert_eq!(types, ["tEXt", "eXIf"]);
+        assert_eq!(args.password, "s3cret");
+
+        assert!(parse("protect dice.png --password s3cret").is_err());
+        assert!(parse("protect dice.png --types tEXt").is_err());
+        assert!(parse("unprotect dice.png").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_spread_encode() {
+        let Command::SpreadEncode(args) =
+            parse("spread-encode in.png hello --password s3cret out.png").unwrap()
+        else {
+            panic!("Expected spread-encode command");
+        };
+        assert_eq!(args.message, Message::Text("hello".to_string()));
+        assert_eq!(args.password, "s3cret");
+        assert_eq!(args.output, Some(PathBuf::from("out.png")));
+
+        assert!(parse("spread-encode in.png hello").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_bug_report() {
+        let Command::BugReport(args) =
+            parse("bug-report crash.png --type text --type ruSt --redact").unwrap()
+        else {
+            panic!("Expected bug-report command");
+        };
+        assert_eq!(args.file_path, PathBuf::from("crash.png"));
+        assert_eq!(args.chunk_types.len(), 2);
+        assert_eq!(args.chunk_types[0].to_string(), "tEXt");
+        assert!(args.redact);
+        assert_eq!(args.output, None);
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_meta() {
+        let Command::Meta(args) =
+            parse("meta dice.png --set-author Jane --set-creation-time 2024 --get description")
+                .unwrap()
+        else {
+            panic!("Expected meta command");
+        };
+        assert_eq!(args.get, ["Description"]);
+        assert_eq!(args.set.len(), 2);
+        assert_eq!(args.set[0], TextEntry::new("Author", "Jane").unwrap());
+        assert_eq!(args.set[1].keyword, "Creation Time");
+
+        assert!(parse("meta dice.png --get").is_err());
+        assert!(parse("meta dice.png --set-title 雪").is_err());
+
+        let Command::Meta(args) =
+            parse("meta dice.png --set-title 雪 --lang ja --translated-keyword 題名").unwrap()
+        else {
+            panic!("Expected meta command");
+        };
+        let international = args.set[0].international.as_ref().unwrap();
+        assert_eq!(args.set[0].text, "雪");
+        assert_eq!(international.language, "ja");
+        assert_eq!(international.translated_keyword, "題名");
+        assert!(!args.set[0].compressed);
+        assert!(parse("meta dice.png --set-title a --lang en_US").is_err());
+        assert!(
+            parse("meta dice.png --set-title a --set-author b --translated-keyword c").is_err()
+        );
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_text_convert() {
+        let Command::TextConvert(args) =
+            parse("text convert dice.png --to zTXt --keyword comment --keyword Foo").unwrap()
+        else {
+            panic!("Expected text convert command");
+        };
+        assert_eq!(args.format, TextFormat::Compressed);
+        assert_eq!(args.keywords, ["Comment", "Foo"]);
+
+        assert!(parse("text convert dice.png").is_err());
+        assert!(parse("text convert dice.png --to gzip").is_err());
+        assert!(parse("text compress dice.png --to ztxt").is_err());
+    }
+
+    #[test]
+    fn test_parse_text_list() {
+        let Command::TextList(args) =
+            parse("text list dice.png --lang de --keyword title --json").unwrap()
+        else {
+            panic!("Expected text list command");
+        };
+        assert_eq!(args.language.as_deref(), Some("de"));
+        assert_eq!(args.keywords, ["Title"]);
+        assert!(args.json);
+        assert!(parse("text list dice.png --lang de_DE").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_info() {
+        let Command::Info(args) = parse("info dice.png --set-srgb perceptual").unwrap() else {
+            panic!("Expected info command");
+        };
+        assert_eq!(args.srgb, Some(RenderingIntent::Perceptual));
+        assert_eq!(args.gamma, Some(Gamma::SRGB));
+        assert_eq!(args.chromaticities, Some(Chromaticities::SRGB));
+
+        let Command::Info(args) = parse("info dice.png --set-gamma 1.0").unwrap() else {
+            panic!("Expected info command");
+        };
+        assert_eq!(args.gamma, Some(Gamma(100000)));
+        assert_eq!(args.srgb, None);
+
+        assert!(parse("info dice.png --set-srgb vivid").is_err());
+        assert!(parse("info dice.png --set-chromaticities 0.3,0.3").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_palette() {
+        let Command::Palette(args) = parse("palette dice.png --export dice.gpl").unwrap() else {
+            panic!("Expected palette command");
+        };
+        assert_eq!(args.export, Some(PathBuf::from("dice.gpl")));
+        assert!(parse("palette dice.png --export dice.txt").is_err());
+    }
+
+    #[test]
+    fn test_parse_stats() {
+        let Command::Stats(args) = parse("stats dice.png").unwrap() else {
+            panic!("Expected stats command");
+        };
+        assert_eq!(args.file_path, PathBuf::from("dice.png"));
+        assert!(parse("stats").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_dpi() {
+        let Command::DpiSet(args) = parse("dpi set dice.png 300 --journal").unwrap() else {
+            panic!("Expected dpi set command");
+        };
+        assert_eq!(args.dpi, 300.0);
+        assert!(args.write.journal);
+        assert!(matches!(parse("dpi get dice.png"), Ok(Command::DpiGet(_))));
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_trailing() {
+        let Command::TrailingSet(args) = parse("trailing set dice.png data.bin --dry-run").unwrap()
+        else {
+            panic!("Expected trailing set command");
+        };
+        assert_eq!(args.payload_path, PathBuf::from("data.bin"));
+        assert!(args.write.dry_run);
+        assert!(matches!(
+            parse("trailing remove dice.png"),
+            Ok(Command::TrailingRemove(_))
+        ));
+        let Command::TrailingGet(args) = parse("trailing get dice.png --raw").unwrap() else {
+            panic!("Expected trailing get command");
+        };
+        assert!(args.raw);
+        assert!(parse("trailing get dice.png --raw --output data.bin").is_err());
+        assert!(parse("trailing append dice.png").is_err());
+
+        assert!(parse("dpi set dice.png many").is_err());
+        assert!(parse("dpi dice.png").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_merge() {
+        let Command::Merge(args) = parse("merge base.png a.png b.png -o out.png").unwrap() else {
+            panic!("Expected merge command");
+        };
+        assert_eq!(args.base_path, PathBuf::from("base.png"));
+        assert_eq!(args.theirs_path, PathBuf::from("b.png"));
+        assert_eq!(args.output, PathBuf::from("out.png"));
+
+        assert!(parse("merge base.png a.png b.png").is_err());
+        assert!(parse("merge base.png a.png --output out.png").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_minimize() {
+        let args = ["minimize", "crash.png", "--command", "pngme print {}"];
+        let (Command::Minimize(args), _) =
+            Command::parse(args.map(String::from), Config::default()).unwrap()
+        else {
+            panic!("Expected minimize command");
+        };
+        assert_eq!(args.command, "pngme print {}");
+        assert_eq!(args.output, None);
+
+        assert!(parse("minimize crash.png").is_err());
+        assert!(parse("minimize crash.png --command true").is_err());
+    }
+
+    #[test]
+    fn test_parse_diff() {
+        let Command::Diff(args) = parse("diff old.png new.png --explain").unwrap() else {
+            panic!("Expected diff command");
+        };
+        assert_eq!(args.old_path, PathBuf::from("old.png"));
+        assert_eq!(args.new_path, PathBuf::from("new.png"));
+        assert!(args.explain);
+        assert!(parse("diff old.png").is_err());
+    }
+
+    #[test]
+    fn test_parse_same_image() {
+        let Command::SameImage(args) = parse("same-image a.png b.png --decompress").unwrap() else {
+            panic!("Expected same-image command");
+        };
+        assert_eq!(args.a_path, PathBuf::from("a.png"));
+        assert_eq!(args.b_path, PathBuf::from("b.png"));
+        assert!(args.decompress);
+        assert!(parse("same-image a.png").is_err());
+    }
+
+    /// Read-only viewer must not run external commands
+    #[cfg(not(feature = "write"))]
+    #[test]
+    fn test_viewer_refuses_commands() {
+        for args in [
+            "scan dice.png --scanner clamdscan",
+            "decode dice.png ruSt --filter cat",
+            "blame dice.png --git",
+            "watch drop --on-add=print",
+            "encode dice.png ruSt hello",
+        ] {
+            assert!(parse(args).is_err(), "{}", args);
+        }
+    }
+
+    #[test]
+    fn test_parse_help() {
+        for args in ["--help", "-h", "help", "decode --help", "info dice.png -h"] {
+            assert!(matches!(parse(args), Ok(Command::Help)), "{}", args);
+        }
+        // errors are short, usage is printed only when asked for
+        for (args, message) in [
+            ("", "Missing command"),
+            ("bogus", "Unknown command 'bogus'"),
+            ("info", "Missing required argument <FILE>"),
+        ] {
+            let Err(err) = parse(args) else {
+                panic!("Expected error for '{}'", args);
+            };
+            assert_eq!(format!("{:#}", err), message);
+        }
+    }
+
+    #[cfg(not(feature = "write"))]
+    #[test]
+    fn test_viewer_usage() {
+        let usage = usage();
+        let synopsis = usage.split("\n\n").next().unwrap();
+        for missing in [
+            "pngme encode",
+            "pngme watch",
+            "--scanner",
+            "--filter",
+            "--git",
+        ] {
+            assert!(!synopsis.contains(missing), "{}", missing);
+        }
+        assert!(synopsis.contains("    pngme scan <FILE> [--visual]"));
+        assert!(synopsis.contains("    pngme doctor <FILE>\n"));
+        assert!(!usage.contains("\nbatch encodes"));
+        assert!(usage.contains("\nblame tells when"));
+    }
+
+    #[test]
+    fn test_parse_blame() {
+        let Command::Blame(args) =
+            parse("blame dice.png --versions old --chunk tEXt:Author").unwrap()
+        else {
+            panic!("Expected blame command");
+        };
+        assert_eq!(args.history, History::Versions("old".into()));
+        assert!(args.chunk.is_some());
+        #[cfg(feature = "write")]
+        assert!(matches!(
+            parse("blame dice.png --git"),
+            Ok(Command::Blame(_))
+        ));
+
+        assert!(parse("blame dice.png").is_err());
+        assert!(parse("blame dice.png --git --versions old").is_err());
+        assert!(parse("blame dice.png --git --chunk bad").is_err());
+    }
+
+    #[test]
+    fn test_parse_grep() {
+        let Command::Grep(args) = parse("grep dice.png secret").unwrap() else {
+            panic!("Expected grep command");
+        };
+        assert!(matches!(args.pattern, Pattern::Bytes(p) if p == b"secret"));
+        assert!(!args.inflate);
+        let Command::Grep(args) = parse("grep dice.png secret --inflate").unwrap() else {
+            panic!("Expected grep command");
+        };
+        assert!(args.inflate);
+
+        let Command::Grep(args) = parse("grep --hex dice.png 89504e47").unwrap() else {
+            panic!("Expected grep command");
+        };
+        assert!(matches!(args.pattern, Pattern::Bytes(p) if p == b"\x89PNG"));
+        assert!(parse("grep dice.png zz --hex").is_err());
+
+        let Command::Grep(args) = parse("grep dice.png --regex [a-z]+@[a-z.]+").unwrap() else {
+            panic!("Expected grep command");
+        };
+        assert!(matches!(args.pattern, Pattern::Regex(_)));
+        assert!(parse("grep dice.png --regex (unclosed").is_err());
+        assert!(parse("grep dice.png --regex --hex 00").is_err());
+    }
+
+    #[test]
+    fn test_parse_verify_message() {
+        let Command::VerifyMessage(args) = parse("verify-message dice.png ruSt hello").unwrap()
+        else {
+            panic!("Expected verify-message command");
+        };
+        assert_eq!(args.chunk_type.to_string(), "ruSt");
+        assert_eq!(args.expected, Expected::Text("hello".to_string()));
+
+        let args = parse("verify-message dice.png --type ruSt --expected-file msg.txt");
+        let Ok(Command::VerifyMessage(args)) = args else {
+            panic!("Expected verify-message command");
+        };
+        assert_eq!(args.expected, Expected::File("msg.txt".into()));
+        assert!(parse("verify-message dice.png ruSt").is_err());
+    }
+
+    #[test]
+    fn test_parse_hash() {
+        let Command::Print(args) = parse("print dice.png --hash SHA256").unwrap() else {
+            panic!("Expected print command");
+        };
+        assert!(args.hash);
+        assert!(
+            matches!(parse("info dice.png --hash sha256"), Ok(Command::Info(args)) if args.hash)
+        );
+        assert!(parse("print dice.png --hash md5").is_err());
+    }
+
+    #[test]
+    fn test_parse_no_color() {
+        let Command::Print(args) = parse("print dice.png --no-color").unwrap() else {
+            panic!("Expected print command");
+        };
+        assert!(args.no_color);
+        assert!(matches!(parse("print dice.png"), Ok(Command::Print(args)) if !args.no_color));
+    }
+
+    #[test]
+    fn test_parse_print_selection() {
+        let Command::Print(args) =
+            parse("print dice.png --type ruSt,IDAT --ancillary-only --unknown-only").unwrap()
+        else {
+            panic!("Expected print command");
+        };
+        let selection = args.selection;
+        assert_eq!(selection.types.len(), 2);
+        assert!(selection.matches(*b"ruSt"));
+        assert!(!selection.matches(*b"IDAT"));
+        assert!(!selection.matches(*b"tEXt"));
+
+        let unknown = ChunkSelection {
+            unknown_only: true,
+            ..Default::default()
+        };
+        assert!(unknown.matches(*b"prVW"));
+        assert!(unknown.matches(*b"\0\0\0\0"));
+        assert!(!unknown.matches(*b"tEXt"));
+        assert!(ChunkSelection::default().matches(*b"IEND"));
+        assert!(parse("print dice.png --critical-only --ancillary-only").is_err());
+    }
+
+    #[test]
+    fn test_parse_print_full() {
+        let Command::Print(args) = parse("print dice.png --full").unwrap() else {
+            panic!("Expected print command");
+        };
+        assert!(args.full);
+        let Command::Print(args) = parse("print dice.png").unwrap() else {
+            panic!("Expected print command");
+        };
+        assert!(!args.full);
+    }
+
+    #[test]
+    fn test_parse_global_options() {
+        let args = "print dice.png -v --quiet --strict-png"
+            .split_whitespace()
+            .map(String::from);
+        let (_, options) = Command::parse(args, Config::default()).unwrap();
+        assert!(options.quiet);
+        assert_eq!(options.verbosity, 1);
+        assert!(options.strict_png);
+
+        let args = "-vv print dice.png".split_whitespace().map(String::from);
+        let (_, options) = Command::parse(args, Config::default()).unwrap();
+        assert_eq!(options.verbosity, 2);
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_with_config() {
+        let parse = |args: &str| {
+            let config = Config {
+                chunk_type: Some("ruSt".to_string()),
+                color: false,
+                backup: Backup::Trash,
+                ..Config::default()
+            };
+            Command::parse(args.split_whitespace().map(String::from), config).map(|(c, _)| c)
+        };
+        let Ok(Command::Encode(args)) = parse("encode dice.png hello") else {
+            panic!("Expected encode command");
+        };
+        assert_eq!(args.chunk_type.unwrap().to_string(), "ruSt");
+        assert_eq!(args.message, Message::Text("hello".to_string()));
+        assert!(args.write.trash);
+        let Ok(Command::Decode(args)) = parse("decode dice.png --type tEXt") else {
+            panic!("Expected decode command");
+        };
+        assert_eq!(args.chunk_types, [ChunkType::from_str("tEXt").unwrap()]);
+        assert!(matches!(parse("print dice.png"), Ok(Command::Print(args)) if args.no_color));
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_batch() {
+        let Command::Batch(args) = parse("batch assets.yaml --journal").unwrap() else {
+            panic!("Expected batch command");
+        };
+        assert_eq!(args.manifest, PathBuf::from("assets.yaml"));
+        assert!(args.write.journal);
+        assert!(parse("batch assets.yaml --receipt r.json --receipt-key me.key").is_err());
+    }
+
+    #[cfg(feature = "clipboard")]
+    #[test]
+    fn test_parse_clipboard() {
+        let Command::Decode(args) = parse("decode dice.png ruSt --copy").unwrap() else {
+            panic!("Expected decode command");
+        };
+        assert!(args.copy);
+        let Command::Encode(args) = parse("encode dice.png ruSt --from-clipboard out.png").unwrap()
+        else {
+            panic!("Expected encode command");
+        };
+        assert_eq!(args.message, Message::Clipboard);
+        assert_eq!(args.output, Some(PathBuf::from("out.png")));
+        assert!(parse("encode dice.png ruSt --from-clipboard --message-file m.txt").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_move() {
+        let Command::Move(args) = parse("move dice.png tEXt --to before-IDAT").unwrap() else {
+            panic!("Expected move command");
+        };
+        assert_eq!(
+            args.chunk,
+            ChunkRef::Type(ChunkType::from_str("tEXt").unwrap())
+        );
+        assert_eq!(
+            args.to,
+            Destination::Before(ChunkType::from_str("IDAT").unwrap())
+        );
+        let Command::Move(args) = parse("move dice.png 5 --to=after-image-header").unwrap() else {
+            panic!("Expected move command");
+        };
+        assert_eq!(args.chunk, ChunkRef::Index(5));
+        assert_eq!(
+            args.to,
+            Destination::After(ChunkType::from_str("IHDR").unwrap())
+        );
+        assert!(
+            matches!(parse("move dice.png 5 --to 1"), Ok(Command::Move(args)) if args.to == Destination::Index(1))
+        );
+        assert!(parse("move dice.png 5").is_err());
+        assert!(parse("move dice.png 5 --to middle").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_export_and_build() {
+        let Command::Export(args) = parse("export dice.png dice.json").unwrap() else {
+            panic!("Expected export command");
+        };
+        assert_eq!(args.file_path, PathBuf::from("dice.png"));
+        assert_eq!(args.output, Some(PathBuf::from("dice.json")));
+        let Command::Build(args) = parse("build dice.json out.png").unwrap() else {
+            panic!("Expected build command");
+        };
+        assert_eq!(args.structure, PathBuf::from("dice.json"));
+        assert_eq!(args.output, PathBuf::from("out.png"));
+        assert!(parse("build dice.json").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_cat() {
+        let Command::Cat(args) =
+            parse("cat head.bin ruSt:msg.txt C:\\tail.bin --out new.png").unwrap()
+        else {
+            panic!("Expected cat command");
+        };
+        assert_eq!(
+            args.parts,
+            [
+                Part::Records("head.bin".into()),
+                Part::Payload(ChunkType::from_str("ruSt").unwrap(), "msg.txt".into()),
+                Part::Records("C:\\tail.bin".into()),
+            ]
+        );
+        assert_eq!(args.output, PathBuf::from("new.png"));
+        assert!(parse("cat head.bin").is_err());
+        assert!(parse("cat ru:msg.txt --out new.png").is_err());
+    }
+
+    #[test]
+    fn test_parse_carve() {
+        let Command::Carve(args) = parse("carve in.png").unwrap() else {
+            panic!("Expected carve command");
+        };
+        assert_eq!(args.file_path, PathBuf::from("in.png"));
+        #[cfg(feature = "write")]
+        {
+            assert_eq!(args.output_dir, None);
+            let Command::Carve(args) = parse("carve in.png --output-dir out").unwrap() else {
+                panic!("Expected carve command");
+            };
+            assert_eq!(args.output_dir, Some(PathBuf::from("out")));
+        }
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_animate() {
+        let Command::Animate(args) =
+            parse("animate a.png b.png --delay 1/30 --out anim.png --plays 2").unwrap()
+        else {
+            panic!("Expected animate command");
+        };
+        assert_eq!(
+            args.frames,
+            [PathBuf::from("a.png"), PathBuf::from("b.png")]
+        );
+        assert_eq!(args.delay, "1/30".parse().unwrap());
+        assert_eq!(args.plays, 2);
+        assert!(parse("animate a.png --out anim.png").is_err());
+        assert!(parse("animate --delay 1s --out anim.png").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_watch() {
+        let Command::Watch(args) = parse("watch drop --on-add=print").unwrap() else {
+            panic!("Expected watch command");
+        };
+        assert_eq!(args.dir, PathBuf::from("drop"));
+        assert_eq!(args.on_add, "print");
+        assert!(parse("watch drop").is_err());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse("").is_err());
+        assert!(parse("frobnicate dice.png").is_err());
+        assert!(parse("decode dice.png").is_err());
+        assert!(parse("print dice.png extra").is_err());
+        assert!(parse("decode dice.png --type").is_err());
+    }
+}
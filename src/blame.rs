@@ -0,0 +1,206 @@
+#[cfg(feature = "write")]
+use std::path::Path;
+#[cfg(feature = "write")]
+use std::process::Command;
+use std::str::FromStr;
+
+#[cfg(feature = "write")]
+use anyhow::{ensure, Context};
+use anyhow::{Error, Result};
+
+use crate::chunk_type::ChunkType;
+use crate::diff::{keyed, Key};
+use crate::png::Png;
+
+/// Chunk type with optional keyword, e.g. `tEXt:Author`
+pub struct ChunkFilter {
+    chunk_type: String,
+    keyword: Option<String>,
+}
+
+impl ChunkFilter {
+    fn matches(&self, (name, keyword, _): &Key) -> bool {
+        *name == self.chunk_type && (self.keyword.is_none() || *keyword == self.keyword)
+    }
+}
+
+impl FromStr for ChunkFilter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (chunk_type, keyword) = match s.split_once(':') {
+            Some((chunk_type, keyword)) => (chunk_type, Some(keyword.to_string())),
+            None => (s, None),
+        };
+        ChunkType::from_str(chunk_type)?;
+        Ok(ChunkFilter {
+            chunk_type: chunk_type.to_string(),
+            keyword,
+        })
+    }
+}
+
+/// Where chunk of the latest version came from
+#[derive(Debug, Eq, PartialEq)]
+pub struct Origin {
+    pub chunk: String,
+    /// Version since which chunk is present without interruption
+    pub added: String,
+    /// Last version that changed the chunk, same as `added` if none did
+    pub changed: String,
+}
+
+/// Finds origin of every chunk in the last of versions, which go oldest first
+pub fn blame(versions: &[(String, Png)], filter: Option<&ChunkFilter>) -> Vec<Origin> {
+    let versions: Vec<_> = versions
+        .iter()
+        .map(|(label, png)| (label, keyed(png.chunks())))
+        .collect();
+    let Some((_, latest)) = versions.last() else {
+        return vec![];
+    };
+
+    let mut origins = vec![];
+    for (key, _) in latest {
+        if filter.is_some_and(|filter| !filter.matches(key)) {
+            continue;
+        }
+        let (mut added, mut changed) = (None, None);
+        let mut previous: Option<&Vec<u8>> = None;
+        for (label, chunks) in &versions {
+            let current = chunks
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, bytes)| bytes);
+            match (previous, current) {
+                (_, None) => added = None,
+                (None, Some(_)) => {
+                    added = Some(*label);
+                    changed = Some(*label);
+                }
+                (Some(previous), Some(current)) if previous != current => changed = Some(*label),
+                _ => {}
+            }
+            previous = current;
+        }
+
+        let (name, keyword, occurrence) = key;
+        let mut chunk = name.clone();
+        if let Some(keyword) = keyword {
+            chunk += &format!(" {}", keyword);
+        }
+        if *occurrence > 0 {
+            chunk += &format!(" #{}", occurrence + 1);
+        }
+        origins.push(Origin {
+            chunk,
+            added: added.unwrap().clone(),
+            changed: changed.unwrap().clone(),
+        });
+    }
+    origins
+}
+
+/// Contents of file in every commit that touched it, oldest first,
+/// labelled with short hash and date of commit
+#[cfg(feature = "write")]
+pub fn git_history(path: &Path) -> Result<Vec<(String, Vec<u8>)>> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .context("Invalid file name")?;
+    let log = git(dir, &["log", "--format=%H %as", "--", name])?;
+    let log = String::from_utf8(log).context("Invalid git log output")?;
+
+    let mut history = vec![];
+    for line in log.lines().rev() {
+        let (hash, date) = line.split_once(' ').context("Invalid git log output")?;
+        let bytes = git(dir, &["show", &format!("{}:./{}", hash, name)])?;
+        history.push((format!("{} {}", &hash[..7], date), bytes));
+    }
+    Ok(history)
+}
+
+#[cfg(feature = "write")]
+fn git(dir: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("Failed to run git")?;
+    ensure!(
+        output.status.success(),
+        "git {} failed: {}",
+        args[0],
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.into())
+    }
+
+    fn versions() -> Vec<(String, Png)> {
+        let version = |label: &str, chunks| (label.to_string(), Png::from_chunks(chunks));
+        vec![
+            version(
+                "v1",
+                vec![chunk("IHDR", "header"), chunk("tEXt", "Author\0Jane")],
+            ),
+            version(
+                "v2",
+                vec![
+                    chunk("IHDR", "header"),
+                    chunk("tEXt", "Author\0Joe"),
+                    chunk("ruSt", "x"),
+                ],
+            ),
+            version(
+                "v3",
+                vec![chunk("IHDR", "header"), chunk("tEXt", "Author\0Joe")],
+            ),
+            version(
+                "v4",
+                vec![
+                    chunk("IHDR", "header"),
+                    chunk("tEXt", "Author\0Joe"),
+                    chunk("ruSt", "x"),
+                ],
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_blame() {
+        let origin = |chunk: &str, added: &str, changed: &str| Origin {
+            chunk: chunk.to_string(),
+            added: added.to_string(),
+            changed: changed.to_string(),
+        };
+        assert_eq!(
+            blame(&versions(), None),
+            [
+                origin("IHDR", "v1", "v1"),
+                origin("tEXt Author", "v1", "v2"),
+                origin("ruSt", "v4", "v4"),
+            ]
+        );
+
+        let filter = ChunkFilter::from_str("tEXt:Author").unwrap();
+        assert_eq!(blame(&versions(), Some(&filter)).len(), 1);
+        let filter = ChunkFilter::from_str("tEXt:Title").unwrap();
+        assert!(blame(&versions(), Some(&filter)).is_empty());
+        assert!(ChunkFilter::from_str("tEX:Author").is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt::Write;
+
+use anyhow::Result;
+
+use crate::chunk::Chunk;
+use crate::png::Png;
+use crate::raw::raw_chunks;
+
+/// Minimized image that still shows the problem, with a description of it
+pub struct Reproducer {
+    pub png: Vec<u8>,
+    /// Error from parsing original image, `None` if it parsed fine
+    pub error: Option<String>,
+    /// One line per chunk that was kept
+    pub kept: Vec<String>,
+}
+
+/// Keeps image header, end and offending chunks: ones that fail to parse
+/// or, if all parse fine, ones with given types. Everything is kept when
+/// nothing can be blamed. With `redact` data of kept chunks (except
+/// IHDR) is zeroed, while lengths, types and validity of CRC are preserved
+pub fn reproducer(bytes: &[u8], types: &[[u8; 4]], redact: bool) -> Result<Reproducer> {
+    let error = Png::try_from(bytes).err().map(|err| err.to_string());
+    let signature = &bytes[..bytes.len().min(8)];
+    let chunks = raw_chunks(&bytes[signature.len()..]);
+
+    let broken: Vec<_> = (0..chunks.len())
+        .filter(|&i| chunks[i].problem().is_some())
+        .collect();
+    let offending: Vec<_> = if !broken.is_empty() {
+        broken
+    } else {
+        (0..chunks.len())
+            .filter(|&i| types.contains(&chunks[i].chunk_type))
+            .collect()
+    };
+
+    let keep = |i: usize| {
+        offending.is_empty()
+            || offending.contains(&i)
+            || (chunks[i].problem().is_none() && matches!(&chunks[i].chunk_type, b"IHDR" | b"IEND"))
+    };
+
+    let mut png = signature.to_vec();
+    let mut kept = vec![];
+    for (i, chunk) in chunks.iter().enumerate().filter(|&(i, _)| keep(i)) {
+        let problem = chunk.problem();
+        let mut line = format!("#{} {}", i, String::from_utf8_lossy(&chunk.chunk_type));
+        write!(line, ": {} bytes", chunk.data().len())?;
+        if let Some(problem) = &problem {
+            write!(line, ", {}", problem)?;
+        }
+        kept.push(line);
+
+        if !redact || &chunk.chunk_type == b"IHDR" {
+            png.extend_from_slice(chunk.bytes);
+            continue;
+        }
+        let mut redacted = chunk.bytes.to_vec();
+        let data_end = 8 + chunk.data().len();
+        redacted[8..data_end].fill(0);
+        if problem.is_none() {
+            // keep CRC valid, so the only change is the content itself
+            let data = vec![0; chunk.data().len()];
+            let crc = Chunk::new(chunk.chunk_type.try_into()?, data).crc();
+            redacted[data_end..].copy_from_slice(&crc.to_be_bytes());
+        }
+        png.extend_from_slice(&redacted);
+    }
+
+    Ok(Reproducer { png, error, kept })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk_type::ChunkType;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.into())
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", "header"),
+            chunk("tEXt", "confidential"),
+            chunk("ruSt", "secret message"),
+            chunk("IDAT", "pixels"),
+            chunk("IEND", ""),
+        ])
+    }
+
+    #[test]
+    fn test_keeps_broken_chunk() {
+        let mut bytes = testing_png().as_bytes();
+        // corrupt CRC of ruSt chunk
+        let crc_pos = 8 + 18 + 24 + 4 + 4 + 14;
+        bytes[crc_pos] ^= 0xff;
+
+        let reproducer = reproducer(&bytes, &[], true).unwrap();
+        assert!(reproducer.error.is_some());
+        assert_eq!(reproducer.kept.len(), 3);
+        assert!(reproducer.kept[1].starts_with("#2 ruSt"));
+
+        let result = Png::try_from(reproducer.png.as_slice());
+        assert!(result.is_err());
+        assert!(!reproducer.png.windows(6).any(|w| w == b"secret"));
+    }
+
+    #[test]
+    fn test_selects_types_of_valid_image() {
+        let bytes = testing_png().as_bytes();
+        let reproducer = reproducer(&bytes, &[*b"tEXt"], true).unwrap();
+        assert!(reproducer.error.is_none());
+
+        let png = Png::try_from(reproducer.png.as_slice()).unwrap();
+        let types: Vec<_> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "tEXt", "IEND"]);
+        assert_eq!(png.chunks()[0].data(), b"header");
+        assert_eq!(png.chunks()[1].data(), &[0; 12]);
+    }
+
+    #[test]
+    fn test_truncated_image() {
+        let bytes = testing_png().as_bytes();
+        let truncated = &bytes[..bytes.len() - 20];
+        let reproducer = reproducer(truncated, &[], false).unwrap();
+        assert!(reproducer.kept.last().unwrap().ends_with("truncated"));
+        assert!(reproducer.png.ends_with(&truncated[truncated.len() - 10..]));
+    }
+}
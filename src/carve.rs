@@ -0,0 +1,139 @@
+use crate::png::Png;
+use crate::raw;
+
+/// Complete PNG found inside another one
+pub struct Nested<'a> {
+    /// Index of chunk holding the image, `None` for data after IEND
+    pub chunk_index: Option<usize>,
+    /// Position of nested signature in outer file
+    pub offset: usize,
+    /// Whole nested file, from signature to the end of its IEND
+    pub bytes: &'a [u8],
+}
+
+/// Finds complete PNG files embedded in chunk data or appended after IEND.
+/// Images nested in found ones are returned too. Compressed or split
+/// across chunks data is not looked into
+pub fn carve(bytes: &[u8]) -> Vec<Nested<'_>> {
+    let mut nested = vec![];
+    let mut end = 8;
+    for (i, chunk) in raw::raw_chunks(bytes.get(8..).unwrap_or_default())
+        .iter()
+        .enumerate()
+    {
+        let start = chunk.offset + 8;
+        for (offset, image) in find_images(chunk.data()) {
+            nested.push(Nested {
+                chunk_index: Some(i),
+                offset: start + offset,
+                bytes: image,
+            });
+        }
+        end = chunk.offset + chunk.bytes.len();
+        if &chunk.chunk_type == b"IEND" {
+            break;
+        }
+    }
+    if let Some(trailing) = bytes.get(end..) {
+        for (offset, image) in find_images(trailing) {
+            nested.push(Nested {
+                chunk_index: None,
+                offset: end + offset,
+                bytes: image,
+            });
+        }
+    }
+    nested
+}
+
+/// Every complete and valid PNG starting at some signature in `data`, with its offset
+fn find_images(data: &[u8]) -> Vec<(usize, &[u8])> {
+    let mut images = vec![];
+    let mut from = 0;
+    while let Some(start) = data[from..]
+        .windows(8)
+        .position(|w| w == Png::STANDARD_HEADER)
+        .map(|p| from + p)
+    {
+        if let Some(length) = image_length(&data[start..]) {
+            let image = &data[start..start + length];
+            if Png::try_from(image).is_ok() {
+                images.push((start, image));
+            }
+        }
+        from = start + 1;
+    }
+    images
+}
+
+/// Length of PNG at the start of `data` up to its first IEND, if data has it whole
+fn image_length(data: &[u8]) -> Option<usize> {
+    let mut position = 8;
+    loop {
+        let header = data.get(position..position + 8)?;
+        let length = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+        position = position.checked_add(length)?.checked_add(12)?;
+        if position > data.len() {
+            return None;
+        }
+        if &header[4..] == b"IEND" {
+            return Some(position);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+
+    fn png(chunks: &[(&str, &[u8])]) -> Vec<u8> {
+        let chunks = chunks
+            .iter()
+            .map(|(t, d)| Chunk::new(ChunkType::from_str(t).unwrap(), d.to_vec()))
+            .collect();
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    #[test]
+    fn test_carve() {
+        let inner = png(&[("IHDR", b"inner"), ("IEND", b"")]);
+        let middle = png(&[("IHDR", b"middle"), ("ruSt", &inner), ("IEND", b"")]);
+        let mut payload = b"junk".to_vec();
+        payload.extend_from_slice(&middle);
+        let mut truncated = inner.clone();
+        truncated.pop();
+        let mut bytes = png(&[
+            ("IHDR", b"outer"),
+            ("tEXt", &payload),
+            ("ruSt", &truncated),
+            ("IEND", b""),
+        ]);
+        let iend_end = bytes.len();
+        bytes.extend_from_slice(b"appended");
+        bytes.extend_from_slice(&inner);
+
+        let nested = carve(&bytes);
+        assert_eq!(nested.len(), 3);
+        assert_eq!(nested[0].chunk_index, Some(1));
+        assert_eq!(nested[0].bytes, middle);
+        assert_eq!(&bytes[nested[0].offset..][..8], Png::STANDARD_HEADER);
+        assert_eq!(nested[1].chunk_index, Some(1));
+        assert_eq!(nested[1].bytes, inner);
+        assert_eq!(nested[2].chunk_index, None);
+        assert_eq!(nested[2].offset, iend_end + 8);
+        assert_eq!(nested[2].bytes, inner);
+    }
+
+    #[test]
+    fn test_carve_corrupted() {
+        let mut inner = png(&[("IHDR", b"inner"), ("IEND", b"")]);
+        inner[20] ^= 1;
+        let bytes = png(&[("IHDR", b"outer"), ("ruSt", &inner), ("IEND", b"")]);
+        assert!(carve(&bytes).is_empty());
+        assert!(carve(&bytes[..30]).is_empty());
+    }
+}
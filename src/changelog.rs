@@ -0,0 +1,144 @@
+//! Detached record of what a command did to images, for review tools:
+//! chunks added, removed and modified, with offsets and hashes
+
+use crate::chunk::Chunk;
+use crate::diff::{self, Delta};
+use crate::json::{json, Value};
+use crate::png::Png;
+use crate::raw;
+use crate::receipt;
+
+/// Chunk and where it is in file, `offset` counts PNG signature
+fn placed(chunk: &Chunk) -> Value {
+    json!({
+        "offset": chunk.offset().unwrap_or_default(),
+        "length": chunk.length(),
+        "sha256": receipt::digest(&chunk.as_bytes()),
+    })
+}
+
+fn entry(action: &str, chunk_type: String, sides: Vec<(&str, Value)>) -> Value {
+    let mut value = json!({ "action": action, "type": chunk_type });
+    for (key, side) in sides {
+        value[key] = side;
+    }
+    value
+}
+
+/// Changes between file contents before and after, `input` is empty for a new file.
+/// Damaged chunks of input never make it to output as they were, so they are
+/// listed as removed, along with chunk that replaced them if there is one
+pub fn changes(input: &[u8], output: &[u8]) -> Vec<Value> {
+    let mut old = vec![];
+    let mut damaged = vec![];
+    if input.starts_with(&Png::STANDARD_HEADER) {
+        for record in raw::raw_chunks(&input[8..]) {
+            match Chunk::try_from(record.bytes) {
+                Ok(chunk) => old.push(chunk.with_offset(record.offset)),
+                Err(_) => damaged.push(record),
+            }
+        }
+    }
+    let new: Vec<_> = raw::raw_chunks(output.get(8..).unwrap_or_default())
+        .iter()
+        .filter_map(|r| {
+            Chunk::try_from(r.bytes)
+                .ok()
+                .map(|c| c.with_offset(r.offset))
+        })
+        .collect();
+
+    let mut changes: Vec<_> = damaged
+        .iter()
+        .map(|record| {
+            let before = json!({
+                "offset": record.offset,
+                "length": record.data().len(),
+                "sha256": receipt::digest(record.bytes),
+            });
+            let chunk_type = String::from_utf8_lossy(&record.chunk_type).into_owned();
+            entry("removed", chunk_type, vec![("before", before)])
+        })
+        .collect();
+    for delta in diff::diff(&old, &new) {
+        changes.push(match delta {
+            Delta::Added(chunk) => entry(
+                "added",
+                chunk.chunk_type().to_string(),
+                vec![("after", placed(chunk))],
+            ),
+            Delta::Removed(chunk) => entry(
+                "removed",
+                chunk.chunk_type().to_string(),
+                vec![("before", placed(chunk))],
+            ),
+            Delta::Changed(before, after) => entry(
+                "modified",
+                after.chunk_type().to_string(),
+                vec![("before", placed(before)), ("after", placed(after))],
+            ),
+        });
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use crate::json::Fields;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_changes() {
+        let before = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("tEXt", b"Title\0old"),
+            chunk("ruSt", b"gone"),
+            chunk("IEND", b""),
+        ]);
+        let after = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("tEXt", b"Title\0new"),
+            chunk("teSt", b"new"),
+            chunk("IEND", b""),
+        ]);
+        let changes = changes(&before.as_bytes(), &after.as_bytes());
+        let summary: Vec<_> = changes
+            .iter()
+            .map(|c| (c.str_field("action").unwrap(), c.str_field("type").unwrap()))
+            .collect();
+        assert_eq!(
+            summary,
+            [("modified", "tEXt"), ("removed", "ruSt"), ("added", "teSt")]
+        );
+        let after = changes[2].get("after").unwrap();
+        assert_eq!(after.u64_field("offset").unwrap(), 8 + 25 + 21);
+        assert_eq!(after.u64_field("length").unwrap(), 3);
+        let bytes = chunk("teSt", b"new").as_bytes();
+        assert_eq!(after.str_field("sha256").unwrap(), receipt::digest(&bytes));
+
+        // new file, everything is added
+        assert_eq!(super::changes(b"", &before.as_bytes()).len(), 4);
+    }
+
+    #[test]
+    fn test_damaged_input() {
+        let png = Png::from_chunks(vec![chunk("IHDR", &[0; 13]), chunk("IEND", b"")]);
+        let mut damaged = png.as_bytes();
+        damaged[8 + 20] ^= 1;
+        let changes = changes(&damaged, &png.as_bytes());
+        let actions: Vec<_> = changes
+            .iter()
+            .map(|c| c.str_field("action").unwrap())
+            .collect();
+        assert_eq!(actions, ["removed", "added"]);
+        let before = changes[0].get("before").unwrap();
+        assert_eq!(before.u64_field("offset").unwrap(), 8);
+    }
+}
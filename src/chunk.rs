@@ -1,15 +1,43 @@
-use std::fmt::{Display, Formatter};
-use std::io::Read;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Debug, Display, Formatter};
+use core::hash::{Hash, Hasher};
 
 use anyhow::{ensure, Error, Result};
 use crc::Crc;
 
 use crate::chunk_type::ChunkType;
 
+/// Character encoding of textual chunk data
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TextEncoding {
+    Utf8,
+    Latin1,
+}
+
+impl TextEncoding {
+    /// Encoding given by PNG specification: Latin-1 for `tEXt` and `zTXt`,
+    /// UTF-8 is assumed for everything else
+    pub fn of(chunk_type: &ChunkType) -> TextEncoding {
+        match &chunk_type.bytes() {
+            b"tEXt" | b"zTXt" => TextEncoding::Latin1,
+            _ => TextEncoding::Utf8,
+        }
+    }
+}
+
+/// Number of data bytes shown by `Debug`
+const PREVIEW_SIZE: usize = 32;
+
+/// Chunks are equal when type and data are, CRC follows from them
+/// and offset only tells where chunk happened to be
+#[derive(Clone)]
 pub struct Chunk {
     chunk_type: ChunkType,
     data: Vec<u8>,
     crc: u32,
+    /// Position in file the chunk was parsed from
+    offset: Option<usize>,
 }
 
 impl TryFrom<&[u8]> for Chunk {
@@ -42,16 +70,67 @@ impl TryFrom<&[u8]> for Chunk {
             chunk_type,
             data,
             crc,
+            offset: None,
         })
     }
 }
 
-impl Display for Chunk {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self.data_as_string() {
-            Ok(str) => write!(f, "{}", str),
-            Err(_) => Ok(()),
+impl PartialEq for Chunk {
+    fn eq(&self, other: &Self) -> bool {
+        self.chunk_type == other.chunk_type && self.data == other.data
+    }
+}
+
+impl Eq for Chunk {}
+
+impl Hash for Chunk {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.chunk_type.hash(state);
+        self.data.hash(state);
+    }
+}
+
+impl Debug for Chunk {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Chunk")
+            .field("chunk_type", &format_args!("{}", self.chunk_type))
+            .field("length", &self.length())
+            .field("crc", &format_args!("{:#010x}", self.crc))
+            .field("offset", &self.offset)
+            .field("data", &Preview(&self.data))
+            .finish()
+    }
+}
+
+/// Leading bytes of data as escaped byte string, with count of the rest
+struct Preview<'a>(&'a [u8]);
+
+impl Debug for Preview<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "b\"")?;
+        for &b in self.0.iter().take(PREVIEW_SIZE) {
+            write!(f, "{}", core::ascii::escape_default(b))?;
+        }
+        write!(f, "\"")?;
+        if self.0.len() > PREVIEW_SIZE {
+            write!(f, "... ({} more bytes)", self.0.len() - PREVIEW_SIZE)?;
         }
+        Ok(())
+    }
+}
+
+/// Short summary like `RuSt (42 bytes, crc ok)`, data is never printed.
+/// Text of textual chunks is available from [`Chunk::data_as_string_in`]
+impl Display for Chunk {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let crc = if self.crc_ok() { "ok" } else { "mismatch" };
+        write!(
+            f,
+            "{} ({} bytes, crc {})",
+            self.chunk_type,
+            self.data.len(),
+            crc
+        )
     }
 }
 
@@ -63,8 +142,31 @@ impl Chunk {
             chunk_type,
             data,
             crc,
+            offset: None,
         }
     }
+
+    /// Parses chunk at start of `buf`, returning it with number of bytes it took.
+    /// `None` means chunk isn't complete yet and more bytes are needed
+    pub fn parse(buf: &[u8]) -> Result<Option<(Chunk, usize)>> {
+        let Some(len) = buf.get(..4) else {
+            return Ok(None);
+        };
+        let len = u32::from_be_bytes(len.try_into().unwrap());
+        ensure!(len <= i32::MAX as u32, "Chunk length {} is too large", len);
+        let size = len as usize + 12;
+        if buf.len() < size {
+            return Ok(None);
+        }
+        Ok(Some((Chunk::try_from(&buf[..size])?, size)))
+    }
+
+    /// Same chunk remembering its byte offset in the source file
+    pub fn with_offset(mut self, offset: usize) -> Chunk {
+        self.offset = Some(offset);
+        self
+    }
+
     pub fn length(&self) -> u32 {
         self.data.len() as u32
     }
@@ -77,18 +179,53 @@ impl Chunk {
     pub fn crc(&self) -> u32 {
         self.crc
     }
-    pub fn data_as_string(&self) -> Result<String> {
-        Ok(String::from_utf8(self.data.clone())?)
+    /// Stored CRC matches type and data
+    pub fn crc_ok(&self) -> bool {
+        self.crc == Chunk::calc_crc(&self.chunk_type.bytes(), &self.data)
+    }
+    /// Offset of chunk length field from start of file, counting PNG signature.
+    /// `None` if chunk wasn't parsed from a file
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+    /// Data as UTF-8 text, validated in place
+    pub fn data_as_string(&self) -> Result<&str> {
+        core::str::from_utf8(&self.data).map_err(Error::msg)
+    }
+    /// Same as [`Chunk::data_as_string`], but copies the text
+    pub fn data_as_string_owned(&self) -> Result<String> {
+        self.data_as_string().map(String::from)
+    }
+    /// Data as UTF-8 text with invalid sequences replaced by U+FFFD
+    pub fn data_as_string_lossy(&self) -> String {
+        self.data_as_string_in(TextEncoding::Utf8)
+    }
+    /// Data decoded from given encoding, never fails
+    pub fn data_as_string_in(&self, encoding: TextEncoding) -> String {
+        match encoding {
+            TextEncoding::Utf8 => String::from_utf8_lossy(&self.data).into_owned(),
+            TextEncoding::Latin1 => self.data.iter().map(|&b| b as char).collect(),
+        }
     }
     pub fn as_bytes(&self) -> Vec<u8> {
+        self.bytes_iter().collect()
+    }
+    /// Length, type, data and CRC of the chunk, same as [`Chunk::as_bytes`] without allocating
+    pub fn bytes_iter(&self) -> impl Iterator<Item = u8> + '_ {
         self.length()
             .to_be_bytes()
-            .iter()
-            .chain(self.chunk_type.bytes().iter())
-            .chain(self.data.iter())
-            .chain(self.crc.to_be_bytes().iter())
-            .copied()
-            .collect()
+            .into_iter()
+            .chain(self.chunk_type.bytes())
+            .chain(self.data.iter().copied())
+            .chain(self.crc.to_be_bytes())
+    }
+    /// Writes whole chunk as it is laid out in file
+    #[cfg(any(feature = "std", test))]
+    pub fn write_bytes(&self, writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        writer.write_all(&self.length().to_be_bytes())?;
+        writer.write_all(&self.chunk_type.bytes())?;
+        writer.write_all(&self.data)?;
+        writer.write_all(&self.crc.to_be_bytes())
     }
 
     /// Returns size of this chunk in bytes.
@@ -163,6 +300,16 @@ mod tests {
         assert_eq!(chunk_string, expected_chunk_string);
     }
 
+    #[test]
+    fn test_chunk_string_lossy() {
+        let text = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Caf\xe9".to_vec());
+        assert!(text.data_as_string().is_err());
+        assert_eq!(text.data_as_string_lossy(), "Caf\u{fffd}");
+        assert_eq!(text.data_as_string_in(TextEncoding::Latin1), "Café");
+        // Display summarizes chunk, text is only given by the methods above
+        assert_eq!(text.to_string(), "tEXt (4 bytes, crc ok)");
+    }
+
     #[test]
     fn test_chunk_crc() {
         let chunk = testing_chunk();
@@ -217,6 +364,36 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_bytes_without_allocation() {
+        let chunk = testing_chunk();
+        let bytes = chunk.as_bytes();
+        assert_eq!(chunk.bytes_iter().collect::<Vec<_>>(), bytes);
+        assert_eq!(chunk.bytes_iter().count(), chunk.chunk_size());
+
+        let mut written = vec![];
+        chunk.write_bytes(&mut written).unwrap();
+        assert_eq!(written, bytes);
+        assert_eq!(
+            chunk.data_as_string_owned().unwrap(),
+            chunk.data_as_string().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_partial() {
+        let bytes = testing_chunk().as_bytes();
+        assert!(Chunk::parse(&bytes[..3]).unwrap().is_none());
+        assert!(Chunk::parse(&bytes[..bytes.len() - 1]).unwrap().is_none());
+
+        let mut buf = bytes.clone();
+        buf.extend_from_slice(b"next");
+        let (chunk, consumed) = Chunk::parse(&buf).unwrap().unwrap();
+        assert_eq!(consumed, bytes.len());
+        assert_eq!(chunk.as_bytes(), bytes);
+        assert!(Chunk::parse(&[0xff; 12]).is_err());
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
@@ -235,6 +412,36 @@ mod tests {
 
         let chunk: Chunk = TryFrom::try_from(chunk_data.as_ref()).unwrap();
 
-        let _chunk_string = format!("{}", chunk);
+        assert_eq!(format!("{}", chunk), "RuSt (42 bytes, crc ok)");
+        assert!(chunk.crc_ok());
+    }
+
+    #[test]
+    fn test_equality_and_hash() {
+        use std::collections::HashSet;
+
+        let chunk = testing_chunk();
+        let moved = testing_chunk().with_offset(100);
+        assert_eq!(chunk, moved);
+        assert_eq!(chunk.clone(), chunk);
+        assert_ne!(
+            chunk,
+            Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![])
+        );
+
+        let set: HashSet<_> = [chunk, moved].into_iter().collect();
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn test_debug_preview() {
+        let debug = format!("{:?}", testing_chunk().with_offset(33));
+        assert_eq!(
+            debug,
+            "Chunk { chunk_type: RuSt, length: 42, crc: 0xabd1d84e, offset: Some(33), \
+             data: b\"This is where your secret messag\"... (10 more bytes) }"
+        );
+        let short = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"\"\0\xff".to_vec());
+        assert!(format!("{:?}", short).ends_with("data: b\"\\\"\\x00\\xff\" }"));
     }
 }
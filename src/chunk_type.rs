@@ -1,25 +1,53 @@
-use std::fmt::{Display, Formatter};
-use std::str::FromStr;
+use core::fmt::{Debug, Display, Formatter};
+use core::str::FromStr;
 
-use anyhow::{ensure, Error, Result};
+use anyhow::{Error, Result};
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
 pub struct ChunkType {
     bytes: [u8; 4],
 }
 
+/// Why bytes can't be a chunk type, telling what to fix
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ChunkTypeError {
+    /// Type given as text is not 4 bytes long
+    Length(usize),
+    /// Byte at `index` is not an ASCII letter
+    NotLetter { index: usize, byte: u8 },
+    /// Third letter is lowercase, which PNG reserves for future use
+    ReservedBit,
+}
+
+impl Display for ChunkTypeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ChunkTypeError::Length(length) => {
+                write!(f, "Chunk type must be 4 letters long, got {} bytes", length)
+            }
+            ChunkTypeError::NotLetter { index, byte } => write!(
+                f,
+                "Character {} of chunk type is '{}', only ASCII letters A-Z and a-z are allowed",
+                index + 1,
+                core::ascii::escape_default(*byte)
+            ),
+            ChunkTypeError::ReservedBit => write!(
+                f,
+                "Third letter of chunk type must be uppercase, lowercase is reserved by PNG specification"
+            ),
+        }
+    }
+}
+
+impl core::error::Error for ChunkTypeError {}
+
 impl TryFrom<[u8; 4]> for ChunkType {
     type Error = Error;
 
+    /// Accepts any four letters, see [`ChunkType::validate`] for the reserved bit
     fn try_from(bytes: [u8; 4]) -> Result<Self> {
-        ensure!(
-            bytes
-                .iter()
-                .filter(|&&b| b.is_ascii_lowercase() || b.is_ascii_uppercase())
-                .count()
-                == 4,
-            "Invalid Chunk Type"
-        );
+        // without std anyhow can't convert foreign errors with `?`
+        check_letters(bytes).map_err(Error::msg)?;
 
         Ok(ChunkType { bytes })
     }
@@ -29,18 +57,41 @@ impl FromStr for ChunkType {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self> {
-        let bytes: [u8; 4] = s.as_bytes().try_into()?;
+        let bytes: [u8; 4] = s
+            .as_bytes()
+            .try_into()
+            .map_err(|_| Error::msg(ChunkTypeError::Length(s.len())))?;
         ChunkType::try_from(bytes)
     }
 }
 
 impl Display for ChunkType {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", std::str::from_utf8(&self.bytes).unwrap())
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", core::str::from_utf8(&self.bytes).unwrap())
+    }
+}
+
+impl Debug for ChunkType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "ChunkType({:?})",
+            core::str::from_utf8(&self.bytes).unwrap()
+        )
     }
 }
 
 impl ChunkType {
+    /// Checks that bytes are letters and reserved bit is not set.
+    /// Parsing accepts types with reserved bit, as decoders must not reject them
+    pub fn validate(bytes: [u8; 4]) -> core::result::Result<(), ChunkTypeError> {
+        check_letters(bytes)?;
+        if bytes[2].is_ascii_lowercase() {
+            return Err(ChunkTypeError::ReservedBit);
+        }
+        Ok(())
+    }
+
     pub fn bytes(&self) -> [u8; 4] {
         self.bytes
     }
@@ -65,6 +116,16 @@ impl ChunkType {
     }
 }
 
+fn check_letters(bytes: [u8; 4]) -> core::result::Result<(), ChunkTypeError> {
+    match bytes.iter().position(|b| !b.is_ascii_alphabetic()) {
+        Some(index) => Err(ChunkTypeError::NotLetter {
+            index,
+            byte: bytes[index],
+        }),
+        None => Ok(()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::convert::TryFrom;
@@ -156,6 +217,33 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_validate() {
+        assert_eq!(ChunkType::validate(*b"RuSt"), Ok(()));
+        assert_eq!(
+            ChunkType::validate(*b"Ru1t"),
+            Err(ChunkTypeError::NotLetter {
+                index: 2,
+                byte: b'1'
+            })
+        );
+        assert_eq!(
+            ChunkType::validate(*b"Rust"),
+            Err(ChunkTypeError::ReservedBit)
+        );
+
+        let error = ChunkType::from_str("ru\0t").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Character 3 of chunk type is '\\x00', only ASCII letters A-Z and a-z are allowed"
+        );
+        let error = ChunkType::from_str("RuStic").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Chunk type must be 4 letters long, got 6 bytes"
+        );
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -169,4 +257,10 @@ mod tests {
         let _chunk_string = format!("{}", chunk_type_1);
         let _are_chunks_equal = chunk_type_1 == chunk_type_2;
     }
+
+    #[test]
+    pub fn test_chunk_type_debug() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        assert_eq!(format!("{:?}", chunk_type), "ChunkType(\"RuSt\")");
+    }
 }
@@ -0,0 +1,17 @@
+use anyhow::{Context, Result};
+use arboard::Clipboard;
+
+/// Puts text on system clipboard. On X11 and Wayland text stays available
+/// after pngme exits only if a clipboard manager picks it up
+pub fn copy(text: &str) -> Result<()> {
+    Clipboard::new()
+        .and_then(|mut clipboard| clipboard.set_text(text))
+        .context("Failed to copy to clipboard")
+}
+
+/// Current text content of system clipboard
+pub fn paste() -> Result<String> {
+    Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get_text())
+        .context("Failed to read text from clipboard")
+}
@@ -0,0 +1,118 @@
+//! Transforms of payload, like application's own encryption, compression or
+//! encoding, applied when it is embedded and reversed when it is extracted.
+//! Codecs added to [`crate::embed::Registry`] apply to every backend.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use anyhow::{Context, Result};
+
+pub trait PayloadCodec {
+    /// Name shown in errors
+    fn name(&self) -> &str;
+
+    /// Pre-write hook, transforms payload before it is written into image
+    fn encode(&self, payload: Vec<u8>) -> Result<Vec<u8>>;
+
+    /// Post-read hook, reverses `encode` on payload read from image
+    fn decode(&self, payload: Vec<u8>) -> Result<Vec<u8>>;
+}
+
+/// Codecs applied in order they are added when encoding, and in reverse when decoding
+#[derive(Default)]
+pub struct Codecs {
+    codecs: Vec<Box<dyn PayloadCodec>>,
+}
+
+impl Codecs {
+    pub fn new() -> Codecs {
+        Codecs::default()
+    }
+
+    pub fn push(&mut self, codec: Box<dyn PayloadCodec>) {
+        self.codecs.push(codec);
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.codecs.iter().map(|c| c.name()).collect()
+    }
+
+    pub fn encode(&self, mut payload: Vec<u8>) -> Result<Vec<u8>> {
+        for codec in &self.codecs {
+            payload = codec.encode(payload).with_context(|| {
+                alloc::format!("Codec {} failed to encode payload", codec.name())
+            })?;
+        }
+        Ok(payload)
+    }
+
+    pub fn decode(&self, mut payload: Vec<u8>) -> Result<Vec<u8>> {
+        for codec in self.codecs.iter().rev() {
+            payload = codec.decode(payload).with_context(|| {
+                alloc::format!("Codec {} failed to decode payload", codec.name())
+            })?;
+        }
+        Ok(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::ensure;
+
+    use super::*;
+
+    /// Adds constant to every byte
+    struct Shift(u8);
+
+    impl PayloadCodec for Shift {
+        fn name(&self) -> &str {
+            "shift"
+        }
+
+        fn encode(&self, payload: Vec<u8>) -> Result<Vec<u8>> {
+            Ok(payload.iter().map(|b| b.wrapping_add(self.0)).collect())
+        }
+
+        fn decode(&self, payload: Vec<u8>) -> Result<Vec<u8>> {
+            Ok(payload.iter().map(|b| b.wrapping_sub(self.0)).collect())
+        }
+    }
+
+    /// Prepends marker, so order of codecs matters
+    struct Tag;
+
+    impl PayloadCodec for Tag {
+        fn name(&self) -> &str {
+            "tag"
+        }
+
+        fn encode(&self, mut payload: Vec<u8>) -> Result<Vec<u8>> {
+            payload.insert(0, b'#');
+            Ok(payload)
+        }
+
+        fn decode(&self, payload: Vec<u8>) -> Result<Vec<u8>> {
+            ensure!(payload.first() == Some(&b'#'), "Missing tag");
+            Ok(payload[1..].to_vec())
+        }
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let mut codecs = Codecs::new();
+        assert_eq!(codecs.encode(b"abc".to_vec()).unwrap(), b"abc");
+        codecs.push(Box::new(Shift(1)));
+        codecs.push(Box::new(Tag));
+        assert_eq!(codecs.names(), ["shift", "tag"]);
+
+        let encoded = codecs.encode(b"abc".to_vec()).unwrap();
+        assert_eq!(encoded, b"#bcd");
+        assert_eq!(codecs.decode(encoded).unwrap(), b"abc");
+        let err = codecs.decode(b"bcd".to_vec()).unwrap_err();
+        assert_eq!(
+            alloc::format!("{:#}", err),
+            "Codec tag failed to decode payload: Missing tag"
+        );
+    }
+}
@@ -0,0 +1,406 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use anyhow::{bail, ensure, Context, Error, Result};
+
+/// Values of `gAMA` and `cHRM` chunks are stored multiplied by this
+const SCALE: f64 = 100_000.0;
+
+/// Image gamma from `gAMA` chunk
+#[derive(Debug, Eq, PartialEq)]
+pub struct Gamma(pub u32);
+
+impl Gamma {
+    /// Gamma that `sRGB` images should declare for older decoders
+    #[cfg(feature = "write")]
+    pub const SRGB: Gamma = Gamma(45455);
+
+    #[cfg(feature = "write")]
+    pub fn from_value(value: f64) -> Result<Gamma> {
+        let scaled = (value * SCALE).round();
+        ensure!(
+            scaled >= 1.0 && scaled <= u32::MAX as f64,
+            "Invalid gamma {}",
+            value
+        );
+        Ok(Gamma(scaled as u32))
+    }
+
+    pub fn from_data(data: &[u8]) -> Result<Gamma> {
+        let data: [u8; 4] = data.try_into().context("gAMA chunk must be 4 bytes long")?;
+        Ok(Gamma(u32::from_be_bytes(data)))
+    }
+
+    #[cfg(feature = "write")]
+    pub fn to_data(&self) -> Vec<u8> {
+        self.0.to_be_bytes().to_vec()
+    }
+
+    pub fn value(&self) -> f64 {
+        self.0 as f64 / SCALE
+    }
+}
+
+/// White point and primaries from `cHRM` chunk, as x and y pairs
+#[derive(Debug, Eq, PartialEq)]
+pub struct Chromaticities {
+    pub white: (u32, u32),
+    pub red: (u32, u32),
+    pub green: (u32, u32),
+    pub blue: (u32, u32),
+}
+
+impl Chromaticities {
+    /// Values that `sRGB` images should declare for older decoders
+    #[cfg(feature = "write")]
+    pub const SRGB: Chromaticities = Chromaticities {
+        white: (31270, 32900),
+        red: (64000, 33000),
+        green: (30000, 60000),
+        blue: (15000, 6000),
+    };
+
+    pub fn from_data(data: &[u8]) -> Result<Chromaticities> {
+        ensure!(
+            data.len() == 32,
+            "cHRM chunk must be 32 bytes long, got {}",
+            data.len()
+        );
+        let value = |i: usize| u32::from_be_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+        Ok(Chromaticities {
+            white: (value(0), value(1)),
+            red: (value(2), value(3)),
+            green: (value(4), value(5)),
+            blue: (value(6), value(7)),
+        })
+    }
+
+    #[cfg(feature = "write")]
+    pub fn to_data(&self) -> Vec<u8> {
+        [self.white, self.red, self.green, self.blue]
+            .iter()
+            .flat_map(|&(x, y)| [x, y])
+            .flat_map(u32::to_be_bytes)
+            .collect()
+    }
+}
+
+/// Parses 8 comma-separated values: white x,y then red, green and blue x,y
+impl FromStr for Chromaticities {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let values = s
+            .split(',')
+            .map(|v| {
+                let value: f64 = v.trim().parse().context("Invalid chromaticity")?;
+                ensure!(
+                    (0.0..=1.0).contains(&value),
+                    "Chromaticity {} is out of 0..1 range",
+                    value
+                );
+                Ok((value * SCALE).round() as u32)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        ensure!(
+            values.len() == 8,
+            "Expected 8 chromaticity values, got {}",
+            values.len()
+        );
+        Ok(Chromaticities {
+            white: (values[0], values[1]),
+            red: (values[2], values[3]),
+            green: (values[4], values[5]),
+            blue: (values[6], values[7]),
+        })
+    }
+}
+
+impl Display for Chromaticities {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let pairs = [
+            ("white", self.white),
+            ("red", self.red),
+            ("green", self.green),
+            ("blue", self.blue),
+        ];
+        for (i, (name, (x, y))) in pairs.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} ({}, {})", name, *x as f64 / SCALE, *y as f64 / SCALE)?;
+        }
+        Ok(())
+    }
+}
+
+/// Rendering intent from `sRGB` chunk
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RenderingIntent {
+    Perceptual,
+    RelativeColorimetric,
+    Saturation,
+    AbsoluteColorimetric,
+}
+
+const INTENTS: [(RenderingIntent, &str); 4] = [
+    (RenderingIntent::Perceptual, "perceptual"),
+    (
+        RenderingIntent::RelativeColorimetric,
+        "relative-colorimetric",
+    ),
+    (RenderingIntent::Saturation, "saturation"),
+    (
+        RenderingIntent::AbsoluteColorimetric,
+        "absolute-colorimetric",
+    ),
+];
+
+impl RenderingIntent {
+    pub fn from_data(data: &[u8]) -> Result<RenderingIntent> {
+        match data {
+            &[value] if (value as usize) < INTENTS.len() => Ok(INTENTS[value as usize].0),
+            [value] => bail!("Unknown rendering intent {}", value),
+            _ => bail!("sRGB chunk must be 1 byte long, got {}", data.len()),
+        }
+    }
+
+    #[cfg(feature = "write")]
+    pub fn to_data(self) -> Vec<u8> {
+        vec![self as u8]
+    }
+}
+
+impl FromStr for RenderingIntent {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        INTENTS
+            .iter()
+            .find(|(_, name)| *name == s)
+            .map(|&(intent, _)| intent)
+            .with_context(|| {
+                let names: Vec<_> = INTENTS.iter().map(|(_, name)| *name).collect();
+                format!(
+                    "Unknown rendering intent '{}', expected one of: {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl Display for RenderingIntent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", INTENTS[*self as usize].1)
+    }
+}
+
+/// Background color from `bKGD` chunk, layout depends on color type
+#[derive(Debug, Eq, PartialEq)]
+pub enum Background {
+    Gray(u16),
+    Rgb(u16, u16, u16),
+    PaletteIndex(u8),
+}
+
+impl Background {
+    pub fn from_data(data: &[u8], color_type: u8) -> Result<Background> {
+        match (color_type, data) {
+            (3, &[index]) => Ok(Background::PaletteIndex(index)),
+            (0 | 4, &[hi, lo]) => Ok(Background::Gray(u16::from_be_bytes([hi, lo]))),
+            (2 | 6, data) if data.len() == 6 => {
+                let (r, g, b) = rgb(data);
+                Ok(Background::Rgb(r, g, b))
+            }
+            _ => bail!(
+                "bKGD chunk of {} bytes is invalid for color type {}",
+                data.len(),
+                color_type
+            ),
+        }
+    }
+}
+
+impl Display for Background {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Background::Gray(gray) => write!(f, "gray {}", gray),
+            Background::Rgb(r, g, b) => write!(f, "RGB ({}, {}, {})", r, g, b),
+            Background::PaletteIndex(index) => write!(f, "palette entry {}", index),
+        }
+    }
+}
+
+/// Transparency from `tRNS` chunk: single transparent color or,
+/// for indexed images, alpha of palette entries
+#[derive(Debug, Eq, PartialEq)]
+pub enum Transparency {
+    Gray(u16),
+    Rgb(u16, u16, u16),
+    PaletteAlpha(Vec<u8>),
+}
+
+impl Transparency {
+    pub fn from_data(data: &[u8], color_type: u8) -> Result<Transparency> {
+        match (color_type, data) {
+            (0, &[hi, lo]) => Ok(Transparency::Gray(u16::from_be_bytes([hi, lo]))),
+            (2, data) if data.len() == 6 => {
+                let (r, g, b) = rgb(data);
+                Ok(Transparency::Rgb(r, g, b))
+            }
+            (3, data) if data.len() <= 256 => Ok(Transparency::PaletteAlpha(data.to_vec())),
+            (4 | 6, _) => bail!("tRNS chunk is not allowed for color type {}", color_type),
+            _ => bail!(
+                "tRNS chunk of {} bytes is invalid for color type {}",
+                data.len(),
+                color_type
+            ),
+        }
+    }
+}
+
+impl Display for Transparency {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Transparency::Gray(gray) => write!(f, "gray {} is transparent", gray),
+            Transparency::Rgb(r, g, b) => write!(f, "RGB ({}, {}, {}) is transparent", r, g, b),
+            Transparency::PaletteAlpha(alpha) => {
+                write!(f, "alpha of {} palette entries", alpha.len())
+            }
+        }
+    }
+}
+
+/// Significant bits per channel from `sBIT` chunk, layout depends on color type.
+/// Indexed images give them for palette colors
+#[derive(Debug, Eq, PartialEq)]
+pub enum SignificantBits {
+    Gray(u8),
+    Rgb(u8, u8, u8),
+    GrayAlpha(u8, u8),
+    Rgba(u8, u8, u8, u8),
+}
+
+impl SignificantBits {
+    pub fn from_data(data: &[u8], color_type: u8, bit_depth: u8) -> Result<SignificantBits> {
+        let bits = match (color_type, data) {
+            (0, &[gray]) => SignificantBits::Gray(gray),
+            (2 | 3, &[r, g, b]) => SignificantBits::Rgb(r, g, b),
+            (4, &[gray, alpha]) => SignificantBits::GrayAlpha(gray, alpha),
+            (6, &[r, g, b, a]) => SignificantBits::Rgba(r, g, b, a),
+            _ => bail!(
+                "sBIT chunk of {} bytes is invalid for color type {}",
+                data.len(),
+                color_type
+            ),
+        };
+        // palette entries are always 8-bit
+        let depth = if color_type == 3 { 8 } else { bit_depth };
+        if let Some(&value) = data.iter().find(|&&v| v == 0 || v > depth) {
+            bail!(
+                "sBIT value {} is out of 1..{} range of {}-bit samples",
+                value,
+                depth,
+                depth
+            );
+        }
+        Ok(bits)
+    }
+}
+
+impl Display for SignificantBits {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignificantBits::Gray(gray) => write!(f, "gray {}", gray),
+            SignificantBits::Rgb(r, g, b) => write!(f, "RGB ({}, {}, {})", r, g, b),
+            SignificantBits::GrayAlpha(gray, alpha) => write!(f, "gray {}, alpha {}", gray, alpha),
+            SignificantBits::Rgba(r, g, b, a) => write!(f, "RGBA ({}, {}, {}, {})", r, g, b, a),
+        }
+    }
+}
+
+fn rgb(data: &[u8]) -> (u16, u16, u16) {
+    let sample = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+    (sample(0), sample(2), sample(4))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_gamma() {
+        let gamma = Gamma::from_value(1.0 / 2.2).unwrap();
+        assert_eq!(gamma, Gamma::SRGB);
+        assert_eq!(Gamma::from_data(&gamma.to_data()).unwrap(), gamma);
+        assert_eq!(gamma.value(), 0.45455);
+        assert!(Gamma::from_value(0.0).is_err());
+        assert!(Gamma::from_data(&[0; 3]).is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_chromaticities() {
+        let srgb = Chromaticities::SRGB;
+        let data = srgb.to_data();
+        assert_eq!(data.len(), 32);
+        assert_eq!(Chromaticities::from_data(&data).unwrap(), srgb);
+
+        let parsed = Chromaticities::from_str("0.3127,0.329,0.64,0.33,0.3,0.6,0.15,0.06");
+        assert_eq!(parsed.unwrap(), srgb);
+        assert!(Chromaticities::from_str("0.3127,0.329").is_err());
+        assert!(Chromaticities::from_str("2,0,0,0,0,0,0,0").is_err());
+        assert_eq!(
+            srgb.to_string(),
+            "white (0.3127, 0.329), red (0.64, 0.33), green (0.3, 0.6), blue (0.15, 0.06)"
+        );
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_rendering_intent() {
+        let intent = RenderingIntent::from_str("saturation").unwrap();
+        assert_eq!(intent.to_data(), [2]);
+        assert_eq!(RenderingIntent::from_data(&[2]).unwrap(), intent);
+        assert_eq!(intent.to_string(), "saturation");
+        assert!(RenderingIntent::from_data(&[4]).is_err());
+        assert!(RenderingIntent::from_str("vivid").is_err());
+    }
+
+    #[test]
+    fn test_significant_bits() {
+        let rgb = SignificantBits::from_data(&[5, 6, 5], 2, 8).unwrap();
+        assert_eq!(rgb, SignificantBits::Rgb(5, 6, 5));
+        assert_eq!(rgb.to_string(), "RGB (5, 6, 5)");
+        let gray = SignificantBits::from_data(&[12, 16], 4, 16).unwrap();
+        assert_eq!(gray.to_string(), "gray 12, alpha 16");
+        assert!(SignificantBits::from_data(&[8, 8, 8], 3, 2).is_ok());
+        assert!(SignificantBits::from_data(&[5, 6, 5], 6, 8).is_err());
+        assert!(SignificantBits::from_data(&[9], 0, 8).is_err());
+        assert!(SignificantBits::from_data(&[0], 0, 8).is_err());
+    }
+
+    #[test]
+    fn test_background() {
+        let rgb = Background::from_data(&[0, 255, 0, 128, 1, 0], 6).unwrap();
+        assert_eq!(rgb, Background::Rgb(255, 128, 256));
+        assert_eq!(rgb.to_string(), "RGB (255, 128, 256)");
+        let gray = Background::from_data(&[0, 7], 4).unwrap();
+        assert_eq!(gray, Background::Gray(7));
+        let index = Background::from_data(&[3], 3).unwrap();
+        assert_eq!(index, Background::PaletteIndex(3));
+        assert!(Background::from_data(&[0, 7], 2).is_err());
+    }
+
+    #[test]
+    fn test_transparency() {
+        let gray = Transparency::from_data(&[0, 1], 0).unwrap();
+        assert_eq!(gray, Transparency::Gray(1));
+        let alpha = Transparency::from_data(&[0, 128, 255], 3).unwrap();
+        assert_eq!(alpha.to_string(), "alpha of 3 palette entries");
+        assert!(Transparency::from_data(&[0, 1], 6).is_err());
+        assert!(Transparency::from_data(&[0; 257], 3).is_err());
+    }
+}
@@ -0,0 +1,3873 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+#[cfg(windows)]
+use std::io::IsTerminal;
+#[cfg(feature = "write")]
+use std::io::Read;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+#[cfg(feature = "write")]
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::SystemTime;
+#[cfg(feature = "write")]
+use std::time::UNIX_EPOCH;
+
+use anyhow::{ensure, Context, Error, Result};
+use tracing::{debug, info};
+
+#[cfg(feature = "write")]
+use crate::apng;
+#[cfg(feature = "self-update")]
+use crate::args::SelfUpdateArgs;
+#[cfg(feature = "serve")]
+use crate::args::ServeArgs;
+use crate::args::{
+    usage, BlameArgs, CarveArgs, Command, DecodeArgs, DiffArgs, DoctorArgs, DpiGetArgs, DumpArgs,
+    Expected, ExportArgs, GrepArgs, History, InfoArgs, JoinDecodeArgs, MetaArgs, PaletteArgs,
+    PrintArgs, ReceiptVerifyArgs, SameImageArgs, ScanArgs, SpreadDecodeArgs, StatsArgs, SurveyArgs,
+    TextListArgs, TrailingGetArgs, VerifyArgs, VerifyMessageArgs, VerifySealArgs,
+};
+#[cfg(feature = "write")]
+use crate::args::{
+    AnimateArgs, BatchArgs, BugReportArgs, BuildArgs, CatArgs, ChunkRef, Destination, DpiSetArgs,
+    EditArgs, EncodeArgs, InjectArgs, KeygenArgs, MergeArgs, Message, MinimizeArgs, MoveArgs,
+    OptimizeArgs, Part, Position, ProtectArgs, RemoveArgs, SealArgs, SignArgs, SplitEncodeArgs,
+    SpreadEncodeArgs, TextConvertArgs, TrailingRemoveArgs, TrailingSetArgs, UndoArgs,
+    UnprotectArgs, WatchArgs, WriteOptions,
+};
+use crate::blame;
+#[cfg(feature = "write")]
+use crate::bug_report;
+use crate::carve;
+#[cfg(feature = "write")]
+use crate::changelog;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+#[cfg(feature = "clipboard")]
+use crate::clipboard;
+use crate::color::{Chromaticities, Gamma, RenderingIntent, Transparency};
+use crate::date;
+use crate::diff;
+use crate::doctor;
+use crate::ecc;
+#[cfg(feature = "write")]
+use crate::editor;
+use crate::embed::{Carrier, ChunkEmbedder, Registry};
+#[cfg(feature = "age")]
+use crate::encryption;
+use crate::envelope::Envelope;
+#[cfg(feature = "write")]
+use crate::filter;
+#[cfg(feature = "write")]
+use crate::hex;
+use crate::hexdump;
+#[cfg(feature = "write")]
+use crate::history;
+use crate::hmac;
+#[cfg(feature = "write")]
+use crate::journal::{Change, Entry, Journal};
+#[cfg(feature = "write")]
+use crate::json::json;
+use crate::json::{self, Value};
+#[cfg(feature = "write")]
+use crate::lock;
+use crate::lsb::LsbEmbedder;
+#[cfg(feature = "write")]
+use crate::manifest;
+#[cfg(feature = "write")]
+use crate::merge;
+#[cfg(feature = "write")]
+use crate::minimize;
+use crate::obfuscate;
+#[cfg(feature = "write")]
+use crate::ordering;
+use crate::palette::Palette;
+use crate::parser;
+#[cfg(feature = "write")]
+use crate::parser::LimitExceeded;
+use crate::phys::PhysicalDimensions;
+use crate::pixels;
+use crate::png::Png;
+use crate::progress::{Progress, Unit};
+#[cfg(feature = "write")]
+use crate::protect;
+#[cfg(feature = "write")]
+use crate::provenance::Provenance;
+use crate::raw::{self, ChunkHeader};
+use crate::receipt::{self, Receipt};
+#[cfg(feature = "write")]
+use crate::receipt::{ChunkChange, FileRecord};
+use crate::registry;
+#[cfg(feature = "yara")]
+use crate::rules::Rules;
+#[cfg(feature = "write")]
+use crate::scan::Finding;
+use crate::scan::{self, Severity};
+#[cfg(feature = "write")]
+use crate::scanner::{self, Verdict};
+use crate::seal::{self, Sealed};
+use crate::search::{self, Pattern};
+#[cfg(feature = "serve")]
+use crate::serve;
+use crate::sharing::{self, Share};
+use crate::signature;
+#[cfg(feature = "write")]
+use crate::signature::Scope;
+use crate::spread;
+use crate::stats::{self, Stats};
+#[cfg(feature = "write")]
+use crate::stealth;
+use crate::storage::Storage;
+use crate::structure;
+use crate::survey::{self, Survey};
+use crate::table::{self, Color, Table};
+#[cfg(feature = "write")]
+use crate::tar;
+use crate::text;
+use crate::text::TextEntry;
+#[cfg(feature = "write")]
+use crate::transaction::Transaction;
+#[cfg(feature = "write")]
+use crate::trash;
+#[cfg(feature = "self-update")]
+use crate::update;
+#[cfg(feature = "write")]
+use crate::watch;
+#[cfg(feature = "write")]
+use crate::wizard;
+
+/// `print --decompress` gives up on chunks that inflate beyond this
+const MAX_DECOMPRESSED_SIZE: usize = 1024 * 1024;
+/// `dump` and `grep` with `--inflate` give up on streams that inflate beyond this
+const MAX_INFLATED_SIZE: usize = 64 * 1024 * 1024;
+/// Amount of decompressed data shown by `print --decompress`
+const PREVIEW_SIZE: usize = 256;
+/// Characters of text shown by `text list`, longer ones are cut
+const TEXT_PREVIEW_CHARS: usize = 60;
+/// Bytes of chunk data shown in PREVIEW column of `print`
+const INLINE_PREVIEW_SIZE: usize = 24;
+
+/// Reject structurally invalid images, set by `--strict-png`
+static STRICT_PNG: AtomicBool = AtomicBool::new(false);
+
+pub fn set_strict_png(strict: bool) {
+    STRICT_PNG.store(strict, Ordering::Relaxed);
+}
+
+/// Error that makes pngme exit with specific code instead of `OTHER`
+#[derive(Debug)]
+pub struct Failure {
+    pub code: u8,
+    message: String,
+}
+
+impl Failure {
+    /// Requested chunk, keyword or share isn't in the image
+    pub const NOT_FOUND: u8 = 1;
+    /// Input isn't a valid PNG
+    pub const PARSE_ERROR: u8 = 2;
+    /// Image was read, but check asked for failed: mismatch, bad signature or MAC
+    pub const VALIDATION: u8 = 3;
+    /// Command line couldn't be parsed
+    pub const USAGE: u8 = 4;
+    /// Anything else, like I/O errors
+    pub const OTHER: u8 = 5;
+
+    pub fn new(code: u8, message: String) -> Failure {
+        Failure { code, message }
+    }
+
+    /// Keeps message of `err` with its causes, giving it the code
+    pub fn wrap(code: u8, err: Error) -> Failure {
+        Failure::new(code, format!("{:#}", err))
+    }
+}
+
+/// Writes bytes to stdout as they are. Rust writes to the raw handle, so line
+/// endings are not translated on Windows either, but Windows console accepts
+/// only UTF-8 and fails on anything else, so binary is refused there up front
+fn write_raw(data: &[u8]) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    #[cfg(windows)]
+    ensure!(
+        !stdout.is_terminal() || std::str::from_utf8(data).is_ok(),
+        "Data is binary and console can't show it, redirect output to a file or pipe"
+    );
+    stdout.write_all(data)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+fn not_found(message: String) -> Error {
+    Failure::new(Failure::NOT_FOUND, message).into()
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// Runs command over images and payloads kept in `storage`.
+/// Key files, journal and trash always live on local file system
+pub fn run(command: Command, storage: &dyn Storage) -> Result<()> {
+    match command {
+        Command::Help => Ok(writeln!(io::stdout(), "{}", usage())?),
+        #[cfg(feature = "write")]
+        Command::Encode(args) => encode(args, storage),
+        #[cfg(feature = "write")]
+        Command::Batch(args) => batch(args, storage),
+        Command::Decode(args) => decode(args, storage),
+        Command::VerifyMessage(args) => verify_message(args, storage),
+        #[cfg(feature = "write")]
+        Command::Edit(args) => edit(args, storage),
+        #[cfg(feature = "write")]
+        Command::Remove(args) => remove(args, storage),
+        #[cfg(feature = "write")]
+        Command::Move(args) => move_chunk(args, storage),
+        Command::Print(args) => print(args, storage),
+        #[cfg(feature = "write")]
+        Command::Inject(args) => inject(args, storage),
+        #[cfg(feature = "write")]
+        Command::Wizard => wizard(storage),
+        #[cfg(feature = "write")]
+        Command::InitType => init_type(),
+        Command::Dump(args) => dump(args, storage),
+        Command::Export(args) => export(args, storage),
+        #[cfg(feature = "write")]
+        Command::Build(args) => build(args, storage),
+        #[cfg(feature = "write")]
+        Command::Cat(args) => cat(args, storage),
+        #[cfg(feature = "write")]
+        Command::Animate(args) => animate(args, storage),
+        #[cfg(feature = "write")]
+        Command::Optimize(args) => optimize(args, storage),
+        #[cfg(feature = "write")]
+        Command::Undo(args) => undo(args, storage),
+        Command::Grep(args) => grep(args, storage),
+        Command::Diff(args) => diff(args, storage),
+        Command::SameImage(args) => same_image(args, storage),
+        Command::Blame(args) => blame(args, storage),
+        #[cfg(feature = "write")]
+        Command::Watch(args) => watch(args, storage),
+        Command::Meta(args) => meta(args, storage),
+        #[cfg(feature = "write")]
+        Command::TextConvert(args) => text_convert(args, storage),
+        Command::TextList(args) => text_list(args, storage),
+        Command::Info(args) => info(args, storage),
+        Command::Palette(args) => palette(args, storage),
+        Command::Stats(args) => stats(args, storage),
+        Command::Survey(args) => survey(args, storage),
+        Command::Carve(args) => carve(args, storage),
+        Command::DpiGet(args) => dpi_get(args, storage),
+        #[cfg(feature = "write")]
+        Command::DpiSet(args) => dpi_set(args, storage),
+        Command::TrailingGet(args) => trailing_get(args, storage),
+        #[cfg(feature = "write")]
+        Command::TrailingSet(args) => trailing_set(args, storage),
+        #[cfg(feature = "write")]
+        Command::TrailingRemove(args) => trailing_remove(args, storage),
+        #[cfg(feature = "write")]
+        Command::BugReport(args) => bug_report(args, storage),
+        #[cfg(feature = "write")]
+        Command::Minimize(args) => minimize(args, storage),
+        #[cfg(feature = "write")]
+        Command::Merge(args) => merge(args, storage),
+        Command::Scan(args) => scan(args, storage),
+        Command::Doctor(args) => doctor(args, storage),
+        #[cfg(feature = "write")]
+        Command::Keygen(args) => keygen(args),
+        #[cfg(feature = "write")]
+        Command::Sign(args) => sign(args, storage),
+        Command::Verify(args) => verify(args, storage),
+        #[cfg(feature = "write")]
+        Command::Seal(args) => seal(args, storage),
+        Command::VerifySeal(args) => verify_seal(args, storage),
+        Command::ReceiptVerify(args) => receipt_verify(args, storage),
+        #[cfg(feature = "write")]
+        Command::SplitEncode(args) => split_encode(args, storage),
+        Command::JoinDecode(args) => join_decode(args, storage),
+        #[cfg(feature = "write")]
+        Command::SpreadEncode(args) => spread_encode(args, storage),
+        Command::SpreadDecode(args) => spread_decode(args, storage),
+        #[cfg(feature = "write")]
+        Command::Protect(args) => protect(args, storage),
+        #[cfg(feature = "write")]
+        Command::Unprotect(args) => unprotect(args, storage),
+        #[cfg(feature = "self-update")]
+        Command::SelfUpdate(args) => self_update(args),
+        #[cfg(feature = "serve")]
+        Command::Serve(args) => serve(args),
+    }
+}
+
+#[cfg(feature = "write")]
+pub fn encode(args: EncodeArgs, storage: &dyn Storage) -> Result<()> {
+    encode_with(args, storage, embedders())
+}
+
+/// Encodes with backends and codecs of `registry`, message goes through
+/// its codecs whether it is hidden by `--mode` backend or in a chunk
+#[cfg(feature = "write")]
+pub fn encode_with(args: EncodeArgs, storage: &dyn Storage, mut registry: Registry) -> Result<()> {
+    let mut png = read_png(storage, &args.file_path)?;
+    let before = png.byte_size();
+    let mut data = read_message(storage, args.message)?;
+    if let Some(encoding) = args.input_encoding {
+        let text = std::str::from_utf8(&data).context("Encoded message is not text")?;
+        data = encoding
+            .decode(text)
+            .context("Failed to decode message from --input-encoding")?;
+    }
+    if let Some(expires) = args.expires {
+        let envelope = Envelope {
+            expires: Some(expires),
+            content: data,
+            ..Default::default()
+        };
+        data = envelope.to_bytes()?;
+    }
+    if let Some(command) = &args.filter {
+        data = filter::pipe(command, &data)?;
+    }
+    #[cfg(feature = "age")]
+    if !args.recipients.is_empty() {
+        data = encryption::encrypt(&data, &args.recipients)?;
+    }
+    if let Some(key) = &args.obfuscate {
+        obfuscate::xor(key.as_bytes(), &mut data);
+    }
+    if let Some(key) = &args.hmac_key {
+        data = hmac::sign(key.as_bytes(), &data);
+    }
+    if let Some(ratio) = args.ecc {
+        data = ecc::wrap(&data, ratio)?;
+    }
+    let mut random_type = None;
+    let mut carrier = png.to_carrier();
+    if let Some(mode) = &args.mode {
+        registry
+            .get(mode)
+            .map_err(|err| Failure::wrap(Failure::VALIDATION, err))?;
+        registry.embed(mode, &mut carrier, &data)?;
+    } else {
+        let chunk_type = match args.chunk_type {
+            Some(chunk_type) => chunk_type,
+            None => {
+                let chunk_type = stealth::random_type()?;
+                random_type = Some(chunk_type);
+                chunk_type
+            }
+        };
+        check_collision(&chunk_type, args.write.force)?;
+        registry.register(Box::new(ChunkEmbedder { chunk_type }));
+        registry.embed("chunk", &mut carrier, &data)?;
+        if let Some(names) = args.provenance {
+            let provenance = Provenance::collect(&chunk_type, &names).to_chunk();
+            carrier.chunks.insert(carrier.end_index(), provenance);
+        }
+    }
+    let changes = Change::between(png.chunks(), &carrier.chunks);
+    png = Png::from_carrier(carrier);
+    if let Some(growth) = args.max_growth {
+        let after = png.byte_size();
+        let sizes = format!(
+            "{} -> {} bytes ({:+})",
+            before,
+            after,
+            after as i64 - before as i64
+        );
+        let limit = growth.limit(before);
+        if after > limit {
+            let message = format!(
+                "Encoded image would exceed --max-growth: {}, limit is {} bytes",
+                sizes, limit
+            );
+            return Err(Failure::new(Failure::VALIDATION, message).into());
+        }
+        outln!("Size: {}", sizes);
+    }
+
+    let file = Modified {
+        input: &args.file_path,
+        output: args.output.as_ref().unwrap_or(&args.file_path),
+        png: &png,
+        changes,
+    };
+    save_pngs(storage, "encode", vec![file], &args.write)?;
+    if let Some(chunk_type) = random_type {
+        outln!("Encoded into random chunk type {}", chunk_type);
+    }
+    Ok(())
+}
+
+/// Runs every encoding from manifest, failed entries don't stop the rest
+#[cfg(feature = "write")]
+pub fn batch(args: BatchArgs, storage: &dyn Storage) -> Result<()> {
+    let text =
+        String::from_utf8(storage.read(&args.manifest)?).context("Manifest is not valid UTF-8")?;
+    let entries = manifest::parse(&text)
+        .with_context(|| format!("Invalid manifest {}", args.manifest.display()))?;
+    let base = args.manifest.parent().unwrap_or(Path::new(""));
+
+    let total = entries.len();
+    let mut failed = 0;
+    for entry in entries {
+        let input = base.join(&entry.input);
+        let output = entry.output.map(|output| base.join(output));
+        let message = match entry.message {
+            Message::File(path) => Message::File(base.join(path)),
+            message => message,
+        };
+        let shown = output.as_ref().unwrap_or(&input).display().to_string();
+        let encoded = encode(
+            EncodeArgs {
+                file_path: input.clone(),
+                chunk_type: Some(entry.chunk_type),
+                message,
+                input_encoding: None,
+                output,
+                hmac_key: entry.hmac_key,
+                ecc: entry.ecc,
+                obfuscate: entry.obfuscate,
+                expires: None,
+                filter: None,
+                #[cfg(feature = "age")]
+                recipients: vec![],
+                provenance: None,
+                max_growth: None,
+                mode: None,
+                write: args.write.clone(),
+            },
+            storage,
+        );
+        match encoded {
+            Ok(()) => outln!("ok      {} -> {}", input.display(), shown),
+            Err(err) => {
+                failed += 1;
+                outln!("FAILED  {}: {:#}", input.display(), err);
+            }
+        }
+    }
+
+    outln!(
+        "{} entries: {} succeeded, {} failed",
+        total,
+        total - failed,
+        failed
+    );
+    ensure!(failed == 0, "{} of {} entries failed", failed, total);
+    Ok(())
+}
+
+pub fn decode(args: DecodeArgs, storage: &dyn Storage) -> Result<()> {
+    decode_with(args, storage, embedders())
+}
+
+/// Decodes with backends and codecs of `registry`, reversing [`encode_with`]
+pub fn decode_with(args: DecodeArgs, storage: &dyn Storage, mut registry: Registry) -> Result<()> {
+    if let Some(mode) = &args.mode {
+        registry
+            .get(mode)
+            .map_err(|err| Failure::wrap(Failure::VALIDATION, err))?;
+        let png = read_png(storage, &args.file_path)?;
+        let payload = registry
+            .extract(mode, &png.to_carrier())?
+            .ok_or_else(|| not_found(format!("No payload found in {} mode", mode)))?;
+        return decode_payload(&args, mode, &payload, false, storage);
+    }
+    #[cfg(feature = "write")]
+    if args.stream {
+        return decode_stream(&args, storage);
+    }
+    let carrier = if args.file_path == Path::new("-") {
+        // stops reading as soon as every chunk arrives
+        Carrier {
+            chunks: parser::find_chunks(io::stdin().lock(), &args.chunk_types)?,
+            trailing: vec![],
+        }
+    } else {
+        read_png(storage, &args.file_path)?.to_carrier()
+    };
+
+    // several types are decoded independently, each payload labeled by its type
+    let labeled = args.chunk_types.len() > 1;
+    #[cfg(feature = "write")]
+    ensure!(
+        !labeled || args.output.as_ref().is_none_or(|path| path.is_dir()),
+        "--output must be a directory when decoding several chunk types"
+    );
+    let mut failed = 0;
+    for chunk_type in &args.chunk_types {
+        registry.register(Box::new(ChunkEmbedder {
+            chunk_type: *chunk_type,
+        }));
+        let decoded = registry
+            .extract("chunk", &carrier)
+            .and_then(|payload| {
+                payload.ok_or_else(|| {
+                    not_found(format!("No {} chunk found", registry::describe(chunk_type)))
+                })
+            })
+            .and_then(|payload| {
+                decode_payload(&args, &chunk_type.to_string(), &payload, labeled, storage)
+            });
+        if let Err(err) = decoded {
+            if !labeled {
+                return Err(err);
+            }
+            eprintln!("{}: {:#}", chunk_type, err);
+            failed += 1;
+        }
+    }
+    ensure!(
+        failed == 0,
+        "{} of {} chunk types could not be decoded",
+        failed,
+        args.chunk_types.len()
+    );
+    Ok(())
+}
+
+/// Decodes payload extracted from a single chunk (or by `--mode` backend, `name`
+/// being the mode then) and prints or saves it as `decode` arguments tell
+#[cfg_attr(not(feature = "write"), allow(unused_variables))]
+fn decode_payload(
+    args: &DecodeArgs,
+    name: &str,
+    payload: &[u8],
+    labeled: bool,
+    storage: &dyn Storage,
+) -> Result<()> {
+    if let Some(max_size) = args.max_size.filter(|&max| payload.len() as u64 > max) {
+        let message = format!(
+            "Payload of {} bytes exceeds --max-size of {} bytes",
+            payload.len(),
+            max_size
+        );
+        return Err(Failure::new(Failure::VALIDATION, message).into());
+    }
+    let data = unwrap_payload(payload, args.hmac_key.as_deref(), args.obfuscate.as_deref())?;
+    #[cfg(feature = "age")]
+    let data = match &args.identity {
+        Some(path) => encryption::decrypt(&data, path)?,
+        None => {
+            ensure!(
+                !data.starts_with(encryption::HEADER),
+                "Message is encrypted with age, decrypt it with --identity <KEY_FILE>"
+            );
+            data
+        }
+    };
+    #[cfg(feature = "write")]
+    let data = match &args.filter {
+        Some(command) => filter::pipe(command, &data)?,
+        None => data,
+    };
+    let envelope = Envelope::from_bytes(&data)?;
+    if let Some(envelope) = envelope
+        .as_ref()
+        .filter(|e| e.is_expired(SystemTime::now()))
+    {
+        let expires = date::format_timestamp(envelope.expires.unwrap());
+        let expired = format!("Message expired on {} UTC", expires.replace('T', " "));
+        if args.strict {
+            return Err(Failure::new(Failure::VALIDATION, expired).into());
+        }
+        eprintln!("Warning: {}", expired);
+    }
+
+    #[cfg(feature = "write")]
+    let dir = match &args.output {
+        Some(path) if path.is_dir() => Some(path),
+        _ => args.output_dir.as_ref(),
+    };
+    #[cfg(feature = "write")]
+    if let Some(mut path) = dir.map(|dir| dir.to_path_buf()).or(args.output.clone()) {
+        let content = envelope.as_ref().map_or(&data, |e| &e.content);
+        let envelope_name = envelope.as_ref().and_then(|e| e.name.as_ref());
+        if let Some(dir) = dir {
+            path = dir.join(envelope_name.cloned().unwrap_or(name.to_string()));
+        }
+        // pick extension by content when neither user nor embedded name gave one
+        if path.extension().is_none() {
+            match scan::sniff(content) {
+                Some((format, extension)) => {
+                    path.set_extension(extension);
+                    eprintln!("Detected {} data", format);
+                }
+                None if dir.is_some() && envelope_name.is_none() => {
+                    path.set_extension("bin");
+                }
+                None => {}
+            }
+        }
+        ensure!(!storage.exists(&path), "{} already exists", path.display());
+        storage.write(&path, content)?;
+        if let Some(mtime) = envelope.and_then(|e| e.mtime) {
+            storage.set_modified(&path, mtime)?;
+        }
+        outln!("Saved {}", path.display());
+        return Ok(());
+    }
+
+    let content = match &envelope {
+        Some(envelope) => {
+            if let Some(name) = &envelope.name {
+                eprintln!("Embedded file {}", name);
+            }
+            &envelope.content
+        }
+        None => &data,
+    };
+    #[cfg(feature = "clipboard")]
+    if args.copy {
+        let text = std::str::from_utf8(content)
+            .context("Message is not valid UTF-8 text, it can't be copied")?;
+        clipboard::copy(text)?;
+        eprintln!("Message copied to clipboard");
+        return Ok(());
+    }
+    if args.raw {
+        write_raw(content)?;
+    } else {
+        let text = match args.encoding {
+            Some(encoding) => encoding.encode(content),
+            None => String::from_utf8_lossy(content).into_owned(),
+        };
+        match labeled {
+            true => outln!("{}: {}", name, text),
+            false => outln!("{}", text),
+        }
+    }
+    Ok(())
+}
+
+/// `decode --stream`: copies data of chunks from file or stdin to output file
+/// while reading, holding only small buffer in memory
+#[cfg(feature = "write")]
+fn decode_stream(args: &DecodeArgs, storage: &dyn Storage) -> Result<()> {
+    let chunk_type = &args.chunk_types[0];
+    let mut path = args.output.clone().context("--stream needs --output")?;
+    if path.is_dir() {
+        path = path.join(chunk_type.to_string());
+    }
+    let input: Box<dyn Read> = if args.file_path == Path::new("-") {
+        Box::new(io::stdin().lock())
+    } else {
+        ensure!(
+            storage.is_local(&args.file_path),
+            "--stream reads only local files or standard input"
+        );
+        let file = fs::File::open(&args.file_path)
+            .with_context(|| format!("Failed to open {}", args.file_path.display()))?;
+        Box::new(io::BufReader::new(file))
+    };
+    let output = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+
+    let copied =
+        parser::stream_payload(input, chunk_type, io::BufWriter::new(output), args.max_size);
+    let (chunks, size) = match copied {
+        Ok((0, _)) => {
+            let _ = fs::remove_file(&path);
+            let name = registry::describe(chunk_type);
+            return Err(not_found(format!("No {} chunk found", name)));
+        }
+        Ok(copied) => copied,
+        Err(err) => {
+            let _ = fs::remove_file(&path);
+            if err.is::<LimitExceeded>() {
+                return Err(Failure::wrap(Failure::VALIDATION, err).into());
+            }
+            return Err(err);
+        }
+    };
+    outln!(
+        "Saved {} bytes from {} chunk(s) to {}",
+        size,
+        chunks,
+        path.display()
+    );
+    Ok(())
+}
+
+/// Undoes error correction, authentication and obfuscation applied by `encode`
+fn unwrap_payload(
+    payload: &[u8],
+    hmac_key: Option<&str>,
+    obfuscate: Option<&str>,
+) -> Result<Vec<u8>> {
+    let mut data = payload.to_vec();
+    if let Some((payload, corrected)) = ecc::unwrap(&data)? {
+        if corrected > 0 {
+            eprintln!("Corrected {} damaged byte(s)", corrected);
+        }
+        data = payload;
+    }
+    if let Some(key) = hmac_key {
+        data = hmac::verify(key.as_bytes(), &data)
+            .map_err(|err| Failure::wrap(Failure::VALIDATION, err))?
+            .to_vec();
+    }
+    if let Some(key) = obfuscate {
+        obfuscate::xor(key.as_bytes(), &mut data);
+    }
+    Ok(data)
+}
+
+pub fn verify_message(args: VerifyMessageArgs, storage: &dyn Storage) -> Result<()> {
+    let png = read_png(storage, &args.file_path)?;
+    let name = registry::describe(&args.chunk_type);
+    let chunk = png
+        .chunk_by_type(&args.chunk_type.to_string())
+        .ok_or_else(|| not_found(format!("No {} chunk found", name)))?;
+
+    let data = unwrap_payload(
+        chunk.data(),
+        args.hmac_key.as_deref(),
+        args.obfuscate.as_deref(),
+    )?;
+    let message = match Envelope::from_bytes(&data)? {
+        Some(envelope) => envelope.content,
+        None => data,
+    };
+    let expected = match &args.expected {
+        Expected::Text(text) => text.as_bytes().to_vec(),
+        Expected::File(path) => {
+            fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?
+        }
+    };
+    if message != expected {
+        let message = format!("Message in {} chunk doesn't match", name);
+        return Err(Failure::new(Failure::VALIDATION, message).into());
+    }
+    outln!("Message in {} chunk matches", name);
+    Ok(())
+}
+
+/// Lets user change message in editor, then wraps it again as it was wrapped before
+#[cfg(feature = "write")]
+pub fn edit(args: EditArgs, storage: &dyn Storage) -> Result<()> {
+    let mut png = read_png(storage, &args.file_path)?;
+    let name = registry::describe(&args.chunk_type);
+    let index = png
+        .chunks()
+        .iter()
+        .position(|c| c.chunk_type() == &args.chunk_type)
+        .ok_or_else(|| not_found(format!("No {} chunk found", name)))?;
+    let chunk = &png.chunks()[index];
+    let ecc = ecc::ratio(chunk.data());
+    let data = unwrap_payload(
+        chunk.data(),
+        args.hmac_key.as_deref(),
+        args.obfuscate.as_deref(),
+    )?;
+    #[cfg(feature = "age")]
+    let data = match &args.identity {
+        Some(path) => encryption::decrypt(&data, path)?,
+        None => {
+            ensure!(
+                !data.starts_with(encryption::HEADER),
+                "Message is encrypted with age, edit it with --identity <KEY_FILE> --recipient <KEY>"
+            );
+            data
+        }
+    };
+    let data = match &args.decode_filter {
+        Some(command) => filter::pipe(command, &data)?,
+        None => data,
+    };
+    let mut envelope = Envelope::from_bytes(&data)?;
+    let content = envelope
+        .as_mut()
+        .map_or(data, |e| std::mem::take(&mut e.content));
+    let extension = envelope
+        .as_ref()
+        .and_then(|e| Path::new(e.name.as_ref()?).extension()?.to_str())
+        .unwrap_or("txt");
+
+    let edited = editor::edit(&content, extension)?;
+    if edited == content {
+        outln!("Message unchanged");
+        return Ok(());
+    }
+
+    let mut data = match envelope {
+        Some(mut envelope) => {
+            envelope.content = edited;
+            envelope.to_bytes()?
+        }
+        None => edited,
+    };
+    if let Some(command) = &args.encode_filter {
+        data = filter::pipe(command, &data)?;
+    }
+    #[cfg(feature = "age")]
+    if !args.recipients.is_empty() {
+        data = encryption::encrypt(&data, &args.recipients)?;
+    }
+    if let Some(key) = &args.obfuscate {
+        obfuscate::xor(key.as_bytes(), &mut data);
+    }
+    if let Some(key) = &args.hmac_key {
+        data = hmac::sign(key.as_bytes(), &data);
+    }
+    if let Some(ratio) = ecc {
+        data = ecc::wrap(&data, ratio)?;
+    }
+
+    let chunk = Chunk::new(args.chunk_type, data);
+    let old = png.remove_chunk_at(index);
+    let changes = vec![
+        Change::Removed {
+            index,
+            chunk: old.as_bytes(),
+        },
+        Change::Added {
+            index,
+            chunk: chunk.as_bytes(),
+        },
+    ];
+    png.insert_chunk(index, chunk);
+    save_png(storage, "edit", &args.file_path, &png, &args.write, changes)?;
+    if !args.write.dry_run {
+        outln!("Saved edited message in {} chunk", name);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "write")]
+pub fn remove(args: RemoveArgs, storage: &dyn Storage) -> Result<()> {
+    let mut png = read_png(storage, &args.file_path)?;
+    let index = png
+        .chunks()
+        .iter()
+        .position(|c| c.chunk_type() == &args.chunk_type)
+        .ok_or_else(|| {
+            not_found(format!(
+                "No {} chunk found",
+                registry::describe(&args.chunk_type)
+            ))
+        })?;
+    let chunk = png.remove_chunk_at(index);
+
+    // saved before the image is modified, so failure keeps the only copy in place
+    if let (Some(path), false) = (&args.dump, args.write.dry_run) {
+        ensure!(!storage.exists(path), "{} already exists", path.display());
+        let bytes = if args.raw {
+            chunk.as_bytes()
+        } else {
+            chunk.data().to_vec()
+        };
+        storage.write(path, &bytes)?;
+        outln!("Saved {} chunk to {}", chunk.chunk_type(), path.display());
+    }
+    let change = Change::Removed {
+        index,
+        chunk: chunk.as_bytes(),
+    };
+    save_png(
+        storage,
+        "remove",
+        &args.file_path,
+        &png,
+        &args.write,
+        vec![change],
+    )?;
+    if !args.write.dry_run {
+        outln!("Removed {} chunk", registry::describe(&args.chunk_type));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "write")]
+pub fn move_chunk(args: MoveArgs, storage: &dyn Storage) -> Result<()> {
+    let mut png = read_png(storage, &args.file_path)?;
+    let position = |png: &Png, chunk_type: &ChunkType, first: bool| {
+        let mut matching = png
+            .chunks()
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.chunk_type() == chunk_type)
+            .map(|(i, _)| i);
+        let found = if first {
+            matching.next()
+        } else {
+            matching.next_back()
+        };
+        found.ok_or_else(|| not_found(format!("No {} chunk found", registry::describe(chunk_type))))
+    };
+
+    let from = match &args.chunk {
+        ChunkRef::Index(index) => {
+            ensure!(
+                *index < png.chunks().len(),
+                "Chunk index {} is out of range, image has {} chunks",
+                index,
+                png.chunks().len()
+            );
+            *index
+        }
+        ChunkRef::Type(chunk_type) => {
+            let index = position(&png, chunk_type, true)?;
+            ensure!(
+                index == position(&png, chunk_type, false)?,
+                "There are several {} chunks, pick one by index",
+                chunk_type
+            );
+            index
+        }
+    };
+    let chunk = png.remove_chunk_at(from);
+    let to = match &args.to {
+        Destination::Index(index) => *index,
+        Destination::Before(chunk_type) => position(&png, chunk_type, true)?,
+        Destination::After(chunk_type) => position(&png, chunk_type, false)? + 1,
+    };
+    ensure!(
+        to <= png.chunks().len(),
+        "Chunk index {} is out of range, image has {} chunks",
+        to,
+        png.chunks().len() + 1
+    );
+    ensure!(to != from, "Chunk is already there");
+
+    let changes = vec![
+        Change::Removed {
+            index: from,
+            chunk: chunk.as_bytes(),
+        },
+        Change::Added {
+            index: to,
+            chunk: chunk.as_bytes(),
+        },
+    ];
+    let name = chunk.chunk_type().to_string();
+    png.insert_chunk(to, chunk);
+
+    save_png(storage, "move", &args.file_path, &png, &args.write, changes)?;
+    if !args.write.dry_run {
+        outln!("Moved {} from index {} to {}", name, from, to);
+    }
+    Ok(())
+}
+
+pub fn export(args: ExportArgs, storage: &dyn Storage) -> Result<()> {
+    let png = read_png(storage, &args.file_path)?;
+    let text = json::pretty(&structure::to_json(&png)) + "\n";
+    #[cfg(feature = "write")]
+    if let Some(path) = &args.output {
+        storage.write(path, text.as_bytes())?;
+        outln!(
+            "Exported {} chunks to {}",
+            png.chunks().len(),
+            path.display()
+        );
+        return Ok(());
+    }
+    out!("{}", text);
+    Ok(())
+}
+
+#[cfg(feature = "write")]
+pub fn build(args: BuildArgs, storage: &dyn Storage) -> Result<()> {
+    let text = String::from_utf8(storage.read(&args.structure)?)
+        .context("Structure is not valid UTF-8")?;
+    let png = json::parse(&text)
+        .and_then(|value| structure::from_json(&value))
+        .with_context(|| format!("Invalid structure {}", args.structure.display()))?;
+    if !args.force {
+        ordering::check(png.chunks())
+            .context("Image would break PNG structure, use --force to build it anyway")?;
+    }
+    storage.write(&args.output, &png.as_bytes())?;
+    outln!(
+        "Built {} with {} chunks",
+        args.output.display(),
+        png.chunks().len()
+    );
+    Ok(())
+}
+
+/// Assembles image from chunk records and payload files, all CRCs are computed anew
+#[cfg(feature = "write")]
+pub fn cat(args: CatArgs, storage: &dyn Storage) -> Result<()> {
+    let mut chunks = vec![];
+    for part in &args.parts {
+        match part {
+            Part::Records(path) => {
+                let records = raw::records(&storage.read(path)?)
+                    .with_context(|| format!("Invalid chunk records in {}", path.display()))?;
+                chunks.extend(records);
+            }
+            Part::Payload(chunk_type, path) => {
+                let data = storage.read(path)?;
+                chunks.push(Chunk::new(ChunkType::try_from(chunk_type.bytes())?, data));
+            }
+        }
+    }
+    if !args.force {
+        ordering::check(&chunks)
+            .context("Image would break PNG structure, use --force to write it anyway")?;
+    }
+    let png = Png::from_chunks(chunks);
+    storage.write(&args.output, &png.as_bytes())?;
+    outln!(
+        "Wrote {} with {} chunks",
+        args.output.display(),
+        png.chunks().len()
+    );
+    Ok(())
+}
+
+#[cfg(feature = "write")]
+pub fn animate(args: AnimateArgs, storage: &dyn Storage) -> Result<()> {
+    let frames = args
+        .frames
+        .iter()
+        .map(|path| read_png(storage, path))
+        .collect::<Result<Vec<_>>>()?;
+    let count = frames.len();
+    let png = apng::assemble(frames, args.delay, args.plays)?;
+    storage.write(&args.output, &png.as_bytes())?;
+    outln!("Wrote {} with {} frames", args.output.display(), count);
+    Ok(())
+}
+
+#[cfg(feature = "write")]
+pub fn optimize(args: OptimizeArgs, storage: &dyn Storage) -> Result<()> {
+    ensure!(args.recompress, "Nothing to optimize, use --recompress");
+    let mut png = read_png(storage, &args.file_path)?;
+    let before = png.byte_size();
+    let original = pixels::image_data(&png);
+    ensure!(!original.is_empty(), "Image has no IDAT chunks");
+    let recompressed = pixels::recompress(&png)?;
+    let data = if recompressed.len() < original.len() {
+        recompressed
+    } else {
+        info!(
+            "Recompressed image data is {} bytes, keeping original {} bytes",
+            recompressed.len(),
+            original.len()
+        );
+        original
+    };
+
+    let mut changes = vec![];
+    let indices: Vec<_> = (0..png.chunks().len())
+        .filter(|&i| &png.chunks()[i].chunk_type().bytes() == b"IDAT")
+        .collect();
+    for &index in indices.iter().rev() {
+        let chunk = png.remove_chunk_at(index);
+        changes.push(Change::Removed {
+            index,
+            chunk: chunk.as_bytes(),
+        });
+    }
+    let chunk = Chunk::new(ChunkType::try_from(*b"IDAT")?, data);
+    changes.push(Change::Added {
+        index: indices[0],
+        chunk: chunk.as_bytes(),
+    });
+    png.insert_chunk(indices[0], chunk);
+
+    let after = png.byte_size();
+    if after == before {
+        outln!("{} is already optimized", args.file_path.display());
+        return Ok(());
+    }
+    save_png(
+        storage,
+        "optimize",
+        &args.file_path,
+        &png,
+        &args.write,
+        changes,
+    )?;
+    if !args.write.dry_run {
+        outln!(
+            "Optimized {}: {} -> {} bytes ({:+})",
+            args.file_path.display(),
+            before,
+            after,
+            after as i64 - before as i64
+        );
+    }
+    Ok(())
+}
+
+pub fn print(args: PrintArgs, storage: &dyn Storage) -> Result<()> {
+    // chunks are listed without full parsing, so corrupted ones are shown too
+    let bytes = storage.read(&args.file_path)?;
+    check_header(&bytes, &args.file_path)?;
+    if args.hash {
+        outln!("sha256 {}", receipt::digest(&bytes));
+    }
+
+    let mut headers = vec!["#", "OFFSET", "TYPE", "NAME", "SIZE", "CRC", "NOTES"];
+    if !args.full {
+        headers.push("PREVIEW");
+    }
+    if args.hash {
+        headers.push("SHA-256");
+    }
+    let mut table = Table::new(headers)
+        .align_right(0)
+        .align_right(1)
+        .align_right(4);
+    let records = raw::raw_chunks(&bytes[8..]);
+    // same as when parsing, anything broken after IEND is data appended to the image
+    let end = raw::end_index(&records);
+    let count = (0..records.len())
+        .position(|i| end.is_some_and(|end| i > end) && records[i].problem().is_some())
+        .unwrap_or(records.len());
+    let records = &records[..count];
+    let trailing = bytes.len() - 8 - records.iter().map(|c| c.bytes.len()).sum::<usize>();
+
+    let mut previews = vec![];
+    let mut payloads = vec![];
+    let mut broken = 0;
+    for (i, raw) in records.iter().enumerate() {
+        // corrupted chunks are counted even when hidden, so filtering doesn't mask damage
+        if !args.selection.matches(raw.chunk_type) {
+            broken += raw.problem().is_some() as usize;
+            continue;
+        }
+        let chunk_type = ChunkType::try_from(raw.chunk_type).ok();
+        let name = chunk_type.as_ref().and_then(registry::name_of);
+        let (status, notes, color) = match raw.problem() {
+            Some(problem) => {
+                broken += 1;
+                let status = if raw.is_truncated() {
+                    "truncated"
+                } else {
+                    "bad"
+                };
+                (status, problem, Color::Red)
+            }
+            None => {
+                let chunk = Chunk::try_from(raw.bytes)?;
+                let stream = scan::compressed_stream(&chunk);
+                let color = match (chunk.chunk_type().is_critical(), name) {
+                    (_, None) => Color::Yellow,
+                    (true, _) => Color::Blue,
+                    (false, _) => Color::Green,
+                };
+                let notes = stream.map(|(format, _)| format.to_string());
+                if let (true, Some((format, stream))) = (args.decompress, stream) {
+                    previews.push((i, format, stream.to_vec()));
+                }
+                ("ok", notes.unwrap_or_default(), color)
+            }
+        };
+
+        let mut cells = vec![
+            i.to_string(),
+            raw.offset.to_string(),
+            String::from_utf8_lossy(&raw.chunk_type).into_owned(),
+            name.unwrap_or("-").to_string(),
+            raw.data().len().to_string(),
+            status.to_string(),
+            notes,
+        ];
+        if args.full {
+            payloads.push((i, raw.data()));
+        } else {
+            cells.push(hexdump::inline(raw.data(), INLINE_PREVIEW_SIZE));
+        }
+        if args.hash {
+            cells.push(receipt::digest(raw.data()));
+        }
+        table.row(cells, Some(color));
+    }
+    out!("{}", table.render(table::use_color(args.no_color)));
+    if trailing > 0 {
+        match end {
+            Some(_) => outln!("{} bytes after IEND", trailing),
+            None => outln!("{} stray bytes at the end of file", trailing),
+        }
+    }
+
+    for (i, data) in payloads {
+        outln!("#{}: {} bytes", i, data.len());
+        print_data(data);
+    }
+    for (i, format, stream) in previews {
+        outln!("#{}:", i);
+        match scan::decompress(format, &stream, MAX_DECOMPRESSED_SIZE) {
+            Ok(data) => print_preview(&data),
+            Err(err) => outln!("    can't decompress: {}", err),
+        }
+    }
+    if broken > 0 {
+        let message = format!("{} chunk(s) are corrupted", broken);
+        return Err(Failure::new(Failure::PARSE_ERROR, message).into());
+    }
+    Ok(())
+}
+
+/// Prints start of decompressed data as text if it looks like text, hex dump otherwise
+fn print_preview(data: &[u8]) {
+    let preview = &data[..data.len().min(PREVIEW_SIZE)];
+    outln!("    decompressed to {} bytes:", data.len());
+    print_data(preview);
+    if data.len() > preview.len() {
+        outln!("    ...");
+    }
+}
+
+/// Prints data indented, as text if it looks like text, hex dump otherwise
+fn print_data(data: &[u8]) {
+    if scan::chunk_stats(data).printable_ratio > 0.9 {
+        for line in String::from_utf8_lossy(data).lines() {
+            outln!("    {}", line);
+        }
+    } else {
+        for line in hexdump::format(data).lines() {
+            outln!("    {}", line);
+        }
+    }
+}
+
+#[cfg(feature = "write")]
+pub fn inject(args: InjectArgs, storage: &dyn Storage) -> Result<()> {
+    let mut png = read_png(storage, &args.file_path)?;
+    let payload = if args.keep_name {
+        Envelope::from_file(storage, &args.payload_path, args.keep_mtime)?.to_bytes()?
+    } else {
+        storage.read(&args.payload_path)?
+    };
+
+    let chunk = Chunk::new(args.chunk_type, payload);
+    let injected = format!(
+        "Injected {} chunk: {} bytes, crc {:08x}",
+        registry::describe(chunk.chunk_type()),
+        chunk.length(),
+        chunk.crc()
+    );
+    let index = match args.position {
+        Position::End => png.chunks().len(),
+        Position::BeforeIend => png
+            .chunks()
+            .iter()
+            .position(|c| c.chunk_type().bytes() == *b"IEND")
+            .context("No IEND chunk found")?,
+    };
+    let change = Change::Added {
+        index,
+        chunk: chunk.as_bytes(),
+    };
+    match args.position {
+        Position::End => png.append_chunk(chunk),
+        Position::BeforeIend => png.insert_chunk(index, chunk),
+    }
+
+    save_png(
+        storage,
+        "inject",
+        &args.file_path,
+        &png,
+        &args.write,
+        vec![change],
+    )?;
+    if !args.write.dry_run {
+        outln!("{}", injected);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "write")]
+pub fn wizard(storage: &dyn Storage) -> Result<()> {
+    let args = wizard::ask_encode(&mut io::stdin().lock(), &mut io::stdout())?;
+    let command = wizard::equivalent_command(&args);
+
+    encode(args, storage)?;
+    outln!("Done! Same result can be achieved with:\n    {}", command);
+    Ok(())
+}
+
+#[cfg(feature = "write")]
+pub fn init_type() -> Result<()> {
+    let chunk_type = wizard::ask_chunk_type(&mut io::stdin().lock(), &mut io::stdout())?;
+    outln!("Chunk type: {}", chunk_type);
+    outln!("Use it as: pngme encode <FILE> {} <MESSAGE>", chunk_type);
+    Ok(())
+}
+
+pub fn dump(args: DumpArgs, storage: &dyn Storage) -> Result<()> {
+    let png = read_png(storage, &args.file_path)?;
+    let chunk = png
+        .chunk_by_type(&args.chunk_type.to_string())
+        .ok_or_else(|| {
+            not_found(format!(
+                "No {} chunk found",
+                registry::describe(&args.chunk_type)
+            ))
+        })?;
+
+    let stream = scan::compressed_stream(chunk);
+    if !args.inflate {
+        if let Some((format, _)) = stream {
+            eprintln!(
+                "Data holds {} stream, --inflate shows it decompressed",
+                format
+            );
+        }
+        out!("{}", hexdump::format(chunk.data()));
+        return Ok(());
+    }
+    let (format, stream) = stream.ok_or_else(|| {
+        let message = format!("No compressed stream found in {} data", chunk.chunk_type());
+        Failure::new(Failure::VALIDATION, message)
+    })?;
+    let data = scan::decompress(format, stream, MAX_INFLATED_SIZE)
+        .with_context(|| format!("Failed to inflate {} stream", format))?;
+    eprintln!(
+        "{} stream of {} bytes inflated to {}",
+        format,
+        stream.len(),
+        data.len()
+    );
+    out!("{}", hexdump::format(&data));
+    Ok(())
+}
+
+/// Reverts operations recorded in journal of directory, or in history chunk of image
+#[cfg(feature = "write")]
+pub fn undo(args: UndoArgs, storage: &dyn Storage) -> Result<()> {
+    if !args.path.is_dir() && storage.exists(&args.path) {
+        let mut png = read_png(storage, &args.path)?;
+        for _ in 0..args.steps {
+            let (reverted, record) = history::undo(&png)?;
+            outln!(
+                "Reverted {} ({} change(s)) in {}",
+                record.operation,
+                record.changes.len(),
+                args.path.display()
+            );
+            png = reverted;
+        }
+        // restoring earlier state, which may have been written with --force
+        let options = WriteOptions {
+            force: true,
+            ..Default::default()
+        };
+        return save_png(storage, "undo", &args.path, &png, &options, vec![]);
+    }
+    let journal = Journal::in_dir(&args.path);
+    for entry in journal.undo(args.steps)? {
+        outln!(
+            "Reverted {} change(s) in {}",
+            entry.changes.len(),
+            entry.file_name
+        );
+    }
+    Ok(())
+}
+
+pub fn meta(args: MetaArgs, storage: &dyn Storage) -> Result<()> {
+    let png = read_png(storage, &args.file_path)?;
+    #[cfg(feature = "write")]
+    let png = if args.set.is_empty() {
+        png
+    } else {
+        set_meta(storage, &args, png)?
+    };
+
+    let entries = text::entries(png.chunks());
+    #[cfg(feature = "write")]
+    let list_all = args.get.is_empty() && args.set.is_empty();
+    #[cfg(not(feature = "write"))]
+    let list_all = args.get.is_empty();
+    if list_all {
+        for entry in &entries {
+            outln!("{}: {}", entry.label(), entry.text);
+        }
+    }
+    for keyword in &args.get {
+        let entry = entries
+            .iter()
+            .find(|e| &e.keyword == keyword)
+            .ok_or_else(|| not_found(format!("No {} keyword found", keyword)))?;
+        outln!("{}", entry.text);
+    }
+    Ok(())
+}
+
+/// Replaces text chunks with keywords being set, new ones go before IEND
+#[cfg(feature = "write")]
+fn set_meta(storage: &dyn Storage, args: &MetaArgs, mut png: Png) -> Result<Png> {
+    let mut changes = vec![];
+    for entry in &args.set {
+        let existing: Vec<_> = (0..png.chunks().len())
+            .filter(|&i| {
+                let chunk = &png.chunks()[i];
+                TextEntry::from_chunk(chunk).is_ok_and(|e| e.keyword == entry.keyword)
+            })
+            .collect();
+        for &index in existing.iter().rev() {
+            let chunk = png.remove_chunk_at(index);
+            changes.push(Change::Removed {
+                index,
+                chunk: chunk.as_bytes(),
+            });
+        }
+
+        let index = match existing.first() {
+            Some(&index) => index,
+            None => png
+                .chunks()
+                .iter()
+                .position(|c| &c.chunk_type().bytes() == b"IEND")
+                .unwrap_or(png.chunks().len()),
+        };
+        let chunk = Chunk::new(ChunkType::try_from(entry.chunk_type())?, entry.to_data());
+        changes.push(Change::Added {
+            index,
+            chunk: chunk.as_bytes(),
+        });
+        png.insert_chunk(index, chunk);
+        outln!("Set {}", entry.keyword);
+    }
+
+    save_png(storage, "meta", &args.file_path, &png, &args.write, changes)?;
+    Ok(png)
+}
+
+/// Rewrites text chunks in another format, each staying at its place
+pub fn text_list(args: TextListArgs, storage: &dyn Storage) -> Result<()> {
+    let png = read_png(storage, &args.file_path)?;
+    let entries: Vec<_> = text::entries(png.chunks())
+        .into_iter()
+        .filter(|e| args.keywords.is_empty() || args.keywords.contains(&e.keyword))
+        .filter(|e| {
+            args.language
+                .as_ref()
+                .is_none_or(|tag| e.matches_language(tag))
+        })
+        .collect();
+    if args.json {
+        let entries = entries.iter().map(TextEntry::to_json).collect();
+        outln!("{}", json::pretty(&Value::Array(entries)));
+        return Ok(());
+    }
+    if entries.is_empty() {
+        let message = match &args.language {
+            Some(tag) => format!("No text entries in language {}", tag),
+            None => "No text entries found".to_string(),
+        };
+        return Err(not_found(message));
+    }
+
+    let mut table = Table::new(vec!["KEYWORD", "LANG", "TRANSLATED", "FORMAT", "TEXT"]);
+    for entry in &entries {
+        let international = entry.international.clone().unwrap_or_default();
+        let or_dash = |s: String| if s.is_empty() { "-".to_string() } else { s };
+        let text: String = entry.text.escape_debug().collect();
+        let text = match text.char_indices().nth(TEXT_PREVIEW_CHARS) {
+            Some((end, _)) => format!("{}...", &text[..end]),
+            None => text,
+        };
+        table.row(
+            vec![
+                entry.keyword.clone(),
+                or_dash(international.language),
+                or_dash(international.translated_keyword),
+                String::from_utf8_lossy(&entry.chunk_type()).into_owned(),
+                text,
+            ],
+            None,
+        );
+    }
+    out!("{}", table.render(false));
+    Ok(())
+}
+
+#[cfg(feature = "write")]
+pub fn text_convert(args: TextConvertArgs, storage: &dyn Storage) -> Result<()> {
+    let mut png = read_png(storage, &args.file_path)?;
+    for keyword in &args.keywords {
+        if !text::entries(png.chunks())
+            .iter()
+            .any(|e| &e.keyword == keyword)
+        {
+            return Err(not_found(format!("No {} keyword found", keyword)));
+        }
+    }
+
+    let mut changes = vec![];
+    for index in 0..png.chunks().len() {
+        let Ok(entry) = TextEntry::from_chunk(&png.chunks()[index]) else {
+            continue;
+        };
+        if entry.format() == args.format
+            || !(args.keywords.is_empty() || args.keywords.contains(&entry.keyword))
+        {
+            continue;
+        }
+        let entry = entry.convert(args.format)?;
+        let chunk = Chunk::new(ChunkType::try_from(entry.chunk_type())?, entry.to_data());
+        let old = png.remove_chunk_at(index);
+        changes.push(Change::Removed {
+            index,
+            chunk: old.as_bytes(),
+        });
+        changes.push(Change::Added {
+            index,
+            chunk: chunk.as_bytes(),
+        });
+        png.insert_chunk(index, chunk);
+    }
+
+    let chunk_type = String::from_utf8_lossy(&args.format.chunk_type()).into_owned();
+    if changes.is_empty() {
+        outln!("All text chunks are already {}", chunk_type);
+        return Ok(());
+    }
+    let count = changes.len() / 2;
+    save_png(storage, "text", &args.file_path, &png, &args.write, changes)?;
+    if !args.write.dry_run {
+        outln!("Converted {} text chunk(s) to {}", count, chunk_type);
+    }
+    Ok(())
+}
+
+pub fn dpi_get(args: DpiGetArgs, storage: &dyn Storage) -> Result<()> {
+    let png = read_png(storage, &args.file_path)?;
+    let chunk = png
+        .chunk_by_type("pHYs")
+        .ok_or_else(|| not_found("Image has no pHYs chunk".to_string()))?;
+    let phys = PhysicalDimensions::from_data(chunk.data())?;
+    match phys.dpi() {
+        Some((x, _)) if phys.x == phys.y => {
+            outln!("{:.0} DPI ({} pixels per meter)", x, phys.x)
+        }
+        Some((x, y)) => outln!(
+            "{:.0}x{:.0} DPI ({}x{} pixels per meter)",
+            x,
+            y,
+            phys.x,
+            phys.y
+        ),
+        None => outln!("Unknown unit, pixel aspect ratio {}:{}", phys.x, phys.y),
+    }
+    Ok(())
+}
+
+/// Replaces pHYs chunk, keeping it before the first IDAT as required
+#[cfg(feature = "write")]
+pub fn dpi_set(args: DpiSetArgs, storage: &dyn Storage) -> Result<()> {
+    let mut png = read_png(storage, &args.file_path)?;
+    let phys = PhysicalDimensions::from_dpi(args.dpi)?;
+    let mut changes = vec![];
+
+    let chunk = Chunk::new(ChunkType::try_from(*b"pHYs")?, phys.to_data());
+    replace_chunk(&mut png, chunk, &[*b"IDAT"], &mut changes);
+
+    save_png(storage, "dpi", &args.file_path, &png, &args.write, changes)?;
+    if !args.write.dry_run {
+        outln!("Set {} DPI ({} pixels per meter)", args.dpi, phys.x);
+    }
+    Ok(())
+}
+
+pub fn trailing_get(args: TrailingGetArgs, storage: &dyn Storage) -> Result<()> {
+    let png = read_png(storage, &args.file_path)?;
+    let data = png.trailing();
+    if data.is_empty() {
+        return Err(not_found("No data after IEND".to_string()));
+    }
+    #[cfg(feature = "write")]
+    if let Some(path) = &args.output {
+        ensure!(!storage.exists(path), "{} already exists", path.display());
+        storage.write(path, data)?;
+        outln!("Saved {} bytes to {}", data.len(), path.display());
+        return Ok(());
+    }
+    if args.raw {
+        write_raw(data)?;
+    } else {
+        out!("{}", hexdump::format(data));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "write")]
+pub fn trailing_set(args: TrailingSetArgs, storage: &dyn Storage) -> Result<()> {
+    let mut png = read_png(storage, &args.file_path)?;
+    let data = storage.read(&args.payload_path)?;
+    ensure!(
+        !data.is_empty(),
+        "{} is empty, use `trailing remove` to strip data after IEND",
+        args.payload_path.display()
+    );
+    let replaced = png.trailing().len();
+    let size = data.len();
+    png.set_trailing(data);
+    save_png(
+        storage,
+        "trailing",
+        &args.file_path,
+        &png,
+        &args.write,
+        vec![],
+    )?;
+    if !args.write.dry_run {
+        match replaced {
+            0 => outln!("Appended {} bytes after IEND", size),
+            _ => outln!("Replaced {} bytes after IEND with {}", replaced, size),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "write")]
+pub fn trailing_remove(args: TrailingRemoveArgs, storage: &dyn Storage) -> Result<()> {
+    let mut png = read_png(storage, &args.file_path)?;
+    let removed = png.trailing().len();
+    if removed == 0 {
+        return Err(not_found("No data after IEND".to_string()));
+    }
+    png.set_trailing(vec![]);
+    save_png(
+        storage,
+        "trailing",
+        &args.file_path,
+        &png,
+        &args.write,
+        vec![],
+    )?;
+    if !args.write.dry_run {
+        outln!("Removed {} bytes after IEND", removed);
+    }
+    Ok(())
+}
+
+/// Replaces all chunks of the same type with the given one. Chunk stays where
+/// it was if that is before the first chunk with one of `before` types,
+/// otherwise it's moved right before that chunk (or IEND)
+#[cfg(feature = "write")]
+fn replace_chunk(png: &mut Png, chunk: Chunk, before: &[[u8; 4]], changes: &mut Vec<Change>) {
+    let chunk_type = chunk.chunk_type().bytes();
+    let limit = |png: &Png| {
+        png.chunks()
+            .iter()
+            .map(|c| c.chunk_type().bytes())
+            .position(|t| before.contains(&t) || &t == b"IEND")
+            .unwrap_or(png.chunks().len())
+    };
+    let existing: Vec<_> = (0..png.chunks().len())
+        .filter(|&i| png.chunks()[i].chunk_type().bytes() == chunk_type)
+        .collect();
+    let in_place = existing.first().filter(|&&i| i < limit(png)).copied();
+    for &index in existing.iter().rev() {
+        let chunk = png.remove_chunk_at(index);
+        changes.push(Change::Removed {
+            index,
+            chunk: chunk.as_bytes(),
+        });
+    }
+
+    let index = in_place.unwrap_or_else(|| limit(png));
+    changes.push(Change::Added {
+        index,
+        chunk: chunk.as_bytes(),
+    });
+    png.insert_chunk(index, chunk);
+}
+
+pub fn info(args: InfoArgs, storage: &dyn Storage) -> Result<()> {
+    let png = read_png(storage, &args.file_path)?;
+    #[cfg(feature = "write")]
+    let png = set_color_space(storage, &args, png)?;
+
+    let header = png.image_header()?;
+    outln!(
+        "Image: {}x{}, {}-bit {}{}",
+        header.width,
+        header.height,
+        header.bit_depth,
+        header.color_type_name(),
+        if header.interlaced {
+            ", interlaced"
+        } else {
+            ""
+        }
+    );
+    if let Some(background) = png.background()? {
+        outln!("Background: {}", background);
+    }
+    if let Some(transparency) = png.transparency()? {
+        outln!("Transparency: {}", transparency);
+    }
+    if let Some(bits) = png.significant_bits()? {
+        outln!("Significant bits: {}", bits);
+    }
+    if let Some(histogram) = png.histogram()? {
+        outln!("Histogram: {}", histogram);
+    }
+    if let Some(chunk) = png.chunk_by_type("gAMA") {
+        outln!("Gamma: {}", Gamma::from_data(chunk.data())?.value());
+    }
+    if let Some(chunk) = png.chunk_by_type("cHRM") {
+        outln!(
+            "Chromaticities: {}",
+            Chromaticities::from_data(chunk.data())?
+        );
+    }
+    if let Some(chunk) = png.chunk_by_type("sRGB") {
+        let intent = RenderingIntent::from_data(chunk.data())?;
+        outln!("sRGB rendering intent: {}", intent);
+    }
+    if let Some(chunk) = png.chunk_by_type("pHYs") {
+        let phys = PhysicalDimensions::from_data(chunk.data())?;
+        match phys.dpi() {
+            Some((x, y)) => outln!("Resolution: {:.0}x{:.0} DPI", x, y),
+            None => outln!("Pixel aspect ratio: {}:{}", phys.x, phys.y),
+        }
+    }
+    let idat = ChunkType::try_from(*b"IDAT")?;
+    outln!(
+        "Size: {} bytes ({} of image data, {} of signature and chunk headers)",
+        png.byte_size(),
+        png.payload_size(&idat),
+        png.overhead()
+    );
+    if args.hash {
+        outln!("SHA-256: {}", receipt::digest(&png.as_bytes()));
+        for (i, chunk) in png.chunks().iter().enumerate() {
+            let digest = receipt::digest(chunk.data());
+            outln!("    #{} {}: {}", i, chunk.chunk_type(), digest);
+        }
+    }
+    Ok(())
+}
+
+/// Writes color space chunks that are set, all of them go before PLTE and IDAT
+#[cfg(feature = "write")]
+fn set_color_space(storage: &dyn Storage, args: &InfoArgs, mut png: Png) -> Result<Png> {
+    let mut chunks = vec![];
+    if let Some(gamma) = &args.gamma {
+        chunks.push((*b"gAMA", gamma.to_data()));
+    }
+    if let Some(chromaticities) = &args.chromaticities {
+        chunks.push((*b"cHRM", chromaticities.to_data()));
+    }
+    if let Some(intent) = args.srgb {
+        chunks.push((*b"sRGB", intent.to_data()));
+    }
+    if chunks.is_empty() {
+        return Ok(png);
+    }
+
+    let mut changes = vec![];
+    for (chunk_type, data) in chunks {
+        let chunk = Chunk::new(ChunkType::try_from(chunk_type)?, data);
+        replace_chunk(&mut png, chunk, &[*b"PLTE", *b"IDAT"], &mut changes);
+    }
+    save_png(storage, "info", &args.file_path, &png, &args.write, changes)?;
+    Ok(png)
+}
+
+pub fn carve(args: CarveArgs, storage: &dyn Storage) -> Result<()> {
+    let bytes = storage.read(&args.file_path)?;
+    check_header(&bytes, &args.file_path)?;
+    let chunks = raw::raw_chunks(&bytes[8..]);
+    let nested = carve::carve(&bytes);
+    if nested.is_empty() {
+        outln!("No nested images found");
+        return Ok(());
+    }
+
+    for (number, image) in (1..).zip(&nested) {
+        let place = match image.chunk_index {
+            Some(index) => format!(
+                "chunk #{} {}",
+                index,
+                String::from_utf8_lossy(&chunks[index].chunk_type)
+            ),
+            None => "data after IEND".to_string(),
+        };
+        let size = Png::try_from(image.bytes)?
+            .image_header()
+            .map_or("?".to_string(), |h| format!("{}x{}", h.width, h.height));
+        outln!(
+            "{}: {} PNG of {} bytes at byte {} in {}",
+            number,
+            size,
+            image.bytes.len(),
+            image.offset,
+            place
+        );
+
+        #[cfg(feature = "write")]
+        if let Some(dir) = &args.output_dir {
+            let stem = args.file_path.file_stem().unwrap_or_default();
+            let path = dir.join(format!("{}-{}.png", stem.to_string_lossy(), number));
+            ensure!(!storage.exists(&path), "{} already exists", path.display());
+            storage.write(&path, image.bytes)?;
+            outln!("    saved to {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Sums chunk types over all images in directory tree, unreadable files are skipped
+pub fn survey(args: SurveyArgs, storage: &dyn Storage) -> Result<()> {
+    let paths = survey::find_pngs(&args.dir)?;
+    let mut survey = Survey::default();
+    let mut progress = Progress::new("Reading", Unit::Files, paths.len() as u64);
+    for path in paths {
+        if args.headers_only {
+            let stats = read_headers(storage, &path)
+                .map(|(headers, total)| Stats::from_headers(&headers, total as usize));
+            survey.add_stats(&path, stats);
+        } else {
+            match storage.read(&path) {
+                Ok(bytes) => survey.add(&path, &bytes),
+                Err(err) => survey.failed.push((path, format!("{:#}", err))),
+            }
+        }
+        progress.inc(1);
+    }
+    survey.finish();
+
+    if args.json {
+        outln!("{}", json::pretty(&survey.to_json()));
+        return Ok(());
+    }
+    let mut table = Table::new(vec!["TYPE", "FILES", "COUNT", "BYTES"])
+        .align_right(1)
+        .align_right(2)
+        .align_right(3);
+    for t in &survey.types {
+        table.row(
+            vec![
+                String::from_utf8_lossy(&t.chunk_type).into_owned(),
+                t.files.to_string(),
+                t.count.to_string(),
+                t.bytes.to_string(),
+            ],
+            None,
+        );
+    }
+    out!("{}", table.render(false));
+    if !survey.nonstandard.is_empty() {
+        outln!();
+        outln!("Non-standard chunk types:");
+        for (path, types) in &survey.nonstandard {
+            let types: Vec<_> = types.iter().map(|t| String::from_utf8_lossy(t)).collect();
+            outln!("  {}: {}", path.display(), types.join(", "));
+        }
+    }
+    for (path, err) in &survey.failed {
+        eprintln!("Skipped {}: {}", path.display(), err);
+    }
+    outln!();
+    outln!(
+        "{} files surveyed, {} skipped",
+        survey.files,
+        survey.failed.len()
+    );
+    Ok(())
+}
+
+pub fn stats(args: StatsArgs, storage: &dyn Storage) -> Result<()> {
+    let bytes = storage.read(&args.file_path)?;
+    let stats = Stats::from_bytes(&bytes).map_err(|err| {
+        let err = err.context(format!("Failed to parse {}", args.file_path.display()));
+        Failure::wrap(Failure::PARSE_ERROR, err)
+    })?;
+
+    outln!("File size:   {} bytes", stats.total);
+    outln!("Chunks:      {}", stats.chunks);
+    outln!(
+        "Ancillary:   {} bytes ({})",
+        stats.ancillary,
+        stats::percent(stats.ancillary, stats.total)
+    );
+    if let Some((index, chunk_type, size)) = stats.largest {
+        outln!(
+            "Largest:     #{} {} ({} bytes)",
+            index,
+            String::from_utf8_lossy(&chunk_type),
+            size
+        );
+    }
+    outln!("After IEND:  {} bytes", stats.after_iend);
+    outln!();
+
+    let mut table = Table::new(vec!["TYPE", "COUNT", "BYTES", "SHARE"])
+        .align_right(1)
+        .align_right(2)
+        .align_right(3);
+    for t in &stats.types {
+        table.row(
+            vec![
+                String::from_utf8_lossy(&t.chunk_type).into_owned(),
+                t.count.to_string(),
+                t.bytes.to_string(),
+                stats::percent(t.bytes, stats.total),
+            ],
+            None,
+        );
+    }
+    out!("{}", table.render(false));
+    Ok(())
+}
+
+pub fn palette(args: PaletteArgs, storage: &dyn Storage) -> Result<()> {
+    let png = read_png(storage, &args.file_path)?;
+    let chunk = png.chunk_by_type("PLTE").context("Image has no palette")?;
+    let palette = Palette::from_data(chunk.data())?;
+    let alpha = match png.transparency()? {
+        Some(Transparency::PaletteAlpha(alpha)) => Some(alpha),
+        _ => None,
+    };
+
+    for (i, [r, g, b]) in palette.colors.iter().enumerate() {
+        out!(
+            "{:3}: #{:02x}{:02x}{:02x} ({}, {}, {})",
+            i,
+            r,
+            g,
+            b,
+            r,
+            g,
+            b
+        );
+        if let Some(alpha) = &alpha {
+            // entries not covered by tRNS are opaque
+            out!(", alpha {}", alpha.get(i).unwrap_or(&255));
+        }
+        outln!();
+    }
+    let header = png.image_header()?;
+    palette.validate(header.bit_depth, header.color_type)?;
+
+    #[cfg(feature = "write")]
+    if let Some(export) = &args.export {
+        let text = match export.extension().and_then(|ext| ext.to_str()) {
+            Some("gpl") => {
+                let name = args.file_path.file_stem().unwrap_or_default();
+                palette.to_gimp(&name.to_string_lossy())
+            }
+            _ => palette.to_jasc(),
+        };
+        storage.write(export, text.as_bytes())?;
+        outln!("Exported palette to {}", export.display());
+    }
+    Ok(())
+}
+
+pub fn diff(args: DiffArgs, storage: &dyn Storage) -> Result<()> {
+    let old = read_png(storage, &args.old_path)?;
+    let new = read_png(storage, &args.new_path)?;
+    let deltas = diff::diff(old.chunks(), new.chunks());
+    if deltas.is_empty() {
+        outln!("No differences");
+        return Ok(());
+    }
+
+    let lines = if args.explain {
+        diff::explain(&deltas)
+    } else {
+        diff::describe(&deltas)
+    };
+    for line in lines {
+        outln!("{}", line);
+    }
+    Ok(())
+}
+
+pub fn same_image(args: SameImageArgs, storage: &dyn Storage) -> Result<()> {
+    let a = read_png(storage, &args.a_path)?;
+    let b = read_png(storage, &args.b_path)?;
+    match pixels::compare(&a, &b, args.decompress)? {
+        None => {
+            outln!("Images are the same");
+            Ok(())
+        }
+        Some(difference) => Err(Failure::new(Failure::VALIDATION, difference).into()),
+    }
+}
+
+pub fn blame(args: BlameArgs, storage: &dyn Storage) -> Result<()> {
+    let mut versions = vec![];
+    match &args.history {
+        History::Versions(dir) => {
+            let mut paths = vec![];
+            for entry in
+                fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?
+            {
+                let path = entry?.path();
+                let is_png = path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+                if is_png && path.is_file() {
+                    paths.push(path);
+                }
+            }
+            paths.sort();
+            let mut progress = Progress::new("Reading", Unit::Files, paths.len() as u64);
+            for path in paths {
+                let label = path.file_name().unwrap().to_string_lossy().into_owned();
+                versions.push((label, read_png(storage, &path)?));
+                progress.inc(1);
+            }
+        }
+        #[cfg(feature = "write")]
+        History::Git => {
+            ensure!(
+                storage.is_local(&args.file_path),
+                "--git works only with local files"
+            );
+            for (label, bytes) in blame::git_history(&args.file_path)? {
+                let png = Png::try_from(bytes.as_slice())
+                    .with_context(|| format!("Failed to parse version {}", label))?;
+                versions.push((label, png));
+            }
+        }
+    }
+    versions.push((
+        "current file".to_string(),
+        read_png(storage, &args.file_path)?,
+    ));
+
+    let origins = blame::blame(&versions, args.chunk.as_ref());
+    if origins.is_empty() {
+        return Err(not_found("No matching chunks found".to_string()));
+    }
+    for origin in origins {
+        if origin.added == origin.changed {
+            outln!("{}: added in {}", origin.chunk, origin.added);
+        } else {
+            outln!(
+                "{}: added in {}, last changed in {}",
+                origin.chunk,
+                origin.added,
+                origin.changed
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "write")]
+pub fn watch(args: WatchArgs, storage: &dyn Storage) -> Result<()> {
+    let parse = |path: &Path| {
+        Command::parse(
+            watch::operation_args(&args.on_add, path),
+            args.config.clone(),
+        )
+        .map(|(command, _)| command)
+    };
+    // report invalid operation right away instead of on the first file
+    parse(Path::new("dice.png")).context("Invalid --on-add operation")?;
+
+    outln!("Watching {}, press Ctrl+C to stop", args.dir.display());
+    watch::watch(&args.dir, |path| {
+        outln!("== {}", path.display());
+        if let Err(err) = parse(path).and_then(|command| run(command, storage)) {
+            eprintln!("Error: {:?}", err);
+        }
+    })
+}
+
+pub fn grep(args: GrepArgs, storage: &dyn Storage) -> Result<()> {
+    let png = read_png(storage, &args.file_path)?;
+    let inflated: Vec<Option<Vec<u8>>> = png
+        .chunks()
+        .iter()
+        .map(|chunk| args.inflate.then(|| inflated(chunk)).flatten())
+        .collect();
+    let data_of = |i: usize| inflated[i].as_deref().unwrap_or(png.chunks()[i].data());
+    let matches = search::search((0..inflated.len()).map(data_of), &args.pattern);
+    for m in &matches {
+        let chunk = &png.chunks()[m.chunk_index];
+        let data = data_of(m.chunk_index);
+        out!(
+            "chunk #{} {}{}: offset {}",
+            m.chunk_index,
+            registry::describe(chunk.chunk_type()),
+            if inflated[m.chunk_index].is_some() {
+                " (inflated)"
+            } else {
+                ""
+            },
+            m.offset
+        );
+        if let Pattern::Regex(_) = &args.pattern {
+            let text = |range: Range<usize>| {
+                String::from_utf8_lossy(&data[range])
+                    .escape_debug()
+                    .to_string()
+            };
+            out!(": {}", text(m.offset..m.offset + m.length));
+            for (i, group) in m.groups.iter().enumerate() {
+                match group {
+                    Some(range) => out!(" [{}: {}]", i + 1, text(range.clone())),
+                    None => out!(" [{}: -]", i + 1),
+                }
+            }
+        }
+        outln!();
+    }
+    match matches.len() {
+        0 => outln!("No matches found"),
+        n => outln!("{} match(es)", n),
+    }
+    Ok(())
+}
+
+/// Decompressed content of ancillary chunk holding compressed stream,
+/// `None` if there is none or it doesn't inflate
+fn inflated(chunk: &Chunk) -> Option<Vec<u8>> {
+    if chunk.chunk_type().is_critical() {
+        return None;
+    }
+    let (format, stream) = scan::compressed_stream(chunk)?;
+    scan::decompress(format, stream, MAX_INFLATED_SIZE).ok()
+}
+
+/// Reports every problem found in the image along with a suggested fix,
+/// applying safe fixes when asked
+pub fn doctor(args: DoctorArgs, storage: &dyn Storage) -> Result<()> {
+    // image is read raw, damaged one wouldn't get through `read_png`
+    #[cfg(feature = "write")]
+    if args.fix && storage.is_local(&args.file_path) {
+        lock::acquire(&args.file_path)?;
+    }
+    let bytes = storage.read(&args.file_path)?;
+    let file = args.file_path.display().to_string();
+    let issues = doctor::diagnose(&bytes, &file).map_err(|err| {
+        let err = err.context(format!("Failed to parse {}", file));
+        Failure::wrap(Failure::PARSE_ERROR, err)
+    })?;
+    if issues.is_empty() {
+        outln!("No problems found");
+        return Ok(());
+    }
+
+    for issue in &issues {
+        match issue.chunk_index {
+            Some(index) => outln!("{:<6}  chunk #{}: {}", issue.severity, index, issue.message),
+            None => outln!("{:<6}  {}", issue.severity, issue.message),
+        }
+        outln!("        {}", issue.advice);
+    }
+    let fixable = issues.iter().filter(|i| i.fixable).count();
+    outln!(
+        "{} problem(s) found, {} can be fixed with --fix",
+        issues.len(),
+        fixable
+    );
+
+    let serious = issues.iter().filter(|i| i.severity == Severity::High);
+    #[cfg(feature = "write")]
+    if args.fix && fixable > 0 {
+        let (png, changes) = doctor::repair(&bytes)?;
+        save_png(
+            storage,
+            "doctor",
+            &args.file_path,
+            &png,
+            &args.write,
+            changes,
+        )?;
+        if !args.write.dry_run {
+            outln!("Fixed {} problem(s)", fixable);
+            return match serious.filter(|i| !i.fixable).count() {
+                0 => Ok(()),
+                n => Err(Failure::new(
+                    Failure::VALIDATION,
+                    format!("{} serious problem(s) remain", n),
+                )
+                .into()),
+            };
+        }
+    }
+    match serious.count() {
+        0 => Ok(()),
+        n => Err(Failure::new(
+            Failure::VALIDATION,
+            format!("{} serious problem(s) found", n),
+        )
+        .into()),
+    }
+}
+
+/// Headers of chunks in file, local files are read without loading chunk data
+fn read_headers(storage: &dyn Storage, path: &Path) -> Result<(Vec<ChunkHeader>, u64)> {
+    let headers = if storage.is_local(path) && path.is_file() {
+        let file =
+            fs::File::open(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        raw::read_headers(file)
+    } else {
+        raw::read_headers(io::Cursor::new(storage.read(path)?))
+    };
+    headers.map_err(|err| {
+        let err = err.context(format!("Failed to parse {}", path.display()));
+        Failure::wrap(Failure::PARSE_ERROR, err).into()
+    })
+}
+
+fn scan_headers(args: ScanArgs, storage: &dyn Storage) -> Result<()> {
+    let (headers, total) = read_headers(storage, &args.file_path)?;
+    let describe = |t: [u8; 4]| match ChunkType::try_from(t) {
+        Ok(chunk_type) => registry::describe(&chunk_type),
+        Err(_) => String::from_utf8_lossy(&t).into_owned(),
+    };
+    let findings = scan::scan_headers(&headers);
+    for finding in &findings {
+        // header checks always point at a chunk
+        let index = finding.chunk_index.unwrap_or_default();
+        outln!(
+            "{:<6}  chunk #{} {}: {}",
+            finding.severity,
+            index,
+            describe(headers[index].chunk_type),
+            finding.message
+        );
+    }
+    let end: u64 = 8 + headers.iter().map(|h| h.size).sum::<u64>();
+    if total > end {
+        outln!("{} bytes after the last chunk", total - end);
+    }
+    match findings.first() {
+        Some(worst) => outln!(
+            "{} finding(s), highest severity {}",
+            findings.len(),
+            worst.severity
+        ),
+        None => outln!("Nothing suspicious found"),
+    }
+
+    outln!();
+    outln!(
+        "{:>3}  {:<28} {:>12} {:>10}",
+        "#",
+        "TYPE",
+        "OFFSET",
+        "LENGTH"
+    );
+    for (index, header) in headers.iter().enumerate() {
+        outln!(
+            "{:>3}  {:<28} {:>12} {:>10}",
+            index,
+            describe(header.chunk_type),
+            header.offset,
+            header.length
+        );
+    }
+    Ok(())
+}
+
+pub fn scan(args: ScanArgs, storage: &dyn Storage) -> Result<()> {
+    if args.headers_only {
+        return scan_headers(args, storage);
+    }
+    let png = read_png(storage, &args.file_path)?;
+    #[cfg_attr(not(any(feature = "write", feature = "yara")), allow(unused_mut))]
+    let mut findings = scan::scan(&png);
+    #[cfg(feature = "write")]
+    if let Some(command) = &args.scanner {
+        let mut flagged: Vec<_> = findings.iter().map(|f| f.chunk_index).collect();
+        flagged.sort_unstable();
+        flagged.dedup();
+        let data_of = |index: Option<usize>| match index {
+            Some(i) => png.chunks()[i].data(),
+            None => png.trailing(),
+        };
+        let total = flagged.iter().map(|&i| data_of(i).len() as u64).sum();
+        let mut progress = Progress::new("Scanning", Unit::Bytes, total);
+        for chunk_index in flagged {
+            let data = data_of(chunk_index);
+            let verdict = scanner::run(command, data)?;
+            progress.inc(data.len() as u64);
+            if let Verdict::Infected(reason) = verdict {
+                findings.push(Finding {
+                    severity: Severity::High,
+                    chunk_index,
+                    message: format!("flagged by scanner: {}", reason),
+                });
+            }
+        }
+        findings.sort_by_key(|f| std::cmp::Reverse(f.severity));
+    }
+    #[cfg(feature = "yara")]
+    if let Some(path) = &args.yara {
+        let rules = Rules::load(path)?;
+        let total = png.chunks().iter().map(|c| c.length() as u64).sum::<u64>()
+            + png.trailing().len() as u64;
+        let mut progress = Progress::new("Matching", Unit::Bytes, total);
+        for (chunk_index, chunk) in png.chunks().iter().enumerate() {
+            progress.inc(chunk.length() as u64);
+            let mut matched = rules.matches(chunk.data())?;
+            if let Some(payload) = scan::decoded_payload(chunk) {
+                for rule in rules.matches(&payload)? {
+                    if !matched.contains(&rule) {
+                        matched.push(format!("{} (in decoded payload)", rule));
+                    }
+                }
+            }
+            findings.extend(matched.into_iter().map(|rule| Finding {
+                severity: Severity::High,
+                chunk_index: Some(chunk_index),
+                message: format!("matched YARA rule {}", rule),
+            }));
+        }
+        if !png.trailing().is_empty() {
+            progress.inc(png.trailing().len() as u64);
+            findings.extend(
+                rules
+                    .matches(png.trailing())?
+                    .into_iter()
+                    .map(|rule| Finding {
+                        severity: Severity::High,
+                        chunk_index: None,
+                        message: format!("matched YARA rule {}", rule),
+                    }),
+            );
+        }
+        findings.sort_by_key(|f| std::cmp::Reverse(f.severity));
+    }
+
+    for finding in &findings {
+        match finding.chunk_index {
+            Some(index) => outln!(
+                "{:<6}  chunk #{} {}: {}",
+                finding.severity,
+                index,
+                registry::describe(png.chunks()[index].chunk_type()),
+                finding.message
+            ),
+            None => outln!("{:<6}  {}", finding.severity, finding.message),
+        }
+    }
+    match findings.first() {
+        Some(worst) => outln!(
+            "{} finding(s), highest severity {}",
+            findings.len(),
+            worst.severity
+        ),
+        None => outln!("Nothing suspicious found"),
+    }
+
+    let stats: Vec<_> = png
+        .chunks()
+        .iter()
+        .map(|chunk| scan::chunk_stats(chunk.data()))
+        .collect();
+    if args.visual {
+        let entropies: Vec<_> = stats.iter().map(|s| s.entropy).collect();
+        let markers: String = (0..stats.len())
+            .map(|i| {
+                if findings.iter().any(|f| f.chunk_index == Some(i)) {
+                    '^'
+                } else {
+                    ' '
+                }
+            })
+            .collect();
+        outln!();
+        outln!("Entropy by chunk (▁ is 0, █ is 8 bits/byte), ^ marks findings:");
+        outln!("    {}", scan::sparkline(&entropies, 8.0));
+        let markers = markers.trim_end();
+        if !markers.is_empty() {
+            outln!("    {}", markers);
+        }
+    }
+
+    outln!();
+    outln!(
+        "{:>3}  {:<28} {:>10} {:>8} {:>10}  MAGIC",
+        "#",
+        "TYPE",
+        "LENGTH",
+        "ENTROPY",
+        "PRINTABLE"
+    );
+    for (index, (chunk, stats)) in png.chunks().iter().zip(&stats).enumerate() {
+        outln!(
+            "{:>3}  {:<28} {:>10} {:>8.2} {:>9.0}%  {}",
+            index,
+            registry::describe(chunk.chunk_type()),
+            chunk.length(),
+            stats.entropy,
+            stats.printable_ratio * 100.0,
+            stats.magic.unwrap_or("-")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(feature = "write")]
+pub fn keygen(args: KeygenArgs) -> Result<()> {
+    let key = signature::generate_key()?;
+    let secret_path = args.name.with_extension("key");
+    let public_path = args.name.with_extension("pub");
+    for path in [&secret_path, &public_path] {
+        if path.exists() && !args.force {
+            let message = format!(
+                "{} already exists, use --force to replace it",
+                path.display()
+            );
+            return Err(Failure::new(Failure::USAGE, message).into());
+        }
+    }
+    // replaced rather than overwritten, so the new key doesn't keep old permissions
+    if args.force && secret_path.exists() {
+        fs::remove_file(&secret_path)
+            .with_context(|| format!("Failed to remove {}", secret_path.display()))?;
+    }
+
+    write_secret(&secret_path, hex::encode(&key.to_bytes()).as_bytes())
+        .with_context(|| format!("Failed to write {}", secret_path.display()))?;
+    fs::write(&public_path, hex::encode(&key.verifying_key().to_bytes()))
+        .with_context(|| format!("Failed to write {}", public_path.display()))?;
+
+    outln!(
+        "Secret key written to {}, keep it private",
+        secret_path.display()
+    );
+    outln!("Public key written to {}", public_path.display());
+    Ok(())
+}
+
+/// Creates new file readable only by its owner (on Unix), failing if it exists
+#[cfg(feature = "write")]
+fn write_secret(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(data)
+}
+
+#[cfg(feature = "write")]
+pub fn sign(args: SignArgs, storage: &dyn Storage) -> Result<()> {
+    let mut png = read_png(storage, &args.file_path)?;
+    let key = signature::read_signing_key(&args.key_path)?;
+    let scope = match &args.chunk_type {
+        Some(chunk_type) => Scope::Chunk(chunk_type.bytes()),
+        None => Scope::Critical,
+    };
+
+    let chunk = signature::sign(&png, &scope, &key)?;
+    let index = iend_index(&png);
+    let change = Change::Added {
+        index,
+        chunk: chunk.as_bytes(),
+    };
+    png.insert_chunk(index, chunk);
+
+    save_png(
+        storage,
+        "sign",
+        &args.file_path,
+        &png,
+        &args.write,
+        vec![change],
+    )?;
+    if !args.write.dry_run {
+        outln!("Signed {}", scope);
+    }
+    Ok(())
+}
+
+pub fn verify(args: VerifyArgs, storage: &dyn Storage) -> Result<()> {
+    let png = read_png(storage, &args.file_path)?;
+    let key = signature::read_verifying_key(&args.key_path)?;
+
+    let scopes =
+        signature::verify(&png, &key).map_err(|err| Failure::wrap(Failure::VALIDATION, err))?;
+    for scope in scopes {
+        outln!("Valid signature over {}", scope);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "write")]
+pub fn seal(args: SealArgs, storage: &dyn Storage) -> Result<()> {
+    let mut png = read_png(storage, &args.file_path)?;
+    let key = match &args.key_path {
+        Some(path) => Some(signature::read_signing_key(path)?),
+        None => None,
+    };
+
+    let mut changes = vec![];
+    while let Some(index) = png.chunks().iter().position(seal::is_seal) {
+        let old = png.remove_chunk_at(index);
+        changes.push(Change::Removed {
+            index,
+            chunk: old.as_bytes(),
+        });
+    }
+    let chunk = seal::seal(&png, key.as_ref())?;
+    let index = iend_index(&png);
+    changes.push(Change::Added {
+        index,
+        chunk: chunk.as_bytes(),
+    });
+    png.insert_chunk(index, chunk);
+
+    save_png(storage, "seal", &args.file_path, &png, &args.write, changes)?;
+    if !args.write.dry_run {
+        match key {
+            Some(_) => outln!("Sealed image with signed digest"),
+            None => outln!("Sealed image"),
+        }
+    }
+    Ok(())
+}
+
+pub fn verify_seal(args: VerifySealArgs, storage: &dyn Storage) -> Result<()> {
+    let png = read_png(storage, &args.file_path)?;
+    let key = match &args.key_path {
+        Some(path) => Some(signature::read_verifying_key(path)?),
+        None => None,
+    };
+    let chunk = png
+        .chunks()
+        .iter()
+        .find(|c| seal::is_seal(c))
+        .ok_or_else(|| not_found("Image is not sealed".to_string()))?;
+
+    let sealed = seal::verify(&png, chunk, key.as_ref())
+        .map_err(|err| Failure::wrap(Failure::VALIDATION, err))?;
+    match sealed {
+        Sealed::Unsigned => outln!("Image is unchanged since it was sealed"),
+        Sealed::SignatureNotChecked => {
+            outln!("Image is unchanged since it was sealed, give --key to check seal signature")
+        }
+        Sealed::Signed => outln!("Image is unchanged since it was sealed, signature is valid"),
+    }
+    Ok(())
+}
+
+pub fn receipt_verify(args: ReceiptVerifyArgs, storage: &dyn Storage) -> Result<()> {
+    let text = fs::read_to_string(&args.receipt_path)
+        .with_context(|| format!("Failed to read {}", args.receipt_path.display()))?;
+    let key = signature::read_verifying_key(&args.key_path)?;
+    let receipt =
+        Receipt::verify(&text, &key).map_err(|err| Failure::wrap(Failure::VALIDATION, err))?;
+    outln!(
+        "Valid receipt for {} at {} (Unix time)",
+        receipt.operation,
+        receipt.timestamp
+    );
+
+    let mut modified = 0;
+    for file in &receipt.files {
+        let path = Path::new(&file.output);
+        let current = match storage.read(path) {
+            Ok(data) => receipt::digest(&data),
+            Err(_) => String::new(),
+        };
+        if current == file.output_sha256 {
+            outln!("{}: unchanged", file.output);
+        } else {
+            outln!("{}: differs from receipt", file.output);
+            modified += 1;
+        }
+    }
+    if modified > 0 {
+        let message = format!("{} file(s) don't match receipt", modified);
+        return Err(Failure::new(Failure::VALIDATION, message).into());
+    }
+    Ok(())
+}
+
+/// Packs minimized copy of image and environment details into a tarball,
+/// which can be attached to an issue
+#[cfg(feature = "write")]
+pub fn bug_report(args: BugReportArgs, storage: &dyn Storage) -> Result<()> {
+    let bytes = storage.read(&args.file_path)?;
+    let types: Vec<_> = args.chunk_types.iter().map(ChunkType::bytes).collect();
+    let reproducer = bug_report::reproducer(&bytes, &types, args.redact)?;
+
+    let command = args
+        .command
+        .unwrap_or_else(|| format!("pngme print {}", args.file_path.display()));
+    let mut report = format!(
+        "pngme version: {}\nplatform: {} {}\ncommand: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        command
+    );
+    match &reproducer.error {
+        Some(error) => report.push_str(&format!("parse error: {}\n", error)),
+        None => report.push_str("parse error: none, image is valid\n"),
+    }
+    report.push_str(&format!(
+        "redacted: {}\noriginal size: {} bytes\nkept chunks (file is reproducer.png):\n",
+        if args.redact { "yes" } else { "no" },
+        bytes.len()
+    ));
+    for line in &reproducer.kept {
+        report.push_str(&format!("    {}\n", line));
+    }
+
+    let mtime = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let archive = tar::archive(
+        &[
+            ("report.txt", report.as_bytes()),
+            ("reproducer.png", &reproducer.png),
+        ],
+        mtime,
+    )?;
+    let output = args
+        .output
+        .unwrap_or_else(|| args.file_path.with_extension("bug-report.tar"));
+    fs::write(&output, archive).with_context(|| format!("Failed to write {}", output.display()))?;
+
+    out!("{}", report);
+    outln!("Bug report written to {}", output.display());
+    Ok(())
+}
+
+#[cfg(feature = "write")]
+pub fn merge(args: MergeArgs, storage: &dyn Storage) -> Result<()> {
+    let base = read_png(storage, &args.base_path)?;
+    let ours = read_png(storage, &args.ours_path)?;
+    let theirs = read_png(storage, &args.theirs_path)?;
+    let merged = merge::merge(base.chunks(), ours.chunks(), theirs.chunks())?;
+
+    storage.write(&args.output, &Png::from_chunks(merged.chunks).as_bytes())?;
+    for conflict in &merged.conflicts {
+        outln!("CONFLICT {}", conflict);
+    }
+    ensure!(
+        merged.conflicts.is_empty(),
+        "{} conflict(s), our version of them was written to {}",
+        merged.conflicts.len(),
+        args.output.display()
+    );
+    outln!("Merged into {}", args.output.display());
+    Ok(())
+}
+
+/// Runs command on ever smaller copies of image, keeping the ones it still fails on
+#[cfg(feature = "write")]
+pub fn minimize(args: MinimizeArgs, storage: &dyn Storage) -> Result<()> {
+    let png = storage.read(&args.file_path)?;
+    // candidates are written to local file system, as the command needs a real file
+    let candidate = std::env::temp_dir().join(format!("pngme-minimize-{}.png", std::process::id()));
+    let command = args.command.replace("{}", &candidate.display().to_string());
+
+    let fails = |data: &[u8]| -> Result<bool> {
+        fs::write(&candidate, data)
+            .with_context(|| format!("Failed to write {}", candidate.display()))?;
+        let status = scanner::shell(&command)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .with_context(|| format!("Failed to run '{}'", command))?;
+        Ok(!status.success())
+    };
+    let result = minimize::minimize(&png, fails);
+    let _ = fs::remove_file(&candidate);
+    let (minimized, runs) = result?;
+
+    let output = args
+        .output
+        .unwrap_or_else(|| args.file_path.with_extension("min.png"));
+    storage.write(&output, &minimized)?;
+    outln!(
+        "Minimized {} to {} bytes after {} runs, saved to {}",
+        png.len(),
+        minimized.len(),
+        runs,
+        output.display()
+    );
+    Ok(())
+}
+
+#[cfg(feature = "write")]
+pub fn split_encode(args: SplitEncodeArgs, storage: &dyn Storage) -> Result<()> {
+    check_collision(&args.chunk_type, args.write.force)?;
+    let message = read_message(storage, args.message)?;
+    let shares = sharing::split(&message, args.file_paths.len(), args.threshold)?;
+
+    let mut pngs = args
+        .file_paths
+        .iter()
+        .map(|path| read_png(storage, path))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut changes = vec![];
+    for (png, share) in pngs.iter_mut().zip(shares) {
+        let chunk = Chunk::new(
+            ChunkType::try_from(args.chunk_type.bytes())?,
+            share.to_bytes(),
+        );
+        let index = iend_index(png);
+        changes.push(Change::Added {
+            index,
+            chunk: chunk.as_bytes(),
+        });
+        png.insert_chunk(index, chunk);
+    }
+    let files = args
+        .file_paths
+        .iter()
+        .zip(&pngs)
+        .zip(changes)
+        .map(|((path, png), change)| Modified {
+            input: path,
+            output: path,
+            png,
+            changes: vec![change],
+        })
+        .collect();
+    save_pngs(storage, "split-encode", files, &args.write)?;
+    if args.write.dry_run {
+        return Ok(());
+    }
+
+    let needed = args.threshold.unwrap_or(args.file_paths.len());
+    outln!(
+        "Message split across {} files, {} of them are needed to recover it",
+        args.file_paths.len(),
+        needed
+    );
+    Ok(())
+}
+
+pub fn join_decode(args: JoinDecodeArgs, storage: &dyn Storage) -> Result<()> {
+    let mut shares = vec![];
+    let mut progress = Progress::new("Reading", Unit::Files, args.file_paths.len() as u64);
+    for path in &args.file_paths {
+        let png = read_png(storage, path)?;
+        let chunk = png
+            .chunk_by_type(&args.chunk_type.to_string())
+            .ok_or_else(|| not_found(format!("No share found in {}", path.display())))?;
+        shares.push(Share::from_bytes(chunk.data())?);
+        progress.inc(1);
+    }
+    drop(progress);
+
+    let message = sharing::join(shares)?;
+    outln!("{}", String::from_utf8_lossy(&message));
+    Ok(())
+}
+
+#[cfg(feature = "write")]
+pub fn spread_encode(args: SpreadEncodeArgs, storage: &dyn Storage) -> Result<()> {
+    let mut png = read_png(storage, &args.file_path)?;
+    let message = read_message(storage, args.message)?;
+    let fragments = spread::fragments(&args.password, &message)?;
+    let count = fragments.len();
+    let mut changes = vec![];
+    for chunk in fragments {
+        let index = spread::random_slot(&png)?;
+        changes.push(Change::Added {
+            index,
+            chunk: chunk.as_bytes(),
+        });
+        png.insert_chunk(index, chunk);
+    }
+    // positions don't change when index goes after all fragments
+    let index = iend_index(&png);
+    let chunk = spread::index(&png, &args.password)?;
+    changes.push(Change::Added {
+        index,
+        chunk: chunk.as_bytes(),
+    });
+    png.insert_chunk(index, chunk);
+
+    let file = Modified {
+        input: &args.file_path,
+        output: args.output.as_ref().unwrap_or(&args.file_path),
+        png: &png,
+        changes,
+    };
+    save_pngs(storage, "spread-encode", vec![file], &args.write)?;
+    if !args.write.dry_run {
+        outln!("Message spread across {} chunks", count);
+    }
+    Ok(())
+}
+
+pub fn spread_decode(args: SpreadDecodeArgs, storage: &dyn Storage) -> Result<()> {
+    let png = read_png(storage, &args.file_path)?;
+    let message = spread::gather(&png, &args.password)?
+        .ok_or_else(|| not_found("No spread message found for this password".to_string()))?;
+    outln!("{}", String::from_utf8_lossy(&message));
+    Ok(())
+}
+
+#[cfg(feature = "write")]
+pub fn protect(args: ProtectArgs, storage: &dyn Storage) -> Result<()> {
+    let mut png = read_png(storage, &args.file_path)?;
+    let mut changes = vec![];
+    for index in 0..png.chunks().len() {
+        if !args.chunk_types.contains(png.chunks()[index].chunk_type()) {
+            continue;
+        }
+        let chunk = protect::protect(&png.chunks()[index], &args.password, protect::ITERATIONS)?;
+        let old = png.remove_chunk_at(index);
+        changes.push(Change::Removed {
+            index,
+            chunk: old.as_bytes(),
+        });
+        changes.push(Change::Added {
+            index,
+            chunk: chunk.as_bytes(),
+        });
+        png.insert_chunk(index, chunk);
+    }
+    if changes.is_empty() {
+        let types: Vec<_> = args.chunk_types.iter().map(registry::describe).collect();
+        return Err(not_found(format!("No {} chunk found", types.join(" or "))));
+    }
+
+    let count = changes.len() / 2;
+    save_png(
+        storage,
+        "protect",
+        &args.file_path,
+        &png,
+        &args.write,
+        changes,
+    )?;
+    if !args.write.dry_run {
+        outln!("Protected {} chunk(s)", count);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "write")]
+pub fn unprotect(args: UnprotectArgs, storage: &dyn Storage) -> Result<()> {
+    let mut png = read_png(storage, &args.file_path)?;
+    let mut changes = vec![];
+    let mut failed = 0;
+    for index in 0..png.chunks().len() {
+        if png.chunks()[index].chunk_type().to_string() != protect::CHUNK_TYPE {
+            continue;
+        }
+        // chunks protected with other passwords are left as they are
+        let chunk = match protect::unprotect(&png.chunks()[index], &args.password) {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                eprintln!("chunk #{}: {:#}", index, err);
+                failed += 1;
+                continue;
+            }
+        };
+        let old = png.remove_chunk_at(index);
+        changes.push(Change::Removed {
+            index,
+            chunk: old.as_bytes(),
+        });
+        changes.push(Change::Added {
+            index,
+            chunk: chunk.as_bytes(),
+        });
+        png.insert_chunk(index, chunk);
+    }
+    if changes.is_empty() {
+        if failed > 0 {
+            let message = format!("None of {} protected chunk(s) could be restored", failed);
+            return Err(Failure::new(Failure::VALIDATION, message).into());
+        }
+        return Err(not_found("No protected chunks found".to_string()));
+    }
+
+    let count = changes.len() / 2;
+    save_png(
+        storage,
+        "unprotect",
+        &args.file_path,
+        &png,
+        &args.write,
+        changes,
+    )?;
+    if !args.write.dry_run {
+        outln!("Restored {} chunk(s)", count);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "self-update")]
+pub fn self_update(args: SelfUpdateArgs) -> Result<()> {
+    let Some(version) = update::check()? else {
+        outln!("pngme {} is the latest version", env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    };
+    if args.check {
+        outln!(
+            "Update available: {} -> {}",
+            env!("CARGO_PKG_VERSION"),
+            version
+        );
+        return Ok(());
+    }
+
+    let release = update::download(&version)?;
+    update::install(&release)?;
+    outln!("Updated to pngme {}", release.version);
+    Ok(())
+}
+
+#[cfg(feature = "serve")]
+pub fn serve(args: ServeArgs) -> Result<()> {
+    serve::run(&args.listen)
+}
+
+#[cfg(feature = "write")]
+fn read_message(storage: &dyn Storage, message: Message) -> Result<Vec<u8>> {
+    match message {
+        Message::Text(text) => Ok(text.into_bytes()),
+        Message::File(path) => storage.read(&path),
+        Message::Stdin => {
+            let mut data = vec![];
+            io::stdin()
+                .read_to_end(&mut data)
+                .context("Failed to read message from stdin")?;
+            Ok(data)
+        }
+        #[cfg(feature = "clipboard")]
+        Message::Clipboard => Ok(clipboard::paste()?.into_bytes()),
+    }
+}
+
+/// Backends selected by `--mode`. Chunk backend is registered by encode and decode
+/// for the chunk type they are given, it is not available as a mode
+fn embedders() -> Registry {
+    let mut registry = Registry::with_builtins();
+    registry.register(Box::new(LsbEmbedder));
+    registry
+}
+
+/// Refuses to put message into standard or critical chunk, which decoders
+/// would interpret as part of the image, unless forced
+#[cfg(feature = "write")]
+fn check_collision(chunk_type: &ChunkType, force: bool) -> Result<()> {
+    let refuse = |message: String| -> Error {
+        Failure::new(
+            Failure::USAGE,
+            format!("{}, use --force to write anyway", message),
+        )
+        .into()
+    };
+    if let Err(err) = ChunkType::validate(chunk_type.bytes()) {
+        if !force {
+            return Err(refuse(err.to_string()));
+        }
+        eprintln!("Warning: {}", err);
+    }
+    let reason = match registry::name_of(chunk_type) {
+        Some(_) => "a standard",
+        None if chunk_type.is_critical() => "a critical",
+        None => return Ok(()),
+    };
+    let message = format!(
+        "{} is {} chunk type, writing message into it can break the image",
+        registry::describe(chunk_type),
+        reason
+    );
+    if !force {
+        return Err(refuse(message));
+    }
+    eprintln!("Warning: {}", message);
+    Ok(())
+}
+
+/// Index of IEND, where new ancillary chunks go, or end of image without it
+#[cfg(feature = "write")]
+fn iend_index(png: &Png) -> usize {
+    png.chunks()
+        .iter()
+        .position(|c| &c.chunk_type().bytes() == b"IEND")
+        .unwrap_or(png.chunks().len())
+}
+
+/// For commands that walk raw chunks instead of parsing image with `read_png`
+fn check_header(bytes: &[u8], path: &Path) -> Result<()> {
+    if !bytes.starts_with(&Png::STANDARD_HEADER) {
+        let message = format!("Failed to parse {}: Header is invalid", path.display());
+        return Err(Failure::new(Failure::PARSE_ERROR, message).into());
+    }
+    Ok(())
+}
+
+fn read_png(storage: &dyn Storage, path: &Path) -> Result<Png> {
+    #[cfg(feature = "write")]
+    if storage.is_local(path) {
+        lock::acquire(path)?;
+    }
+    let bytes = storage.read(path)?;
+    let png = if STRICT_PNG.load(Ordering::Relaxed) {
+        Png::try_from_strict(&bytes)
+    } else {
+        Png::try_from(bytes.as_slice())
+    }
+    .map_err(|err| {
+        let err = err.context(format!("Failed to parse {}", path.display()));
+        Failure::wrap(Failure::PARSE_ERROR, err)
+    })?;
+    for duplicate in png.duplicates() {
+        eprintln!(
+            "Warning: {} in {}, `pngme doctor --fix` keeps only the first one",
+            duplicate,
+            path.display()
+        );
+    }
+    info!(
+        "Parsed {} chunks from {} ({} bytes)",
+        png.chunks().len(),
+        path.display(),
+        bytes.len()
+    );
+    for (i, chunk) in png.chunks().iter().enumerate() {
+        debug!(
+            "chunk #{} {}: {} bytes, CRC {:08x}",
+            i,
+            chunk.chunk_type(),
+            chunk.length(),
+            chunk.crc()
+        );
+    }
+    Ok(png)
+}
+
+/// Image modified by a command, waiting to be saved
+#[cfg(feature = "write")]
+struct Modified<'a> {
+    /// File the image was read from, same as `output` unless saved under a new name
+    input: &'a Path,
+    output: &'a Path,
+    png: &'a Png,
+    changes: Vec<Change>,
+}
+
+#[cfg(feature = "write")]
+/// Writes modified image, trashing overwritten original and recording
+/// applied changes in journal if requested
+fn save_png(
+    storage: &dyn Storage,
+    operation: &str,
+    path: &Path,
+    png: &Png,
+    options: &WriteOptions,
+    changes: Vec<Change>,
+) -> Result<()> {
+    let file = Modified {
+        input: path,
+        output: path,
+        png,
+        changes,
+    };
+    save_pngs(storage, operation, vec![file], options)
+}
+
+#[cfg(feature = "write")]
+/// Same as [`save_png`] for several images at once:
+/// either all of them are updated or none of them
+fn save_pngs(
+    storage: &dyn Storage,
+    operation: &str,
+    files: Vec<Modified>,
+    options: &WriteOptions,
+) -> Result<()> {
+    if !options.force {
+        for file in &files {
+            // original is the modified image with its changes undone, not read again
+            let before = match storage.exists(file.input) {
+                true => match Change::revert(file.png.chunks(), &file.changes) {
+                    Some(chunks) => chunks,
+                    // changes that don't match the image leave nothing to compare with
+                    None => continue,
+                },
+                false => vec![],
+            };
+            ordering::check_modified(&before, file.png.chunks()).with_context(|| {
+                format!(
+                    "{} would break PNG structure of {}, use --force to write it anyway",
+                    operation,
+                    file.output.display()
+                )
+            })?;
+        }
+    }
+    if options.dry_run {
+        return print_dry_run(storage, &files);
+    }
+    ensure!(
+        files
+            .iter()
+            .all(|f| storage.is_local(f.input) && storage.is_local(f.output))
+            || !(options.trash || options.journal),
+        "--trash and --journal only work with local files"
+    );
+    let recorded;
+    let files = if options.history {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        recorded = files
+            .iter()
+            .map(|file| history::record(file.png, operation, &file.changes, timestamp))
+            .collect::<Result<Vec<_>>>()?;
+        files
+            .into_iter()
+            .zip(&recorded)
+            .map(|(file, png)| Modified { png, ..file })
+            .collect()
+    } else {
+        files
+    };
+
+    let receipt = match &options.receipt {
+        Some((_, key_path)) => Some((
+            signature::read_signing_key(key_path)?,
+            receipt(storage, operation, &files)?,
+        )),
+        None => None,
+    };
+
+    let changelog = match &options.changelog {
+        Some(_) => Some(changelog(storage, operation, &files)?),
+        None => None,
+    };
+
+    let mut originals = vec![];
+    for file in &files {
+        if options.preserve_times.unwrap_or(file.input == file.output) {
+            let modified = storage.modified(file.input)?;
+            let permissions = storage.permissions(file.input)?;
+            originals.push((file.output, modified, permissions));
+        }
+    }
+
+    // single file with chunks added before IEND only needs its end rewritten,
+    // several files go through transaction so that they are updated together
+    let rewrite_tail = files.len() == 1 && !options.trash;
+    let mut transaction = Transaction::new(storage);
+    let mut progress = Progress::new("Writing", Unit::Files, files.len() as u64);
+    for file in &files {
+        for change in &file.changes {
+            let (action, index, chunk) = match change {
+                Change::Added { index, chunk } => ("Inserted", index, chunk),
+                Change::Removed { index, chunk } => ("Removed", index, chunk),
+            };
+            let chunk_type = String::from_utf8_lossy(&chunk[4..8]);
+            info!("{} {} chunk at index {}", action, chunk_type, index);
+        }
+        if rewrite_tail && file.input == file.output && replace_tail(storage, file)? {
+            debug!("Rewrote end of {}", file.output.display());
+        } else {
+            transaction.stage(file.output, &file.png.as_bytes())?;
+        }
+        progress.inc(1);
+    }
+    drop(progress);
+    if options.trash {
+        for file in &files {
+            if storage.exists(file.output) {
+                let trashed = trash::trash(file.output)?;
+                outln!("Original saved to {}", trashed.display());
+            }
+        }
+    }
+    transaction.commit()?;
+    for file in &files {
+        info!("Saved {}", file.output.display());
+    }
+    // permissions go last, original ones may not allow writing to set time
+    for (path, modified, permissions) in originals {
+        if let Some(modified) = modified {
+            storage.set_modified(path, modified)?;
+        }
+        if let Some(permissions) = permissions {
+            storage.set_permissions(path, permissions)?;
+        }
+    }
+
+    if options.journal {
+        for file in files {
+            let file_name = file
+                .output
+                .file_name()
+                .context("Can't journal file without name")?
+                .to_string_lossy()
+                .into_owned();
+            Journal::for_file(file.output).record(&Entry {
+                file_name,
+                changes: file.changes,
+            })?;
+        }
+    }
+    if let (Some((path, _)), Some((key, receipt))) = (&options.receipt, receipt) {
+        fs::write(path, receipt.sign(&key) + "\n")
+            .with_context(|| format!("Failed to write receipt {}", path.display()))?;
+        outln!("Receipt written to {}", path.display());
+    }
+    if let (Some(path), Some(changelog)) = (&options.changelog, changelog) {
+        storage.write(path, (json::pretty(&changelog) + "\n").as_bytes())?;
+        outln!("Changelog written to {}", path.display());
+    }
+    Ok(())
+}
+
+/// Writes chunks inserted before IEND over the end of file in place,
+/// `false` if file has to be written whole
+#[cfg(feature = "write")]
+fn replace_tail(storage: &dyn Storage, file: &Modified) -> Result<bool> {
+    let Some((offset, tail)) = file.png.appended_tail() else {
+        return Ok(false);
+    };
+    // tail ends with the IEND that is in the file now
+    let end = &tail[tail.len() - 12..];
+    storage.replace_tail(file.output, offset as u64, end, &tail)
+}
+
+/// Lists changes of every file along with its size before and after
+#[cfg(feature = "write")]
+fn print_dry_run(storage: &dyn Storage, files: &[Modified]) -> Result<()> {
+    for file in files {
+        let before = storage.read(file.input)?.len();
+        let after = file.png.byte_size();
+        outln!(
+            "Would write {}: {} -> {} bytes ({:+})",
+            file.output.display(),
+            before,
+            after,
+            after as i64 - before as i64
+        );
+        for change in &file.changes {
+            let (sign, index, chunk) = match change {
+                Change::Added { index, chunk } => ('+', index, chunk),
+                Change::Removed { index, chunk } => ('-', index, chunk),
+            };
+            outln!(
+                "    {} {} at index {}, {} bytes",
+                sign,
+                String::from_utf8_lossy(&chunk[4..8]),
+                index,
+                chunk.len()
+            );
+        }
+    }
+    outln!("Dry run, nothing was written");
+    Ok(())
+}
+
+/// Chunk changes of every file, must be called before they are written
+#[cfg(feature = "write")]
+fn changelog(storage: &dyn Storage, operation: &str, files: &[Modified]) -> Result<Value> {
+    let files = files
+        .iter()
+        .map(|file| {
+            let input = match storage.exists(file.input) {
+                true => storage.read(file.input)?,
+                false => vec![],
+            };
+            let output = file.png.as_bytes();
+            let input_sha256 = match input.is_empty() {
+                true => None,
+                false => Some(receipt::digest(&input)),
+            };
+            Ok(json!({
+                "input": file.input.display().to_string(),
+                "input_sha256": input_sha256,
+                "output": file.output.display().to_string(),
+                "output_sha256": receipt::digest(&output),
+                "changes": changelog::changes(&input, &output),
+            }))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(json!({ "operation": operation, "files": files }))
+}
+
+/// Describes files before and after operation, must be called before they are written
+#[cfg(feature = "write")]
+fn receipt(storage: &dyn Storage, operation: &str, files: &[Modified]) -> Result<Receipt> {
+    let files = files
+        .iter()
+        .map(|file| {
+            let changes = file
+                .changes
+                .iter()
+                .map(|change| {
+                    let (action, index, chunk) = match change {
+                        Change::Added { index, chunk } => ("added", *index, chunk),
+                        Change::Removed { index, chunk } => ("removed", *index, chunk),
+                    };
+                    ChunkChange {
+                        action: action.to_string(),
+                        index,
+                        chunk_type: String::from_utf8_lossy(&chunk[4..8]).into_owned(),
+                        sha256: receipt::digest(chunk),
+                    }
+                })
+                .collect();
+            Ok(FileRecord {
+                input: file.input.display().to_string(),
+                input_sha256: receipt::digest(&storage.read(file.input)?),
+                output: file.output.display().to_string(),
+                output_sha256: receipt::digest(&file.png.as_bytes()),
+                changes,
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(Receipt {
+        operation: operation.to_string(),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        files,
+    })
+}
+
+#[cfg(all(test, feature = "write"))]
+mod tests {
+    use std::str::FromStr;
+
+    use pngme::codec::PayloadCodec;
+
+    use super::*;
+    use crate::args::{Encoding, Growth};
+    use crate::config::{ProvenanceNames, Recorded};
+    use crate::deflate;
+    use crate::json::Fields;
+    use crate::provenance;
+    use crate::storage::memory::MemoryStorage;
+
+    fn storage_with_image(path: &str) -> MemoryStorage {
+        let storage = MemoryStorage::default();
+        let png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ]);
+        storage.write(Path::new(path), &png.as_bytes()).unwrap();
+        storage
+    }
+
+    fn encode_args(write: WriteOptions) -> EncodeArgs {
+        EncodeArgs {
+            file_path: "dice.png".into(),
+            chunk_type: Some(ChunkType::from_str("ruSt").unwrap()),
+            message: Message::Text("hello".to_string()),
+            input_encoding: None,
+            output: Some("out.png".into()),
+            hmac_key: None,
+            ecc: None,
+            obfuscate: None,
+            expires: None,
+            filter: None,
+            #[cfg(feature = "age")]
+            recipients: vec![],
+            provenance: None,
+            max_growth: None,
+            mode: None,
+            write,
+        }
+    }
+
+    #[test]
+    fn test_encode_in_memory() {
+        let storage = storage_with_image("dice.png");
+        encode(encode_args(WriteOptions::default()), &storage).unwrap();
+
+        let png = read_png(&storage, Path::new("out.png")).unwrap();
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"hello");
+        assert!(read_png(&storage, Path::new("dice.png"))
+            .unwrap()
+            .chunk_by_type("ruSt")
+            .is_none());
+    }
+
+    #[test]
+    fn test_encode_decode_with_codec() {
+        /// Adds one to every byte
+        struct Shift;
+
+        impl PayloadCodec for Shift {
+            fn name(&self) -> &str {
+                "shift"
+            }
+
+            fn encode(&self, payload: Vec<u8>) -> Result<Vec<u8>> {
+                Ok(payload.iter().map(|b| b.wrapping_add(1)).collect())
+            }
+
+            fn decode(&self, payload: Vec<u8>) -> Result<Vec<u8>> {
+                Ok(payload.iter().map(|b| b.wrapping_sub(1)).collect())
+            }
+        }
+
+        let with_shift = || {
+            let mut registry = embedders();
+            registry.add_codec(Box::new(Shift));
+            registry
+        };
+        let storage = storage_with_image("dice.png");
+        encode_with(encode_args(WriteOptions::default()), &storage, with_shift()).unwrap();
+        let png = read_png(&storage, Path::new("out.png")).unwrap();
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"ifmmp");
+
+        let args = DecodeArgs {
+            file_path: "out.png".into(),
+            chunk_types: vec![ChunkType::from_str("ruSt").unwrap()],
+            hmac_key: None,
+            obfuscate: None,
+            raw: false,
+            encoding: None,
+            output_dir: None,
+            output: Some("message.txt".into()),
+            #[cfg(feature = "clipboard")]
+            copy: false,
+            #[cfg(feature = "age")]
+            identity: None,
+            filter: None,
+            strict: false,
+            mode: None,
+            stream: false,
+            max_size: None,
+        };
+        decode_with(args, &storage, with_shift()).unwrap();
+        assert_eq!(storage.read(Path::new("message.txt")).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_trailing() {
+        let storage = storage_with_image("dice.png");
+        let path = Path::new("dice.png");
+        let original = storage.read(path).unwrap();
+        storage.write(Path::new("data.bin"), b"appended").unwrap();
+        let set = TrailingSetArgs {
+            file_path: path.into(),
+            payload_path: "data.bin".into(),
+            write: WriteOptions::default(),
+        };
+        trailing_set(set, &storage).unwrap();
+        let png = read_png(&storage, path).unwrap();
+        assert_eq!(png.trailing(), b"appended");
+        assert_eq!(storage.read(path).unwrap().len(), original.len() + 8);
+
+        let remove = |storage: &MemoryStorage| {
+            let args = TrailingRemoveArgs {
+                file_path: path.into(),
+                write: WriteOptions::default(),
+            };
+            trailing_remove(args, storage)
+        };
+        remove(&storage).unwrap();
+        assert_eq!(storage.read(path).unwrap(), original);
+        let err = remove(&storage).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Failure>().unwrap().code,
+            Failure::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn test_encode_max_growth() {
+        let storage = storage_with_image("dice.png");
+        // "hello" takes 17 bytes as a chunk
+        let mut args = encode_args(WriteOptions::default());
+        args.max_growth = Some(Growth::Bytes(16));
+        let err = encode(args, &storage).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Failure>().unwrap().code,
+            Failure::VALIDATION
+        );
+        assert!(!storage.exists(Path::new("out.png")));
+
+        let mut args = encode_args(WriteOptions::default());
+        args.max_growth = Some(Growth::Bytes(17));
+        encode(args, &storage).unwrap();
+        assert!(storage.exists(Path::new("out.png")));
+    }
+
+    #[test]
+    fn test_encode_with_provenance() {
+        let storage = storage_with_image("dice.png");
+        encode(encode_args(WriteOptions::default()), &storage).unwrap();
+        let png = read_png(&storage, Path::new("out.png")).unwrap();
+        assert!(png.chunk_by_type(provenance::PROVENANCE_CHUNK).is_none());
+
+        let mut args = encode_args(WriteOptions::default());
+        args.provenance = Some(ProvenanceNames {
+            user: Recorded::Name("alice".to_string()),
+            host: Recorded::Omitted,
+        });
+        encode(args, &storage).unwrap();
+        let png = read_png(&storage, Path::new("out.png")).unwrap();
+        let types: Vec<_> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "ruSt", "prVn", "IEND"]);
+        let text = png.chunks()[2].data_as_string().unwrap();
+        assert!(text.contains("chunk: ruSt\n"));
+        assert!(text.contains("user: alice\n"));
+        assert!(!text.contains("host"));
+    }
+
+    #[test]
+    fn test_encode_encoded_input() {
+        let storage = storage_with_image("dice.png");
+        let mut args = encode_args(WriteOptions::default());
+        args.message = Message::Text("00ff".to_string());
+        args.input_encoding = Some(Encoding::Hex);
+        encode(args, &storage).unwrap();
+        let png = read_png(&storage, Path::new("out.png")).unwrap();
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), [0, 0xff]);
+
+        let mut args = encode_args(WriteOptions::default());
+        args.message = Message::Text("not hex".to_string());
+        args.input_encoding = Some(Encoding::Hex);
+        assert!(encode(args, &storage).is_err());
+    }
+
+    #[test]
+    fn test_check_collision_exit_code() {
+        let code = |chunk_type: &str| {
+            let chunk_type = ChunkType::from_str(chunk_type).unwrap();
+            check_collision(&chunk_type, false)
+                .err()
+                .map(|err| err.downcast_ref::<Failure>().unwrap().code)
+        };
+        assert_eq!(code("ruSt"), None);
+        assert_eq!(code("tEXt"), Some(Failure::USAGE));
+        assert_eq!(code("RUST"), Some(Failure::USAGE));
+        let text = ChunkType::from_str("tEXt").unwrap();
+        assert!(check_collision(&text, true).is_ok());
+    }
+
+    #[test]
+    fn test_dpi_get_without_phys() {
+        let storage = storage_with_image("dice.png");
+        let args = DpiGetArgs {
+            file_path: "dice.png".into(),
+        };
+        let err = dpi_get(args, &storage).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Failure>().unwrap().code,
+            Failure::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let storage = storage_with_image("dice.png");
+        encode(encode_args(WriteOptions::default()), &storage).unwrap();
+        let code = |path: &str, chunk_type: &str, expected: &str| {
+            let args = VerifyMessageArgs {
+                file_path: path.into(),
+                chunk_type: ChunkType::from_str(chunk_type).unwrap(),
+                expected: Expected::Text(expected.to_string()),
+                hmac_key: None,
+                obfuscate: None,
+            };
+            verify_message(args, &storage)
+                .err()
+                .map(|err| err.downcast_ref::<Failure>().unwrap().code)
+        };
+        assert_eq!(code("out.png", "ruSt", "hello"), None);
+        assert_eq!(code("out.png", "ruSt", "bye"), Some(Failure::VALIDATION));
+        assert_eq!(code("out.png", "abCd", "hello"), Some(Failure::NOT_FOUND));
+        storage.write(Path::new("bad.png"), b"not a png").unwrap();
+        assert_eq!(code("bad.png", "ruSt", "hello"), Some(Failure::PARSE_ERROR));
+    }
+
+    #[test]
+    fn test_remove_with_dump() {
+        let storage = storage_with_image("dice.png");
+        encode(encode_args(WriteOptions::default()), &storage).unwrap();
+        let remove_args = |dump: &str, raw: bool| RemoveArgs {
+            file_path: "out.png".into(),
+            chunk_type: ChunkType::from_str("ruSt").unwrap(),
+            dump: Some(dump.into()),
+            raw,
+            write: WriteOptions::default(),
+        };
+        storage.write(Path::new("taken.bin"), b"").unwrap();
+        assert!(remove(remove_args("taken.bin", false), &storage).is_err());
+
+        remove(remove_args("ruSt.bin", false), &storage).unwrap();
+        assert_eq!(storage.read(Path::new("ruSt.bin")).unwrap(), b"hello");
+        let png = read_png(&storage, Path::new("out.png")).unwrap();
+        assert!(png.chunk_by_type("ruSt").is_none());
+    }
+
+    #[test]
+    fn test_move_checks_structure() {
+        let storage = storage_with_image("dice.png");
+        encode(encode_args(WriteOptions::default()), &storage).unwrap();
+        let move_args = |force| MoveArgs {
+            file_path: "out.png".into(),
+            chunk: ChunkRef::Type(ChunkType::from_str("ruSt").unwrap()),
+            to: Destination::Index(2),
+            write: WriteOptions {
+                force,
+                ..Default::default()
+            },
+        };
+        let original = storage.read(Path::new("out.png")).unwrap();
+        let err = move_chunk(move_args(false), &storage).unwrap_err();
+        assert!(format!("{:#}", err).contains("IEND must be the last chunk"));
+        assert_eq!(storage.read(Path::new("out.png")).unwrap(), original);
+
+        move_chunk(move_args(true), &storage).unwrap();
+        let png = read_png(&storage, Path::new("out.png")).unwrap();
+        assert_eq!(png.chunks().len(), 2);
+        assert_eq!(png.trailing().len(), 17);
+    }
+
+    #[test]
+    fn test_optimize() {
+        let storage = MemoryStorage::default();
+        let pixels = [0u8, 7, 7, 7, 7, 7, 7, 7, 7].repeat(50);
+        // poorly compressed, as some encoders write it
+        let compressed = deflate::zlib_store(&pixels);
+        let (a, b) = compressed.split_at(compressed.len() / 2);
+        let png = Png::from_chunks(
+            [
+                ("IHDR", &[0; 13][..]),
+                ("IDAT", a),
+                ("IDAT", b),
+                ("IEND", b""),
+            ]
+            .map(|(t, d)| Chunk::new(ChunkType::from_str(t).unwrap(), d.to_vec()))
+            .into(),
+        );
+        storage.write(Path::new("in.png"), &png.as_bytes()).unwrap();
+        let args = || OptimizeArgs {
+            file_path: "in.png".into(),
+            recompress: true,
+            write: WriteOptions::default(),
+        };
+        optimize(args(), &storage).unwrap();
+
+        let optimized = read_png(&storage, Path::new("in.png")).unwrap();
+        assert_eq!(optimized.chunks().len(), 3);
+        assert!(optimized.chunks()[1].length() < compressed.len() as u32 / 4);
+        assert_eq!(pixels::compare(&png, &optimized, true).unwrap(), None);
+        // nothing left to gain second time
+        optimize(args(), &storage).unwrap();
+        let again = read_png(&storage, Path::new("in.png")).unwrap();
+        assert_eq!(again.as_bytes(), optimized.as_bytes());
+    }
+
+    #[test]
+    fn test_batch() {
+        let storage = storage_with_image("assets/dice.png");
+        let manifest = "- input: dice.png\n  output: out.png\n  type: ruSt\n  message: hi\n\
+                        - input: missing.png\n  type: ruSt\n  message: hi\n";
+        storage
+            .write(Path::new("assets/manifest.yaml"), manifest.as_bytes())
+            .unwrap();
+        let args = BatchArgs {
+            manifest: "assets/manifest.yaml".into(),
+            write: WriteOptions::default(),
+        };
+        let err = batch(args, &storage).unwrap_err();
+        assert_eq!(err.to_string(), "1 of 2 entries failed");
+
+        let png = read_png(&storage, Path::new("assets/out.png")).unwrap();
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"hi");
+    }
+
+    #[test]
+    fn test_encode_into_standard_type() {
+        let storage = storage_with_image("dice.png");
+        for chunk_type in ["IDAT", "tRNS", "RuSt"] {
+            let mut args = encode_args(WriteOptions::default());
+            args.chunk_type = Some(ChunkType::from_str(chunk_type).unwrap());
+            assert!(encode(args, &storage).is_err(), "{}", chunk_type);
+        }
+        assert!(!storage.exists(Path::new("out.png")));
+
+        let mut args = encode_args(WriteOptions {
+            force: true,
+            ..Default::default()
+        });
+        args.chunk_type = Some(ChunkType::from_str("tRNS").unwrap());
+        encode(args, &storage).unwrap();
+        assert!(storage.exists(Path::new("out.png")));
+    }
+
+    #[test]
+    fn test_decode_detects_extension() {
+        let storage = storage_with_image("dice.png");
+        let mut args = encode_args(WriteOptions::default());
+        args.message = Message::Text("%PDF-1.7".to_string());
+        encode(args, &storage).unwrap();
+
+        let decode_args = |output_dir: Option<&str>, output: Option<&str>| DecodeArgs {
+            file_path: "out.png".into(),
+            chunk_types: vec![ChunkType::from_str("ruSt").unwrap()],
+            hmac_key: None,
+            obfuscate: None,
+            raw: false,
+            encoding: None,
+            output_dir: output_dir.map(Into::into),
+            output: output.map(Into::into),
+            #[cfg(feature = "clipboard")]
+            copy: false,
+            #[cfg(feature = "age")]
+            identity: None,
+            filter: None,
+            strict: false,
+            mode: None,
+            stream: false,
+            max_size: None,
+        };
+        decode(decode_args(Some("payloads"), None), &storage).unwrap();
+        assert_eq!(
+            storage.read(Path::new("payloads/ruSt.pdf")).unwrap(),
+            b"%PDF-1.7"
+        );
+        decode(decode_args(None, Some("report")), &storage).unwrap();
+        assert!(storage.exists(Path::new("report.pdf")));
+        decode(decode_args(None, Some("report.bin")), &storage).unwrap();
+        assert!(storage.exists(Path::new("report.bin")));
+    }
+
+    #[test]
+    fn test_encode_decode_modes() {
+        let storage = storage_with_image("dice.png");
+        let mut args = encode_args(WriteOptions::default());
+        args.chunk_type = None;
+        args.mode = Some("trailing".to_string());
+        encode(args, &storage).unwrap();
+        let png = read_png(&storage, Path::new("out.png")).unwrap();
+        assert_eq!(png.trailing(), b"hello");
+        assert_eq!(png.chunks().len(), 2);
+
+        let decode_args = |mode: &str, output: &str| DecodeArgs {
+            file_path: "out.png".into(),
+            chunk_types: vec![],
+            hmac_key: None,
+            obfuscate: None,
+            raw: false,
+            encoding: None,
+            output_dir: None,
+            output: Some(output.into()),
+            #[cfg(feature = "clipboard")]
+            copy: false,
+            #[cfg(feature = "age")]
+            identity: None,
+            filter: None,
+            strict: false,
+            mode: Some(mode.to_string()),
+            stream: false,
+            max_size: None,
+        };
+        decode(decode_args("trailing", "trailing.txt"), &storage).unwrap();
+        assert_eq!(storage.read(Path::new("trailing.txt")).unwrap(), b"hello");
+        let err = decode(decode_args("text", "text.txt"), &storage).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Failure>().unwrap().code,
+            Failure::NOT_FOUND
+        );
+        let err = decode(decode_args("unknown", "x.txt"), &storage).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Failure>().unwrap().code,
+            Failure::VALIDATION
+        );
+    }
+
+    #[test]
+    fn test_dry_run_writes_nothing() {
+        let storage = storage_with_image("dice.png");
+        let write = WriteOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        encode(encode_args(write), &storage).unwrap();
+        assert!(!storage.exists(Path::new("out.png")));
+    }
+
+    #[test]
+    fn test_emit_changelog() {
+        let storage = storage_with_image("dice.png");
+        let write = WriteOptions {
+            changelog: Some("changes.json".into()),
+            ..Default::default()
+        };
+        encode(encode_args(write), &storage).unwrap();
+        let text = storage.read(Path::new("changes.json")).unwrap();
+        let log = json::parse(std::str::from_utf8(&text).unwrap()).unwrap();
+        assert_eq!(log.str_field("operation").unwrap(), "encode");
+        let file = &log.array_field("files").unwrap()[0];
+        assert_eq!(file.str_field("output").unwrap(), "out.png");
+        let changes = file.array_field("changes").unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].str_field("action").unwrap(), "added");
+        assert_eq!(changes[0].str_field("type").unwrap(), "ruSt");
+        let after = changes[0].get("after").unwrap();
+        assert_eq!(after.u64_field("offset").unwrap(), 8 + 25);
+    }
+
+    #[test]
+    fn test_preserve_times_in_place() {
+        use std::time::Duration;
+
+        use crate::storage::LocalFs;
+
+        let path = std::env::temp_dir().join("pngme_test_preserve.png");
+        let bytes = storage_with_image("dice.png").read(Path::new("dice.png"));
+        fs::write(&path, bytes.unwrap()).unwrap();
+        let old = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        LocalFs.set_modified(&path, old).unwrap();
+
+        let mut args = encode_args(WriteOptions::default());
+        args.file_path = path.clone();
+        args.output = None;
+        encode(args, &LocalFs).unwrap();
+        let modified = LocalFs.modified(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(modified, Some(old));
+    }
+
+    #[test]
+    fn test_undo_from_history() {
+        let storage = storage_with_image("dice.png");
+        let write = WriteOptions {
+            history: true,
+            ..Default::default()
+        };
+        encode(encode_args(write), &storage).unwrap();
+        let png = read_png(&storage, Path::new("out.png")).unwrap();
+        assert!(png.chunk_by_type("hiSt").is_some());
+
+        let args = UndoArgs {
+            path: "out.png".into(),
+            steps: 1,
+        };
+        undo(args, &storage).unwrap();
+        assert_eq!(
+            storage.read(Path::new("out.png")).unwrap(),
+            storage.read(Path::new("dice.png")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_trash_needs_local_storage() {
+        let storage = storage_with_image("dice.png");
+        let write = WriteOptions {
+            trash: true,
+            ..Default::default()
+        };
+        assert!(encode(encode_args(write), &storage).is_err());
+    }
+}
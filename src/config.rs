@@ -0,0 +1,223 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::registry;
+
+/// What to do with original file before it is overwritten
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backup {
+    #[default]
+    None,
+    Journal,
+    Trash,
+}
+
+/// User or host name written by `encode --provenance`
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum Recorded {
+    /// Taken from environment
+    #[default]
+    Detected,
+    Name(String),
+    /// Set to `false` in config, left out of provenance
+    Omitted,
+}
+
+/// Names recorded in provenance chunk
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ProvenanceNames {
+    pub user: Recorded,
+    pub host: Recorded,
+}
+
+/// Defaults for command line options, read from `config.toml`.
+/// Flags given on command line take precedence
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Config {
+    /// Chunk type used by encode, decode and verify-message when none is given
+    pub chunk_type: Option<String>,
+    /// Show content of compressed chunks in print
+    pub decompress: bool,
+    pub color: bool,
+    pub backup: Backup,
+    /// Record operations in history chunk of modified images
+    pub history: bool,
+    /// Command that encode pipes message through, e.g. `gpg --encrypt -r KEY`
+    pub encode_filter: Option<String>,
+    /// Command that decode pipes message through, e.g. `gpg --decrypt`
+    pub decode_filter: Option<String>,
+    pub provenance: ProvenanceNames,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            chunk_type: None,
+            decompress: false,
+            color: true,
+            backup: Backup::None,
+            history: false,
+            encode_filter: None,
+            decode_filter: None,
+            provenance: ProvenanceNames::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads config from path given by `--config` (taking it out of args),
+    /// or from default location if file exists there
+    pub fn load(args: &mut Vec<String>) -> Result<Config> {
+        let path = match args.iter().position(|a| a == "--config") {
+            Some(pos) if pos + 1 < args.len() => {
+                args.remove(pos);
+                PathBuf::from(args.remove(pos))
+            }
+            Some(_) => bail!("Option --config requires a value"),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config {}", path.display()))?;
+        Config::parse(&text).with_context(|| format!("Invalid config {}", path.display()))
+    }
+
+    /// Parses flat TOML table, unknown keys and values of wrong type are errors
+    fn parse(text: &str) -> Result<Config> {
+        let raw: RawConfig = toml::from_str(text)?;
+        let mut config = Config::default();
+        if let Some(name) = raw.chunk_type {
+            registry::resolve(&name).context("Invalid value of 'type'")?;
+            config.chunk_type = Some(name);
+        }
+        config.decompress = raw.decompress.unwrap_or(config.decompress);
+        config.color = raw.color.unwrap_or(config.color);
+        config.backup = raw.backup.unwrap_or(config.backup);
+        config.history = raw.history.unwrap_or(config.history);
+        config.encode_filter = raw.encode_filter;
+        config.decode_filter = raw.decode_filter;
+        if let Some(user) = raw.provenance_user {
+            config.provenance.user = user
+                .try_into()
+                .context("Invalid value of 'provenance-user'")?;
+        }
+        if let Some(host) = raw.provenance_host {
+            config.provenance.host = host
+                .try_into()
+                .context("Invalid value of 'provenance-host'")?;
+        }
+        Ok(config)
+    }
+}
+
+/// Config file as written, flat table with every key optional
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct RawConfig {
+    #[serde(rename = "type")]
+    chunk_type: Option<String>,
+    decompress: Option<bool>,
+    color: Option<bool>,
+    backup: Option<Backup>,
+    history: Option<bool>,
+    encode_filter: Option<String>,
+    decode_filter: Option<String>,
+    provenance_user: Option<RawRecorded>,
+    provenance_host: Option<RawRecorded>,
+}
+
+/// Name in quotes, or `false` to leave it out
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawRecorded {
+    Name(String),
+    Flag(bool),
+}
+
+impl TryFrom<RawRecorded> for Recorded {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawRecorded) -> Result<Self> {
+        match raw {
+            RawRecorded::Name(name) => Ok(Recorded::Name(name)),
+            RawRecorded::Flag(false) => Ok(Recorded::Omitted),
+            RawRecorded::Flag(true) => bail!("expected quoted name or false"),
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/pngme/config.toml`, `~/.config/pngme/config.toml` if it is not set
+fn default_path() -> Option<PathBuf> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("pngme").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(
+            "# pngme defaults\n\ntype = \"ruSt\"  # hide here\ncolor = false\nbackup = \"trash\"\nhistory = true\n\
+             decode-filter = \"gpg --decrypt\"\nprovenance-user = \"ci\"\nprovenance-host = false\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                chunk_type: Some("ruSt".to_string()),
+                decompress: false,
+                color: false,
+                backup: Backup::Trash,
+                history: true,
+                encode_filter: None,
+                decode_filter: Some("gpg --decrypt".to_string()),
+                provenance: ProvenanceNames {
+                    user: Recorded::Name("ci".to_string()),
+                    host: Recorded::Omitted,
+                },
+            }
+        );
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Config::parse("type = ruSt").is_err());
+        assert!(Config::parse("type = \"ru\"").is_err());
+        assert!(Config::parse("color = yes").is_err());
+        assert!(Config::parse("backup = \"cloud\"").is_err());
+        assert!(Config::parse("compress = true").is_err());
+        assert!(Config::parse("[defaults]").is_err());
+        assert!(Config::parse("history = \"yes\"").is_err());
+        assert!(Config::parse("provenance-user = true").is_err());
+        assert!(Config::parse("color = false\ncolor = true").is_err());
+    }
+
+    #[test]
+    fn test_load_explicit_path() {
+        let mut args = vec!["print".to_string(), "--config".to_string()];
+        assert!(Config::load(&mut args).is_err());
+
+        let path = env::temp_dir().join("pngme_test_config.toml");
+        fs::write(&path, "decompress = true\n").unwrap();
+        let mut args: Vec<String> = ["--config", path.to_str().unwrap(), "print", "dice.png"]
+            .map(String::from)
+            .to_vec();
+        let config = Config::load(&mut args).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(config.decompress);
+        assert_eq!(args, ["print", "dice.png"]);
+    }
+}
@@ -0,0 +1,128 @@
+#[cfg(feature = "write")]
+use std::time::Duration;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "write")]
+use anyhow::{bail, ensure, Context, Result};
+
+/// Formats time as `YYYY-MM-DDThh:mm:ss` in UTC
+pub fn format_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // civil-from-days algorithm by Howard Hinnant
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Parses `YYYY-MM-DD` or `YYYY-MM-DDThh:mm:ss` in UTC, not earlier than 1970
+#[cfg(feature = "write")]
+pub fn parse_timestamp(s: &str) -> Result<SystemTime> {
+    let (date, time) = s.split_once('T').unwrap_or((s, "00:00:00"));
+    let numbers = |s: &str, separator| -> Result<Vec<u64>> {
+        s.split(separator)
+            .map(|n| {
+                ensure!(
+                    n.bytes().all(|b| b.is_ascii_digit()),
+                    "'{}' is not a number",
+                    n
+                );
+                n.parse()
+                    .with_context(|| format!("'{}' is not a number", n))
+            })
+            .collect()
+    };
+    let (year, month, day) = match numbers(date, '-')?[..] {
+        [year, month, day] => (year, month, day),
+        _ => bail!("Expected date as YYYY-MM-DD, got '{}'", s),
+    };
+    let (hour, minute, second) = match numbers(time, ':')?[..] {
+        [hour, minute, second] => (hour, minute, second),
+        _ => bail!("Expected time as hh:mm:ss, got '{}'", s),
+    };
+    ensure!(year >= 1970, "Dates before 1970 are not supported");
+    ensure!((1..=12).contains(&month), "Invalid month in '{}'", s);
+    ensure!(
+        (1..=days_in_month(year, month)).contains(&day),
+        "Invalid day in '{}'",
+        s
+    );
+    ensure!(
+        hour < 24 && minute < 60 && second < 60,
+        "Invalid time in '{}'",
+        s
+    );
+
+    // days-from-civil algorithm by Howard Hinnant
+    let y = year as i64 - i64::from(month <= 2);
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = (era * 146097 + doe - 719468) as u64;
+    Ok(UNIX_EPOCH + Duration::from_secs(days * 86400 + hour * 3600 + minute * 60 + second))
+}
+
+#[cfg(feature = "write")]
+fn days_in_month(year: u64, month: u64) -> u64 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00");
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        assert_eq!(format_timestamp(time), "2024-02-29T12:34:56");
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_parse_timestamp() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        assert_eq!(parse_timestamp("2024-02-29T12:34:56").unwrap(), time);
+        assert_eq!(parse_timestamp("1970-01-01").unwrap(), UNIX_EPOCH);
+        let time = parse_timestamp("2025-12-31").unwrap();
+        assert_eq!(format_timestamp(time), "2025-12-31T00:00:00");
+
+        assert!(parse_timestamp("2023-02-29").is_err());
+        assert!(parse_timestamp("2025-13-01").is_err());
+        assert!(parse_timestamp("2025-12-31T24:00:00").is_err());
+        assert!(parse_timestamp("31.12.2025").is_err());
+        assert!(parse_timestamp("2025-+1-01").is_err());
+        assert!(parse_timestamp("1969-12-31").is_err());
+    }
+}
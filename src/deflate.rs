@@ -0,0 +1,60 @@
+use std::io::Write;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+/// Compresses data into zlib stream (RFC 1950) with default compression level
+#[cfg(any(test, feature = "write"))]
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    compress(data, Compression::default())
+}
+
+/// Same as [`zlib_compress`], but with the best compression level. Several times slower
+pub fn zlib_compress_best(data: &[u8]) -> Vec<u8> {
+    compress(data, Compression::best())
+}
+
+/// zlib stream of stored blocks, as written by encoders that don't compress
+#[cfg(test)]
+pub fn zlib_store(data: &[u8]) -> Vec<u8> {
+    compress(data, Compression::none())
+}
+
+fn compress(data: &[u8], level: Compression) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::with_capacity(data.len() / 2), level);
+    encoder
+        .write_all(data)
+        .expect("writing to memory doesn't fail");
+    encoder.finish().expect("writing to memory doesn't fail")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inflate::zlib_decompress;
+
+    #[test]
+    fn test_roundtrip() {
+        let repetitive = "hello, hello, hello world! ".repeat(100).into_bytes();
+        let mut noise = vec![];
+        let mut x = 1u32;
+        for _ in 0..70_000 {
+            x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            noise.push((x >> 16) as u8 % 7);
+        }
+        for data in [
+            vec![],
+            b"a".to_vec(),
+            vec![0; 1000],
+            repetitive.clone(),
+            noise,
+        ] {
+            let compressed = zlib_compress(&data);
+            assert_eq!(zlib_decompress(&compressed, data.len()).unwrap(), data);
+            let best = zlib_compress_best(&data);
+            assert_eq!(zlib_decompress(&best, data.len()).unwrap(), data);
+            assert!(best.len() <= compressed.len());
+        }
+        assert!(zlib_compress(&repetitive).len() < repetitive.len() / 10);
+    }
+}
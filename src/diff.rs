@@ -0,0 +1,327 @@
+use crate::chunk::Chunk;
+use crate::registry;
+use crate::text::TextEntry;
+
+/// Chunk-level difference between two images
+pub enum Delta<'a> {
+    Added(&'a Chunk),
+    Removed(&'a Chunk),
+    /// Chunk with the same identity (type and, for text, keyword) got new content
+    Changed(&'a Chunk, &'a Chunk),
+}
+
+/// Finds chunks that are not present in both images, using longest
+/// common subsequence of identical chunks. Leftover removed and added
+/// chunks with the same identity are paired into changes
+pub fn diff<'a>(old: &'a [Chunk], new: &'a [Chunk]) -> Vec<Delta<'a>> {
+    let old_bytes: Vec<_> = old.iter().map(Chunk::as_bytes).collect();
+    let new_bytes: Vec<_> = new.iter().map(Chunk::as_bytes).collect();
+
+    // lcs[i][j] is length of common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old_bytes[i] == new_bytes[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut removed, mut added) = (vec![], vec![]);
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old_bytes[i] == new_bytes[j] {
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            added.push(&new[j]);
+            j += 1;
+        } else {
+            removed.push(&old[i]);
+            i += 1;
+        }
+    }
+
+    let mut deltas = vec![];
+    for old_chunk in removed {
+        match added
+            .iter()
+            .position(|c| identity(c) == identity(old_chunk))
+        {
+            Some(pos) => deltas.push(Delta::Changed(old_chunk, added.remove(pos))),
+            None => deltas.push(Delta::Removed(old_chunk)),
+        }
+    }
+    deltas.extend(added.into_iter().map(Delta::Added));
+    deltas
+}
+
+/// Type of chunk plus keyword for text chunks, which can appear many times
+pub fn identity(chunk: &Chunk) -> (String, Option<String>) {
+    let chunk_type = chunk.chunk_type().to_string();
+    let keyword = matches!(chunk_type.as_str(), "tEXt" | "zTXt" | "iTXt")
+        .then(|| {
+            let end = chunk.data().iter().position(|&b| b == 0)?;
+            Some(String::from_utf8_lossy(&chunk.data()[..end]).into_owned())
+        })
+        .flatten();
+    (chunk_type, keyword)
+}
+
+/// Identity of chunk plus number of earlier chunks with the same identity
+pub type Key = (String, Option<String>, usize);
+
+/// Pairs chunks with keys that tell them apart, along with their bytes
+pub fn keyed(chunks: &[Chunk]) -> Vec<(Key, Vec<u8>)> {
+    let mut keyed: Vec<(Key, Vec<u8>)> = vec![];
+    for chunk in chunks {
+        let (name, keyword) = identity(chunk);
+        let occurrence = keyed
+            .iter()
+            .filter(|((n, k, _), _)| *n == name && *k == keyword)
+            .count();
+        keyed.push(((name, keyword, occurrence), chunk.as_bytes()));
+    }
+    keyed
+}
+
+/// One line per delta, as raw chunk changes
+pub fn describe(deltas: &[Delta]) -> Vec<String> {
+    deltas
+        .iter()
+        .map(|delta| match delta {
+            Delta::Added(chunk) => format!(
+                "+ {}: {} bytes",
+                registry::describe(chunk.chunk_type()),
+                chunk.length()
+            ),
+            Delta::Removed(chunk) => format!(
+                "- {}: {} bytes",
+                registry::describe(chunk.chunk_type()),
+                chunk.length()
+            ),
+            Delta::Changed(old, new) => format!(
+                "~ {}: {} -> {} bytes",
+                registry::describe(new.chunk_type()),
+                old.length(),
+                new.length()
+            ),
+        })
+        .collect()
+}
+
+/// Interprets deltas for humans: decodes well-known chunks and
+/// summarizes image data and private chunks instead of listing them
+pub fn explain(deltas: &[Delta]) -> Vec<String> {
+    let mut lines = vec![];
+    let (mut image_old, mut image_new, mut image_changed) = (0, 0, false);
+    let (mut private_added, mut private_added_size) = (0, 0);
+    let (mut private_removed, mut private_removed_size) = (0, 0);
+
+    for delta in deltas {
+        let chunk = match delta {
+            Delta::Added(chunk) | Delta::Removed(chunk) | Delta::Changed(_, chunk) => chunk,
+        };
+        let chunk_type = chunk.chunk_type();
+        if &chunk_type.bytes() == b"IDAT" {
+            image_changed = true;
+            match delta {
+                Delta::Added(c) => image_new += c.length(),
+                Delta::Removed(c) => image_old += c.length(),
+                Delta::Changed(old, new) => {
+                    image_old += old.length();
+                    image_new += new.length();
+                }
+            }
+            continue;
+        }
+        if !chunk_type.is_public() {
+            match delta {
+                Delta::Added(c) => {
+                    private_added += 1;
+                    private_added_size += c.length() as usize;
+                    continue;
+                }
+                Delta::Removed(c) => {
+                    private_removed += 1;
+                    private_removed_size += c.length() as usize;
+                    continue;
+                }
+                Delta::Changed(..) => {}
+            }
+        }
+
+        let name = chunk_type.to_string();
+        lines.push(match delta {
+            Delta::Added(c) => match summary(c) {
+                Some(summary) => format!("{} added: {}", name, summary),
+                None => format!("{} added ({})", name, format_size(c.length() as usize)),
+            },
+            Delta::Removed(c) => match summary(c) {
+                Some(summary) => format!("{} removed: {}", name, summary),
+                None => format!("{} removed ({})", name, format_size(c.length() as usize)),
+            },
+            Delta::Changed(old, new) => match (&name[..], summary(old), summary(new)) {
+                ("iCCP", Some(old), Some(new)) => {
+                    format!("iCCP profile replaced: {} → {}", old, new)
+                }
+                (_, Some(old), Some(new)) if old == new => {
+                    format!("{} content changed: {}", name, new)
+                }
+                (_, Some(old), Some(new)) => {
+                    format!("{} updated from {} to {}", name, old, new)
+                }
+                _ => format!(
+                    "{} changed ({} → {})",
+                    name,
+                    format_size(old.length() as usize),
+                    format_size(new.length() as usize)
+                ),
+            },
+        });
+    }
+
+    if image_changed {
+        lines.push(format!(
+            "image data changed ({} → {})",
+            format_size(image_old as usize),
+            format_size(image_new as usize)
+        ));
+    }
+    if private_added > 0 {
+        lines.push(format!(
+            "{} new private chunk(s) totalling {}",
+            private_added,
+            format_size(private_added_size)
+        ));
+    }
+    if private_removed > 0 {
+        lines.push(format!(
+            "{} private chunk(s) removed, totalling {}",
+            private_removed,
+            format_size(private_removed_size)
+        ));
+    }
+    lines
+}
+
+/// Short human-readable content of well-known chunks
+fn summary(chunk: &Chunk) -> Option<String> {
+    let data = chunk.data();
+    match &chunk.chunk_type().bytes() {
+        b"tIME" if data.len() == 7 => Some(format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            u16::from_be_bytes([data[0], data[1]]),
+            data[2],
+            data[3],
+            data[4],
+            data[5],
+            data[6]
+        )),
+        b"iCCP" => {
+            let end = data.iter().position(|&b| b == 0)?;
+            Some(String::from_utf8_lossy(&data[..end]).into_owned())
+        }
+        b"tEXt" => {
+            let entry = TextEntry::from_data(data).ok()?;
+            Some(format!("{} = {:?}", entry.keyword, entry.text))
+        }
+        b"iTXt" => {
+            let entry = TextEntry::from_itxt(data).ok()?;
+            Some(format!("{} = {:?}", entry.label(), entry.text))
+        }
+        b"IHDR" if data.len() == 13 => Some(format!(
+            "{}x{}, bit depth {}, color type {}",
+            u32::from_be_bytes(data[0..4].try_into().unwrap()),
+            u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            data[8],
+            data[9]
+        )),
+        _ => None,
+    }
+}
+
+/// Formats byte count as B, KB or MB
+pub fn format_size(bytes: usize) -> String {
+    match bytes {
+        0..1024 => format!("{} B", bytes),
+        1024..1_048_576 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk_type::ChunkType;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn time(year: u16, minute: u8) -> Chunk {
+        let [hi, lo] = year.to_be_bytes();
+        chunk("tIME", &[hi, lo, 5, 1, 10, minute, 0])
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = [
+            chunk("IHDR", b"header"),
+            chunk("tEXt", b"Author\0Jane"),
+            chunk("tEXt", b"Title\0Sunset"),
+            chunk("IDAT", b"pixels"),
+            chunk("IEND", b""),
+        ];
+        let new = [
+            chunk("IHDR", b"header"),
+            chunk("tEXt", b"Author\0Joe"),
+            chunk("tEXt", b"Title\0Sunset"),
+            chunk("IDAT", b"pixels"),
+            chunk("ruSt", b"secret"),
+            chunk("IEND", b""),
+        ];
+        let lines = describe(&diff(&old, &new));
+        assert_eq!(lines, ["~ tEXt (text): 11 -> 10 bytes", "+ ruSt: 6 bytes"]);
+        assert!(diff(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn test_explain() {
+        let old = [
+            chunk("iCCP", b"sRGB\0\0data"),
+            time(2023, 0),
+            chunk("tEXt", b"Author\0Jane"),
+            chunk("IDAT", &[0; 100]),
+        ];
+        let new = [
+            chunk("iCCP", b"Display P3\0\0data"),
+            time(2024, 30),
+            chunk("IDAT", &[1; 2048]),
+            chunk("prVa", &[0; 10240]),
+            chunk("prVb", &[0; 2048]),
+        ];
+        let lines = explain(&diff(&old, &new));
+        assert_eq!(
+            lines,
+            [
+                "iCCP profile replaced: sRGB → Display P3",
+                "tIME updated from 2023-05-01 10:00:00 to 2024-05-01 10:30:00",
+                "tEXt removed: Author = \"Jane\"",
+                "image data changed (100 B → 2.0 KB)",
+                "2 new private chunk(s) totalling 12.0 KB",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(12 * 1024), "12.0 KB");
+        assert_eq!(format_size(3 * 1024 * 1024 / 2), "1.5 MB");
+    }
+}
@@ -0,0 +1,424 @@
+use anyhow::{ensure, Result};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+#[cfg(feature = "write")]
+use crate::journal::Change;
+use crate::ordering;
+use crate::png::{Png, StructureError};
+use crate::raw::{self, RawChunk};
+use crate::scan::{self, Severity};
+use crate::stats::{self, Stats};
+
+/// Metadata is worth pointing out when it takes more than half of a file this big
+const LARGE_METADATA: usize = 4 * 1024;
+
+/// Problem found in the image, with a hint on how to deal with it
+pub struct Issue {
+    pub severity: Severity,
+    /// Position of chunk in file, counting damaged ones
+    pub chunk_index: Option<usize>,
+    pub message: String,
+    pub advice: String,
+    /// Handled by `doctor --fix`
+    pub fixable: bool,
+}
+
+/// What could be made of a chunk record
+enum Found {
+    Intact(Chunk),
+    /// Type is fine, so chunk can be kept with CRC computed anew
+    BadCrc(Chunk),
+    Truncated,
+    InvalidType,
+}
+
+fn classify(raw: &RawChunk) -> Found {
+    if raw.is_truncated() {
+        return Found::Truncated;
+    }
+    if let Ok(chunk) = Chunk::try_from(raw.bytes) {
+        return Found::Intact(chunk);
+    }
+    match ChunkType::try_from(raw.chunk_type) {
+        Ok(chunk_type) => Found::BadCrc(Chunk::new(chunk_type, raw.data().to_vec())),
+        Err(_) => Found::InvalidType,
+    }
+}
+
+/// Runs damage, structure, ordering, size and hidden data checks together.
+/// `file` is used in suggested commands. Issues are sorted by severity, most severe first
+pub fn diagnose(bytes: &[u8], file: &str) -> Result<Vec<Issue>> {
+    ensure!(
+        bytes.starts_with(&Png::STANDARD_HEADER),
+        "Not a PNG file, header is invalid"
+    );
+    let mut issues = vec![];
+    let mut report = |severity, chunk_index, message: String, advice: String, fixable| {
+        issues.push(Issue {
+            severity,
+            chunk_index,
+            message,
+            advice,
+            fixable,
+        })
+    };
+
+    let records = raw::raw_chunks(&bytes[8..]);
+    let end = raw::end_index(&records);
+    let mut chunks = vec![];
+    // position in file of every recovered chunk
+    let mut indices = vec![];
+    let mut trailing = bytes.len() - 8 - records.iter().map(|c| c.bytes.len()).sum::<usize>();
+    for (i, record) in records.iter().enumerate() {
+        let name = String::from_utf8_lossy(&record.chunk_type);
+        match classify(record) {
+            Found::Intact(chunk) => {
+                chunks.push(chunk);
+                indices.push(i);
+            }
+            _ if end.is_some_and(|end| i > end) => trailing += record.bytes.len(),
+            Found::BadCrc(chunk) => {
+                report(
+                    Severity::High,
+                    Some(i),
+                    format!("CRC check failed for {} chunk", name),
+                    "--fix computes CRC anew, data of the chunk may be damaged too".to_string(),
+                    true,
+                );
+                chunks.push(chunk);
+                indices.push(i);
+            }
+            Found::Truncated => report(
+                Severity::High,
+                Some(i),
+                format!("{} chunk is truncated, file ends early", name),
+                "File is incomplete, --fix drops the partial chunk".to_string(),
+                true,
+            ),
+            Found::InvalidType => report(
+                Severity::High,
+                Some(i),
+                format!("Chunk has invalid type {:?}", name),
+                "--fix drops it, such chunk can't be kept in a valid image".to_string(),
+                true,
+            ),
+        }
+    }
+    if trailing > 0 {
+        let message = match end {
+            Some(_) => format!("{} bytes of data after IEND", trailing),
+            None => format!("{} stray bytes at the end of file", trailing),
+        };
+        let advice = format!(
+            "`pngme carve {}` finds images hidden there, --fix drops the data",
+            file
+        );
+        report(Severity::Medium, None, message, advice, true);
+    }
+
+    let png = Png::from_chunks(chunks);
+    match png.check_structure() {
+        Ok(()) => {
+            for violation in ordering::violations(png.chunks()) {
+                let advice = format!(
+                    "`pngme move {} <TYPE|INDEX> --to <INDEX|before-<TYPE>|after-<TYPE>>` puts chunk in place",
+                    file
+                );
+                report(Severity::Medium, None, violation, advice, false);
+            }
+        }
+        // scan reports every such chunk, duplicates are reported below
+        Err(
+            StructureError::ChunksAfterEnd(_)
+            | StructureError::MultipleHeaders(_)
+            | StructureError::MultiplePalettes(_)
+            | StructureError::MultipleEnds(_),
+        ) => {}
+        Err(StructureError::MissingEnd) => report(
+            Severity::High,
+            None,
+            StructureError::MissingEnd.to_string(),
+            "--fix appends it".to_string(),
+            true,
+        ),
+        Err(err) => {
+            let advice = match &err {
+                StructureError::UnknownCritical(t) => format!(
+                    "Decoders refuse such images, `pngme remove {} {} --force` drops the chunk",
+                    file, t
+                ),
+                _ => "Image can't be displayed, it can't be fixed automatically".to_string(),
+            };
+            report(Severity::High, None, err.to_string(), advice, false);
+        }
+    }
+    // checked on their own, as structure check stops at the first problem
+    for duplicate in png.duplicates() {
+        let advice = "Decoders may pick either one, --fix keeps only the first".to_string();
+        report(Severity::High, None, duplicate.to_string(), advice, true);
+    }
+
+    // only worth checking against a header that is there
+    if png.image_header().is_ok() {
+        let checks = [
+            ("sBIT", png.significant_bits().err()),
+            ("hIST", png.histogram().err()),
+        ];
+        for (chunk_type, error) in checks {
+            if let Some(error) = error {
+                let advice = format!(
+                    "Decoders may reject or ignore it, `pngme remove {} {}` drops the chunk",
+                    file, chunk_type
+                );
+                report(
+                    Severity::Medium,
+                    None,
+                    format!("{:#}", error),
+                    advice,
+                    false,
+                );
+            }
+        }
+    }
+
+    if let Ok(stats) = Stats::from_bytes(bytes) {
+        if stats.ancillary >= LARGE_METADATA && stats.ancillary * 2 > stats.total {
+            let message = format!(
+                "Metadata takes {} of file",
+                stats::percent(stats.ancillary, stats.total)
+            );
+            let advice = format!(
+                "`pngme print {} --ancillary-only` lists it, `pngme remove` drops what isn't needed",
+                file
+            );
+            report(Severity::Low, None, message, advice, false);
+        }
+    }
+
+    for finding in scan::scan(&png) {
+        // data after IEND is reported above
+        let Some(chunk_index) = finding.chunk_index else {
+            continue;
+        };
+        let index = indices[chunk_index];
+        let chunk_type = png.chunks()[chunk_index].chunk_type();
+        let after_end = finding.message == "chunk placed after IEND";
+        // repeated IEND is reported as duplicate, which --fix handles
+        if after_end && &chunk_type.bytes() == b"IEND" {
+            continue;
+        }
+        let advice = if after_end {
+            format!(
+                "`pngme move {} {} --to before-IEND` moves it into the image",
+                file, index
+            )
+        } else {
+            format!(
+                "`pngme dump {} {}` shows its data, `pngme remove {} {}` drops it",
+                file, chunk_type, file, chunk_type
+            )
+        };
+        let message = format!("{} chunk: {}", chunk_type, finding.message);
+        report(finding.severity, Some(index), message, advice, false);
+    }
+
+    issues.sort_by_key(|issue| std::cmp::Reverse(issue.severity));
+    Ok(issues)
+}
+
+/// Applies fixes that don't touch intact chunks: CRCs are computed anew, truncated
+/// and invalid chunks, repeated IHDR, PLTE and IEND and data after IEND are dropped,
+/// missing IEND is appended
+#[cfg(feature = "write")]
+pub fn repair(bytes: &[u8]) -> Result<(Png, Vec<Change>)> {
+    ensure!(
+        bytes.starts_with(&Png::STANDARD_HEADER),
+        "Not a PNG file, header is invalid"
+    );
+    let records = raw::raw_chunks(&bytes[8..]);
+    let end = raw::end_index(&records);
+    let mut chunks = vec![];
+    let mut changes = vec![];
+    for (i, record) in records.iter().enumerate() {
+        let index = chunks.len();
+        match classify(record) {
+            Found::Intact(chunk) | Found::BadCrc(chunk) if is_duplicate(&chunk, &chunks) => changes
+                .push(Change::Removed {
+                    index,
+                    chunk: record.bytes.to_vec(),
+                }),
+            Found::Intact(chunk) => chunks.push(chunk),
+            Found::BadCrc(chunk) if end.is_none_or(|end| i < end) => {
+                changes.push(Change::Removed {
+                    index,
+                    chunk: record.bytes.to_vec(),
+                });
+                changes.push(Change::Added {
+                    index,
+                    chunk: chunk.as_bytes(),
+                });
+                chunks.push(chunk);
+            }
+            _ => changes.push(Change::Removed {
+                index,
+                chunk: record.bytes.to_vec(),
+            }),
+        }
+    }
+
+    let mut png = Png::from_chunks(chunks);
+    if png.check_structure() == Err(StructureError::MissingEnd) {
+        let chunk = Chunk::new(ChunkType::try_from(*b"IEND")?, vec![]);
+        changes.push(Change::Added {
+            index: png.chunks().len(),
+            chunk: chunk.as_bytes(),
+        });
+        png.append_chunk(chunk);
+    }
+    Ok((png, changes))
+}
+
+#[cfg(feature = "write")]
+fn is_duplicate(chunk: &Chunk, kept: &[Chunk]) -> bool {
+    let chunk_type = chunk.chunk_type();
+    crate::png::SINGLE_CRITICAL.contains(&chunk_type.bytes())
+        && kept.iter().any(|c| c.chunk_type() == chunk_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn png() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("IDAT", b"pixels"),
+            chunk("IEND", b""),
+        ])
+    }
+
+    #[test]
+    fn test_healthy() {
+        assert!(diagnose(&png().as_bytes(), "a.png").unwrap().is_empty());
+        assert!(diagnose(b"GIF89a", "a.gif").is_err());
+    }
+
+    #[test]
+    fn test_damaged() {
+        let mut bytes = png().as_bytes();
+        // CRC of IDAT
+        bytes[8 + 25 + 14] ^= 1;
+        bytes.extend_from_slice(b"appended");
+        let issues = diagnose(&bytes, "a.png").unwrap();
+        let messages: Vec<_> = issues.iter().map(|i| i.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "CRC check failed for IDAT chunk",
+                "8 bytes of data after IEND"
+            ]
+        );
+        assert_eq!(issues[0].chunk_index, Some(1));
+        assert!(issues.iter().all(|i| i.fixable));
+
+        let truncated = &png().as_bytes()[..8 + 25 + 10];
+        let issues = diagnose(truncated, "a.png").unwrap();
+        assert_eq!(
+            issues[0].message,
+            "IDAT chunk is truncated, file ends early"
+        );
+        assert_eq!(issues[1].message, "Image has no IEND chunk");
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_suggestions() {
+        let mut png = png();
+        png.insert_chunk(2, chunk("ruSt", b"hidden"));
+        png.append_chunk(chunk("tEXt", b"Comment\0after end"));
+        let issues = diagnose(&png.as_bytes(), "a.png").unwrap();
+        assert_eq!(issues[0].severity, Severity::High);
+        assert_eq!(issues[0].chunk_index, Some(4));
+        assert_eq!(
+            issues[0].advice,
+            "`pngme move a.png 4 --to before-IEND` moves it into the image"
+        );
+        assert!(issues.iter().any(|i| i.message.starts_with("ruSt chunk")));
+        assert!(issues.iter().all(|i| !i.fixable));
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_typed_chunks() {
+        let mut png = png();
+        // 1x1 8-bit grayscale
+        png.remove_chunk_at(0);
+        png.insert_chunk(0, chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]));
+        png.insert_chunk(1, chunk("sBIT", &[9]));
+        png.insert_chunk(1, chunk("hIST", &[0, 1]));
+        let issues = diagnose(&png.as_bytes(), "a.png").unwrap();
+        let messages: Vec<_> = issues.iter().map(|i| i.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "sBIT value 9 is out of 1..8 range of 8-bit samples",
+                "hIST chunk is not allowed without PLTE"
+            ]
+        );
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_repair() {
+        let healthy = png().as_bytes();
+        let mut bytes = healthy.clone();
+        bytes[8 + 25 + 14] ^= 1;
+        bytes.extend_from_slice(b"appended");
+        let (repaired, changes) = repair(&bytes).unwrap();
+        assert_eq!(repaired.as_bytes(), healthy);
+        assert_eq!(changes.len(), 3);
+        assert!(diagnose(&repaired.as_bytes(), "a.png").unwrap().is_empty());
+
+        let (repaired, _) = repair(&healthy[..8 + 25 + 10]).unwrap();
+        let types: Vec<_> = repaired
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "IEND"]);
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_duplicate_critical() {
+        let mut png = png();
+        png.insert_chunk(1, chunk("PLTE", &[0; 3]));
+        png.insert_chunk(1, chunk("PLTE", &[1; 3]));
+        png.insert_chunk(1, chunk("IHDR", &[1; 13]));
+        png.append_chunk(chunk("IEND", b""));
+        let issues = diagnose(&png.as_bytes(), "a.png").unwrap();
+        let messages: Vec<_> = issues.iter().map(|i| i.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "Image has 2 IHDR chunks",
+                "Image has 2 PLTE chunks",
+                "Image has 2 IEND chunks"
+            ]
+        );
+        assert!(issues.iter().all(|i| i.fixable));
+
+        let (repaired, changes) = repair(&png.as_bytes()).unwrap();
+        assert_eq!(changes.len(), 3);
+        assert_eq!(repaired.chunks()[0].data(), [0; 13]);
+        assert_eq!(repaired.chunks()[1].data(), [1; 3]);
+        assert!(repaired.check_structure().is_ok());
+    }
+}
@@ -0,0 +1,343 @@
+use anyhow::{bail, ensure, Result};
+
+use crate::gf256;
+
+/// Marks payload wrapped with Reed-Solomon parity
+const MAGIC: &[u8; 4] = b"PRS1";
+/// Header is magic, parity bytes per block and payload length
+const HEADER_SIZE: usize = 9;
+/// Header is protected by its own block with fixed parity
+const HEADER_PARITY: usize = 8;
+/// Maximum length of Reed-Solomon codeword over GF(256)
+const BLOCK_SIZE: usize = 255;
+
+/// Wraps payload with Reed-Solomon parity.
+/// `ratio` is amount of parity relative to data, e.g. 0.25 adds 25% of parity bytes.
+/// Up to half of parity bytes can be corrected in every block
+#[cfg(feature = "write")]
+pub fn wrap(payload: &[u8], ratio: f64) -> Result<Vec<u8>> {
+    ensure!(
+        ratio > 0.0 && ratio <= 1.0,
+        "ECC ratio must be in range (0, 1]"
+    );
+    let parity = ((BLOCK_SIZE as f64 * ratio / (1.0 + ratio)).round() as usize).max(2);
+    let block_data = BLOCK_SIZE - parity;
+
+    let mut header = MAGIC.to_vec();
+    header.push(parity as u8);
+    header.extend_from_slice(&u32::try_from(payload.len())?.to_be_bytes());
+
+    let mut out = encode_block(&header, HEADER_PARITY);
+    for block in payload.chunks(block_data) {
+        out.extend(encode_block(block, parity));
+    }
+    Ok(out)
+}
+
+/// Ratio that data was wrapped with, `None` for data without parity.
+/// Wrapping again with it gives the same amount of parity per block
+#[cfg(feature = "write")]
+pub fn ratio(data: &[u8]) -> Option<f64> {
+    let (header, _) =
+        correct_block(data.get(..HEADER_SIZE + HEADER_PARITY)?, HEADER_PARITY).ok()?;
+    let parity = *header.get(4).filter(|_| header.starts_with(MAGIC))? as f64;
+    // parity of ratio 1 is rounded up, so ratio computed back from it is slightly above
+    Some((parity / (BLOCK_SIZE as f64 - parity)).min(1.0))
+}
+
+/// Checks whether data is a payload wrapped by [`wrap`], and if it is,
+/// corrects errors and returns payload together with number of corrected bytes.
+/// Returns `None` for data without parity
+pub fn unwrap(data: &[u8]) -> Result<Option<(Vec<u8>, usize)>> {
+    let header_len = HEADER_SIZE + HEADER_PARITY;
+    if data.len() < header_len {
+        return Ok(None);
+    }
+    let Ok((header, mut corrected)) = correct_block(&data[..header_len], HEADER_PARITY) else {
+        return Ok(None);
+    };
+    if !header.starts_with(MAGIC) {
+        return Ok(None);
+    }
+
+    let parity = header[4] as usize;
+    let length = u32::from_be_bytes(header[5..9].try_into().unwrap()) as usize;
+    ensure!((2..BLOCK_SIZE).contains(&parity), "Invalid ECC header");
+    let block_data = BLOCK_SIZE - parity;
+
+    let mut rest = &data[header_len..];
+    // length comes from the data itself, so it can't be trusted to allocate
+    let mut payload = Vec::with_capacity(length.min(rest.len()));
+    while payload.len() < length {
+        let block_len = block_data.min(length - payload.len()) + parity;
+        ensure!(rest.len() >= block_len, "ECC payload is truncated");
+        let (block, tail) = rest.split_at(block_len);
+        let (block, fixed) = correct_block(block, parity)?;
+        payload.extend(block);
+        corrected += fixed;
+        rest = tail;
+    }
+    ensure!(rest.is_empty(), "Unexpected data after ECC payload");
+
+    Ok(Some((payload, corrected)))
+}
+
+#[cfg(feature = "write")]
+fn encode_block(data: &[u8], parity: usize) -> Vec<u8> {
+    let generator = generator_poly(parity);
+    let mut out = data.to_vec();
+    out.resize(data.len() + parity, 0);
+
+    for i in 0..data.len() {
+        let coef = out[i];
+        if coef != 0 {
+            for (j, &g) in generator.iter().enumerate().skip(1) {
+                out[i + j] ^= gf256::mul(g, coef);
+            }
+        }
+    }
+    out[..data.len()].copy_from_slice(data);
+    out
+}
+
+/// Corrects errors in codeword, returns data part and number of corrected bytes
+fn correct_block(block: &[u8], parity: usize) -> Result<(Vec<u8>, usize)> {
+    let data_len = block.len() - parity;
+    let mut block = block.to_vec();
+
+    let syndromes = calc_syndromes(&block, parity);
+    if syndromes.iter().all(|&s| s == 0) {
+        block.truncate(data_len);
+        return Ok((block, 0));
+    }
+
+    let locator = error_locator(&syndromes, parity)?;
+    let positions = error_positions(&locator, block.len())?;
+    correct_errata(&mut block, &syndromes, &positions);
+
+    ensure!(
+        calc_syndromes(&block, parity).iter().all(|&s| s == 0),
+        "Too many errors to correct"
+    );
+    block.truncate(data_len);
+    Ok((block, positions.len()))
+}
+
+// Polynomials below are stored with highest degree coefficient first.
+// Decoding follows Berlekamp-Massey for error locator, Chien search
+// for error positions and Forney algorithm for error magnitudes.
+
+#[cfg(feature = "write")]
+fn generator_poly(parity: usize) -> Vec<u8> {
+    (0..parity).fold(vec![1], |g, i| poly_mul(&g, &[1, gf256::pow(2, i as i32)]))
+}
+
+/// Syndromes with leading zero, so indices match the algorithm description
+fn calc_syndromes(block: &[u8], parity: usize) -> Vec<u8> {
+    std::iter::once(0)
+        .chain((0..parity).map(|i| poly_eval(block, gf256::pow(2, i as i32))))
+        .collect()
+}
+
+fn error_locator(syndromes: &[u8], parity: usize) -> Result<Vec<u8>> {
+    let mut locator = vec![1];
+    let mut old_locator = vec![1];
+    let shift = syndromes.len() - parity;
+
+    for i in 0..parity {
+        let k = i + shift;
+        let mut delta = syndromes[k];
+        for j in 1..locator.len() {
+            delta ^= gf256::mul(locator[locator.len() - 1 - j], syndromes[k - j]);
+        }
+        old_locator.push(0);
+
+        if delta != 0 {
+            if old_locator.len() > locator.len() {
+                let new_locator = poly_scale(&old_locator, delta);
+                old_locator = poly_scale(&locator, gf256::inverse(delta));
+                locator = new_locator;
+            }
+            locator = poly_add(&locator, &poly_scale(&old_locator, delta));
+        }
+    }
+
+    let leading_zeros = locator.iter().take_while(|&&c| c == 0).count();
+    locator.drain(..leading_zeros);
+    ensure!(
+        (locator.len() - 1) * 2 <= parity,
+        "Too many errors to correct"
+    );
+    Ok(locator)
+}
+
+fn error_positions(locator: &[u8], block_len: usize) -> Result<Vec<usize>> {
+    let reversed: Vec<u8> = locator.iter().rev().copied().collect();
+    let positions: Vec<usize> = (0..block_len)
+        .filter(|&i| poly_eval(&reversed, gf256::pow(2, i as i32)) == 0)
+        .map(|i| block_len - 1 - i)
+        .collect();
+
+    if positions.len() != locator.len() - 1 {
+        bail!("Too many errors to correct");
+    }
+    Ok(positions)
+}
+
+fn correct_errata(block: &mut [u8], syndromes: &[u8], positions: &[usize]) {
+    let coef_positions: Vec<usize> = positions.iter().map(|&p| block.len() - 1 - p).collect();
+    let locator = coef_positions.iter().fold(vec![1], |loc, &p| {
+        poly_mul(&loc, &poly_add(&[1], &[gf256::pow(2, p as i32), 0]))
+    });
+
+    let reversed_syndromes: Vec<u8> = syndromes.iter().rev().copied().collect();
+    let mut divisor = vec![0; locator.len() + 1];
+    divisor[0] = 1;
+    let product = poly_mul(&reversed_syndromes, &locator);
+    let evaluator = poly_remainder(&product, &divisor);
+
+    let x: Vec<u8> = coef_positions
+        .iter()
+        .map(|&p| gf256::pow(2, p as i32 - 255))
+        .collect();
+
+    for (i, &xi) in x.iter().enumerate() {
+        let xi_inv = gf256::inverse(xi);
+        let locator_derivative = x
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| j != i)
+            .fold(1, |acc, (_, &xj)| {
+                gf256::mul(acc, 1 ^ gf256::mul(xi_inv, xj))
+            });
+
+        let y = gf256::mul(xi, poly_eval(&evaluator, xi_inv));
+        block[positions[i]] ^= gf256::div(y, locator_derivative);
+    }
+}
+
+fn poly_scale(p: &[u8], x: u8) -> Vec<u8> {
+    p.iter().map(|&c| gf256::mul(c, x)).collect()
+}
+
+fn poly_add(p: &[u8], q: &[u8]) -> Vec<u8> {
+    let len = p.len().max(q.len());
+    let mut r = vec![0; len];
+    for (i, &c) in p.iter().enumerate() {
+        r[i + len - p.len()] = c;
+    }
+    for (i, &c) in q.iter().enumerate() {
+        r[i + len - q.len()] ^= c;
+    }
+    r
+}
+
+fn poly_mul(p: &[u8], q: &[u8]) -> Vec<u8> {
+    let mut r = vec![0; p.len() + q.len() - 1];
+    for (j, &qj) in q.iter().enumerate() {
+        for (i, &pi) in p.iter().enumerate() {
+            r[i + j] ^= gf256::mul(pi, qj);
+        }
+    }
+    r
+}
+
+fn poly_remainder(dividend: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut out = dividend.to_vec();
+    for i in 0..dividend.len().saturating_sub(divisor.len() - 1) {
+        let coef = out[i];
+        if coef != 0 {
+            for (j, &d) in divisor.iter().enumerate().skip(1) {
+                if d != 0 {
+                    out[i + j] ^= gf256::mul(d, coef);
+                }
+            }
+        }
+    }
+    out.split_off(out.len() - (divisor.len() - 1))
+}
+
+fn poly_eval(p: &[u8], x: u8) -> u8 {
+    p.iter().fold(0, |y, &c| gf256::mul(y, x) ^ c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "write")]
+    fn message(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + 3) as u8).collect()
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_roundtrip_without_errors() {
+        let payload = message(1000);
+        let wrapped = wrap(&payload, 0.25).unwrap();
+
+        assert_eq!(unwrap(&wrapped).unwrap(), Some((payload, 0)));
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_ratio() {
+        let payload = message(300);
+        for ratio in [0.01, 0.1, 0.25, 1.0] {
+            let wrapped = wrap(&payload, ratio).unwrap();
+            let found = super::ratio(&wrapped).unwrap();
+            assert_eq!(wrap(&payload, found).unwrap(), wrapped);
+        }
+        assert_eq!(super::ratio(&payload), None);
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_corrects_errors() {
+        let payload = message(600);
+        let mut wrapped = wrap(&payload, 0.1).unwrap();
+        // header, and then a few bytes in every data block
+        for i in [0, 5, 20, 21, 100, 230, 300, 301, 302, 600] {
+            wrapped[i] ^= 0x5a;
+        }
+
+        assert_eq!(unwrap(&wrapped).unwrap(), Some((payload, 10)));
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_too_many_errors() {
+        let payload = message(100);
+        let mut wrapped = wrap(&payload, 0.05).unwrap();
+        for byte in &mut wrapped[20..40] {
+            *byte ^= 0xff;
+        }
+
+        assert!(unwrap(&wrapped).is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_huge_length_is_truncated() {
+        let mut header = MAGIC.to_vec();
+        header.push(16);
+        header.extend_from_slice(&u32::MAX.to_be_bytes());
+        let mut data = encode_block(&header, HEADER_PARITY);
+        data.extend(encode_block(&message(100), 16));
+
+        assert!(unwrap(&data).is_err());
+    }
+
+    #[test]
+    fn test_plain_payload_is_not_unwrapped() {
+        assert_eq!(unwrap(b"Just a regular message").unwrap(), None);
+        assert_eq!(unwrap(b"short").unwrap(), None);
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_invalid_ratio() {
+        assert!(wrap(b"data", 0.0).is_err());
+        assert!(wrap(b"data", 1.5).is_err());
+    }
+}
@@ -0,0 +1,71 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{anyhow, ensure, Context, Result};
+
+use crate::hex;
+use crate::scanner;
+
+/// Lets user change `content` in $VISUAL or $EDITOR (vi or notepad if neither is set).
+/// Content is kept in a temporary file readable only by the user, which is
+/// removed as soon as editor exits
+pub fn edit(content: &[u8], extension: &str) -> Result<Vec<u8>> {
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .find_map(|name| env::var(name).ok().filter(|v| !v.trim().is_empty()))
+        .unwrap_or_else(|| if cfg!(windows) { "notepad" } else { "vi" }.to_string());
+    edit_with(&editor, content, extension)
+}
+
+fn edit_with(editor: &str, content: &[u8], extension: &str) -> Result<Vec<u8>> {
+    let mut suffix = [0; 8];
+    getrandom::getrandom(&mut suffix).map_err(|e| anyhow!("Failed to get random bytes: {}", e))?;
+    let path = env::temp_dir().join(format!("pngme-edit-{}.{}", hex::encode(&suffix), extension));
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let written = options
+        .open(&path)
+        .and_then(|mut file| file.write_all(content))
+        .with_context(|| format!("Failed to write {}", path.display()));
+    let edited = written.and_then(|()| run(editor, &path));
+    let _ = fs::remove_file(&path);
+    edited
+}
+
+fn run(editor: &str, path: &Path) -> Result<Vec<u8>> {
+    // on Unix path is passed as argument, so it needs no quoting
+    let mut command = if cfg!(windows) {
+        scanner::shell(&format!("{} \"{}\"", editor, path.display()))
+    } else {
+        let mut command = scanner::shell(&format!("{} \"$1\"", editor));
+        command.arg("pngme").arg(path);
+        command
+    };
+    let status = command
+        .status()
+        .with_context(|| format!("Failed to start editor '{}'", editor))?;
+    ensure!(
+        status.success(),
+        "Editor '{}' exited with {}, message is left as it was",
+        editor,
+        status
+    );
+    fs::read(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_with() {
+        let edited = edit_with("sed -i s/old/new/", b"old note\n", "txt").unwrap();
+        assert_eq!(edited, b"new note\n");
+        assert!(edit_with("false", b"old note\n", "txt").is_err());
+    }
+}
@@ -0,0 +1,303 @@
+//! Steganography backends hiding payload in PNG chunks, selected by name.
+//! Library users can implement `Embedder` for their own strategies and
+//! register them next to the built-in ones.
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::codec::{Codecs, PayloadCodec};
+
+/// Chunks of an image (signature excluded) and bytes following its IEND
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Carrier {
+    pub chunks: Vec<Chunk>,
+    pub trailing: Vec<u8>,
+}
+
+impl Carrier {
+    /// Index of IEND, or the end of chunk list when there is none
+    pub fn end_index(&self) -> usize {
+        self.chunks
+            .iter()
+            .position(|c| &c.chunk_type().bytes() == b"IEND")
+            .unwrap_or(self.chunks.len())
+    }
+
+    fn find(&self, chunk_type: &ChunkType) -> Option<&Chunk> {
+        self.chunks.iter().find(|c| c.chunk_type() == chunk_type)
+    }
+}
+
+/// Strategy of hiding payload in image
+pub trait Embedder {
+    /// Name the backend is selected by
+    fn name(&self) -> &str;
+
+    fn embed(&self, carrier: &mut Carrier, payload: &[u8]) -> Result<()>;
+
+    /// Payload hidden by `embed`, or `None` if image holds none
+    fn extract(&self, carrier: &Carrier) -> Result<Option<Vec<u8>>>;
+}
+
+/// Payload is data of its own chunk, placed before IEND
+pub struct ChunkEmbedder {
+    pub chunk_type: ChunkType,
+}
+
+impl Embedder for ChunkEmbedder {
+    fn name(&self) -> &str {
+        "chunk"
+    }
+
+    fn embed(&self, carrier: &mut Carrier, payload: &[u8]) -> Result<()> {
+        let index = carrier.end_index();
+        carrier
+            .chunks
+            .insert(index, Chunk::new(self.chunk_type, payload.to_vec()));
+        Ok(())
+    }
+
+    fn extract(&self, carrier: &Carrier) -> Result<Option<Vec<u8>>> {
+        Ok(carrier.find(&self.chunk_type).map(|c| c.data().to_vec()))
+    }
+}
+
+/// Payload is appended after IEND, where decoders stop reading
+pub struct TrailingEmbedder;
+
+impl Embedder for TrailingEmbedder {
+    fn name(&self) -> &str {
+        "trailing"
+    }
+
+    fn embed(&self, carrier: &mut Carrier, payload: &[u8]) -> Result<()> {
+        ensure!(
+            carrier.trailing.is_empty(),
+            "Image already has {} bytes after IEND",
+            carrier.trailing.len()
+        );
+        carrier.trailing = payload.to_vec();
+        Ok(())
+    }
+
+    fn extract(&self, carrier: &Carrier) -> Result<Option<Vec<u8>>> {
+        Ok(Some(carrier.trailing.clone()).filter(|t| !t.is_empty()))
+    }
+}
+
+/// Payload is hex text of a `tEXt` chunk, passing for ordinary metadata
+pub struct TextEmbedder {
+    pub keyword: String,
+}
+
+impl Default for TextEmbedder {
+    fn default() -> Self {
+        TextEmbedder {
+            keyword: "Comment".to_string(),
+        }
+    }
+}
+
+impl TextEmbedder {
+    fn chunks<'a>(&'a self, carrier: &'a Carrier) -> impl Iterator<Item = &'a [u8]> + 'a {
+        carrier
+            .chunks
+            .iter()
+            .filter(|c| &c.chunk_type().bytes() == b"tEXt")
+            .filter_map(|c| c.data().strip_prefix(self.keyword.as_bytes()))
+            .filter_map(|text| text.strip_prefix(&[0]))
+    }
+}
+
+impl Embedder for TextEmbedder {
+    fn name(&self) -> &str {
+        "text"
+    }
+
+    fn embed(&self, carrier: &mut Carrier, payload: &[u8]) -> Result<()> {
+        ensure!(
+            self.chunks(carrier).next().is_none(),
+            "Image already has tEXt chunk with keyword {}",
+            self.keyword
+        );
+        let mut data = self.keyword.as_bytes().to_vec();
+        data.push(0);
+        for byte in payload {
+            data.extend_from_slice(&hex_pair(*byte));
+        }
+        let index = carrier.end_index();
+        let chunk_type = ChunkType::try_from(*b"tEXt")?;
+        carrier.chunks.insert(index, Chunk::new(chunk_type, data));
+        Ok(())
+    }
+
+    fn extract(&self, carrier: &Carrier) -> Result<Option<Vec<u8>>> {
+        let Some(text) = self.chunks(carrier).next() else {
+            return Ok(None);
+        };
+        ensure!(text.len() % 2 == 0, "Text payload has odd length");
+        let payload = text
+            .chunks(2)
+            .map(|pair| Some(hex_digit(pair[0])? << 4 | hex_digit(pair[1])?))
+            .collect::<Option<Vec<u8>>>()
+            .context("Text payload is not hex")?;
+        Ok(Some(payload))
+    }
+}
+
+fn hex_pair(byte: u8) -> [u8; 2] {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    [DIGITS[(byte >> 4) as usize], DIGITS[(byte & 0xf) as usize]]
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|d| d as u8)
+}
+
+/// Backends available by name, registering one with taken name replaces it.
+/// Payload goes through codecs added to registry whichever backend is used
+#[derive(Default)]
+pub struct Registry {
+    embedders: Vec<Box<dyn Embedder>>,
+    codecs: Codecs,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    /// Trailing-data and text-chunk backends, chunk one needs a type
+    /// so it is left to the caller
+    pub fn with_builtins() -> Registry {
+        let mut registry = Registry::new();
+        registry.register(Box::new(TrailingEmbedder));
+        registry.register(Box::new(TextEmbedder::default()));
+        registry
+    }
+
+    pub fn register(&mut self, embedder: Box<dyn Embedder>) {
+        self.embedders.retain(|e| e.name() != embedder.name());
+        self.embedders.push(embedder);
+    }
+
+    pub fn get(&self, name: &str) -> Result<&dyn Embedder> {
+        match self.embedders.iter().find(|e| e.name() == name) {
+            Some(embedder) => Ok(embedder.as_ref()),
+            None => bail!(
+                "Unknown embedding mode '{}', available: {}",
+                name,
+                self.names().join(", ")
+            ),
+        }
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.embedders.iter().map(|e| e.name()).collect()
+    }
+
+    /// Adds codec applied after ones added before it
+    pub fn add_codec(&mut self, codec: Box<dyn PayloadCodec>) {
+        self.codecs.push(codec);
+    }
+
+    /// Encodes payload with codecs and hides it with backend `name`
+    pub fn embed(&self, name: &str, carrier: &mut Carrier, payload: &[u8]) -> Result<()> {
+        let embedder = self.get(name)?;
+        embedder.embed(carrier, &self.codecs.encode(payload.to_vec())?)
+    }
+
+    /// Payload found by backend `name`, decoded with codecs
+    pub fn extract(&self, name: &str, carrier: &Carrier) -> Result<Option<Vec<u8>>> {
+        match self.get(name)?.extract(carrier)? {
+            Some(payload) => Ok(Some(self.codecs.decode(payload)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn carrier() -> Carrier {
+        let iend = Chunk::new(ChunkType::try_from(*b"IEND").unwrap(), vec![]);
+        Carrier {
+            chunks: vec![iend],
+            trailing: vec![],
+        }
+    }
+
+    #[test]
+    fn test_builtins_roundtrip() {
+        let mut registry = Registry::with_builtins();
+        registry.register(Box::new(ChunkEmbedder {
+            chunk_type: ChunkType::try_from(*b"ruSt").unwrap(),
+        }));
+        assert_eq!(registry.names(), ["trailing", "text", "chunk"]);
+        for name in registry.names() {
+            let embedder = registry.get(name).unwrap();
+            let mut carrier = carrier();
+            assert_eq!(embedder.extract(&carrier).unwrap(), None);
+            embedder.embed(&mut carrier, b"\x00secret\xff").unwrap();
+            assert_eq!(
+                embedder.extract(&carrier).unwrap().unwrap(),
+                b"\x00secret\xff"
+            );
+            let iend = &carrier.chunks[carrier.end_index()];
+            assert_eq!(&iend.chunk_type().bytes(), b"IEND");
+        }
+        assert!(registry.get("lsb").is_err());
+    }
+
+    #[test]
+    fn test_register_replaces() {
+        let mut registry = Registry::with_builtins();
+        registry.register(Box::new(TextEmbedder {
+            keyword: "Author".to_string(),
+        }));
+        assert_eq!(registry.names(), ["trailing", "text"]);
+        let mut carrier = carrier();
+        registry
+            .get("text")
+            .unwrap()
+            .embed(&mut carrier, b"a")
+            .unwrap();
+        assert_eq!(carrier.chunks[0].data(), b"Author\x0061");
+    }
+
+    struct Reverse;
+
+    impl PayloadCodec for Reverse {
+        fn name(&self) -> &str {
+            "reverse"
+        }
+
+        fn encode(&self, mut payload: Vec<u8>) -> Result<Vec<u8>> {
+            payload.reverse();
+            Ok(payload)
+        }
+
+        fn decode(&self, payload: Vec<u8>) -> Result<Vec<u8>> {
+            self.encode(payload)
+        }
+    }
+
+    #[test]
+    fn test_codecs() {
+        let mut registry = Registry::with_builtins();
+        registry.add_codec(Box::new(Reverse));
+        let mut carrier = carrier();
+        registry.embed("trailing", &mut carrier, b"abc").unwrap();
+        assert_eq!(carrier.trailing, b"cba");
+        let payload = registry.extract("trailing", &carrier).unwrap();
+        assert_eq!(payload.unwrap(), b"abc");
+        assert_eq!(registry.extract("text", &carrier).unwrap(), None);
+        assert!(registry.embed("lsb", &mut carrier, b"abc").is_err());
+    }
+}
@@ -0,0 +1,47 @@
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+/// Start of every binary age file
+pub const HEADER: &[u8] = b"age-encryption.org/v1\n";
+
+/// Encrypts data with age so that any of the recipients can decrypt it
+pub fn encrypt(data: &[u8], recipients: &[String]) -> Result<Vec<u8>> {
+    let recipients = recipients
+        .iter()
+        .map(|recipient| {
+            let parsed: age::x25519::Recipient = recipient
+                .parse()
+                .map_err(|err| anyhow!("Invalid age recipient '{}': {}", recipient, err))?;
+            Ok(Box::new(parsed) as Box<dyn age::Recipient + Send>)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let encryptor = age::Encryptor::with_recipients(recipients).context("No age recipients")?;
+
+    let mut encrypted = vec![];
+    let mut writer = encryptor.wrap_output(&mut encrypted)?;
+    writer.write_all(data)?;
+    writer.finish()?;
+    Ok(encrypted)
+}
+
+/// Decrypts age-encrypted data with identities from file created by `age-keygen`
+pub fn decrypt(data: &[u8], identity_path: &Path) -> Result<Vec<u8>> {
+    let file = std::fs::File::open(identity_path)
+        .with_context(|| format!("Failed to open {}", identity_path.display()))?;
+    let identities = age::IdentityFile::from_buffer(BufReader::new(file))
+        .and_then(|file| file.into_identities().map_err(std::io::Error::other))
+        .with_context(|| format!("Invalid age identity file {}", identity_path.display()))?;
+
+    let decryptor = match age::Decryptor::new(data).context("Message is not age-encrypted")? {
+        age::Decryptor::Recipients(decryptor) => decryptor,
+        _ => bail!("Message is encrypted with passphrase, not to recipients"),
+    };
+    let mut reader = decryptor
+        .decrypt(identities.iter().map(|i| i.as_ref() as &dyn age::Identity))
+        .context("Failed to decrypt message, no matching identity")?;
+    let mut decrypted = vec![];
+    reader.read_to_end(&mut decrypted)?;
+    Ok(decrypted)
+}
@@ -0,0 +1,237 @@
+#[cfg(feature = "write")]
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{ensure, Context, Result};
+use crc::Crc;
+
+#[cfg(feature = "write")]
+use crate::storage::Storage;
+
+/// Marks payload wrapped together with file metadata
+const MAGIC: &[u8; 4] = b"PFE2";
+/// CRC-32 of everything before it closes the envelope, so a message that
+/// merely starts with magic isn't taken for one
+const CRC_SIZE: usize = 4;
+/// Flag set when modification time is stored
+const HAS_MTIME: u8 = 0x01;
+/// Flag set when expiry time is stored
+const HAS_EXPIRY: u8 = 0x02;
+
+/// Embedded file or message with metadata needed to restore it
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Envelope {
+    /// File name, not set for plain messages
+    pub name: Option<String>,
+    pub mtime: Option<SystemTime>,
+    /// Time after which message should not be used
+    pub expires: Option<SystemTime>,
+    pub content: Vec<u8>,
+}
+
+impl Envelope {
+    /// Reads file, keeping only its name (without directories)
+    /// and, if asked, modification time
+    #[cfg(feature = "write")]
+    pub fn from_file(storage: &dyn Storage, path: &Path, keep_mtime: bool) -> Result<Envelope> {
+        let content = storage.read(path)?;
+        let name = path
+            .file_name()
+            .with_context(|| format!("{} has no file name", path.display()))?
+            .to_string_lossy()
+            .into_owned();
+        let mtime = if keep_mtime {
+            storage.modified(path)?
+        } else {
+            None
+        };
+        Ok(Envelope {
+            name: Some(name),
+            mtime,
+            expires: None,
+            content,
+        })
+    }
+
+    /// Whether message has expired by given time
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// Serializes as magic, flags, u16 name length, name (empty for messages),
+    /// optional u64 mtime and expiry (seconds since epoch), content and CRC-32
+    #[cfg(feature = "write")]
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let name = self.name.as_deref().unwrap_or_default();
+        let name_len = u16::try_from(name.len()).context("File name is too long")?;
+
+        let mut flags = 0;
+        if self.mtime.is_some() {
+            flags |= HAS_MTIME;
+        }
+        if self.expires.is_some() {
+            flags |= HAS_EXPIRY;
+        }
+        let mut out = MAGIC.to_vec();
+        out.push(flags);
+        out.extend_from_slice(&name_len.to_be_bytes());
+        out.extend_from_slice(name.as_bytes());
+        for time in [self.mtime, self.expires].into_iter().flatten() {
+            let secs = time
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            out.extend_from_slice(&secs.to_be_bytes());
+        }
+        out.extend_from_slice(&self.content);
+        let crc = checksum(&out);
+        out.extend_from_slice(&crc.to_be_bytes());
+        Ok(out)
+    }
+
+    /// Returns `None` for data that is not an envelope
+    pub fn from_bytes(data: &[u8]) -> Result<Option<Envelope>> {
+        if !data.starts_with(MAGIC) || data.len() < MAGIC.len() + CRC_SIZE {
+            return Ok(None);
+        }
+        let (data, crc) = data.split_at(data.len() - CRC_SIZE);
+        if checksum(data).to_be_bytes() != crc {
+            return Ok(None);
+        }
+        let rest = &data[MAGIC.len()..];
+        ensure!(rest.len() >= 3, "File envelope is truncated");
+        let flags = rest[0];
+        let name_len = u16::from_be_bytes([rest[1], rest[2]]) as usize;
+        let rest = &rest[3..];
+        ensure!(rest.len() >= name_len, "File envelope is truncated");
+        let (name, mut rest) = rest.split_at(name_len);
+        let name = String::from_utf8(name.to_vec()).context("Invalid file name in envelope")?;
+        let name = match name.is_empty() {
+            true => None,
+            false => {
+                ensure!(
+                    is_plain_name(&name),
+                    "Unsafe file name in envelope: {}",
+                    name
+                );
+                Some(name)
+            }
+        };
+
+        let mut time = |flag, what| -> Result<Option<SystemTime>> {
+            if flags & flag == 0 {
+                return Ok(None);
+            }
+            ensure!(rest.len() >= 8, "File envelope is truncated");
+            let secs = u64::from_be_bytes(rest[..8].try_into().unwrap());
+            rest = &rest[8..];
+            UNIX_EPOCH
+                .checked_add(Duration::from_secs(secs))
+                .with_context(|| format!("{} in file envelope is out of range", what))
+                .map(Some)
+        };
+        let mtime = time(HAS_MTIME, "Modification time")?;
+        let expires = time(HAS_EXPIRY, "Expiry time")?;
+
+        Ok(Some(Envelope {
+            name,
+            mtime,
+            expires,
+            content: rest.to_vec(),
+        }))
+    }
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    Crc::<u32>::new(&crc::CRC_32_ISO_HDLC).checksum(data)
+}
+
+/// Name must not escape output directory when joined to it
+fn is_plain_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\', '\0', ':'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_roundtrip() {
+        let envelope = Envelope {
+            name: Some("report.pdf".to_string()),
+            mtime: Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            expires: Some(UNIX_EPOCH + Duration::from_secs(1_800_000_000)),
+            content: b"%PDF-1.7".to_vec(),
+        };
+        let bytes = envelope.to_bytes().unwrap();
+        assert_eq!(Envelope::from_bytes(&bytes).unwrap(), Some(envelope));
+
+        let envelope = Envelope {
+            name: Some("notes.txt".to_string()),
+            ..Default::default()
+        };
+        let bytes = envelope.to_bytes().unwrap();
+        assert_eq!(Envelope::from_bytes(&bytes).unwrap(), Some(envelope));
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_expiring_message() {
+        let expires = UNIX_EPOCH + Duration::from_secs(1_800_000_000);
+        let envelope = Envelope {
+            expires: Some(expires),
+            content: b"secret".to_vec(),
+            ..Default::default()
+        };
+        let bytes = envelope.to_bytes().unwrap();
+        assert_eq!(Envelope::from_bytes(&bytes).unwrap(), Some(envelope));
+
+        let envelope = Envelope::from_bytes(&bytes).unwrap().unwrap();
+        assert!(!envelope.is_expired(expires - Duration::from_secs(1)));
+        assert!(envelope.is_expired(expires));
+    }
+
+    /// Header and content closed with valid CRC, as crafted file would have
+    fn sealed(data: &[u8]) -> Vec<u8> {
+        let mut data = data.to_vec();
+        data.extend_from_slice(&checksum(&data).to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn test_plain_payload() {
+        assert_eq!(Envelope::from_bytes(b"just a message").unwrap(), None);
+        assert_eq!(Envelope::from_bytes(b"PFE2 starts like one").unwrap(), None);
+        assert_eq!(Envelope::from_bytes(b"PFE2\x00\x00").unwrap(), None);
+        assert!(Envelope::from_bytes(&sealed(b"PFE2\x00\x00")).is_err());
+    }
+
+    #[test]
+    fn test_huge_mtime() {
+        let mut data = b"PFE2\x01\x00\x00".to_vec();
+        data.extend_from_slice(&u64::MAX.to_be_bytes());
+        assert!(Envelope::from_bytes(&sealed(&data)).is_err());
+    }
+
+    #[test]
+    fn test_huge_expiry() {
+        let mut data = b"PFE2\x02\x00\x00".to_vec();
+        data.extend_from_slice(&u64::MAX.to_be_bytes());
+        let err = Envelope::from_bytes(&sealed(&data)).unwrap_err();
+        assert!(err.to_string().starts_with("Expiry time"), "{}", err);
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_rejects_unsafe_names() {
+        for name in ["../etc/passwd", "..", "dir/file", r"C:\file"] {
+            let envelope = Envelope {
+                name: Some(name.to_string()),
+                ..Default::default()
+            };
+            let bytes = envelope.to_bytes().unwrap();
+            assert!(Envelope::from_bytes(&bytes).is_err(), "{}", name);
+        }
+    }
+}
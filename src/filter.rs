@@ -0,0 +1,48 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use anyhow::{ensure, Context, Result};
+
+use crate::scanner;
+
+/// Passes data through stdin and stdout of shell command, e.g. `gpg --encrypt -r KEY`.
+/// Data is written and read as raw bytes, stderr is left to the terminal
+/// so that command can show prompts and errors
+pub fn pipe(command: &str, input: &[u8]) -> Result<Vec<u8>> {
+    run(scanner::shell(command), input).with_context(|| format!("Filter '{}' failed", command))
+}
+
+fn run(mut command: Command, input: &[u8]) -> Result<Vec<u8>> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .context("Failed to start")?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    let output = thread::scope(|s| -> Result<_> {
+        // writing from another thread, so that full stdout pipe can't block both sides
+        let writer = s.spawn(move || stdin.write_all(input));
+        let output = child.wait_with_output()?;
+        // exit status tells more than broken pipe of command that didn't read everything
+        ensure!(output.status.success(), "Exited with {}", output.status);
+        writer.join().unwrap().context("Failed to write input")?;
+        Ok(output.stdout)
+    })?;
+    Ok(output)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pipe() {
+        let data: Vec<u8> = (0..=255).cycle().take(200_000).collect();
+        assert_eq!(pipe("cat", &data).unwrap(), data);
+        assert_eq!(pipe("tr a-z A-Z", b"hello\0\xff").unwrap(), b"HELLO\0\xff");
+        assert!(pipe("cat >/dev/null; exit 3", b"x").is_err());
+    }
+}
@@ -0,0 +1,74 @@
+//! Arithmetic in Galois field GF(2^8) with primitive polynomial 0x11d and generator 2
+
+struct Tables {
+    exp: [u8; 512],
+    log: [u8; 256],
+}
+
+static TABLES: Tables = Tables::new();
+
+impl Tables {
+    const fn new() -> Tables {
+        let mut exp = [0; 512];
+        let mut log = [0; 256];
+        let mut x: u16 = 1;
+        let mut i = 0;
+        while i < 255 {
+            exp[i] = x as u8;
+            log[x as usize] = i as u8;
+            x <<= 1;
+            if x & 0x100 != 0 {
+                x ^= 0x11d;
+            }
+            i += 1;
+        }
+        while i < 512 {
+            exp[i] = exp[i - 255];
+            i += 1;
+        }
+        Tables { exp, log }
+    }
+}
+
+pub fn mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    TABLES.exp[TABLES.log[a as usize] as usize + TABLES.log[b as usize] as usize]
+}
+
+/// Divides `a` by non-zero `b`
+pub fn div(a: u8, b: u8) -> u8 {
+    if a == 0 {
+        return 0;
+    }
+    TABLES.exp[(TABLES.log[a as usize] as usize + 255 - TABLES.log[b as usize] as usize) % 255]
+}
+
+/// Raises non-zero `x` to given (possibly negative) power
+pub fn pow(x: u8, power: i32) -> u8 {
+    let exponent = (TABLES.log[x as usize] as i32 * power).rem_euclid(255);
+    TABLES.exp[exponent as usize]
+}
+
+/// Multiplicative inverse of non-zero `x`
+pub fn inverse(x: u8) -> u8 {
+    TABLES.exp[255 - TABLES.log[x as usize] as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_properties() {
+        for a in 1..=255u8 {
+            assert_eq!(mul(a, inverse(a)), 1);
+            assert_eq!(div(mul(a, 7), 7), a);
+            assert_eq!(pow(a, 255), 1);
+            assert_eq!(pow(a, -1), inverse(a));
+        }
+        assert_eq!(mul(0, 5), 0);
+        assert_eq!(mul(2, 0x80), 0x1d);
+    }
+}
@@ -0,0 +1,54 @@
+use anyhow::{ensure, Result};
+
+/// Fields of `IHDR` chunk
+#[derive(Debug, Eq, PartialEq)]
+pub struct ImageHeader {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: u8,
+    pub interlaced: bool,
+}
+
+impl ImageHeader {
+    pub fn from_data(data: &[u8]) -> Result<ImageHeader> {
+        ensure!(
+            data.len() == 13,
+            "IHDR chunk must be 13 bytes long, got {}",
+            data.len()
+        );
+        Ok(ImageHeader {
+            width: u32::from_be_bytes(data[0..4].try_into().unwrap()),
+            height: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            bit_depth: data[8],
+            color_type: data[9],
+            interlaced: data[12] != 0,
+        })
+    }
+
+    pub fn color_type_name(&self) -> &'static str {
+        match self.color_type {
+            0 => "grayscale",
+            2 => "RGB",
+            3 => "indexed",
+            4 => "grayscale with alpha",
+            6 => "RGBA",
+            _ => "unknown color type",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_data() {
+        let data = [0, 0, 2, 128, 0, 0, 1, 224, 8, 6, 0, 0, 1];
+        let header = ImageHeader::from_data(&data).unwrap();
+        assert_eq!((header.width, header.height), (640, 480));
+        assert_eq!(header.color_type_name(), "RGBA");
+        assert!(header.interlaced);
+        assert!(ImageHeader::from_data(&data[..12]).is_err());
+    }
+}
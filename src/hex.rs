@@ -0,0 +1,38 @@
+use anyhow::{ensure, Result};
+
+/// Encodes bytes as lowercase hex string
+pub fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decodes hex string (case-insensitive), surrounding whitespace is ignored
+pub fn decode(s: &str) -> Result<Vec<u8>> {
+    let s = s.trim();
+    ensure!(
+        s.len().is_multiple_of(2) && s.is_ascii(),
+        "Invalid hex string"
+    );
+    (0..s.len())
+        .step_by(2)
+        .map(|i| Ok(u8::from_str_radix(&s[i..i + 2], 16)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        assert_eq!(encode(&[0, 1, 0xab, 0xff]), "0001abff");
+        assert_eq!(decode("0001ABff\n").unwrap(), vec![0, 1, 0xab, 0xff]);
+        assert_eq!(decode("").unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert!(decode("abc").is_err());
+        assert!(decode("zz").is_err());
+        assert!(decode("é1").is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use std::fmt::Write;
+
+const BYTES_PER_LINE: usize = 16;
+
+/// Formats data as canonical hex+ASCII dump (same as `hexdump -C`).
+/// Each line has offset, 16 hex columns split in two groups and printable characters,
+/// last line contains total length
+pub fn format(data: &[u8]) -> String {
+    let mut out = String::new();
+    for (line, bytes) in data.chunks(BYTES_PER_LINE).enumerate() {
+        write!(out, "{:08x} ", line * BYTES_PER_LINE).unwrap();
+        for i in 0..BYTES_PER_LINE {
+            if i % 8 == 0 {
+                out.push(' ');
+            }
+            match bytes.get(i) {
+                Some(b) => write!(out, "{:02x} ", b).unwrap(),
+                None => out.push_str("   "),
+            }
+        }
+
+        let printable: String = bytes
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        writeln!(out, " |{}|", printable).unwrap();
+    }
+    writeln!(out, "{:08x}", data.len()).unwrap();
+    out
+}
+
+/// Formats start of data on one line: quoted with escapes if it is mostly text,
+/// space separated hex otherwise. Hex shows half as many bytes to take about the same width
+pub fn inline(data: &[u8], max: usize) -> String {
+    let text = data.iter().take(max).filter(|b| is_text(**b)).count();
+    let is_text = text * 10 >= data.len().min(max) * 9;
+    let shown = if is_text { max } else { max / 2 };
+    let preview = &data[..data.len().min(shown)];
+    let mut out = if is_text {
+        format!("\"{}\"", preview.escape_ascii())
+    } else {
+        let bytes: Vec<_> = preview.iter().map(|b| format!("{:02x}", b)).collect();
+        bytes.join(" ")
+    };
+    if data.len() > preview.len() {
+        out.push_str("...");
+    }
+    out
+}
+
+fn is_text(b: u8) -> bool {
+    b.is_ascii_graphic() || b.is_ascii_whitespace()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_empty() {
+        assert_eq!(format(&[]), "00000000\n");
+    }
+
+    #[test]
+    fn test_format_partial_line() {
+        let expected = "00000000  00 41 42 0a                                       |.AB.|\n\
+                        00000004\n";
+        assert_eq!(format(&[0, b'A', b'B', b'\n']), expected);
+    }
+
+    #[test]
+    fn test_inline() {
+        assert_eq!(inline(b"", 8), "\"\"");
+        assert_eq!(inline(b"Comment\0hi\n", 16), "\"Comment\\x00hi\\n\"");
+        assert_eq!(inline(b"long message", 8), "\"long mes\"...");
+        assert_eq!(inline(&[0x89, b'P', b'N', b'G', 0, 1], 8), "89 50 4e 47...");
+        assert_eq!(inline(&[0, 1], 8), "00 01");
+    }
+
+    #[test]
+    fn test_format_multiple_lines() {
+        let data: Vec<u8> = (0x40..0x54).collect();
+        let expected =
+            "00000000  40 41 42 43 44 45 46 47  48 49 4a 4b 4c 4d 4e 4f  |@ABCDEFGHIJKLMNO|\n\
+                        00000010  50 51 52 53                                       |PQRS|\n\
+                        00000014\n";
+        assert_eq!(format(&data), expected);
+    }
+}
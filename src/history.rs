@@ -0,0 +1,321 @@
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use sha2::{Digest, Sha256};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::hex;
+use crate::journal::Change;
+use crate::png::Png;
+
+/// Private chunk with log of operations made by pngme, safe to copy
+pub const CHUNK_TYPE: [u8; 4] = *b"hiSt";
+
+/// Removed chunks up to this size are kept in history, so they can be restored
+const MAX_PRESERVED: usize = 64 * 1024;
+
+/// Operation recorded in history chunk
+#[derive(Debug, Eq, PartialEq)]
+pub struct Record {
+    /// Seconds since Unix epoch
+    pub timestamp: u64,
+    pub operation: String,
+    pub changes: Vec<RecordedChange>,
+}
+
+/// Chunk added or removed by operation. Index doesn't count history chunks
+#[derive(Debug, Eq, PartialEq)]
+pub struct RecordedChange {
+    pub added: bool,
+    pub index: usize,
+    pub chunk_type: String,
+    /// SHA-256 of whole chunk (length, type, data and CRC)
+    pub hash: [u8; 32],
+    /// Bytes of removed chunk, unless it was too large to keep
+    pub preserved: Option<Vec<u8>>,
+}
+
+/// Records in history chunks of image, oldest first
+pub fn records(png: &Png) -> Result<Vec<Record>> {
+    let mut records = vec![];
+    for chunk in png.chunks() {
+        if chunk.chunk_type().bytes() != CHUNK_TYPE {
+            continue;
+        }
+        let text = std::str::from_utf8(chunk.data()).context("History chunk is not UTF-8")?;
+        for line in text.lines() {
+            records.push(Record::from_line(line)?);
+        }
+    }
+    Ok(records)
+}
+
+/// Image with record of operation added to its history chunk.
+/// History chunk goes right before IEND, replacing existing ones
+pub fn record(png: &Png, operation: &str, changes: &[Change], timestamp: u64) -> Result<Png> {
+    let mut records = records(png)?;
+    let positions: Vec<_> = (0..png.chunks().len())
+        .filter(|&i| png.chunks()[i].chunk_type().bytes() == CHUNK_TYPE)
+        .collect();
+    let changes = changes
+        .iter()
+        .map(|change| {
+            let (added, index, chunk) = match change {
+                Change::Added { index, chunk } => (true, *index, chunk),
+                Change::Removed { index, chunk } => (false, *index, chunk),
+            };
+            RecordedChange {
+                added,
+                index: index - positions.iter().filter(|&&p| p < index).count(),
+                chunk_type: String::from_utf8_lossy(&chunk[4..8]).into_owned(),
+                hash: Sha256::digest(chunk).into(),
+                preserved: (!added && chunk.len() <= MAX_PRESERVED).then(|| chunk.clone()),
+            }
+        })
+        .collect();
+    records.push(Record {
+        timestamp,
+        operation: operation.to_string(),
+        changes,
+    });
+    with_history(without_history(png), &records)
+}
+
+/// Reverts last recorded operation, returning image without it and the record
+pub fn undo(png: &Png) -> Result<(Png, Record)> {
+    let mut records = records(png)?;
+    let record = records.pop().context("Image has no recorded history")?;
+    let mut chunks = without_history(png);
+
+    for change in record.changes.iter().rev() {
+        if change.added {
+            let matches = |c: &Chunk| Sha256::digest(c.as_bytes())[..] == change.hash;
+            let index = match chunks.get(change.index) {
+                Some(chunk) if matches(chunk) => change.index,
+                _ => chunks.iter().position(matches).with_context(|| {
+                    format!(
+                        "{} chunk added by {} was modified or removed, can't undo",
+                        change.chunk_type, record.operation
+                    )
+                })?,
+            };
+            chunks.remove(index);
+        } else {
+            let Some(bytes) = &change.preserved else {
+                bail!(
+                    "{} chunk removed by {} was too large to be kept in history, can't undo",
+                    change.chunk_type,
+                    record.operation
+                );
+            };
+            let chunk = Chunk::try_from(bytes.as_slice())?;
+            chunks.insert(change.index.min(chunks.len()), chunk);
+        }
+    }
+    Ok((with_history(chunks, &records)?, record))
+}
+
+fn without_history(png: &Png) -> Vec<Chunk> {
+    png.chunks()
+        .iter()
+        .filter(|c| c.chunk_type().bytes() != CHUNK_TYPE)
+        .map(|c| Chunk::try_from(c.as_bytes().as_slice()).unwrap())
+        .collect()
+}
+
+fn with_history(mut chunks: Vec<Chunk>, records: &[Record]) -> Result<Png> {
+    if !records.is_empty() {
+        let lines: Vec<_> = records.iter().map(Record::to_line).collect::<Result<_>>()?;
+        let chunk = Chunk::new(
+            ChunkType::try_from(CHUNK_TYPE)?,
+            lines.join("\n").into_bytes(),
+        );
+        let index = chunks
+            .iter()
+            .position(|c| &c.chunk_type().bytes() == b"IEND")
+            .unwrap_or(chunks.len());
+        chunks.insert(index, chunk);
+    }
+    Ok(Png::from_chunks(chunks))
+}
+
+impl Record {
+    /// Serializes record as `timestamp<TAB>operation<TAB>+:3:ruSt:<sha256>[<TAB>-:5:tEXt:<sha256>:<base64>...]`
+    fn to_line(&self) -> Result<String> {
+        ensure!(
+            !self.operation.contains(['\t', '\n']),
+            "Operation can't be recorded: {}",
+            self.operation
+        );
+        let mut line = format!("{}\t{}", self.timestamp, self.operation);
+        for change in &self.changes {
+            let sign = if change.added { '+' } else { '-' };
+            line.push_str(&format!(
+                "\t{}:{}:{}:{}",
+                sign,
+                change.index,
+                change.chunk_type,
+                hex::encode(&change.hash)
+            ));
+            if let Some(bytes) = &change.preserved {
+                line.push(':');
+                line.push_str(&BASE64_STANDARD.encode(bytes));
+            }
+        }
+        Ok(line)
+    }
+
+    fn from_line(line: &str) -> Result<Record> {
+        let mut fields = line.split('\t');
+        let timestamp = fields
+            .next()
+            .unwrap_or_default()
+            .parse()
+            .context("Invalid history timestamp")?;
+        let operation = fields.next().context("History record has no operation")?;
+        let changes = fields
+            .map(|field| {
+                let parts: Vec<_> = field.split(':').collect();
+                let (sign, index, chunk_type, hash, preserved) = match parts[..] {
+                    [sign, index, chunk_type, hash] => (sign, index, chunk_type, hash, None),
+                    [sign, index, chunk_type, hash, data] => (
+                        sign,
+                        index,
+                        chunk_type,
+                        hash,
+                        Some(BASE64_STANDARD.decode(data).context("Invalid base64")?),
+                    ),
+                    _ => bail!("Malformed history change: {}", field),
+                };
+                let added = match sign {
+                    "+" => true,
+                    "-" => false,
+                    _ => bail!("Unknown history change '{}'", sign),
+                };
+                Ok(RecordedChange {
+                    added,
+                    index: index.parse()?,
+                    chunk_type: chunk_type.to_string(),
+                    hash: hex::decode(hash)?
+                        .try_into()
+                        .map_err(|_| anyhow!("Invalid hash in history"))?,
+                    preserved,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Record {
+            timestamp,
+            operation: operation.to_string(),
+            changes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.into())
+    }
+
+    fn types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_record_and_undo() {
+        let original = Png::from_chunks(vec![
+            chunk("IHDR", ""),
+            chunk("tEXt", "Title\0Dice"),
+            chunk("IDAT", ""),
+            chunk("IEND", ""),
+        ]);
+
+        // replace text and add message
+        let mut png = Png::from_chunks(vec![
+            chunk("IHDR", ""),
+            chunk("tEXt", "Title\0Cube"),
+            chunk("IDAT", ""),
+            chunk("ruSt", "hello"),
+            chunk("IEND", ""),
+        ]);
+        let changes = [
+            Change::Removed {
+                index: 1,
+                chunk: chunk("tEXt", "Title\0Dice").as_bytes(),
+            },
+            Change::Added {
+                index: 1,
+                chunk: chunk("tEXt", "Title\0Cube").as_bytes(),
+            },
+            Change::Added {
+                index: 3,
+                chunk: chunk("ruSt", "hello").as_bytes(),
+            },
+        ];
+        png = record(&png, "meta", &changes[..2], 1_700_000_000).unwrap();
+        png = record(&png, "encode", &changes[2..], 1_700_000_001).unwrap();
+        assert_eq!(
+            types(&png),
+            ["IHDR", "tEXt", "IDAT", "ruSt", "hiSt", "IEND"]
+        );
+
+        let records = records(&png).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].operation, "meta");
+        assert_eq!(records[1].changes[0].chunk_type, "ruSt");
+        assert_eq!(records[1].timestamp, 1_700_000_001);
+
+        let (png, undone) = undo(&png).unwrap();
+        assert_eq!(undone.operation, "encode");
+        assert_eq!(types(&png), ["IHDR", "tEXt", "IDAT", "hiSt", "IEND"]);
+        let (png, _) = undo(&png).unwrap();
+        assert_eq!(png.as_bytes(), original.as_bytes());
+        assert!(undo(&png).is_err());
+    }
+
+    #[test]
+    fn test_undo_modified() {
+        let png = Png::from_chunks(vec![chunk("IHDR", ""), chunk("IEND", "")]);
+        let added = chunk("ruSt", "hello").as_bytes();
+        let png = record(
+            &png,
+            "encode",
+            &[Change::Added {
+                index: 1,
+                chunk: added,
+            }],
+            0,
+        )
+        .unwrap();
+        // recorded chunk is not there anymore
+        assert!(undo(&png).is_err());
+    }
+
+    #[test]
+    fn test_line_roundtrip() {
+        let record = Record {
+            timestamp: 42,
+            operation: "remove".to_string(),
+            changes: vec![RecordedChange {
+                added: false,
+                index: 2,
+                chunk_type: "ruSt".to_string(),
+                hash: [7; 32],
+                preserved: Some(b"\0\0\0\0ruSt".to_vec()),
+            }],
+        };
+        assert_eq!(
+            Record::from_line(&record.to_line().unwrap()).unwrap(),
+            record
+        );
+        assert!(Record::from_line("42\tremove\t*:1:ruSt:00").is_err());
+        assert!(Record::from_line("soon\tremove").is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use ::hmac::{Hmac, Mac};
+use anyhow::{anyhow, ensure, Result};
+use sha2::Sha256;
+
+pub const TAG_SIZE: usize = 32;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// HMAC-SHA256 as specified in RFC 2104
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; TAG_SIZE] {
+    mac(key, data).finalize().into_bytes().into()
+}
+
+/// Appends authentication tag to the payload
+#[cfg(feature = "write")]
+pub fn sign(key: &[u8], payload: &[u8]) -> Vec<u8> {
+    let mut tagged = payload.to_vec();
+    tagged.extend_from_slice(&hmac_sha256(key, payload));
+    tagged
+}
+
+/// Checks authentication tag at the end of data and returns payload without it
+pub fn verify<'a>(key: &[u8], data: &'a [u8]) -> Result<&'a [u8]> {
+    ensure!(
+        data.len() >= TAG_SIZE,
+        "Payload is too short to contain HMAC tag"
+    );
+    let (payload, tag) = data.split_at(data.len() - TAG_SIZE);
+    // comparison takes the same time wherever the first mismatch is
+    mac(key, payload).verify_slice(tag).map_err(|_| {
+        anyhow!("HMAC verification failed: payload was tampered with or key is wrong")
+    })?;
+    Ok(payload)
+}
+
+fn mac(key: &[u8], data: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC takes key of any size");
+    mac.update(data);
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    #[test]
+    fn test_rfc4231_vectors() {
+        assert_eq!(
+            hex::encode(&hmac_sha256(&[0x0b; 20], b"Hi There")),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+        assert_eq!(
+            hex::encode(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            hex::encode(&hmac_sha256(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_sign_verify() {
+        let tagged = sign(b"key", b"secret message");
+        assert_eq!(verify(b"key", &tagged).unwrap(), b"secret message");
+        assert!(verify(b"other key", &tagged).is_err());
+
+        let mut tampered = tagged.clone();
+        tampered[0] ^= 1;
+        assert!(verify(b"key", &tampered).is_err());
+        assert!(verify(b"key", b"short").is_err());
+    }
+}
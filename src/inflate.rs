@@ -0,0 +1,133 @@
+use std::io::Read;
+
+use anyhow::{ensure, Context, Result};
+use flate2::read::{GzDecoder, ZlibDecoder};
+use ruzstd::decoding::StreamingDecoder;
+
+/// Zlib stream starts with deflate method, window size up to 32K and a header checksum
+pub fn is_zlib_header(data: &[u8]) -> bool {
+    match data {
+        [cmf, flg, ..] => {
+            cmf & 0x0f == 8 && cmf >> 4 <= 7 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0
+        }
+        _ => false,
+    }
+}
+
+/// Decompresses zlib stream (RFC 1950), failing if output grows beyond `limit` bytes
+pub fn zlib_decompress(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    ensure!(is_zlib_header(data), "Not a zlib stream");
+    ensure!(
+        data[1] & 0x20 == 0,
+        "Zlib preset dictionaries are not supported"
+    );
+    read_limited(ZlibDecoder::new(data), limit, "zlib")
+}
+
+/// Decompresses gzip member (RFC 1952), failing if output grows beyond `limit` bytes
+pub fn gzip_decompress(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    ensure!(data.starts_with(&[0x1f, 0x8b, 8]), "Not a gzip stream");
+    read_limited(GzDecoder::new(data), limit, "gzip")
+}
+
+/// Decompresses zstd frame (RFC 8878), failing if output grows beyond `limit` bytes
+pub fn zstd_decompress(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    let decoder = StreamingDecoder::new(data).context("Invalid zstd stream")?;
+    read_limited(decoder, limit, "zstd")
+}
+
+/// Reads decompressed data, but never more than one byte past `limit`,
+/// so a small stream can't make it allocate gigabytes
+fn read_limited(decoder: impl Read, limit: usize, format: &str) -> Result<Vec<u8>> {
+    let mut out = vec![];
+    decoder
+        .take(limit as u64 + 1)
+        .read_to_end(&mut out)
+        .with_context(|| format!("Invalid {} stream", format))?;
+    ensure!(
+        out.len() <= limit,
+        "Decompressed data exceeds {} bytes",
+        limit
+    );
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    #[test]
+    fn test_fixed_block() {
+        let data = hex::decode("789ccb48cdc9c957c8402701680308b1").unwrap();
+        assert_eq!(
+            zlib_decompress(&data, 1024).unwrap(),
+            b"hello hello hello hello"
+        );
+    }
+
+    #[test]
+    fn test_dynamic_block() {
+        let data = hex::decode(
+            "78dab5cbd10180101446e155fe1668961e2c401115378498bebb44cfe73bc26ac4e2d6132a510b\
+             30f4e228fecea0aa131ece971c1d1bed33c46f7891ec7c8762d4dc63615cd59c860eb85c2c94f8\
+             ddf3f401b2ee3f00",
+        )
+        .unwrap();
+        let text = zlib_decompress(&data, 1024).unwrap();
+        assert_eq!(text.len(), 175);
+        assert!(text.starts_with(b"The quick brown fox"));
+        assert!(text.ends_with(b"five dozen liquor jugs!"));
+    }
+
+    #[test]
+    fn test_stored_block() {
+        let data = hex::decode("7801010600f9ff73746f726564093c0292").unwrap();
+        assert_eq!(zlib_decompress(&data, 1024).unwrap(), b"stored");
+    }
+
+    #[test]
+    fn test_gzip() {
+        let data = hex::decode(
+            "1f8b08080000000002ff6d73672e74787400cb48cdc9c95748afca2c0000196ad2df0a000000",
+        )
+        .unwrap();
+        assert_eq!(gzip_decompress(&data, 1024).unwrap(), b"hello gzip");
+        assert!(gzip_decompress(&data[..20], 1024).is_err());
+        assert!(gzip_decompress(&data, 5).is_err());
+    }
+
+    #[test]
+    fn test_zstd() {
+        let data = ruzstd::encoding::compress_to_vec(
+            &b"hello zstd, hello zstd"[..],
+            ruzstd::encoding::CompressionLevel::Fastest,
+        );
+        assert_eq!(
+            zstd_decompress(&data, 1024).unwrap(),
+            b"hello zstd, hello zstd"
+        );
+        assert!(zstd_decompress(&data, 5).is_err());
+        assert!(zstd_decompress(&data[..data.len() - 3], 1024).is_err());
+        assert!(zstd_decompress(b"\x28\xb5\x2f\xfd", 1024).is_err());
+    }
+
+    #[test]
+    fn test_limit() {
+        let bomb = crate::deflate::zlib_compress(&vec![0; 1 << 20]);
+        let err = zlib_decompress(&bomb, 1000).unwrap_err();
+        assert_eq!(err.to_string(), "Decompressed data exceeds 1000 bytes");
+        assert_eq!(zlib_decompress(&bomb, 1 << 20).unwrap().len(), 1 << 20);
+    }
+
+    #[test]
+    fn test_invalid_streams() {
+        let data = hex::decode("789ccb48cdc9c957c8402701680308b1").unwrap();
+        assert!(zlib_decompress(&data, 10).is_err());
+        assert!(zlib_decompress(&data[..10], 1024).is_err());
+        let mut corrupted = data.clone();
+        corrupted[15] ^= 1;
+        assert!(zlib_decompress(&corrupted, 1024).is_err());
+        assert!(zlib_decompress(b"hello", 1024).is_err());
+    }
+}
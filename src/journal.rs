@@ -0,0 +1,316 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::chunk::Chunk;
+use crate::hex;
+use crate::png::Png;
+
+/// Single chunk-level modification of a file
+#[derive(Debug, Eq, PartialEq)]
+pub enum Change {
+    Added { index: usize, chunk: Vec<u8> },
+    Removed { index: usize, chunk: Vec<u8> },
+}
+
+/// All changes made to one file by a single command
+#[derive(Debug, Eq, PartialEq)]
+pub struct Entry {
+    pub file_name: String,
+    pub changes: Vec<Change>,
+}
+
+/// Per-directory log of operations, stored in a hidden file next to edited images.
+/// Every entry keeps enough data (full chunk bytes) to revert it later
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    pub const FILE_NAME: &'static str = ".pngme-journal";
+
+    pub fn in_dir(dir: &Path) -> Journal {
+        Journal {
+            path: dir.join(Journal::FILE_NAME),
+        }
+    }
+
+    /// Journal that tracks operations on given file
+    pub fn for_file(file: &Path) -> Journal {
+        let dir = file.parent().unwrap_or_else(|| Path::new(""));
+        Journal::in_dir(dir)
+    }
+
+    pub fn record(&self, entry: &Entry) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open journal {}", self.path.display()))?;
+        writeln!(file, "{}", entry.to_line()?)?;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> Result<Vec<Entry>> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+        fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read journal {}", self.path.display()))?
+            .lines()
+            .map(Entry::from_line)
+            .collect()
+    }
+
+    /// Reverts last `steps` entries, newest first, and drops them from journal.
+    /// Journal is updated after every reverted entry, so a failure leaves it consistent
+    pub fn undo(&self, steps: usize) -> Result<Vec<Entry>> {
+        let mut entries = self.entries()?;
+        ensure!(
+            steps <= entries.len(),
+            "Only {} operations can be undone",
+            entries.len()
+        );
+
+        let dir = self.path.parent().unwrap_or_else(|| Path::new(""));
+        let mut undone = vec![];
+        for _ in 0..steps {
+            let entry = entries.pop().unwrap();
+            entry.revert(dir)?;
+            self.save(&entries)?;
+            undone.push(entry);
+        }
+        Ok(undone)
+    }
+
+    fn save(&self, entries: &[Entry]) -> Result<()> {
+        let mut content = String::new();
+        for entry in entries {
+            content.push_str(&entry.to_line()?);
+            content.push('\n');
+        }
+        fs::write(&self.path, content)
+            .with_context(|| format!("Failed to write journal {}", self.path.display()))
+    }
+}
+
+impl Change {
+    /// Changes turning `before` into `after`: chunks between common head
+    /// and tail are removed, then new ones are added in their place
+    pub fn between(before: &[Chunk], after: &[Chunk]) -> Vec<Change> {
+        let head = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+        let tail = before[head..]
+            .iter()
+            .rev()
+            .zip(after[head..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let removed = before[head..before.len() - tail]
+            .iter()
+            .map(|chunk| Change::Removed {
+                index: head,
+                chunk: chunk.as_bytes(),
+            });
+        let added = after[head..after.len() - tail]
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| Change::Added {
+                index: head + i,
+                chunk: chunk.as_bytes(),
+            });
+        removed.chain(added).collect()
+    }
+
+    /// Chunks as they were before `changes` turned them into `after`, `None` if
+    /// `after` doesn't hold what was added or has no room for what was removed
+    pub fn revert(after: &[Chunk], changes: &[Change]) -> Option<Vec<Chunk>> {
+        let mut chunks = after.to_vec();
+        for change in changes.iter().rev() {
+            match change {
+                Change::Added { index, chunk } => {
+                    if &chunks.get(*index)?.as_bytes() != chunk {
+                        return None;
+                    }
+                    chunks.remove(*index);
+                }
+                Change::Removed { index, chunk } => {
+                    if *index > chunks.len() {
+                        return None;
+                    }
+                    chunks.insert(*index, Chunk::try_from(chunk.as_slice()).ok()?);
+                }
+            }
+        }
+        Some(chunks)
+    }
+}
+
+impl Entry {
+    fn revert(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(&self.file_name);
+        let bytes =
+            fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let modified = Png::try_from(bytes.as_slice())?;
+
+        let chunks = Change::revert(modified.chunks(), &self.changes).with_context(|| {
+            format!(
+                "{} was modified after operation, can't undo",
+                self.file_name
+            )
+        })?;
+        let mut png = Png::from_chunks(chunks);
+        png.set_trailing(modified.trailing().to_vec());
+
+        fs::write(&path, png.as_bytes())
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Serializes entry as `file<TAB>added:3:<hex>[<TAB>removed:5:<hex>...]`
+    fn to_line(&self) -> Result<String> {
+        ensure!(
+            !self.file_name.contains(['\t', '\n']),
+            "File name can't be journaled: {}",
+            self.file_name
+        );
+
+        let mut line = self.file_name.clone();
+        for change in &self.changes {
+            let (kind, index, chunk) = match change {
+                Change::Added { index, chunk } => ("added", index, chunk),
+                Change::Removed { index, chunk } => ("removed", index, chunk),
+            };
+            line.push_str(&format!("\t{}:{}:{}", kind, index, hex::encode(chunk)));
+        }
+        Ok(line)
+    }
+
+    fn from_line(line: &str) -> Result<Entry> {
+        let mut fields = line.split('\t');
+        let file_name = fields.next().unwrap_or_default().to_string();
+        let changes = fields
+            .map(|field| {
+                let mut parts = field.splitn(3, ':');
+                let (Some(kind), Some(index), Some(data)) =
+                    (parts.next(), parts.next(), parts.next())
+                else {
+                    bail!("Malformed journal entry: {}", field);
+                };
+                let index = index.parse()?;
+                let chunk = hex::decode(data)?;
+                match kind {
+                    "added" => Ok(Change::Added { index, chunk }),
+                    "removed" => Ok(Change::Removed { index, chunk }),
+                    _ => bail!("Unknown journal change '{}'", kind),
+                }
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Entry { file_name, changes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk_type::ChunkType;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.into())
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pngme-journal-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_entry_line_roundtrip() {
+        let entry = Entry {
+            file_name: "dice.png".to_string(),
+            changes: vec![
+                Change::Added {
+                    index: 3,
+                    chunk: vec![0, 1, 0xff],
+                },
+                Change::Removed {
+                    index: 0,
+                    chunk: vec![],
+                },
+            ],
+        };
+
+        let line = entry.to_line().unwrap();
+        assert_eq!(line, "dice.png\tadded:3:0001ff\tremoved:0:");
+        assert_eq!(Entry::from_line(&line).unwrap(), entry);
+    }
+
+    #[test]
+    fn test_undo_steps() {
+        let dir = temp_dir("undo");
+        let file = dir.join("test.png");
+        let mut png = Png::from_chunks(vec![chunk("FrSt", "first"), chunk("LASt", "last")]);
+        let original = png.as_bytes();
+        let journal = Journal::for_file(&file);
+
+        let added = chunk("ruSt", "secret");
+        png.insert_chunk(1, chunk("ruSt", "secret"));
+        journal
+            .record(&Entry {
+                file_name: "test.png".to_string(),
+                changes: vec![Change::Added {
+                    index: 1,
+                    chunk: added.as_bytes(),
+                }],
+            })
+            .unwrap();
+
+        let removed = png.remove_chunk_at(0);
+        journal
+            .record(&Entry {
+                file_name: "test.png".to_string(),
+                changes: vec![Change::Removed {
+                    index: 0,
+                    chunk: removed.as_bytes(),
+                }],
+            })
+            .unwrap();
+        fs::write(&file, png.as_bytes()).unwrap();
+
+        assert!(journal.undo(3).is_err());
+        assert_eq!(journal.undo(2).unwrap().len(), 2);
+        assert_eq!(fs::read(&file).unwrap(), original);
+        assert!(journal.entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_changes_between() {
+        let dir = temp_dir("between");
+        let before = vec![
+            chunk("IHDR", ""),
+            chunk("IDAT", "a"),
+            chunk("IDAT", "b"),
+            chunk("IEND", ""),
+        ];
+        let after = vec![chunk("IHDR", ""), chunk("IDAT", "ab"), chunk("IEND", "")];
+        let changes = Change::between(&before, &after);
+        assert_eq!(changes.len(), 3);
+        assert!(Change::between(&before, &before).is_empty());
+        assert_eq!(Change::revert(&after, &changes).unwrap(), before);
+        assert!(Change::revert(&before, &changes).is_none());
+
+        fs::write(dir.join("test.png"), Png::from_chunks(after).as_bytes()).unwrap();
+        let entry = Entry {
+            file_name: "test.png".to_string(),
+            changes,
+        };
+        entry.revert(&dir).unwrap();
+        let reverted = fs::read(dir.join("test.png")).unwrap();
+        assert_eq!(reverted, Png::from_chunks(before).as_bytes());
+    }
+}
@@ -0,0 +1,90 @@
+//! JSON documents as `serde_json` values. Object keys keep their order,
+//! so serializing a parsed document gives the same bytes back
+
+use anyhow::{Context, Result};
+pub use serde_json::{json, Map, Value};
+
+pub fn parse(text: &str) -> Result<Value> {
+    serde_json::from_str(text).context("Invalid JSON")
+}
+
+/// Serialization with one item per line and two-space indentation, for files meant to be
+/// read, diffed and edited by people
+pub fn pretty(value: &Value) -> String {
+    serde_json::to_string_pretty(value).expect("JSON value always serializes")
+}
+
+/// Typed lookups of object fields, failing with a readable error
+pub trait Fields {
+    fn str_field(&self, key: &str) -> Result<&str>;
+    fn u64_field(&self, key: &str) -> Result<u64>;
+    fn array_field(&self, key: &str) -> Result<&[Value]>;
+}
+
+impl Fields for Value {
+    fn str_field(&self, key: &str) -> Result<&str> {
+        self.get(key)
+            .and_then(Value::as_str)
+            .with_context(|| format!("Missing string field '{}'", key))
+    }
+
+    fn u64_field(&self, key: &str) -> Result<u64> {
+        self.get(key)
+            .and_then(Value::as_u64)
+            .with_context(|| format!("Missing integer field '{}'", key))
+    }
+
+    fn array_field(&self, key: &str) -> Result<&[Value]> {
+        self.get(key)
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+            .with_context(|| format!("Missing array field '{}'", key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let text = r#"{"name":"dice \"1\".png","size":4803,"tags":["a\nb",true,null],"empty":{}}"#;
+        let value = parse(text).unwrap();
+        assert_eq!(value.to_string(), text);
+        assert_eq!(value.str_field("name").unwrap(), "dice \"1\".png");
+        assert_eq!(value.u64_field("size").unwrap(), 4803);
+        assert_eq!(value.array_field("tags").unwrap().len(), 3);
+        assert!(value.str_field("size").is_err());
+        assert!(value.array_field("name").is_err());
+    }
+
+    #[test]
+    fn test_pretty() {
+        let value = parse(r#"{"a":[1,{"b":"c"}],"d":[],"e":{}}"#).unwrap();
+        let expected = "{\n  \"a\": [\n    1,\n    {\n      \"b\": \"c\"\n    }\n  ],\n  \"d\": [],\n  \"e\": {}\n}";
+        assert_eq!(pretty(&value), expected);
+        assert_eq!(parse(expected).unwrap(), value);
+    }
+
+    #[test]
+    fn test_parse_whitespace_and_escapes() {
+        let value = parse(" [ 1.5 , -2, \"\\u00e9\\ud83d\\ude00\" ]\n").unwrap();
+        assert_eq!(value, json!([1.5, -2, "é😀"]));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for text in [
+            "",
+            "{",
+            "[1,]",
+            "{\"a\" 1}",
+            "\"abc",
+            "tru",
+            "[1] 2",
+            "\"\\ud800\"",
+        ] {
+            assert!(parse(text).is_err(), "{}", text);
+        }
+    }
+}
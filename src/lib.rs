@@ -0,0 +1,12 @@
+//! Chunk types, CRC validation, embedding backends and payload codecs of pngme, usable
+//! without `std` (only `alloc` is needed) when built without default features
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+pub mod chunk;
+pub mod chunk_type;
+pub mod codec;
+pub mod embed;
+#[cfg(any(feature = "testing", test))]
+pub mod testing;
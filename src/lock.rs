@@ -0,0 +1,118 @@
+use std::fs::{File, TryLockError};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use tracing::debug;
+
+/// Set by commands that modify files, unless `--no-lock` is given
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// Locked files, kept open so locks last until the process exits
+static HELD: Mutex<Vec<(PathBuf, File)>> = Mutex::new(vec![]);
+
+/// Deadlocks are possible when two processes lock the same files in different order,
+/// so waiting is not endless
+const TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Takes exclusive advisory lock on file before it is read, so another pngme
+/// modifying the same file waits until this one has written its changes.
+/// Does nothing when locking is disabled or file can't be opened
+pub fn acquire(path: &Path) -> Result<()> {
+    // Windows locks are mandatory, they would stop file from being read even by this process
+    if !cfg!(unix) || !ENABLED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let mut held = HELD.lock().unwrap();
+    if held.iter().any(|(locked, _)| locked == path) {
+        return Ok(());
+    }
+    if let Some(file) = lock(path)? {
+        debug!("Locked {}", path.display());
+        held.push((path.to_path_buf(), file));
+    }
+    Ok(())
+}
+
+/// Waits until file can be locked, `None` if it can't be opened
+fn lock(path: &Path) -> Result<Option<File>> {
+    let start = Instant::now();
+    let mut waiting = false;
+    loop {
+        let Ok(file) = File::open(path) else {
+            return Ok(None);
+        };
+        match file.try_lock() {
+            // file is replaced by rename when written, lock of replaced one protects nothing
+            Ok(()) if is_same_file(&file, path) => return Ok(Some(file)),
+            Ok(()) => continue,
+            Err(TryLockError::WouldBlock) => {}
+            Err(TryLockError::Error(err)) => {
+                return Err(err).with_context(|| {
+                    format!(
+                        "Failed to lock {}, use --no-lock if file system doesn't support locking",
+                        path.display()
+                    )
+                });
+            }
+        }
+        if !waiting {
+            eprintln!(
+                "Waiting for another pngme to finish with {}",
+                path.display()
+            );
+            waiting = true;
+        }
+        if start.elapsed() > TIMEOUT {
+            bail!(
+                "Timed out waiting for lock of {}, use --no-lock to modify it anyway",
+                path.display()
+            );
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_file: &File, _path: &Path) -> bool {
+    true
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_lock() {
+        let path = env::temp_dir().join(format!("pngme-lock-{}.png", std::process::id()));
+        fs::write(&path, b"png").unwrap();
+        let file = lock(&path).unwrap().unwrap();
+        let other = File::open(&path).unwrap();
+        assert!(matches!(other.try_lock(), Err(TryLockError::WouldBlock)));
+        drop(file);
+        assert!(other.try_lock().is_ok());
+
+        assert!(lock(&path.with_extension("missing")).unwrap().is_none());
+        fs::remove_file(&path).unwrap();
+    }
+}
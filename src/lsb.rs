@@ -0,0 +1,319 @@
+//! Payload hidden in least significant bits of pixel samples,
+//! for 8-bit non-interlaced images without palette
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::deflate;
+use crate::embed::{Carrier, Embedder};
+use crate::header::ImageHeader;
+use crate::inflate;
+
+/// Written before payload length, so images without payload are told apart
+const MAGIC: &[u8; 4] = b"pnLS";
+const PREFIX_SIZE: usize = MAGIC.len() + 4;
+
+pub struct LsbEmbedder;
+
+impl Embedder for LsbEmbedder {
+    fn name(&self) -> &str {
+        "lsb"
+    }
+
+    fn embed(&self, carrier: &mut Carrier, payload: &[u8]) -> Result<()> {
+        let mut pixels = Pixels::decode(carrier)?;
+        let capacity = pixels.capacity();
+        ensure!(
+            payload.len() <= capacity,
+            "Payload of {} bytes doesn't fit, image holds at most {} bytes",
+            payload.len(),
+            capacity
+        );
+        let length = u32::try_from(payload.len())?.to_be_bytes();
+        let bits = MAGIC
+            .iter()
+            .chain(&length)
+            .chain(payload)
+            .flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1));
+        for (sample, bit) in pixels.samples.iter_mut().zip(bits) {
+            *sample = *sample & !1 | bit;
+        }
+        pixels.encode(carrier)
+    }
+
+    fn extract(&self, carrier: &Carrier) -> Result<Option<Vec<u8>>> {
+        let pixels = Pixels::decode(carrier)?;
+        let mut bytes = pixels
+            .samples
+            .chunks_exact(8)
+            .map(|bits| bits.iter().fold(0, |byte, sample| byte << 1 | sample & 1));
+        let prefix: Vec<u8> = bytes.by_ref().take(PREFIX_SIZE).collect();
+        if prefix.len() < PREFIX_SIZE || &prefix[..MAGIC.len()] != MAGIC {
+            return Ok(None);
+        }
+        let length = u32::from_be_bytes(prefix[MAGIC.len()..].try_into().unwrap()) as usize;
+        ensure!(
+            length <= pixels.capacity(),
+            "Hidden payload length {} exceeds image capacity",
+            length
+        );
+        Ok(Some(bytes.take(length).collect()))
+    }
+}
+
+/// Unfiltered samples of all rows and filter type each row was stored with
+struct Pixels {
+    samples: Vec<u8>,
+    filters: Vec<u8>,
+    /// Bytes per pixel, distance to the sample filters refer to as "left"
+    bpp: usize,
+}
+
+impl Pixels {
+    fn decode(carrier: &Carrier) -> Result<Pixels> {
+        let ihdr = carrier
+            .chunks
+            .iter()
+            .find(|c| &c.chunk_type().bytes() == b"IHDR")
+            .context("Image has no IHDR chunk")?;
+        let header = ImageHeader::from_data(ihdr.data())?;
+        let bpp = match header.color_type {
+            0 => 1,
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => bail!(
+                "LSB mode doesn't support {} images",
+                header.color_type_name()
+            ),
+        };
+        ensure!(
+            header.bit_depth == 8,
+            "LSB mode needs 8-bit samples, image has {}-bit",
+            header.bit_depth
+        );
+        ensure!(
+            !header.interlaced,
+            "LSB mode doesn't support interlaced images"
+        );
+
+        let stride = header.width as usize * bpp;
+        let height = header.height as usize;
+        let size = (stride + 1) * height;
+        let data = inflate::zlib_decompress(&image_data(carrier), size)
+            .context("Failed to decompress image data")?;
+        ensure!(
+            data.len() == size,
+            "Image data is {} bytes, expected {}",
+            data.len(),
+            size
+        );
+
+        let mut samples = vec![0; stride * height];
+        let mut filters = Vec::with_capacity(height);
+        for (y, line) in data.chunks_exact(stride + 1).enumerate() {
+            let (done, rest) = samples.split_at_mut(y * stride);
+            let prev = done
+                .get(done.len().saturating_sub(stride)..)
+                .filter(|_| y > 0);
+            let row = &mut rest[..stride];
+            for i in 0..stride {
+                let predicted = predict(line[0], row, prev, i, bpp)?;
+                row[i] = line[i + 1].wrapping_add(predicted);
+            }
+            filters.push(line[0]);
+        }
+        Ok(Pixels {
+            samples,
+            filters,
+            bpp,
+        })
+    }
+
+    /// Payload bytes that fit after length prefix
+    fn capacity(&self) -> usize {
+        (self.samples.len() / 8).saturating_sub(PREFIX_SIZE)
+    }
+
+    /// Filters rows as they were originally and compresses them as tightly as
+    /// most encoders do, into IDAT chunks of the same sizes as before where possible,
+    /// so neither file size nor chunk layout tells that pixels were changed
+    fn encode(&self, carrier: &mut Carrier) -> Result<()> {
+        let stride = self.samples.len() / self.filters.len().max(1);
+        let mut data = Vec::with_capacity(self.samples.len() + self.filters.len());
+        for (y, row) in self.samples.chunks_exact(stride).enumerate() {
+            let prev = y
+                .checked_sub(1)
+                .map(|y| &self.samples[y * stride..][..stride]);
+            data.push(self.filters[y]);
+            for i in 0..stride {
+                let predicted = predict(self.filters[y], row, prev, i, self.bpp)?;
+                data.push(row[i].wrapping_sub(predicted));
+            }
+        }
+
+        let is_idat = |c: &Chunk| &c.chunk_type().bytes() == b"IDAT";
+        let index = carrier
+            .chunks
+            .iter()
+            .position(is_idat)
+            .context("Image has no IDAT chunk")?;
+        let sizes: Vec<usize> = carrier
+            .chunks
+            .iter()
+            .filter(|c| is_idat(c))
+            .map(|c| c.data().len())
+            .collect();
+        carrier.chunks.retain(|c| !is_idat(c));
+
+        let compressed = deflate::zlib_compress_best(&data);
+        let mut rest = compressed.as_slice();
+        for (i, &size) in sizes.iter().enumerate() {
+            // the last chunk takes whatever is left
+            let (part, tail) = match i + 1 == sizes.len() {
+                true => (rest, &[][..]),
+                false => rest.split_at(size.min(rest.len())),
+            };
+            let idat = Chunk::new(ChunkType::try_from(*b"IDAT")?, part.to_vec());
+            carrier.chunks.insert(index + i, idat);
+            rest = tail;
+            if rest.is_empty() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn image_data(carrier: &Carrier) -> Vec<u8> {
+    carrier
+        .chunks
+        .iter()
+        .filter(|c| &c.chunk_type().bytes() == b"IDAT")
+        .flat_map(|c| c.data().iter().copied())
+        .collect()
+}
+
+/// Value filter type `filter` predicts for sample `i` of `row` from already known
+/// samples to the left and in previous row (which is zeros for the first row)
+fn predict(filter: u8, row: &[u8], prev: Option<&[u8]>, i: usize, bpp: usize) -> Result<u8> {
+    let a = if i >= bpp { row[i - bpp] } else { 0 };
+    let b = prev.map_or(0, |prev| prev[i]);
+    let c = match prev {
+        Some(prev) if i >= bpp => prev[i - bpp],
+        _ => 0,
+    };
+    Ok(match filter {
+        0 => 0,
+        1 => a,
+        2 => b,
+        3 => ((a as u16 + b as u16) / 2) as u8,
+        4 => paeth(a, b, c),
+        _ => bail!("Unknown filter type {}", filter),
+    })
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 8x3 RGB image, holding one byte of payload, with rows stored with filters 1, 2 and 4
+    fn carrier() -> Carrier {
+        let chunk =
+            |name: &[u8; 4], data: Vec<u8>| Chunk::new(ChunkType::try_from(*name).unwrap(), data);
+        let ihdr = vec![0, 0, 0, 8, 0, 0, 0, 3, 8, 2, 0, 0, 0];
+        let mut data = vec![];
+        for filter in [1, 2, 4] {
+            data.push(filter);
+            data.extend((0..24).map(|i| i * 7 + filter));
+        }
+        Carrier {
+            chunks: vec![
+                chunk(b"IHDR", ihdr),
+                chunk(b"IDAT", deflate::zlib_compress(&data)),
+                chunk(b"IEND", vec![]),
+            ],
+            trailing: vec![],
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut carrier = carrier();
+        let original = Pixels::decode(&carrier).unwrap().samples;
+        assert_eq!(LsbEmbedder.extract(&carrier).unwrap(), None);
+        assert!(LsbEmbedder.embed(&mut carrier, b"ab").is_err());
+
+        LsbEmbedder.embed(&mut carrier, b"a").unwrap();
+        assert_eq!(LsbEmbedder.extract(&carrier).unwrap().unwrap(), b"a");
+        let pixels = Pixels::decode(&carrier).unwrap();
+        assert_eq!(pixels.filters, [1, 2, 4]);
+        for (before, after) in original.iter().zip(&pixels.samples) {
+            assert!(before.abs_diff(*after) <= 1);
+        }
+    }
+
+    #[test]
+    fn test_keeps_size_and_layout() {
+        let chunk =
+            |name: &[u8; 4], data: Vec<u8>| Chunk::new(ChunkType::try_from(*name).unwrap(), data);
+        // 64x64 RGB gradient with some noise, compressed as a typical encoder would
+        let mut data = vec![];
+        let mut x = 1u32;
+        for y in 0..64u32 {
+            data.push(1);
+            for i in 0..64 * 3 {
+                x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                data.push((i % 3 + y % 5) as u8 + (x >> 30) as u8);
+            }
+        }
+        let compressed = deflate::zlib_compress(&data);
+        let (a, b) = compressed.split_at(compressed.len() / 2);
+        let mut carrier = Carrier {
+            chunks: vec![
+                chunk(b"IHDR", vec![0, 0, 0, 64, 0, 0, 0, 64, 8, 2, 0, 0, 0]),
+                chunk(b"IDAT", a.to_vec()),
+                chunk(b"IDAT", b.to_vec()),
+                chunk(b"IEND", vec![]),
+            ],
+            trailing: vec![],
+        };
+
+        LsbEmbedder.embed(&mut carrier, b"secret message").unwrap();
+        assert_eq!(
+            LsbEmbedder.extract(&carrier).unwrap().unwrap(),
+            b"secret message"
+        );
+        let idats: Vec<_> = carrier.chunks[1..3].iter().map(|c| c.length()).collect();
+        assert_eq!(&carrier.chunks[2].chunk_type().bytes(), b"IDAT");
+        assert_eq!(idats[0] as usize, a.len());
+        let size = idats.iter().sum::<u32>() as usize;
+        assert!(size <= compressed.len() * 102 / 100, "{}", size);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let mut carrier = carrier();
+        let mut ihdr = carrier.chunks[0].data().to_vec();
+        ihdr[9] = 3;
+        carrier.chunks[0] = Chunk::new(ChunkType::try_from(*b"IHDR").unwrap(), ihdr);
+        assert!(LsbEmbedder.extract(&carrier).is_err());
+    }
+}
@@ -1,12 +1,166 @@
-use anyhow::Error;
+use std::process::ExitCode;
+
 use anyhow::Result;
+use pngme::{chunk, chunk_type, embed};
+use tracing::Level;
+
+use crate::args::Command;
+use crate::commands::Failure;
+use crate::config::Config;
+#[cfg(not(feature = "cloud"))]
+use crate::storage::LocalFs;
+
+/// `println!` that stays silent under `--quiet`, leaving only exit code and errors
+macro_rules! outln {
+    ($($arg:tt)*) => {
+        if !crate::progress::is_quiet() {
+            println!($($arg)*);
+        }
+    };
+}
 
+/// `print!` counterpart of `outln!`
+macro_rules! out {
+    ($($arg:tt)*) => {
+        if !crate::progress::is_quiet() {
+            print!($($arg)*);
+        }
+    };
+}
+
+#[cfg(feature = "write")]
+mod apng;
+mod archive;
 mod args;
-mod chunk;
-mod chunk_type;
+mod blame;
+#[cfg(feature = "write")]
+mod bug_report;
+mod carve;
+#[cfg(feature = "write")]
+mod changelog;
+#[cfg(feature = "clipboard")]
+mod clipboard;
+#[cfg(feature = "cloud")]
+mod cloud;
+mod color;
 mod commands;
+mod config;
+mod date;
+mod deflate;
+mod diff;
+mod doctor;
+mod ecc;
+#[cfg(feature = "write")]
+mod editor;
+#[cfg(feature = "age")]
+mod encryption;
+mod envelope;
+#[cfg(feature = "write")]
+mod filter;
+mod gf256;
+mod header;
+mod hex;
+mod hexdump;
+#[cfg(feature = "write")]
+mod history;
+mod hmac;
+mod inflate;
+#[cfg(feature = "write")]
+mod journal;
+mod json;
+#[cfg(feature = "write")]
+mod lock;
+mod lsb;
+#[cfg(feature = "write")]
+mod manifest;
+#[cfg(feature = "write")]
+mod merge;
+#[cfg(feature = "write")]
+mod minimize;
+mod obfuscate;
+mod ordering;
+mod palette;
+mod parser;
+mod phys;
+mod pixels;
 mod png;
+mod progress;
+#[cfg(feature = "write")]
+mod protect;
+#[cfg(feature = "write")]
+mod provenance;
+mod raw;
+mod receipt;
+mod registry;
+#[cfg(feature = "yara")]
+mod rules;
+mod scan;
+#[cfg(feature = "write")]
+mod scanner;
+mod seal;
+mod search;
+#[cfg(feature = "serve")]
+mod serve;
+mod sharing;
+mod signature;
+mod spread;
+mod stats;
+mod stealth;
+mod storage;
+mod structure;
+mod survey;
+mod table;
+#[cfg(feature = "write")]
+mod tar;
+mod text;
+#[cfg(feature = "write")]
+mod transaction;
+#[cfg(feature = "write")]
+mod trash;
+#[cfg(feature = "self-update")]
+mod update;
+#[cfg(feature = "write")]
+mod watch;
+#[cfg(feature = "write")]
+mod wizard;
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            let code = err
+                .downcast_ref::<Failure>()
+                .map_or(Failure::OTHER, |f| f.code);
+            ExitCode::from(code)
+        }
+    }
+}
 
-fn main() -> Result<()> {
-    todo!()
+fn run() -> Result<()> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let config = Config::load(&mut args)?;
+    let (command, options) = Command::parse(args, config).map_err(|err| {
+        let message = format!("{:#}\nRun 'pngme --help' to see usage", err);
+        Failure::new(Failure::USAGE, message)
+    })?;
+    progress::set_quiet(options.quiet);
+    commands::set_strict_png(options.strict_png);
+    #[cfg(feature = "write")]
+    lock::set_enabled(options.lock);
+    let level = match options.verbosity {
+        0 => Level::WARN,
+        1 => Level::INFO,
+        _ => Level::DEBUG,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .without_time()
+        .with_target(false)
+        .init();
+    #[cfg(feature = "cloud")]
+    return commands::run(command, &cloud::CloudFs);
+    #[cfg(not(feature = "cloud"))]
+    commands::run(command, &LocalFs)
 }
@@ -0,0 +1,133 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::args::Message;
+use crate::chunk_type::ChunkType;
+use crate::registry;
+
+/// One embedding described in batch manifest
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    pub input: PathBuf,
+    /// Input is overwritten if not set
+    pub output: Option<PathBuf>,
+    pub chunk_type: ChunkType,
+    pub message: Message,
+    pub hmac_key: Option<String>,
+    pub ecc: Option<f64>,
+    pub obfuscate: Option<String>,
+}
+
+/// Entry as written in manifest, before its values are checked
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct RawEntry {
+    input: PathBuf,
+    output: Option<PathBuf>,
+    #[serde(rename = "type")]
+    chunk_type: String,
+    message: Option<String>,
+    message_file: Option<PathBuf>,
+    hmac_key: Option<String>,
+    ecc: Option<f64>,
+    obfuscate: Option<String>,
+}
+
+/// Parses manifest written as YAML list of mappings:
+///
+/// ```yaml
+/// - input: dice.png
+///   output: out/dice.png
+///   type: ruSt
+///   message: "hello, world"
+/// ```
+///
+/// Unknown keys, values of wrong type and anything but a list of mappings are errors
+pub fn parse(text: &str) -> Result<Vec<Entry>> {
+    let entries: Option<Vec<RawEntry>> = serde_yaml::from_str(text)?;
+    entries
+        .unwrap_or_default()
+        .into_iter()
+        .enumerate()
+        .map(|(i, raw)| entry(raw).with_context(|| format!("Invalid entry #{}", i + 1)))
+        .collect()
+}
+
+fn entry(raw: RawEntry) -> Result<Entry> {
+    let message = match (raw.message, raw.message_file) {
+        (Some(text), None) => Message::Text(text),
+        (None, Some(path)) => Message::File(path),
+        _ => bail!("Exactly one of message and message-file is required"),
+    };
+    Ok(Entry {
+        input: raw.input,
+        output: raw.output,
+        chunk_type: registry::resolve(&raw.chunk_type)?,
+        message,
+        hmac_key: raw.hmac_key,
+        ecc: raw.ecc,
+        obfuscate: raw.obfuscate,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let entries = parse(
+            "# assets\n\
+             - input: dice.png\n  \
+               output: out/dice.png # copy\n  \
+               type: ruSt\n  \
+               message: \"hello # not a comment\\n\"\n\
+             -\n  \
+               input: 'it''s.png'\n  \
+               type: text\n  \
+               message-file: notes.txt\n  \
+               ecc: 0.5\n",
+        )
+        .unwrap();
+        assert_eq!(
+            entries,
+            [
+                Entry {
+                    input: "dice.png".into(),
+                    output: Some("out/dice.png".into()),
+                    chunk_type: ChunkType::from_str("ruSt").unwrap(),
+                    message: Message::Text("hello # not a comment\n".to_string()),
+                    hmac_key: None,
+                    ecc: None,
+                    obfuscate: None,
+                },
+                Entry {
+                    input: "it's.png".into(),
+                    output: None,
+                    chunk_type: ChunkType::from_str("tEXt").unwrap(),
+                    message: Message::File("notes.txt".into()),
+                    hmac_key: None,
+                    ecc: Some(0.5),
+                    obfuscate: None,
+                },
+            ]
+        );
+        assert!(parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse("input: dice.png").is_err());
+        assert!(parse("- input: dice.png\n  type: ruSt").is_err());
+        assert!(parse("- input: a.png\n  type: ruSt\n  message: a\n  message-file: b").is_err());
+        assert!(parse("- input: a.png\n  input: b.png\n  type: ruSt\n  message: a").is_err());
+        assert!(parse("- input: a.png\n  type: ruSt\n  message: a\n  level: 9").is_err());
+        assert!(parse("- input: a.png\n  type: ruSt\n  message: \"a").is_err());
+        assert!(parse("- input: a.png\n  type: ruSt\n  message: [a, b]").is_err());
+        assert!(parse("- input: a.png\n  type: ruSt\n  message: a\n  ecc: half").is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use anyhow::{ensure, Result};
+
+use crate::chunk::Chunk;
+use crate::diff::{keyed, Key};
+
+pub struct Merged {
+    pub chunks: Vec<Chunk>,
+    /// One line per chunk changed differently on both sides, our version is
+    /// kept unless we removed the chunk
+    pub conflicts: Vec<String>,
+}
+
+/// Three-way merge of chunk lists. Image itself (critical chunks) is taken
+/// as a whole from the side that changed it, ancillary chunks are merged
+/// one by one, matched by type and, for text chunks, keyword
+pub fn merge(base: &[Chunk], ours: &[Chunk], theirs: &[Chunk]) -> Result<Merged> {
+    let image = |chunks: &[Chunk]| -> Vec<Vec<u8>> {
+        chunks
+            .iter()
+            .filter(|c| c.chunk_type().is_critical())
+            .map(Chunk::as_bytes)
+            .collect()
+    };
+    let (base_image, our_image, their_image) = (image(base), image(ours), image(theirs));
+    ensure!(
+        our_image == base_image || their_image == base_image || our_image == their_image,
+        "Image data was changed on both sides, can't merge"
+    );
+
+    // order of chunks follows the side whose image is used
+    let (main, other) = if our_image == base_image && their_image != base_image {
+        (keyed(theirs), keyed(ours))
+    } else {
+        (keyed(ours), keyed(theirs))
+    };
+    let (base, ours, theirs) = (keyed(base), keyed(ours), keyed(theirs));
+    let find = |chunks: &[(Key, Vec<u8>)], key: &Key| {
+        chunks
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, bytes)| bytes.clone())
+    };
+
+    let mut conflicts = vec![];
+    let mut resolve = |key: &Key| -> Option<Vec<u8>> {
+        let (b, o, t) = (find(&base, key), find(&ours, key), find(&theirs, key));
+        if o == t || t == b {
+            return o;
+        }
+        if o == b {
+            return t;
+        }
+        let (name, keyword, _) = key;
+        let name = match keyword {
+            Some(keyword) => format!("{} {}", name, keyword),
+            None => name.clone(),
+        };
+        let problem = match (&b, &o, &t) {
+            (None, _, _) => "added on both sides with different content",
+            (_, None, _) => "removed in ours, changed in theirs",
+            (_, _, None) => "changed in ours, removed in theirs",
+            _ => "changed on both sides",
+        };
+        conflicts.push(format!("{}: {}", name, problem));
+        // keep whatever was edited, so nobody's work is lost
+        o.or(t)
+    };
+
+    let mut merged: Vec<(Key, Vec<u8>)> = vec![];
+    for (key, bytes) in &main {
+        if is_critical(key) {
+            merged.push((key.clone(), bytes.clone()));
+        } else if let Some(bytes) = resolve(key) {
+            merged.push((key.clone(), bytes));
+        }
+    }
+
+    // chunks only the other side has go after the closest chunk preceding them there
+    let mut position = merged.iter().take_while(|(k, _)| k.0 == "IHDR").count();
+    for (key, _) in &other {
+        if let Some(index) = merged.iter().position(|(k, _)| k == key) {
+            position = index + 1;
+            continue;
+        }
+        if main.iter().any(|(k, _)| k == key) || is_critical(key) {
+            continue;
+        }
+        if let Some(bytes) = resolve(key) {
+            merged.insert(position, (key.clone(), bytes));
+            position += 1;
+        }
+    }
+
+    let chunks = merged
+        .iter()
+        .map(|(_, bytes)| Chunk::try_from(bytes.as_slice()))
+        .collect::<Result<_>>()?;
+    Ok(Merged { chunks, conflicts })
+}
+
+fn is_critical((name, _, _): &Key) -> bool {
+    name.starts_with(|c: char| c.is_ascii_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk_type::ChunkType;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.into())
+    }
+
+    fn base() -> Vec<Chunk> {
+        vec![
+            chunk("IHDR", "header"),
+            chunk("tEXt", "Author\0Jane"),
+            chunk("tEXt", "Title\0Sunset"),
+            chunk("IDAT", "pixels"),
+            chunk("IEND", ""),
+        ]
+    }
+
+    fn contents(chunks: &[Chunk]) -> Vec<String> {
+        chunks
+            .iter()
+            .map(|c| format!("{} {}", c.chunk_type(), String::from_utf8_lossy(c.data())))
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_independent_edits() {
+        let mut ours = base();
+        ours[1] = chunk("tEXt", "Author\0Joe");
+        ours.insert(3, chunk("ruSt", "note"));
+        let mut theirs = base();
+        theirs.remove(2);
+        theirs.insert(1, chunk("tIME", "time"));
+        theirs[3] = chunk("IDAT", "new pixels");
+
+        let merged = merge(&base(), &ours, &theirs).unwrap();
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(
+            contents(&merged.chunks),
+            [
+                "IHDR header",
+                "tIME time",
+                "tEXt Author\0Joe",
+                "ruSt note",
+                "IDAT new pixels",
+                "IEND ",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_conflicts() {
+        let mut ours = base();
+        ours[1] = chunk("tEXt", "Author\0Joe");
+        ours.remove(2);
+        let mut theirs = base();
+        theirs[1] = chunk("tEXt", "Author\0Ann");
+        theirs[2] = chunk("tEXt", "Title\0Sunrise");
+
+        let merged = merge(&base(), &ours, &theirs).unwrap();
+        assert_eq!(
+            merged.conflicts,
+            [
+                "tEXt Author: changed on both sides",
+                "tEXt Title: removed in ours, changed in theirs",
+            ]
+        );
+        assert_eq!(
+            contents(&merged.chunks),
+            [
+                "IHDR header",
+                "tEXt Author\0Joe",
+                "tEXt Title\0Sunrise",
+                "IDAT pixels",
+                "IEND ",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_image_conflict() {
+        let mut ours = base();
+        ours[3] = chunk("IDAT", "ours");
+        let mut theirs = base();
+        theirs[3] = chunk("IDAT", "theirs");
+        assert!(merge(&base(), &ours, &theirs).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use anyhow::{ensure, Result};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::raw::{self, RawChunk};
+
+/// Shrinks image while `fails` keeps returning true for it: first drops
+/// whole chunks, then halves chunk data. Repeats until nothing else can
+/// be removed. Returns smallest image found and number of `fails` calls
+pub fn minimize<F>(png: &[u8], mut fails: F) -> Result<(Vec<u8>, usize)>
+where
+    F: FnMut(&[u8]) -> Result<bool>,
+{
+    ensure!(fails(png)?, "Command doesn't fail on the original image");
+    let mut runs = 1;
+
+    let signature = png[..png.len().min(8)].to_vec();
+    let mut chunks: Vec<Vec<u8>> = raw::raw_chunks(&png[signature.len()..])
+        .iter()
+        .map(|c| c.bytes.to_vec())
+        .collect();
+    let assemble = |chunks: &[Vec<u8>]| -> Vec<u8> {
+        let mut png = signature.clone();
+        for chunk in chunks {
+            png.extend_from_slice(chunk);
+        }
+        png
+    };
+
+    loop {
+        let mut progress = false;
+
+        let mut i = chunks.len();
+        while i > 0 {
+            i -= 1;
+            let mut candidate = chunks.clone();
+            candidate.remove(i);
+            runs += 1;
+            if fails(&assemble(&candidate))? {
+                chunks = candidate;
+                progress = true;
+            }
+        }
+
+        for i in 0..chunks.len() {
+            while let Some(shorter) = halve(&chunks[i]) {
+                let mut candidate = chunks.clone();
+                candidate[i] = shorter;
+                runs += 1;
+                if !fails(&assemble(&candidate))? {
+                    break;
+                }
+                chunks = candidate;
+                progress = true;
+            }
+        }
+
+        if !progress {
+            return Ok((assemble(&chunks), runs));
+        }
+    }
+}
+
+/// Same chunk with first half of its data. CRC is recalculated only if
+/// it was valid, so broken chunks stay broken
+fn halve(bytes: &[u8]) -> Option<Vec<u8>> {
+    let chunk = RawChunk {
+        offset: 0,
+        chunk_type: bytes[4..8].try_into().unwrap(),
+        bytes,
+    };
+    if chunk.is_truncated() || chunk.data().is_empty() {
+        return None;
+    }
+    let data = &chunk.data()[..chunk.data().len() / 2];
+    let crc = match (chunk.problem(), ChunkType::try_from(chunk.chunk_type)) {
+        (None, Ok(chunk_type)) => Chunk::new(chunk_type, data.to_vec()).crc().to_be_bytes(),
+        _ => bytes[bytes.len() - 4..].try_into().unwrap(),
+    };
+
+    let mut out = (data.len() as u32).to_be_bytes().to_vec();
+    out.extend_from_slice(&chunk.chunk_type);
+    out.extend_from_slice(data);
+    out.extend_from_slice(&crc);
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::png::Png;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.into())
+    }
+
+    #[test]
+    fn test_minimize() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", "header"),
+            chunk("tEXt", "Comment\0hello"),
+            chunk("ruSt", "BUG and a lot of unrelated data"),
+            chunk("IDAT", "pixels"),
+            chunk("IEND", ""),
+        ])
+        .as_bytes();
+
+        let fails = |png: &[u8]| -> Result<bool> {
+            Ok(Png::try_from(png).is_ok() && png.windows(3).any(|w| w == b"BUG"))
+        };
+        let (minimized, runs) = minimize(&png, fails).unwrap();
+        assert!(runs > 1);
+
+        let minimized = Png::try_from(minimized.as_slice()).unwrap();
+        assert_eq!(minimized.chunks().len(), 1);
+        assert!(minimized.chunks()[0].data().starts_with(b"BUG"));
+        assert!(minimized.chunks()[0].data().len() < 8);
+    }
+
+    #[test]
+    fn test_original_must_fail() {
+        assert!(minimize(&Png::STANDARD_HEADER, |_| Ok(false)).is_err());
+    }
+
+    #[test]
+    fn test_halve_keeps_broken_crc() {
+        let mut bytes = chunk("ruSt", "abcd").as_bytes();
+        let valid = halve(&bytes).unwrap();
+        assert_eq!(valid, chunk("ruSt", "ab").as_bytes());
+
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        let broken = halve(&bytes).unwrap();
+        assert!(Chunk::try_from(broken.as_slice()).is_err());
+        assert_eq!(&broken[8..10], b"ab");
+    }
+}
@@ -0,0 +1,51 @@
+/// XORs data in place with a keystream from a PRNG seeded by key.
+/// Applying it twice with the same key restores data.
+/// This only hides payload from casual inspection, it is NOT encryption
+pub fn xor(key: &[u8], data: &mut [u8]) {
+    let mut rng = SplitMix64(fnv1a(key));
+    for block in data.chunks_mut(8) {
+        let stream = rng.next().to_le_bytes();
+        for (byte, mask) in block.iter_mut().zip(stream) {
+            *byte ^= mask;
+        }
+    }
+}
+
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// SplitMix64 generator by Sebastiano Vigna
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xor_roundtrip() {
+        let message = b"This is a secret message!";
+        let mut data = message.to_vec();
+
+        xor(b"key", &mut data);
+        assert_ne!(data, message);
+        let mut other = message.to_vec();
+        xor(b"other key", &mut other);
+        assert_ne!(data, other);
+
+        xor(b"key", &mut data);
+        assert_eq!(data, message);
+    }
+}
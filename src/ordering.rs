@@ -0,0 +1,188 @@
+#[cfg(feature = "write")]
+use anyhow::{bail, Result};
+
+use crate::chunk::Chunk;
+
+/// Chunks that must come before PLTE, and so before image data too
+const BEFORE_PLTE: [&[u8; 4]; 8] = [
+    b"cHRM", b"cICP", b"cLLI", b"gAMA", b"iCCP", b"mDCV", b"sBIT", b"sRGB",
+];
+/// Chunks that must come before the first IDAT
+const BEFORE_IDAT: [&[u8; 4]; 10] = [
+    b"PLTE", b"acTL", b"bKGD", b"hIST", b"oFFs", b"pCAL", b"pHYs", b"sCAL", b"sPLT", b"tRNS",
+];
+/// Chunks that describe palette, so must come after it
+const AFTER_PLTE: [&[u8; 4]; 3] = [b"bKGD", b"hIST", b"tRNS"];
+/// Chunks that may appear at most once
+const SINGLE: [&[u8; 4]; 21] = [
+    b"IHDR", b"PLTE", b"IEND", b"acTL", b"bKGD", b"cHRM", b"cICP", b"cLLI", b"eXIf", b"gAMA",
+    b"hIST", b"iCCP", b"mDCV", b"oFFs", b"pCAL", b"pHYs", b"sBIT", b"sCAL", b"sRGB", b"tIME",
+    b"tRNS",
+];
+
+/// Checks that chunks are placed where PNG specification allows them
+#[cfg(feature = "write")]
+pub fn check(chunks: &[Chunk]) -> Result<()> {
+    match violations(chunks).into_iter().next() {
+        Some(violation) => bail!("{}", violation),
+        None => Ok(()),
+    }
+}
+
+/// Checks only rules that were followed before modification, so already
+/// malformed images can still be edited as long as it doesn't make them worse
+#[cfg(feature = "write")]
+pub fn check_modified(before: &[Chunk], after: &[Chunk]) -> Result<()> {
+    let existing = violations(before);
+    let new: Vec<_> = violations(after)
+        .into_iter()
+        .filter(|v| !existing.contains(v))
+        .collect();
+    if !new.is_empty() {
+        bail!("{}", new.join(", "));
+    }
+    Ok(())
+}
+
+/// Every ordering rule broken by chunks, empty for a well-formed image
+pub fn violations(chunks: &[Chunk]) -> Vec<String> {
+    let types: Vec<_> = chunks.iter().map(|c| c.chunk_type().bytes()).collect();
+    let first = |t: &[u8; 4]| types.iter().position(|x| x == t);
+    let last = |t: &[u8; 4]| types.iter().rposition(|x| x == t);
+    let mut violations = vec![];
+    let mut ensure = |ok: bool, violation: String| {
+        if !ok {
+            violations.push(violation);
+        }
+    };
+
+    ensure(
+        types.first() == Some(b"IHDR"),
+        "IHDR must be the first chunk".to_string(),
+    );
+    ensure(
+        types.last() == Some(b"IEND"),
+        "IEND must be the last chunk".to_string(),
+    );
+    for t in SINGLE {
+        let count = types.iter().filter(|x| x == &t).count();
+        ensure(count <= 1, format!("{} must appear at most once", name(t)));
+    }
+    ensure(
+        first(b"iCCP").is_none() || first(b"sRGB").is_none(),
+        "iCCP and sRGB can't be used together".to_string(),
+    );
+
+    if let Some(idat) = first(b"IDAT") {
+        let end = last(b"IDAT").unwrap();
+        ensure(
+            types[idat..=end].iter().all(|t| t == b"IDAT"),
+            "IDAT chunks must be consecutive".to_string(),
+        );
+        for t in BEFORE_PLTE.iter().chain(&BEFORE_IDAT) {
+            ensure(
+                last(t).is_none_or(|i| i < idat),
+                format!("{} must come before IDAT", name(t)),
+            );
+        }
+    }
+    if let Some(plte) = first(b"PLTE") {
+        for t in BEFORE_PLTE {
+            ensure(
+                last(t).is_none_or(|i| i < plte),
+                format!("{} must come before PLTE", name(t)),
+            );
+        }
+        for t in AFTER_PLTE {
+            ensure(
+                first(t).is_none_or(|i| i > plte),
+                format!("{} must come after PLTE", name(t)),
+            );
+        }
+    }
+
+    // frames of animated PNG share a single sequence starting from 0
+    let mut expected = 0;
+    for chunk in chunks {
+        let chunk_type = chunk.chunk_type().bytes();
+        if &chunk_type != b"fcTL" && &chunk_type != b"fdAT" {
+            continue;
+        }
+        let sequence = chunk
+            .data()
+            .get(..4)
+            .map(|b| u32::from_be_bytes(b.try_into().unwrap()));
+        if sequence != Some(expected) {
+            violations.push(format!(
+                "{} has sequence number {}, expected {}",
+                name(&chunk_type),
+                sequence.map_or("none".to_string(), |s| s.to_string()),
+                expected
+            ));
+            break;
+        }
+        expected += 1;
+    }
+    violations
+}
+
+fn name(chunk_type: &[u8; 4]) -> String {
+    String::from_utf8_lossy(chunk_type).into_owned()
+}
+
+#[cfg(all(test, feature = "write"))]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk_type::ChunkType;
+
+    fn chunks(types: &str) -> Vec<Chunk> {
+        types
+            .split_whitespace()
+            .map(|t| Chunk::new(ChunkType::from_str(t).unwrap(), vec![]))
+            .collect()
+    }
+
+    fn frame(chunk_type: &str, sequence: u32) -> Chunk {
+        Chunk::new(
+            ChunkType::from_str(chunk_type).unwrap(),
+            sequence.to_be_bytes().to_vec(),
+        )
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(check(&chunks("IHDR gAMA PLTE tRNS IDAT IDAT tEXt IEND")).is_ok());
+        assert!(check(&chunks("gAMA IHDR IDAT IEND")).is_err());
+        assert!(check(&chunks("IHDR IDAT IEND tEXt")).is_err());
+        assert!(check(&chunks("IHDR IDAT PLTE IEND")).is_err());
+        assert!(check(&chunks("IHDR IDAT tEXt IDAT IEND")).is_err());
+        assert!(check(&chunks("IHDR PLTE gAMA IDAT IEND")).is_err());
+        assert!(check(&chunks("IHDR tRNS PLTE IDAT IEND")).is_err());
+        assert!(check(&chunks("IHDR gAMA gAMA IDAT IEND")).is_err());
+        assert!(check(&chunks("IHDR iCCP sRGB IDAT IEND")).is_err());
+        assert!(check(&chunks("IHDR IDAT tEXt tEXt IEND")).is_ok());
+    }
+
+    #[test]
+    fn test_check_modified() {
+        let malformed = chunks("IHDR IDAT IEND ruSt");
+        assert!(check_modified(&malformed, &chunks("IHDR IDAT IEND ruSt tEXt")).is_ok());
+        assert!(check_modified(&malformed, &chunks("IHDR IDAT PLTE IEND ruSt")).is_err());
+        assert!(check_modified(&chunks("IHDR IDAT IEND"), &malformed).is_err());
+        assert_eq!(violations(&chunks("IHDR IDAT IEND")), Vec::<String>::new());
+        assert_eq!(violations(&chunks("IDAT IEND IEND")).len(), 2);
+    }
+
+    #[test]
+    fn test_check_sequence() {
+        let mut png = chunks("IHDR acTL IDAT IEND");
+        png.insert(2, frame("fcTL", 0));
+        png.insert(4, frame("fcTL", 1));
+        png.insert(5, frame("fdAT", 2));
+        assert!(check(&png).is_ok());
+        png.swap(4, 5);
+        assert!(check(&png).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use std::cmp::Reverse;
+#[cfg(feature = "write")]
+use std::fmt::Write;
+use std::fmt::{Display, Formatter};
+
+use anyhow::{bail, ensure, Result};
+
+/// Colors from `PLTE` chunk
+pub struct Palette {
+    pub colors: Vec<[u8; 3]>,
+}
+
+/// Approximate usage frequency of every palette entry from `hIST` chunk
+#[derive(Debug, Eq, PartialEq)]
+pub struct Histogram {
+    pub frequencies: Vec<u16>,
+}
+
+impl Palette {
+    pub fn from_data(data: &[u8]) -> Result<Palette> {
+        ensure!(
+            !data.is_empty() && data.len().is_multiple_of(3) && data.len() <= 256 * 3,
+            "PLTE chunk must hold 1-256 RGB entries, got {} bytes",
+            data.len()
+        );
+        let colors = data.chunks(3).map(|rgb| rgb.try_into().unwrap()).collect();
+        Ok(Palette { colors })
+    }
+
+    /// Checks that palette is allowed for color type and fits its bit depth
+    pub fn validate(&self, bit_depth: u8, color_type: u8) -> Result<()> {
+        match color_type {
+            3 => {
+                let max = 1usize << bit_depth.min(8);
+                ensure!(
+                    self.colors.len() <= max,
+                    "Palette has {} entries, but {}-bit indices address only {}",
+                    self.colors.len(),
+                    bit_depth,
+                    max
+                );
+            }
+            2 | 6 => {}
+            _ => bail!("Palette is not allowed for color type {}", color_type),
+        }
+        Ok(())
+    }
+
+    /// GIMP palette (.gpl)
+    #[cfg(feature = "write")]
+    pub fn to_gimp(&self, name: &str) -> String {
+        let mut text = format!("GIMP Palette\nName: {}\nColumns: 16\n#\n", name);
+        for (i, [r, g, b]) in self.colors.iter().enumerate() {
+            writeln!(text, "{:3} {:3} {:3}\tIndex {}", r, g, b, i).unwrap();
+        }
+        text
+    }
+
+    /// JASC palette (.pal) used by Paint Shop Pro and many other tools
+    #[cfg(feature = "write")]
+    pub fn to_jasc(&self) -> String {
+        let mut text = format!("JASC-PAL\n0100\n{}\n", self.colors.len());
+        for [r, g, b] in &self.colors {
+            writeln!(text, "{} {} {}", r, g, b).unwrap();
+        }
+        text.replace('\n', "\r\n")
+    }
+}
+
+impl Histogram {
+    /// Histogram must have exactly one entry per palette color
+    pub fn from_data(data: &[u8], palette: &Palette) -> Result<Histogram> {
+        ensure!(
+            data.len() == palette.colors.len() * 2,
+            "hIST chunk must hold {} entries to match palette, got {} bytes",
+            palette.colors.len(),
+            data.len()
+        );
+        let frequencies = data
+            .chunks(2)
+            .map(|f| u16::from_be_bytes([f[0], f[1]]))
+            .collect();
+        Ok(Histogram { frequencies })
+    }
+
+    /// Index of the most frequently used palette entry
+    pub fn most_used(&self) -> Option<usize> {
+        (0..self.frequencies.len()).max_by_key(|&i| (self.frequencies[i], Reverse(i)))
+    }
+}
+
+impl Display for Histogram {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} entries", self.frequencies.len())?;
+        if let Some(i) = self.most_used() {
+            write!(f, ", entry {} is used most", i)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_data() {
+        let palette = Palette::from_data(&[255, 0, 0, 0, 0, 255]).unwrap();
+        assert_eq!(palette.colors, [[255, 0, 0], [0, 0, 255]]);
+        assert!(Palette::from_data(&[]).is_err());
+        assert!(Palette::from_data(&[0; 4]).is_err());
+        assert!(Palette::from_data(&[0; 257 * 3]).is_err());
+    }
+
+    #[test]
+    fn test_histogram() {
+        let palette = Palette::from_data(&[0; 3 * 3]).unwrap();
+        let histogram = Histogram::from_data(&[0, 1, 1, 0, 1, 0], &palette).unwrap();
+        assert_eq!(histogram.frequencies, [1, 256, 256]);
+        assert_eq!(histogram.most_used(), Some(1));
+        assert_eq!(histogram.to_string(), "3 entries, entry 1 is used most");
+        assert!(Histogram::from_data(&[0, 1], &palette).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let palette = Palette::from_data(&[0; 5 * 3]).unwrap();
+        assert!(palette.validate(4, 3).is_ok());
+        assert!(palette.validate(2, 3).is_err());
+        assert!(palette.validate(8, 6).is_ok());
+        assert!(palette.validate(8, 0).is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_export() {
+        let palette = Palette::from_data(&[255, 0, 0, 0, 128, 255]).unwrap();
+        assert_eq!(
+            palette.to_gimp("dice"),
+            "GIMP Palette\nName: dice\nColumns: 16\n#\n255   0   0\tIndex 0\n  0 128 255\tIndex 1\n"
+        );
+        assert_eq!(
+            palette.to_jasc(),
+            "JASC-PAL\r\n0100\r\n2\r\n255 0 0\r\n0 128 255\r\n"
+        );
+    }
+}
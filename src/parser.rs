@@ -0,0 +1,279 @@
+#[cfg(feature = "write")]
+use std::fmt::{Display, Formatter};
+use std::io::Read;
+#[cfg(feature = "write")]
+use std::io::{ErrorKind, Write};
+
+#[cfg(feature = "write")]
+use anyhow::bail;
+use anyhow::{ensure, Context, Result};
+#[cfg(feature = "write")]
+use crc::Crc;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+
+/// Amount of chunk data [`stream_payload`] holds in memory at once
+#[cfg(feature = "write")]
+const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+/// [`stream_payload`] stopped because payload is larger than given limit
+#[cfg(feature = "write")]
+#[derive(Debug)]
+pub struct LimitExceeded(pub u64);
+
+#[cfg(feature = "write")]
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Payload exceeds limit of {} bytes", self.0)
+    }
+}
+
+#[cfg(feature = "write")]
+impl std::error::Error for LimitExceeded {}
+
+/// Parses PNG fed in pieces of any size, e.g. as they arrive from network.
+/// Only bytes of the chunk that isn't complete yet are kept between pieces
+#[derive(Default)]
+pub struct ChunkParser {
+    buffer: Vec<u8>,
+    signature_checked: bool,
+    /// Total bytes of signature and chunks returned so far
+    consumed: usize,
+}
+
+impl ChunkParser {
+    pub fn new() -> ChunkParser {
+        ChunkParser::default()
+    }
+
+    /// Takes next piece of file and returns chunks completed by it
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<Chunk>> {
+        self.buffer.extend_from_slice(data);
+        let mut start = 0;
+        if !self.signature_checked {
+            if self.buffer.len() < 8 {
+                return Ok(vec![]);
+            }
+            ensure!(
+                self.buffer.starts_with(&Png::STANDARD_HEADER),
+                "Header is invalid"
+            );
+            self.signature_checked = true;
+            start = 8;
+        }
+
+        let mut chunks = vec![];
+        while let Some((chunk, size)) = Chunk::parse(&self.buffer[start..])
+            .with_context(|| format!("Invalid chunk at byte {}", self.consumed + start))?
+        {
+            chunks.push(chunk.with_offset(self.consumed + start));
+            start += size;
+        }
+        self.buffer.drain(..start);
+        self.consumed += start;
+        Ok(chunks)
+    }
+
+    /// Number of bytes given to parser that are not part of returned chunks yet
+    pub fn pending(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Number of bytes that made up signature and returned chunks
+    pub fn consumed(&self) -> usize {
+        self.consumed
+    }
+}
+
+/// Reads PNG from `reader` until first chunk of each given type is found,
+/// without reading whole file. Found chunks are returned in file order, missing types are skipped
+pub fn find_chunks(mut reader: impl Read, chunk_types: &[ChunkType]) -> Result<Vec<Chunk>> {
+    let mut parser = ChunkParser::new();
+    let mut buf = [0; 8192];
+    let mut found: Vec<Chunk> = vec![];
+    loop {
+        let n = reader.read(&mut buf).context("Failed to read PNG")?;
+        if n == 0 {
+            ensure!(
+                parser.pending() == 0,
+                "PNG is truncated after {} bytes",
+                parser.consumed()
+            );
+            return Ok(found);
+        }
+        for chunk in parser.push(&buf[..n])? {
+            let wanted = chunk_types.contains(chunk.chunk_type());
+            if wanted && found.iter().all(|c| c.chunk_type() != chunk.chunk_type()) {
+                found.push(chunk);
+            }
+        }
+        if found.len() == chunk_types.len() {
+            return Ok(found);
+        }
+    }
+}
+
+/// Copies data of every chunk of `chunk_type` from PNG in `reader` to `writer`,
+/// in file order, so payload split over several chunks is joined back. Only small
+/// buffer is held in memory, CRC of every chunk is checked as it passes through.
+/// Fails once more than `limit` bytes would be written. Reading stops at IEND.
+/// Returns number of chunks and bytes copied
+#[cfg(feature = "write")]
+pub fn stream_payload(
+    mut reader: impl Read,
+    chunk_type: &ChunkType,
+    mut writer: impl Write,
+    limit: Option<u64>,
+) -> Result<(usize, u64)> {
+    let mut signature = [0; 8];
+    reader
+        .read_exact(&mut signature)
+        .context("Failed to read PNG")?;
+    ensure!(signature == Png::STANDARD_HEADER, "Header is invalid");
+
+    let crc = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+    let mut buf = vec![0; STREAM_BUFFER_SIZE];
+    let mut offset = signature.len() as u64;
+    let (mut chunks, mut written) = (0, 0);
+    loop {
+        let truncated = || format!("PNG is truncated after {} bytes", offset);
+        let mut header = [0; 8];
+        match reader.read_exact(&mut header) {
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => bail!("{}", truncated()),
+            result => result.context("Failed to read PNG")?,
+        }
+        let length = u32::from_be_bytes(header[..4].try_into().unwrap());
+        ensure!(
+            length <= i32::MAX as u32,
+            "Chunk length {} is too large",
+            length
+        );
+        let wanted = header[4..] == chunk_type.bytes();
+        if let (true, Some(limit)) = (wanted, limit) {
+            if written + length as u64 > limit {
+                return Err(LimitExceeded(limit).into());
+            }
+        }
+
+        let mut digest = crc.digest();
+        digest.update(&header[4..]);
+        let mut rest = length as usize;
+        while rest > 0 {
+            let piece = &mut buf[..rest.min(STREAM_BUFFER_SIZE)];
+            reader.read_exact(piece).with_context(truncated)?;
+            digest.update(piece);
+            if wanted {
+                writer.write_all(piece).context("Failed to write payload")?;
+            }
+            rest -= piece.len();
+        }
+        let mut stored = [0; 4];
+        reader.read_exact(&mut stored).with_context(truncated)?;
+        ensure!(
+            u32::from_be_bytes(stored) == digest.finalize(),
+            "CRC check failed for chunk at byte {}",
+            offset
+        );
+
+        if wanted {
+            chunks += 1;
+            written += length as u64;
+        }
+        offset += length as u64 + 12;
+        if &header[4..] == b"IEND" {
+            break;
+        }
+    }
+    writer.flush().context("Failed to write payload")?;
+    Ok((chunks, written))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn testing_png() -> Vec<u8> {
+        let chunks = [("IHDR", "header"), ("ruSt", "hello"), ("IEND", "")]
+            .map(|(t, d)| Chunk::new(ChunkType::from_str(t).unwrap(), d.into()));
+        Png::from_chunks(chunks.into()).as_bytes()
+    }
+
+    #[test]
+    fn test_push_in_pieces() {
+        let bytes = testing_png();
+        for piece in [1, 3, 7, 13, bytes.len()] {
+            let mut parser = ChunkParser::new();
+            let mut types = vec![];
+            let mut offsets = vec![];
+            for data in bytes.chunks(piece) {
+                for chunk in parser.push(data).unwrap() {
+                    types.push(chunk.chunk_type().to_string());
+                    offsets.push(chunk.offset().unwrap());
+                }
+                assert!(parser.pending() < 18);
+            }
+            assert_eq!(types, ["IHDR", "ruSt", "IEND"]);
+            assert_eq!(offsets, [8, 26, 43]);
+            assert_eq!(parser.pending(), 0);
+            assert_eq!(parser.consumed(), bytes.len());
+        }
+    }
+
+    #[test]
+    fn test_push_invalid() {
+        assert!(ChunkParser::new().push(b"GIF89a\0\0").is_err());
+        let mut bytes = testing_png();
+        bytes[20] ^= 1;
+        assert!(ChunkParser::new().push(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_find_chunks() {
+        let bytes = testing_png();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let chunks = find_chunks(bytes.as_slice(), &[chunk_type]).unwrap();
+        assert_eq!(chunks[0].data(), b"hello");
+
+        let other = ChunkType::from_str("abCd").unwrap();
+        assert!(find_chunks(bytes.as_slice(), &[other]).unwrap().is_empty());
+        let other = [ChunkType::from_str("abCd").unwrap()];
+        assert!(find_chunks(&bytes[..bytes.len() - 2], &other).is_err());
+
+        let types = ["IEND", "abCd", "ruSt"].map(|t| ChunkType::from_str(t).unwrap());
+        let chunks = find_chunks(bytes.as_slice(), &types).unwrap();
+        let found: Vec<_> = chunks.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(found, ["ruSt", "IEND"]);
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_stream_payload() {
+        let chunks = [
+            ("IHDR", "header"),
+            ("ruSt", "hel"),
+            ("abCd", "x"),
+            ("ruSt", "lo"),
+        ]
+        .map(|(t, d)| Chunk::new(ChunkType::from_str(t).unwrap(), d.into()));
+        let mut chunks = chunks.to_vec();
+        chunks.push(Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]));
+        let mut bytes = Png::from_chunks(chunks).as_bytes();
+        bytes.extend_from_slice(b"trailing");
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+
+        let mut out = vec![];
+        let copied = stream_payload(bytes.as_slice(), &chunk_type, &mut out, None).unwrap();
+        assert_eq!(copied, (2, 5));
+        assert_eq!(out, b"hello");
+
+        let err = stream_payload(bytes.as_slice(), &chunk_type, vec![], Some(4)).unwrap_err();
+        assert!(err.downcast_ref::<LimitExceeded>().is_some());
+        assert!(stream_payload(&bytes[..40], &chunk_type, vec![], None).is_err());
+        bytes[30] ^= 1;
+        assert!(stream_payload(bytes.as_slice(), &chunk_type, vec![], None).is_err());
+    }
+}
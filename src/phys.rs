@@ -0,0 +1,91 @@
+use anyhow::{ensure, Result};
+
+const METERS_PER_INCH: f64 = 0.0254;
+
+/// Physical pixel dimensions stored in `pHYs` chunk
+#[derive(Debug, Eq, PartialEq)]
+pub struct PhysicalDimensions {
+    pub x: u32,
+    pub y: u32,
+    /// Values are pixels per meter if set, otherwise only their ratio is known
+    pub in_meters: bool,
+}
+
+impl PhysicalDimensions {
+    /// Same resolution in both directions, rounded to nearest pixels per meter
+    #[cfg(feature = "write")]
+    pub fn from_dpi(dpi: f64) -> Result<PhysicalDimensions> {
+        ensure!(dpi > 0.0, "DPI must be positive, got {}", dpi);
+        let ppm = (dpi / METERS_PER_INCH).round();
+        ensure!(ppm <= u32::MAX as f64, "DPI {} is too large", dpi);
+        Ok(PhysicalDimensions {
+            x: ppm as u32,
+            y: ppm as u32,
+            in_meters: true,
+        })
+    }
+
+    pub fn from_data(data: &[u8]) -> Result<PhysicalDimensions> {
+        ensure!(
+            data.len() == 9,
+            "pHYs chunk must be 9 bytes long, got {}",
+            data.len()
+        );
+        ensure!(data[8] <= 1, "Unknown pHYs unit {}", data[8]);
+        Ok(PhysicalDimensions {
+            x: u32::from_be_bytes(data[0..4].try_into().unwrap()),
+            y: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            in_meters: data[8] == 1,
+        })
+    }
+
+    #[cfg(feature = "write")]
+    pub fn to_data(&self) -> Vec<u8> {
+        let mut data = self.x.to_be_bytes().to_vec();
+        data.extend(self.y.to_be_bytes());
+        data.push(self.in_meters as u8);
+        data
+    }
+
+    /// Horizontal and vertical DPI, if unit is known
+    pub fn dpi(&self) -> Option<(f64, f64)> {
+        self.in_meters.then_some((
+            self.x as f64 * METERS_PER_INCH,
+            self.y as f64 * METERS_PER_INCH,
+        ))
+    }
+}
+
+#[cfg(all(test, feature = "write"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_dpi() {
+        let phys = PhysicalDimensions::from_dpi(300.0).unwrap();
+        assert_eq!(phys.x, 11811);
+        assert_eq!(phys.y, 11811);
+        let (x, _) = phys.dpi().unwrap();
+        assert_eq!(x.round(), 300.0);
+        assert_eq!(PhysicalDimensions::from_dpi(72.0).unwrap().x, 2835);
+        assert!(PhysicalDimensions::from_dpi(0.0).is_err());
+        assert!(PhysicalDimensions::from_dpi(1e12).is_err());
+    }
+
+    #[test]
+    fn test_data_roundtrip() {
+        let phys = PhysicalDimensions {
+            x: 3780,
+            y: 7560,
+            in_meters: true,
+        };
+        let data = phys.to_data();
+        assert_eq!(data, [0, 0, 0x0e, 0xc4, 0, 0, 0x1d, 0x88, 1]);
+        assert_eq!(PhysicalDimensions::from_data(&data).unwrap(), phys);
+
+        let ratio = PhysicalDimensions::from_data(&[0, 0, 0, 1, 0, 0, 0, 2, 0]).unwrap();
+        assert_eq!(ratio.dpi(), None);
+        assert!(PhysicalDimensions::from_data(&data[..8]).is_err());
+        assert!(PhysicalDimensions::from_data(&[0, 0, 0, 1, 0, 0, 0, 1, 2]).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::{Context, Result};
+
+#[cfg(all(feature = "write", not(feature = "zopfli")))]
+use crate::deflate;
+use crate::inflate;
+use crate::png::Png;
+
+/// Decompressed image data larger than this is not compared
+const MAX_IMAGE_DATA: usize = 512 * 1024 * 1024;
+
+/// Concatenated data of all IDAT chunks, which forms single zlib stream
+pub fn image_data(png: &Png) -> Vec<u8> {
+    png.chunks()
+        .iter()
+        .filter(|c| &c.chunk_type().bytes() == b"IDAT")
+        .flat_map(|c| c.data().iter().copied())
+        .collect()
+}
+
+/// Image data inflated and compressed again as tightly as possible,
+/// with zopfli when built with it
+#[cfg(feature = "write")]
+pub fn recompress(png: &Png) -> Result<Vec<u8>> {
+    let data = inflate::zlib_decompress(&image_data(png), MAX_IMAGE_DATA)
+        .context("Failed to decompress image data")?;
+    #[cfg(feature = "zopfli")]
+    {
+        let mut compressed = vec![];
+        let options = zopfli::Options::default();
+        zopfli::compress(
+            options,
+            zopfli::Format::Zlib,
+            data.as_slice(),
+            &mut compressed,
+        )
+        .context("Failed to compress image data")?;
+        Ok(compressed)
+    }
+    #[cfg(not(feature = "zopfli"))]
+    Ok(deflate::zlib_compress_best(&data))
+}
+
+/// Compares IHDR and image data of two images, ignoring all other chunks.
+/// With `decompress` image data is compared after inflating, so images
+/// compressed differently are still the same.
+/// Returns description of the first difference found
+pub fn compare(a: &Png, b: &Png, decompress: bool) -> Result<Option<String>> {
+    let header = |png: &Png| png.chunk_by_type("IHDR").map(|c| c.data().to_vec());
+    if header(a) != header(b) {
+        return Ok(Some("IHDR differs".to_string()));
+    }
+
+    let (mut a, mut b) = (image_data(a), image_data(b));
+    if decompress {
+        a = inflate::zlib_decompress(&a, MAX_IMAGE_DATA)
+            .context("Failed to decompress first image")?;
+        b = inflate::zlib_decompress(&b, MAX_IMAGE_DATA)
+            .context("Failed to decompress second image")?;
+    }
+    let what = if decompress {
+        "Decompressed image data"
+    } else {
+        "Image data"
+    };
+    if let Some(offset) = a.iter().zip(&b).position(|(x, y)| x != y) {
+        return Ok(Some(format!("{} differs at byte {}", what, offset)));
+    }
+    if a.len() != b.len() {
+        return Ok(Some(format!(
+            "{} differs in length: {} and {} bytes",
+            what,
+            a.len(),
+            b.len()
+        )));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::deflate;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn png(chunks: &[(&str, &[u8])]) -> Png {
+        Png::from_chunks(chunks.iter().map(|(t, d)| chunk(t, d)).collect())
+    }
+
+    #[test]
+    fn test_compare_ignores_metadata() {
+        let a = png(&[
+            ("IHDR", b"header"),
+            ("IDAT", b"pix"),
+            ("IDAT", b"els"),
+            ("IEND", b""),
+        ]);
+        let b = png(&[
+            ("IHDR", b"header"),
+            ("tEXt", b"Title\0Dice"),
+            ("IDAT", b"pixels"),
+            ("ruSt", b"hello"),
+            ("IEND", b""),
+        ]);
+        assert_eq!(compare(&a, &b, false).unwrap(), None);
+
+        let c = png(&[("IHDR", b"header"), ("IDAT", b"pixelz"), ("IEND", b"")]);
+        assert_eq!(
+            compare(&a, &c, false).unwrap().unwrap(),
+            "Image data differs at byte 5"
+        );
+        let d = png(&[("IHDR", b"HEADER"), ("IDAT", b"pixels"), ("IEND", b"")]);
+        assert_eq!(compare(&a, &d, false).unwrap().unwrap(), "IHDR differs");
+        let e = png(&[("IHDR", b"header"), ("IDAT", b"pix"), ("IEND", b"")]);
+        assert!(compare(&a, &e, false).unwrap().unwrap().contains("length"));
+    }
+
+    #[test]
+    fn test_compare_decompressed() {
+        let pixels = [0u8, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3];
+        let compressed = deflate::zlib_compress(&pixels);
+        let stored = deflate::zlib_store(&pixels);
+
+        let a = png(&[("IHDR", b"header"), ("IDAT", &compressed), ("IEND", b"")]);
+        let b = png(&[("IHDR", b"header"), ("IDAT", &stored), ("IEND", b"")]);
+        assert!(compare(&a, &b, false).unwrap().is_some());
+        assert_eq!(compare(&a, &b, true).unwrap(), None);
+        assert!(compare(&a, &png(&[("IHDR", b"header")]), true).is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_recompress() {
+        let mut pixels = vec![];
+        for y in 0..64u8 {
+            pixels.push(0);
+            pixels.extend((0..192u8).map(|x| x.wrapping_mul(y) / 4));
+        }
+        let stored = deflate::zlib_store(&pixels);
+        let png = png(&[("IHDR", b"header"), ("IDAT", &stored), ("IEND", b"")]);
+
+        let recompressed = recompress(&png).unwrap();
+        assert!(recompressed.len() < stored.len() / 2);
+        assert!(recompressed.len() <= deflate::zlib_compress(&pixels).len());
+        assert_eq!(
+            inflate::zlib_decompress(&recompressed, pixels.len()).unwrap(),
+            pixels
+        );
+    }
+}
@@ -1,14 +1,58 @@
 use std::fmt::{Display, Formatter};
 
-use anyhow::{ensure, Error, Result};
+use anyhow::{ensure, Context, Error, Result};
 
 use crate::chunk::Chunk;
 use crate::chunk_type::ChunkType;
+use crate::color::{Background, SignificantBits, Transparency};
+use crate::embed::Carrier;
+use crate::header::ImageHeader;
+use crate::palette::{Histogram, Palette};
 
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct Png {
     chunks: Vec<Chunk>,
+    /// Bytes after first IEND, written back as they were even if they look like chunks
+    trailing: Vec<u8>,
 }
 
+/// Critical chunk types defined by PNG specification
+const KNOWN_CRITICAL: [[u8; 4]; 4] = [*b"IHDR", *b"PLTE", *b"IDAT", *b"IEND"];
+/// Critical chunk types that may appear only once
+pub const SINGLE_CRITICAL: [[u8; 4]; 3] = [*b"IHDR", *b"PLTE", *b"IEND"];
+
+/// Structural problem rejected by strict parsing
+#[derive(Debug, Eq, PartialEq)]
+pub enum StructureError {
+    MissingHeader,
+    /// Number of IHDR chunks
+    MultipleHeaders(usize),
+    /// Number of PLTE chunks
+    MultiplePalettes(usize),
+    MissingEnd,
+    /// Number of IEND chunks
+    MultipleEnds(usize),
+    /// Number of chunks following first IEND
+    ChunksAfterEnd(usize),
+    UnknownCritical(String),
+}
+
+impl Display for StructureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StructureError::MissingHeader => write!(f, "Image has no IHDR chunk"),
+            StructureError::MultipleHeaders(n) => write!(f, "Image has {} IHDR chunks", n),
+            StructureError::MultiplePalettes(n) => write!(f, "Image has {} PLTE chunks", n),
+            StructureError::MissingEnd => write!(f, "Image has no IEND chunk"),
+            StructureError::MultipleEnds(n) => write!(f, "Image has {} IEND chunks", n),
+            StructureError::ChunksAfterEnd(n) => write!(f, "{} chunks follow IEND", n),
+            StructureError::UnknownCritical(t) => write!(f, "Unknown critical chunk {}", t),
+        }
+    }
+}
+
+impl std::error::Error for StructureError {}
+
 impl TryFrom<&[u8]> for Png {
     type Error = Error;
 
@@ -20,26 +64,42 @@ impl TryFrom<&[u8]> for Png {
 
         ensure!(sig == Png::STANDARD_HEADER, "Header is invalid");
 
-        let mut chunks = vec![];
-        loop {
-            if data.is_empty() {
-                break;
-            }
-
-            let chunk: Chunk = data.try_into()?;
+        let mut chunks: Vec<Chunk> = vec![];
+        // anything appended to complete image is kept as it is, even if it looks like chunks
+        while !data.is_empty() {
+            let offset = value.len() - data.len();
+            let chunk = Chunk::try_from(data)?.with_offset(offset);
+            let is_end = &chunk.chunk_type().bytes() == b"IEND";
             let (_, rest) = data.split_at(chunk.chunk_size());
             data = rest;
             chunks.push(chunk);
+            if is_end {
+                break;
+            }
         }
 
-        Ok(Png { chunks })
+        Ok(Png {
+            chunks,
+            trailing: data.to_vec(),
+        })
     }
 }
 
+/// Size and chunk count, then one line per chunk with its index, type, length
+/// and whether CRC is correct
 impl Display for Png {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for chunk in &self.chunks {
-            writeln!(f, "{}", chunk)?;
+        writeln!(
+            f,
+            "PNG, {} chunks, {} bytes",
+            self.chunks.len(),
+            self.byte_size()
+        )?;
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            writeln!(f, "  #{} {}", i, chunk)?;
+        }
+        if !self.trailing.is_empty() {
+            writeln!(f, "  {} bytes after IEND", self.trailing.len())?;
         }
         Ok(())
     }
@@ -49,13 +109,122 @@ impl Png {
     pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
     pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
-        Png { chunks }
+        Png {
+            chunks,
+            trailing: vec![],
+        }
+    }
+
+    /// Chunks and trailing data, for embedding backends to modify
+    pub fn to_carrier(&self) -> Carrier {
+        Carrier {
+            chunks: self.chunks.clone(),
+            trailing: self.trailing.clone(),
+        }
+    }
+
+    #[cfg(feature = "write")]
+    pub fn from_carrier(carrier: Carrier) -> Png {
+        Png {
+            chunks: carrier.chunks,
+            trailing: carrier.trailing,
+        }
+    }
+
+    /// Data after first IEND
+    pub fn trailing(&self) -> &[u8] {
+        &self.trailing
+    }
+
+    #[cfg(feature = "write")]
+    pub fn set_trailing(&mut self, data: Vec<u8>) {
+        self.trailing = data;
+    }
+
+    /// Parses like `try_from`, but also rejects structurally invalid images,
+    /// see [`Png::check_structure`], and chunks kept as trailing data after IEND
+    pub fn try_from_strict(bytes: &[u8]) -> Result<Png> {
+        let png = Png::try_from(bytes)?;
+        png.check_structure()?;
+        let mut rest = png.trailing();
+        let mut after_end = 0;
+        while let Ok(chunk) = Chunk::try_from(rest) {
+            rest = &rest[chunk.chunk_size()..];
+            after_end += 1;
+        }
+        if after_end > 0 {
+            return Err(StructureError::ChunksAfterEnd(after_end).into());
+        }
+        Ok(png)
     }
 
+    /// Checks that image has single IHDR, at most one PLTE, ends with single IEND
+    /// and has no critical chunks unknown to PNG specification
+    pub fn check_structure(&self) -> std::result::Result<(), StructureError> {
+        let types: Vec<_> = self.chunks.iter().map(|c| c.chunk_type().bytes()).collect();
+        if !types.contains(b"IHDR") {
+            return Err(StructureError::MissingHeader);
+        }
+        if let Some(duplicate) = self.duplicates().into_iter().next() {
+            return Err(duplicate);
+        }
+        let end = types
+            .iter()
+            .position(|t| t == b"IEND")
+            .ok_or(StructureError::MissingEnd)?;
+        if end + 1 < types.len() {
+            return Err(StructureError::ChunksAfterEnd(types.len() - end - 1));
+        }
+        if let Some(t) = types
+            .iter()
+            .find(|t| t[0].is_ascii_uppercase() && !KNOWN_CRITICAL.contains(t))
+        {
+            return Err(StructureError::UnknownCritical(
+                String::from_utf8_lossy(t).into_owned(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Critical chunks that appear more than once, which decoders handle
+    /// inconsistently. Lenient parsing keeps them, `doctor --fix` drops the extras
+    pub fn duplicates(&self) -> Vec<StructureError> {
+        SINGLE_CRITICAL
+            .iter()
+            .filter_map(|t| {
+                let count = self
+                    .chunks
+                    .iter()
+                    .filter(|c| &c.chunk_type().bytes() == t)
+                    .count();
+                match (t, count) {
+                    (_, 0 | 1) => None,
+                    (b"IHDR", n) => Some(StructureError::MultipleHeaders(n)),
+                    (b"PLTE", n) => Some(StructureError::MultiplePalettes(n)),
+                    (_, n) => Some(StructureError::MultipleEnds(n)),
+                }
+            })
+            .collect()
+    }
+
+    #[cfg_attr(not(feature = "write"), allow(dead_code))]
     pub fn append_chunk(&mut self, chunk: Chunk) {
         self.chunks.push(chunk);
     }
 
+    #[cfg(feature = "write")]
+    pub fn insert_chunk(&mut self, index: usize, chunk: Chunk) {
+        self.chunks.insert(index, chunk);
+    }
+
+    #[cfg(feature = "write")]
+    pub fn remove_chunk_at(&mut self, index: usize) -> Chunk {
+        self.chunks.remove(index)
+    }
+
+    /// Removes first chunk of given type. Commands remove by index instead, to record
+    /// where the chunk was
+    #[cfg_attr(not(test), allow(dead_code))]
     pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
         let chunk_type: ChunkType = chunk_type.parse()?;
         let pos = self
@@ -79,11 +248,125 @@ impl Png {
         self.chunks.iter().find(|&c| c.chunk_type() == &chunk_type)
     }
 
+    /// Background color from bKGD chunk, if there is one
+    pub fn background(&self) -> Result<Option<Background>> {
+        match self.chunk_by_type("bKGD") {
+            Some(chunk) => Ok(Some(Background::from_data(
+                chunk.data(),
+                self.color_type()?,
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Transparency from tRNS chunk, if there is one
+    pub fn transparency(&self) -> Result<Option<Transparency>> {
+        match self.chunk_by_type("tRNS") {
+            Some(chunk) => Ok(Some(Transparency::from_data(
+                chunk.data(),
+                self.color_type()?,
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Significant bits from sBIT chunk, if there is one
+    pub fn significant_bits(&self) -> Result<Option<SignificantBits>> {
+        match self.chunk_by_type("sBIT") {
+            Some(chunk) => {
+                let header = self.image_header()?;
+                Ok(Some(SignificantBits::from_data(
+                    chunk.data(),
+                    header.color_type,
+                    header.bit_depth,
+                )?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Palette usage from hIST chunk, if there is one
+    pub fn histogram(&self) -> Result<Option<Histogram>> {
+        let Some(chunk) = self.chunk_by_type("hIST") else {
+            return Ok(None);
+        };
+        let palette = self
+            .chunk_by_type("PLTE")
+            .context("hIST chunk is not allowed without PLTE")?;
+        let palette = Palette::from_data(palette.data())?;
+        Ok(Some(Histogram::from_data(chunk.data(), &palette)?))
+    }
+
+    /// Parsed IHDR chunk
+    pub fn image_header(&self) -> Result<ImageHeader> {
+        let chunk = self
+            .chunk_by_type("IHDR")
+            .context("Image has no IHDR chunk")?;
+        ImageHeader::from_data(chunk.data())
+    }
+
+    fn color_type(&self) -> Result<u8> {
+        Ok(self.image_header()?.color_type)
+    }
+
+    /// When image only differs from the file it was parsed from by chunks inserted
+    /// right before final IEND, offset of IEND in that file and serialized chunks
+    /// from there on. Writing them at the offset gives the whole image
+    #[cfg(feature = "write")]
+    pub fn appended_tail(&self) -> Option<(usize, Vec<u8>)> {
+        let (end, chunks) = self.chunks.split_last()?;
+        if &end.chunk_type().bytes() != b"IEND" || !self.trailing.is_empty() {
+            return None;
+        }
+        let mut offset = Png::STANDARD_HEADER.len();
+        let mut kept = 0;
+        for chunk in chunks {
+            if chunk.offset() != Some(offset) {
+                break;
+            }
+            offset += chunk.chunk_size();
+            kept += 1;
+        }
+        if end.offset() != Some(offset)
+            || kept == chunks.len()
+            || chunks[kept..].iter().any(|c| c.offset().is_some())
+        {
+            return None;
+        }
+        let tail = self.chunks[kept..]
+            .iter()
+            .flat_map(Chunk::as_bytes)
+            .collect();
+        Some((offset, tail))
+    }
+
+    /// Size of serialized image, same as `as_bytes().len()` without allocations
+    pub fn byte_size(&self) -> usize {
+        self.header().len()
+            + self.chunks.iter().map(Chunk::chunk_size).sum::<usize>()
+            + self.trailing.len()
+    }
+
+    /// Total data length of chunks of given type, excluding their length, type and CRC
+    pub fn payload_size(&self, chunk_type: &ChunkType) -> usize {
+        self.chunks
+            .iter()
+            .filter(|c| c.chunk_type() == chunk_type)
+            .map(|c| c.data().len())
+            .sum()
+    }
+
+    /// Bytes taken by signature and by length, type and CRC of every chunk
+    pub fn overhead(&self) -> usize {
+        self.header().len() + self.chunks.len() * 12
+    }
+
     pub fn as_bytes(&self) -> Vec<u8> {
-        Png::STANDARD_HEADER
+        self.header()
             .iter()
             .copied()
-            .chain(self.chunks.iter().flat_map(|c| c.as_bytes().into_iter()))
+            .chain(self.chunks.iter().flat_map(Chunk::bytes_iter))
+            .chain(self.trailing.iter().copied())
             .collect()
     }
 }
@@ -195,12 +478,24 @@ mod tests {
         assert_eq!(chunks.len(), 3);
     }
 
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_clone_and_eq() {
+        let png = testing_png();
+        let parsed = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        // parsed chunks know their offsets, they still compare equal
+        assert_eq!(parsed, png);
+        let mut changed = png.clone();
+        changed.append_chunk(chunk_from_strings("miDl", "more").unwrap());
+        assert_ne!(changed, png);
+    }
+
     #[test]
     fn test_chunk_by_type() {
         let png = testing_png();
         let chunk = png.chunk_by_type("FrSt").unwrap();
         assert_eq!(&chunk.chunk_type().to_string(), "FrSt");
-        assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
+        assert_eq!(chunk.data_as_string().unwrap(), "I am the first chunk");
     }
 
     #[test]
@@ -209,7 +504,16 @@ mod tests {
         png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
         let chunk = png.chunk_by_type("TeSt").unwrap();
         assert_eq!(&chunk.chunk_type().to_string(), "TeSt");
-        assert_eq!(&chunk.data_as_string().unwrap(), "Message");
+        assert_eq!(chunk.data_as_string().unwrap(), "Message");
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_insert_chunk() {
+        let mut png = testing_png();
+        png.insert_chunk(2, chunk_from_strings("TeSt", "Message").unwrap());
+        assert_eq!(&png.chunks()[2].chunk_type().to_string(), "TeSt");
+        assert_eq!(&png.chunks()[3].chunk_type().to_string(), "LASt");
     }
 
     #[test]
@@ -235,6 +539,38 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_background_and_transparency() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert!(png.background().unwrap().is_none());
+        let header = png.image_header().unwrap();
+        assert_eq!(header.color_type, 6);
+
+        png.insert_chunk(1, chunk_from_strings("bKGD", "\0\x01\0\x02\0\x03").unwrap());
+        assert_eq!(png.background().unwrap(), Some(Background::Rgb(1, 2, 3)));
+        png.insert_chunk(1, chunk_from_strings("tRNS", "\0\x01").unwrap());
+        assert!(png.transparency().is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_significant_bits_and_histogram() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert!(png.significant_bits().unwrap().is_none());
+        assert!(png.histogram().unwrap().is_none());
+
+        png.insert_chunk(1, chunk_from_strings("sBIT", "\x05\x06\x05\x08").unwrap());
+        assert_eq!(
+            png.significant_bits().unwrap(),
+            Some(SignificantBits::Rgba(5, 6, 5, 8))
+        );
+        png.insert_chunk(1, chunk_from_strings("hIST", "\0\x01").unwrap());
+        assert!(png.histogram().is_err());
+        png.insert_chunk(1, chunk_from_strings("PLTE", "\0\0\0").unwrap());
+        assert_eq!(png.histogram().unwrap().unwrap().frequencies, [1]);
+    }
+
     #[test]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -250,7 +586,167 @@ mod tests {
 
         let png: Png = TryFrom::try_from(bytes.as_ref()).unwrap();
 
-        let _png_string = format!("{}", png);
+        assert_eq!(
+            format!("{}", png),
+            "PNG, 3 chunks, 101 bytes\n\
+             \x20 #0 FrSt (20 bytes, crc ok)\n\
+             \x20 #1 miDl (18 bytes, crc ok)\n\
+             \x20 #2 LASt (19 bytes, crc ok)\n"
+        );
+    }
+
+    #[test]
+    fn test_sizes() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.byte_size(), PNG_FILE.len());
+        assert_eq!(png.overhead(), 8 + 7 * 12);
+        let idat = ChunkType::from_str("IDAT").unwrap();
+        assert_eq!(png.payload_size(&idat), 4681);
+        let data: usize = png.chunks().iter().map(|c| c.data().len()).sum();
+        assert_eq!(png.overhead() + data, png.byte_size());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_trailing_data() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend_from_slice(b"appended");
+        let mut png = Png::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(png.trailing(), b"appended");
+        assert_eq!(png.as_bytes(), bytes);
+        assert_eq!(png.byte_size(), bytes.len());
+
+        png.set_trailing(vec![]);
+        assert_eq!(png.as_bytes(), PNG_FILE);
+        // junk is only tolerated after IEND
+        let truncated = &PNG_FILE[..PNG_FILE.len() - 12];
+        let mut bytes = truncated.to_vec();
+        bytes.extend_from_slice(b"appended");
+        assert!(Png::try_from(bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_chunks_after_end_are_trailing() {
+        let appended = chunk_from_strings("TeSt", "Message").unwrap().as_bytes();
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend_from_slice(&appended);
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(
+            png.chunks().last().unwrap().chunk_type().to_string(),
+            "IEND"
+        );
+        assert!(png.chunk_by_type("TeSt").is_none());
+        assert_eq!(png.trailing(), appended);
+        assert_eq!(png.as_bytes(), bytes);
+
+        // strict parsing still rejects them, but not other appended data
+        let valid: Vec<u8> = ["IHDR", "IDAT", "IEND"]
+            .iter()
+            .flat_map(|t| chunk_from_strings(t, "").unwrap().as_bytes())
+            .collect();
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        bytes.extend_from_slice(&valid);
+        bytes.extend_from_slice(b"appended");
+        assert!(Png::try_from_strict(&bytes).is_ok());
+        bytes.truncate(bytes.len() - 8);
+        bytes.extend_from_slice(&appended);
+        let err = Png::try_from_strict(&bytes).err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<StructureError>(),
+            Some(&StructureError::ChunksAfterEnd(1))
+        );
+    }
+
+    #[test]
+    fn test_chunk_offsets() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let offsets: Vec<_> = png.chunks().iter().map(|c| c.offset().unwrap()).collect();
+        assert_eq!(offsets[..3], [8, 33, 46]);
+        let last = png.chunks().last().unwrap();
+        assert_eq!(last.offset().unwrap() + last.chunk_size(), PNG_FILE.len());
+        assert_eq!(testing_png().chunks()[0].offset(), None);
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_appended_tail() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.appended_tail(), None);
+
+        let end = png.chunks().len() - 1;
+        let iend = png.chunks()[end].offset().unwrap();
+        png.insert_chunk(end, chunk_from_strings("ruSt", "hidden").unwrap());
+        png.insert_chunk(end + 1, chunk_from_strings("ruSt", "more").unwrap());
+        let (offset, tail) = png.appended_tail().unwrap();
+        assert_eq!(offset, iend);
+        let mut bytes = PNG_FILE[..offset].to_vec();
+        bytes.extend_from_slice(&tail);
+        assert_eq!(bytes, png.as_bytes());
+
+        // removed chunk shifts the rest
+        let mut removed = png.clone();
+        removed.remove_chunk_at(1);
+        assert_eq!(removed.appended_tail(), None);
+        // inserted before other original chunks
+        let mut moved = png.clone();
+        let chunk = moved.remove_chunk_at(end);
+        moved.insert_chunk(1, chunk);
+        assert_eq!(moved.appended_tail(), None);
+        assert_eq!(testing_png().appended_tail(), None);
+    }
+
+    #[test]
+    fn test_strict_structure() {
+        let error = |chunks: &[(&str, &str)]| {
+            let chunks = chunks
+                .iter()
+                .map(|(t, d)| chunk_from_strings(t, d).unwrap())
+                .collect();
+            Png::from_chunks(chunks).check_structure().unwrap_err()
+        };
+        assert_eq!(error(&[("IEND", "")]), StructureError::MissingHeader);
+        assert_eq!(
+            error(&[("IHDR", ""), ("IHDR", ""), ("IEND", "")]),
+            StructureError::MultipleHeaders(2)
+        );
+        assert_eq!(
+            error(&[("IHDR", ""), ("PLTE", ""), ("PLTE", ""), ("IEND", "")]),
+            StructureError::MultiplePalettes(2)
+        );
+        assert_eq!(
+            error(&[("IHDR", ""), ("IEND", ""), ("IEND", "")]),
+            StructureError::MultipleEnds(2)
+        );
+        assert_eq!(
+            error(&[("IHDR", ""), ("IDAT", "")]),
+            StructureError::MissingEnd
+        );
+        assert_eq!(
+            error(&[("IHDR", ""), ("IEND", ""), ("ruSt", "")]),
+            StructureError::ChunksAfterEnd(1)
+        );
+        assert_eq!(
+            error(&[("IHDR", ""), ("RUST", ""), ("IEND", "")]),
+            StructureError::UnknownCritical("RUST".to_string())
+        );
+        let valid = ["IHDR", "PLTE", "IDAT", "IEND"]
+            .map(|t| chunk_from_strings(t, "").unwrap())
+            .into();
+        assert!(Png::from_chunks(valid).check_structure().is_ok());
+        // sample image has critical RuSt chunk
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(
+            png.check_structure(),
+            Err(StructureError::UnknownCritical("RuSt".to_string()))
+        );
+        // lenient parsing still accepts them
+        let png = Png::from_chunks(testing_chunks()).as_bytes();
+        assert!(Png::try_from(png.as_slice()).is_ok());
+        let err = Png::try_from_strict(&png).err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<StructureError>(),
+            Some(&StructureError::MissingHeader)
+        );
     }
 
     // This is the raw bytes for a shrunken version of the `dice.png` image on Wikipedia
@@ -0,0 +1,143 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::diff::format_size;
+
+/// Set by `--quiet`
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Bar isn't drawn for operations that finish faster than this
+const DELAY: Duration = Duration::from_millis(300);
+/// Minimal time between redraws
+const INTERVAL: Duration = Duration::from_millis(100);
+const WIDTH: usize = 24;
+
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+#[derive(Clone, Copy)]
+pub enum Unit {
+    /// Only scanners and YARA rules count bytes
+    #[cfg_attr(not(any(feature = "write", feature = "yara")), allow(dead_code))]
+    Bytes,
+    Files,
+}
+
+/// Progress bar on stderr, shown only in interactive terminal
+/// and only if operation takes noticeable time
+pub struct Progress {
+    label: &'static str,
+    unit: Unit,
+    total: u64,
+    done: u64,
+    start: Instant,
+    drawn: Option<Instant>,
+    visible: bool,
+}
+
+impl Progress {
+    pub fn new(label: &'static str, unit: Unit, total: u64) -> Progress {
+        let visible = !is_quiet() && io::stdout().is_terminal() && io::stderr().is_terminal();
+        Progress {
+            label,
+            unit,
+            total,
+            done: 0,
+            start: Instant::now(),
+            drawn: None,
+            visible,
+        }
+    }
+
+    pub fn inc(&mut self, amount: u64) {
+        self.done = (self.done + amount).min(self.total);
+        if !self.visible {
+            return;
+        }
+        let now = Instant::now();
+        let due = match self.drawn {
+            Some(drawn) => now - drawn >= INTERVAL,
+            None => now - self.start >= DELAY,
+        };
+        if due {
+            eprint!("\r{}", self.render(now - self.start));
+            let _ = io::stderr().flush();
+            self.drawn = Some(now);
+        }
+    }
+
+    fn render(&self, elapsed: Duration) -> String {
+        let ratio = match self.total {
+            0 => 1.0,
+            total => self.done as f64 / total as f64,
+        };
+        let filled = (ratio * WIDTH as f64) as usize;
+        let amount = match self.unit {
+            Unit::Bytes => format!(
+                "{}/{}",
+                format_size(self.done as usize),
+                format_size(self.total as usize)
+            ),
+            Unit::Files => format!("{}/{} files", self.done, self.total),
+        };
+        let eta = match ratio {
+            r if r > 0.0 && r < 1.0 => {
+                let remaining = elapsed.as_secs_f64() * (1.0 - r) / r;
+                format!(
+                    ", ETA {}:{:02}",
+                    remaining as u64 / 60,
+                    remaining as u64 % 60
+                )
+            }
+            _ => String::new(),
+        };
+        format!(
+            "{} [{}{}] {}{}",
+            self.label,
+            "#".repeat(filled),
+            "-".repeat(WIDTH - filled),
+            amount,
+            eta
+        )
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if self.drawn.is_some() {
+            // erase bar, so it doesn't mix with output that follows
+            eprint!("\r\x1b[2K");
+            let _ = io::stderr().flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut progress = Progress::new("Scanning", Unit::Files, 4);
+        progress.visible = false;
+        progress.inc(1);
+        assert_eq!(
+            progress.render(Duration::from_secs(10)),
+            "Scanning [######------------------] 1/4 files, ETA 0:30"
+        );
+
+        let mut progress = Progress::new("Reading", Unit::Bytes, 2048);
+        progress.visible = false;
+        progress.inc(4096);
+        assert_eq!(
+            progress.render(Duration::from_secs(1)),
+            "Reading [########################] 2.0 KB/2.0 KB"
+        );
+    }
+}
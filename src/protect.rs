@@ -0,0 +1,127 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, ensure, Result};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use sha2::Sha256;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+
+/// Ancillary, private and safe to copy, so viewers skip protected chunks and editors keep them
+pub const CHUNK_TYPE: &str = "prOt";
+/// PBKDF2 rounds for new chunks, older ones keep theirs
+pub const ITERATIONS: u32 = 100_000;
+/// Refuses chunks that would take too long to derive key for
+const MAX_ITERATIONS: u32 = 10_000_000;
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+/// Salt, iterations and nonce, authenticated along with encrypted chunk
+const HEADER_SIZE: usize = SALT_SIZE + 4 + NONCE_SIZE;
+
+/// Encrypts type and data of `chunk` into `prOt` chunk: salt, PBKDF2 iterations,
+/// nonce and ChaCha20-Poly1305 ciphertext of type and data. Key is derived from
+/// password with PBKDF2-HMAC-SHA256
+pub fn protect(chunk: &Chunk, password: &str, iterations: u32) -> Result<Chunk> {
+    ensure!(
+        !chunk.chunk_type().is_critical(),
+        "{} is critical chunk, only ancillary ones can be protected",
+        chunk.chunk_type()
+    );
+    ensure!(
+        chunk.chunk_type().to_string() != CHUNK_TYPE,
+        "Chunk is already protected"
+    );
+    let mut random = [0; SALT_SIZE + NONCE_SIZE];
+    getrandom::getrandom(&mut random).map_err(|e| anyhow!("Failed to get random bytes: {}", e))?;
+    let (salt, nonce) = random.split_at(SALT_SIZE);
+
+    let mut header = salt.to_vec();
+    header.extend_from_slice(&iterations.to_be_bytes());
+    header.extend_from_slice(nonce);
+    let mut body = chunk.chunk_type().bytes().to_vec();
+    body.extend_from_slice(chunk.data());
+    let payload = Payload {
+        msg: &body,
+        aad: &header,
+    };
+    let encrypted = cipher(password, salt, iterations)
+        .encrypt(Nonce::from_slice(nonce), payload)
+        .map_err(|_| anyhow!("Failed to encrypt chunk"))?;
+
+    let mut data = header;
+    data.extend(encrypted);
+    Ok(Chunk::new(ChunkType::from_str(CHUNK_TYPE)?, data))
+}
+
+/// Restores chunk encrypted by `protect`, failing on wrong password or tampered data
+pub fn unprotect(chunk: &Chunk, password: &str) -> Result<Chunk> {
+    let data = chunk.data();
+    ensure!(
+        data.len() >= HEADER_SIZE + 4 + TAG_SIZE,
+        "Protected chunk is too short"
+    );
+    let (header, encrypted) = data.split_at(HEADER_SIZE);
+    let (salt, rest) = header.split_at(SALT_SIZE);
+    let (iterations, nonce) = rest.split_at(4);
+    let iterations = u32::from_be_bytes(iterations.try_into().unwrap());
+    ensure!(
+        iterations <= MAX_ITERATIONS,
+        "Protected chunk asks for too many key derivation iterations: {}",
+        iterations
+    );
+    let payload = Payload {
+        msg: encrypted,
+        aad: header,
+    };
+    let body = cipher(password, salt, iterations)
+        .decrypt(Nonce::from_slice(nonce), payload)
+        .map_err(|_| anyhow!("Wrong password or protected chunk was modified"))?;
+
+    let chunk_type = ChunkType::try_from(<[u8; 4]>::try_from(&body[..4]).unwrap())?;
+    Ok(Chunk::new(chunk_type, body[4..].to_vec()))
+}
+
+fn cipher(password: &str, salt: &[u8], iterations: u32) -> ChaCha20Poly1305 {
+    let key = pbkdf2::pbkdf2_hmac_array::<Sha256, 32>(password.as_bytes(), salt, iterations);
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let text = chunk("tEXt", b"Author\0Jane Doe, somewhere in the hills");
+        let protected = protect(&text, "s3cret", 10).unwrap();
+        assert_eq!(protected.chunk_type().to_string(), CHUNK_TYPE);
+        let data = protected.data();
+        assert!(!data.windows(4).any(|w| w == b"tEXt" || w == b"Jane"));
+
+        let restored = unprotect(&protected, "s3cret").unwrap();
+        assert_eq!(restored.as_bytes(), text.as_bytes());
+        assert!(unprotect(&protected, "wrong").is_err());
+
+        // both ciphertext and header are authenticated
+        for index in [data.len() - 1, HEADER_SIZE, SALT_SIZE + 3] {
+            let mut tampered = data.to_vec();
+            tampered[index] ^= 1;
+            let tampered = chunk(CHUNK_TYPE, &tampered);
+            assert!(unprotect(&tampered, "s3cret").is_err());
+        }
+    }
+
+    #[test]
+    fn test_protect_rejected() {
+        assert!(protect(&chunk("PLTE", &[0; 3]), "s3cret", 10).is_err());
+        let protected = protect(&chunk("eXIf", b"exif"), "s3cret", 10).unwrap();
+        assert!(protect(&protected, "s3cret", 10).is_err());
+        assert!(unprotect(&chunk(CHUNK_TYPE, b"short"), "s3cret").is_err());
+    }
+}
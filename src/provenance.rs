@@ -0,0 +1,117 @@
+use std::env;
+use std::fs;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::config::{ProvenanceNames, Recorded};
+use crate::date;
+
+/// Private ancillary chunk written next to payload by `encode --provenance`
+pub const PROVENANCE_CHUNK: &str = "prVn";
+
+/// Who embedded which chunk, when and with what tool
+#[derive(Debug, Eq, PartialEq)]
+pub struct Provenance {
+    pub tool: String,
+    /// Type of payload chunk the provenance is about
+    pub chunk_type: String,
+    pub time: SystemTime,
+    pub user: Option<String>,
+    pub host: Option<String>,
+}
+
+impl Provenance {
+    /// Describes payload of `chunk_type` embedded now, user and host are
+    /// detected from environment unless config names or omits them
+    pub fn collect(chunk_type: &ChunkType, names: &ProvenanceNames) -> Provenance {
+        let resolve = |recorded: &Recorded, detect: fn() -> Option<String>| match recorded {
+            Recorded::Detected => detect(),
+            Recorded::Name(name) => Some(name.clone()),
+            Recorded::Omitted => None,
+        };
+        Provenance {
+            tool: format!("pngme {}", env!("CARGO_PKG_VERSION")),
+            chunk_type: chunk_type.to_string(),
+            time: SystemTime::now(),
+            user: resolve(&names.user, user_name),
+            host: resolve(&names.host, host_name),
+        }
+    }
+
+    /// Text chunk of `key: value` lines, readable with `dump`
+    pub fn to_chunk(&self) -> Chunk {
+        let mut text = format!(
+            "tool: {}\nchunk: {}\ntime: {}Z\n",
+            self.tool,
+            self.chunk_type,
+            date::format_timestamp(self.time)
+        );
+        if let Some(user) = &self.user {
+            text.push_str(&format!("user: {}\n", user));
+        }
+        if let Some(host) = &self.host {
+            text.push_str(&format!("host: {}\n", host));
+        }
+        Chunk::new(
+            ChunkType::from_str(PROVENANCE_CHUNK).unwrap(),
+            text.into_bytes(),
+        )
+    }
+}
+
+fn user_name() -> Option<String> {
+    ["USER", "USERNAME", "LOGNAME"]
+        .iter()
+        .find_map(|name| env::var(name).ok().filter(|v| !v.is_empty()))
+}
+
+fn host_name() -> Option<String> {
+    ["HOSTNAME", "COMPUTERNAME"]
+        .iter()
+        .find_map(|name| env::var(name).ok())
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    #[test]
+    fn test_to_chunk() {
+        let mut provenance = Provenance {
+            tool: "pngme 0.1.0".to_string(),
+            chunk_type: "ruSt".to_string(),
+            time: UNIX_EPOCH + Duration::from_secs(86400),
+            user: Some("alice".to_string()),
+            host: None,
+        };
+        let chunk = provenance.to_chunk();
+        assert_eq!(chunk.chunk_type().to_string(), PROVENANCE_CHUNK);
+        assert_eq!(
+            chunk.data(),
+            b"tool: pngme 0.1.0\nchunk: ruSt\ntime: 1970-01-02T00:00:00Z\nuser: alice\n"
+        );
+
+        provenance.user = None;
+        let text = String::from_utf8(provenance.to_chunk().data().to_vec()).unwrap();
+        assert!(!text.contains("user"));
+    }
+
+    #[test]
+    fn test_collect_configured() {
+        let names = ProvenanceNames {
+            user: Recorded::Name("ci".to_string()),
+            host: Recorded::Omitted,
+        };
+        let provenance = Provenance::collect(&ChunkType::from_str("ruSt").unwrap(), &names);
+        assert_eq!(provenance.user.as_deref(), Some("ci"));
+        assert_eq!(provenance.host, None);
+        assert!(provenance.tool.starts_with("pngme "));
+    }
+}
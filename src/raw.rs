@@ -0,0 +1,165 @@
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom};
+
+use anyhow::{ensure, Result};
+
+use crate::chunk::Chunk;
+#[cfg(feature = "write")]
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+
+/// Chunk as laid out in file, read without any validation
+pub struct RawChunk<'a> {
+    /// Position of chunk in file, counting PNG signature
+    pub offset: usize,
+    pub chunk_type: [u8; 4],
+    /// Length, type, data and CRC, cut short if file ends early
+    pub bytes: &'a [u8],
+}
+
+impl<'a> RawChunk<'a> {
+    fn length(&self) -> usize {
+        u32::from_be_bytes(self.bytes[..4].try_into().unwrap()) as usize
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        let end = (8 + self.length()).min(self.bytes.len());
+        &self.bytes[8..end]
+    }
+
+    pub fn header(&self) -> ChunkHeader {
+        ChunkHeader {
+            offset: self.offset as u64,
+            chunk_type: self.chunk_type,
+            length: self.length() as u32,
+            size: self.bytes.len() as u64,
+        }
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.bytes.len() < self.length() + 12
+    }
+
+    /// Why chunk can't be parsed, if it can't
+    pub fn problem(&self) -> Option<String> {
+        if self.is_truncated() {
+            return Some("truncated".to_string());
+        }
+        Chunk::try_from(self.bytes).err().map(|err| err.to_string())
+    }
+}
+
+/// Parses consecutive chunk records (length, type, data and CRC), e.g. saved
+/// by `remove --dump --raw`. CRCs are not checked, chunks get new ones
+#[cfg(feature = "write")]
+pub fn records(mut data: &[u8]) -> Result<Vec<Chunk>> {
+    let mut chunks = vec![];
+    while !data.is_empty() {
+        ensure!(data.len() >= 12, "Chunk record is truncated");
+        let length = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+        ensure!(
+            data.len() >= length.saturating_add(12),
+            "Chunk record is truncated"
+        );
+        let chunk_type = ChunkType::try_from(<[u8; 4]>::try_from(&data[4..8]).unwrap())?;
+        chunks.push(Chunk::new(chunk_type, data[8..8 + length].to_vec()));
+        data = &data[length + 12..];
+    }
+    Ok(chunks)
+}
+
+/// Splits data following PNG signature into chunks, last one may be truncated.
+/// Trailing bytes too short to hold chunk length and type are dropped
+pub fn raw_chunks(mut data: &[u8]) -> Vec<RawChunk<'_>> {
+    let mut chunks = vec![];
+    let mut offset = 8;
+    while data.len() >= 8 {
+        let length = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+        let size = length.saturating_add(12).min(data.len());
+        let (bytes, rest) = data.split_at(size);
+        chunks.push(RawChunk {
+            offset,
+            chunk_type: bytes[4..8].try_into().unwrap(),
+            bytes,
+        });
+        offset += size;
+        data = rest;
+    }
+    chunks
+}
+
+/// Position, type and size of chunk, read without its data
+#[derive(Debug, Eq, PartialEq)]
+pub struct ChunkHeader {
+    /// Position of chunk in file, counting PNG signature
+    pub offset: u64,
+    pub chunk_type: [u8; 4],
+    /// Data length as stored in the chunk
+    pub length: u32,
+    /// Bytes the chunk takes in file: length, type, data and CRC, cut short if file ends early
+    pub size: u64,
+}
+
+/// Same chunks as [`raw_chunks`] with file size, but only 8-byte header of every
+/// chunk is read and data is skipped over, so memory use doesn't depend on chunk
+/// sizes and no CRCs are computed
+pub fn read_headers(reader: impl Read + Seek) -> Result<(Vec<ChunkHeader>, u64)> {
+    let mut reader = BufReader::new(reader);
+    let total = reader.seek(SeekFrom::End(0))?;
+    reader.rewind()?;
+    let mut signature = [0; 8];
+    if total >= 8 {
+        reader.read_exact(&mut signature)?;
+    }
+    ensure!(signature == Png::STANDARD_HEADER, "Header is invalid");
+
+    let mut headers = vec![];
+    let mut offset = 8;
+    let mut header = [0; 8];
+    while total - offset >= 8 {
+        match reader.read_exact(&mut header) {
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+            result => result?,
+        }
+        let length = u32::from_be_bytes(header[..4].try_into().unwrap());
+        let size = (length as u64 + 12).min(total - offset);
+        reader.seek_relative(size as i64 - 8)?;
+        headers.push(ChunkHeader {
+            offset,
+            chunk_type: header[4..].try_into().unwrap(),
+            length,
+            size,
+        });
+        offset += size;
+    }
+    Ok((headers, total))
+}
+
+/// Index of the first intact IEND, anything broken after it is just trailing data
+pub fn end_index(chunks: &[RawChunk]) -> Option<usize> {
+    chunks
+        .iter()
+        .position(|c| &c.chunk_type == b"IEND" && c.problem().is_none())
+}
+
+#[cfg(all(test, feature = "write"))]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_records() {
+        let mut bytes = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hi".to_vec()).as_bytes();
+        // stale CRC is replaced
+        bytes[10] ^= 0xff;
+        bytes.extend(Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]).as_bytes());
+        let chunks = records(&bytes).unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].data(), b"hi");
+        assert!(Chunk::try_from(chunks[0].as_bytes().as_slice()).is_ok());
+
+        assert!(records(&bytes[..bytes.len() - 1]).is_err());
+        assert!(records(b"\0\0\0\0ru5t\0\0\0\0").is_err());
+        assert!(records(b"").unwrap().is_empty());
+    }
+}
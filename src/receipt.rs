@@ -0,0 +1,208 @@
+use anyhow::{anyhow, ensure, Context, Result};
+use ed25519_dalek::{Signature, VerifyingKey};
+#[cfg(feature = "write")]
+use ed25519_dalek::{Signer, SigningKey};
+use sha2::{Digest, Sha256};
+
+use crate::hex;
+#[cfg(feature = "write")]
+use crate::json::json;
+use crate::json::{self, Fields, Value};
+
+/// Prefix mixed into every signed receipt, so signatures can't be reused in other contexts
+const DOMAIN: &[u8] = b"pngme-receipt-v1";
+const VERSION: u64 = 1;
+
+/// Signed record of a command that modified files
+#[derive(Debug, PartialEq)]
+pub struct Receipt {
+    pub operation: String,
+    /// Seconds since Unix epoch
+    pub timestamp: u64,
+    pub files: Vec<FileRecord>,
+}
+
+/// Image touched by operation, with hex SHA-256 of its content before and after
+#[derive(Debug, PartialEq)]
+pub struct FileRecord {
+    pub input: String,
+    pub input_sha256: String,
+    pub output: String,
+    pub output_sha256: String,
+    pub changes: Vec<ChunkChange>,
+}
+
+/// Chunk added or removed by operation
+#[derive(Debug, PartialEq)]
+pub struct ChunkChange {
+    pub action: String,
+    pub index: usize,
+    pub chunk_type: String,
+    /// Hex SHA-256 of full chunk bytes
+    pub sha256: String,
+}
+
+pub fn digest(data: &[u8]) -> String {
+    hex::encode(&Sha256::digest(data))
+}
+
+impl Receipt {
+    /// Serializes receipt as single-line JSON signed by `key`
+    #[cfg(feature = "write")]
+    pub fn sign(&self, key: &SigningKey) -> String {
+        let mut value = self.to_json(&key.verifying_key());
+        let signature = key.sign(&signed_message(&value));
+        value["signature"] = hex::encode(&signature.to_bytes()).into();
+        value.to_string()
+    }
+
+    /// Parses receipt and checks that it was signed by `key`
+    pub fn verify(text: &str, key: &VerifyingKey) -> Result<Receipt> {
+        let mut value = json::parse(text).context("Receipt is not valid JSON")?;
+        let signature = value
+            .as_object_mut()
+            .and_then(|fields| fields.shift_remove("signature"))
+            .context("Receipt is not signed")?;
+        let signature: [u8; 64] = hex::decode(signature.as_str().unwrap_or_default())?
+            .try_into()
+            .map_err(|_| anyhow!("Malformed receipt signature"))?;
+        ensure!(
+            value.str_field("public_key")? == hex::encode(key.as_bytes()),
+            "Receipt was signed by a different key"
+        );
+        key.verify_strict(&signed_message(&value), &Signature::from_bytes(&signature))
+            .context("Invalid receipt signature")?;
+
+        Receipt::from_json(&value)
+    }
+
+    #[cfg(feature = "write")]
+    fn to_json(&self, key: &VerifyingKey) -> Value {
+        let files = self
+            .files
+            .iter()
+            .map(|file| {
+                let changes = file
+                    .changes
+                    .iter()
+                    .map(|change| {
+                        json!({
+                            "action": change.action,
+                            "index": change.index,
+                            "chunk_type": change.chunk_type,
+                            "sha256": change.sha256,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                json!({
+                    "input": file.input,
+                    "input_sha256": file.input_sha256,
+                    "output": file.output,
+                    "output_sha256": file.output_sha256,
+                    "changes": changes,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "version": VERSION,
+            "operation": self.operation,
+            "timestamp": self.timestamp,
+            "files": files,
+            "public_key": hex::encode(key.as_bytes()),
+        })
+    }
+
+    fn from_json(value: &Value) -> Result<Receipt> {
+        let version = value.u64_field("version")?;
+        ensure!(
+            version == VERSION,
+            "Unsupported receipt version {}",
+            version
+        );
+        let files = value
+            .array_field("files")?
+            .iter()
+            .map(|file| {
+                let changes = file
+                    .array_field("changes")?
+                    .iter()
+                    .map(|change| {
+                        Ok(ChunkChange {
+                            action: change.str_field("action")?.to_string(),
+                            index: change.u64_field("index")? as usize,
+                            chunk_type: change.str_field("chunk_type")?.to_string(),
+                            sha256: change.str_field("sha256")?.to_string(),
+                        })
+                    })
+                    .collect::<Result<_>>()?;
+                Ok(FileRecord {
+                    input: file.str_field("input")?.to_string(),
+                    input_sha256: file.str_field("input_sha256")?.to_string(),
+                    output: file.str_field("output")?.to_string(),
+                    output_sha256: file.str_field("output_sha256")?.to_string(),
+                    changes,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Receipt {
+            operation: value.str_field("operation")?.to_string(),
+            timestamp: value.u64_field("timestamp")?,
+            files,
+        })
+    }
+}
+
+fn signed_message(value: &Value) -> Vec<u8> {
+    let mut message = DOMAIN.to_vec();
+    message.extend_from_slice(value.to_string().as_bytes());
+    message
+}
+
+#[cfg(all(test, feature = "write"))]
+mod tests {
+    use super::*;
+
+    fn testing_receipt() -> Receipt {
+        Receipt {
+            operation: "encode".to_string(),
+            timestamp: 1_700_000_000,
+            files: vec![FileRecord {
+                input: "dice.png".to_string(),
+                input_sha256: digest(b"before"),
+                output: "out.png".to_string(),
+                output_sha256: digest(b"after"),
+                changes: vec![ChunkChange {
+                    action: "added".to_string(),
+                    index: 3,
+                    chunk_type: "ruSt".to_string(),
+                    sha256: digest(b"chunk"),
+                }],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let text = testing_receipt().sign(&key);
+        assert_eq!(
+            Receipt::verify(&text, &key.verifying_key()).unwrap(),
+            testing_receipt()
+        );
+
+        let other = SigningKey::from_bytes(&[8; 32]);
+        assert!(Receipt::verify(&text, &other.verifying_key()).is_err());
+    }
+
+    #[test]
+    fn test_verify_tampered() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let text = testing_receipt().sign(&key);
+        let tampered = text.replace("1700000000", "1700000001");
+        assert!(Receipt::verify(&tampered, &key.verifying_key()).is_err());
+
+        let unsigned = text.split(",\"signature\"").next().unwrap().to_string() + "}";
+        assert!(Receipt::verify(&unsigned, &key.verifying_key()).is_err());
+    }
+}
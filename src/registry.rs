@@ -0,0 +1,103 @@
+use anyhow::{Context, Result};
+
+use crate::chunk_type::ChunkType;
+
+/// Well-known chunk types paired with the long-form names accepted on the command line
+const KNOWN_CHUNKS: &[(&str, &str)] = &[
+    ("IHDR", "image-header"),
+    ("PLTE", "palette"),
+    ("IDAT", "image-data"),
+    ("IEND", "image-end"),
+    ("tRNS", "transparency"),
+    ("cHRM", "chromaticities"),
+    ("gAMA", "gamma"),
+    ("iCCP", "icc-profile"),
+    ("sBIT", "significant-bits"),
+    ("sRGB", "srgb"),
+    ("tEXt", "text"),
+    ("zTXt", "compressed-text"),
+    ("iTXt", "international-text"),
+    ("bKGD", "background"),
+    ("hIST", "histogram"),
+    ("pHYs", "physical-dimensions"),
+    ("sPLT", "suggested-palette"),
+    ("eXIf", "exif"),
+    ("tIME", "time"),
+    ("acTL", "animation-control"),
+    ("fcTL", "frame-control"),
+    ("fdAT", "frame-data"),
+];
+
+/// Returns long-form name of chunk type if it is a well-known one
+pub fn name_of(chunk_type: &ChunkType) -> Option<&'static str> {
+    KNOWN_CHUNKS
+        .iter()
+        .find(|(code, _)| code.as_bytes() == chunk_type.bytes())
+        .map(|&(_, name)| name)
+}
+
+/// Whether letters spell a well-known type in any case, e.g. `Text`,
+/// which would be confused with it
+#[cfg(feature = "write")]
+pub fn clashes_with_known(letters: &[u8; 4]) -> bool {
+    KNOWN_CHUNKS
+        .iter()
+        .any(|(code, _)| code.as_bytes().eq_ignore_ascii_case(letters))
+}
+
+/// Resolves either long-form name (case-insensitive) or raw 4-character code into chunk type
+pub fn resolve(s: &str) -> Result<ChunkType> {
+    let known = KNOWN_CHUNKS
+        .iter()
+        .find(|(_, name)| name.eq_ignore_ascii_case(s))
+        .map(|&(code, _)| code);
+
+    known
+        .unwrap_or(s)
+        .parse()
+        .with_context(|| format!("Invalid chunk type '{}'", s))
+}
+
+/// Formats chunk type together with its long-form name, e.g. `tEXt (text)`
+pub fn describe(chunk_type: &ChunkType) -> String {
+    match name_of(chunk_type) {
+        Some(name) => format!("{} ({})", chunk_type, name),
+        None => chunk_type.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_long_name() {
+        assert_eq!(resolve("text").unwrap().to_string(), "tEXt");
+        assert_eq!(resolve("EXIF").unwrap().to_string(), "eXIf");
+        assert_eq!(resolve("physical-dimensions").unwrap().to_string(), "pHYs");
+    }
+
+    #[test]
+    fn test_resolve_raw_code() {
+        assert_eq!(resolve("ruSt").unwrap().to_string(), "ruSt");
+    }
+
+    #[test]
+    fn test_resolve_unknown() {
+        assert!(resolve("not-a-chunk").is_err());
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(describe(&resolve("text").unwrap()), "tEXt (text)");
+        assert_eq!(describe(&resolve("ruSt").unwrap()), "ruSt");
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_clashes_with_known() {
+        assert!(clashes_with_known(b"Text"));
+        assert!(clashes_with_known(b"IEND"));
+        assert!(!clashes_with_known(b"ruSt"));
+    }
+}
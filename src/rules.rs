@@ -0,0 +1,29 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// Seconds libyara may spend on a single chunk
+const SCAN_TIMEOUT: i32 = 10;
+
+/// Compiled YARA rule set
+pub struct Rules(yara::Rules);
+
+impl Rules {
+    pub fn load(path: &Path) -> Result<Rules> {
+        let rules = yara::Compiler::new()?
+            .add_rules_file(path)
+            .and_then(|compiler| compiler.compile_rules())
+            .with_context(|| format!("Failed to compile YARA rules {}", path.display()))?;
+        Ok(Rules(rules))
+    }
+
+    /// Identifiers of rules matching data
+    pub fn matches(&self, data: &[u8]) -> Result<Vec<String>> {
+        Ok(self
+            .0
+            .scan_mem(data, SCAN_TIMEOUT)?
+            .iter()
+            .map(|rule| rule.identifier.to_string())
+            .collect())
+    }
+}
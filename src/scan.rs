@@ -0,0 +1,507 @@
+use std::fmt::{Display, Formatter};
+
+use anyhow::{bail, Result};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ecc;
+use crate::envelope::Envelope;
+use crate::inflate;
+use crate::png::Png;
+use crate::raw::ChunkHeader;
+use crate::registry;
+
+/// Ancillary chunks bigger than this are unusual for real images
+const LARGE_CHUNK_SIZE: u32 = 64 * 1024;
+/// Natural language text stays well below this (in bits per byte),
+/// while base64, compressed or encrypted data is above it
+const HIGH_TEXT_ENTROPY: f64 = 5.0;
+/// Entropy estimate is meaningless for very short texts
+const MIN_ENTROPY_SAMPLE: usize = 64;
+/// Decompressing more than this is not worth it for analysis
+const MAX_DECODED_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Severity::Low => "LOW",
+            Severity::Medium => "MEDIUM",
+            Severity::High => "HIGH",
+        };
+        f.pad(s)
+    }
+}
+
+/// Byte distribution summary of chunk data
+pub struct ChunkStats {
+    /// Shannon entropy in bits per byte
+    pub entropy: f64,
+    /// Share of printable ASCII bytes (including whitespace), from 0.0 to 1.0
+    pub printable_ratio: f64,
+    /// Known file format recognized by leading bytes
+    pub magic: Option<&'static str>,
+}
+
+/// Leading bytes of common formats found inside chunks
+const MAGIC_BYTES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "png"),
+    (b"\xff\xd8\xff", "jpeg"),
+    (b"GIF8", "gif"),
+    (b"PK\x03\x04", "zip"),
+    (b"\x1f\x8b", "gzip"),
+    (b"BZh", "bzip2"),
+    (b"\xfd7zXZ\x00", "xz"),
+    (b"7z\xbc\xaf\x27\x1c", "7z"),
+    (b"\x28\xb5\x2f\xfd", "zstd"),
+    (b"%PDF", "pdf"),
+    (b"\x7fELF", "elf"),
+    (b"MZ", "exe"),
+    (b"Exif\x00\x00", "exif"),
+    (b"II*\x00", "tiff"),
+    (b"MM\x00*", "tiff"),
+    (b"age-encryption.org/", "age"),
+    (b"-----BEGIN PGP", "openpgp"),
+];
+
+pub struct Finding {
+    pub severity: Severity,
+    /// Index of chunk the finding is about, `None` for data after IEND
+    pub chunk_index: Option<usize>,
+    pub message: String,
+}
+
+/// Looks for signs of hidden data in the image.
+/// Findings are sorted by severity, most severe first
+pub fn scan(png: &Png) -> Vec<Finding> {
+    let mut findings = vec![];
+    let mut after_iend = false;
+
+    for (chunk_index, chunk) in png.chunks().iter().enumerate() {
+        let mut report = |severity, message: String| {
+            findings.push(Finding {
+                severity,
+                chunk_index: Some(chunk_index),
+                message,
+            })
+        };
+        check_header(
+            &chunk.chunk_type().bytes(),
+            chunk.length(),
+            &mut after_iend,
+            &mut report,
+        );
+        if let Some(magic) = decoded_payload(chunk).and_then(|p| chunk_stats(&p).magic) {
+            report(
+                Severity::Medium,
+                format!("decoded payload looks like {} data", magic),
+            );
+        }
+        if let Some(text) = text_payload(chunk) {
+            let entropy = entropy(text);
+            if text.len() >= MIN_ENTROPY_SAMPLE && entropy > HIGH_TEXT_ENTROPY {
+                report(
+                    Severity::Low,
+                    format!("high-entropy text ({:.2} bits/byte)", entropy),
+                );
+            }
+        }
+    }
+
+    let trailing = png.trailing();
+    if !trailing.is_empty() {
+        let mut message = format!("data after IEND ({} bytes)", trailing.len());
+        if let Some(magic) = magic(trailing) {
+            message += &format!(", looks like {} data", magic);
+        }
+        findings.push(Finding {
+            severity: Severity::High,
+            chunk_index: None,
+            message,
+        });
+    }
+
+    findings.sort_by_key(|f| std::cmp::Reverse(f.severity));
+    findings
+}
+
+/// Checks of [`scan`] that only need chunk type, length and position,
+/// for files walked by chunk headers without reading data
+pub fn scan_headers(headers: &[ChunkHeader]) -> Vec<Finding> {
+    let mut findings = vec![];
+    let mut after_iend = false;
+    for (chunk_index, header) in headers.iter().enumerate() {
+        let mut report = |severity, message: String| {
+            findings.push(Finding {
+                severity,
+                chunk_index: Some(chunk_index),
+                message,
+            })
+        };
+        if header.size < header.length as u64 + 12 {
+            report(Severity::High, "chunk is truncated".to_string());
+        }
+        check_header(
+            &header.chunk_type,
+            header.length,
+            &mut after_iend,
+            &mut report,
+        );
+    }
+    findings.sort_by_key(|f| std::cmp::Reverse(f.severity));
+    findings
+}
+
+fn check_header(
+    chunk_type: &[u8; 4],
+    length: u32,
+    after_iend: &mut bool,
+    report: &mut impl FnMut(Severity, String),
+) {
+    if *after_iend {
+        report(Severity::High, "chunk placed after IEND".to_string());
+    }
+    if chunk_type == b"IEND" {
+        *after_iend = true;
+    }
+
+    let Ok(chunk_type) = ChunkType::try_from(*chunk_type) else {
+        report(Severity::High, "invalid chunk type".to_string());
+        return;
+    };
+    if registry::name_of(&chunk_type).is_none() {
+        report(Severity::Medium, "non-standard chunk type".to_string());
+    }
+    if !chunk_type.is_critical() && length > LARGE_CHUNK_SIZE {
+        report(
+            Severity::Medium,
+            format!("unusually large ancillary chunk ({} bytes)", length),
+        );
+    }
+}
+
+pub fn chunk_stats(data: &[u8]) -> ChunkStats {
+    let printable = data
+        .iter()
+        .filter(|b| b.is_ascii_graphic() || b.is_ascii_whitespace())
+        .count();
+
+    ChunkStats {
+        entropy: entropy(data),
+        printable_ratio: if data.is_empty() {
+            0.0
+        } else {
+            printable as f64 / data.len() as f64
+        },
+        magic: magic(data),
+    }
+}
+
+/// Known file format recognized by leading bytes
+fn magic(data: &[u8]) -> Option<&'static str> {
+    MAGIC_BYTES
+        .iter()
+        .find(|(magic, _)| data.starts_with(magic))
+        .map(|&(_, name)| name)
+        .or_else(|| inflate::is_zlib_header(data).then_some("zlib"))
+}
+
+/// Known file format of data along with extension usually given to such files
+#[cfg(feature = "write")]
+pub fn sniff(data: &[u8]) -> Option<(&'static str, &'static str)> {
+    let format = magic(data)?;
+    let extension = match format {
+        "jpeg" => "jpg",
+        "gzip" => "gz",
+        "bzip2" => "bz2",
+        "zstd" => "zst",
+        "tiff" => "tif",
+        "openpgp" => "asc",
+        "elf" | "zlib" => "bin",
+        other => other,
+    };
+    Some((format, extension))
+}
+
+/// Shannon entropy of data in bits per byte, 0.0 for empty data
+pub fn entropy(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 0.0;
+    }
+
+    let mut counts = [0usize; 256];
+    for &b in data {
+        counts[b as usize] += 1;
+    }
+
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / len;
+            p * (1.0 / p).log2()
+        })
+        .sum()
+}
+
+/// Renders values from 0.0 to `max` as a line of block characters, one per value
+pub fn sparkline(values: &[f64], max: f64) -> String {
+    const LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    values
+        .iter()
+        .map(|&v| {
+            let level = (v / max * LEVELS.len() as f64) as usize;
+            LEVELS[level.min(LEVELS.len() - 1)]
+        })
+        .collect()
+}
+
+/// Compressed stream inside chunk data together with its format:
+/// text of zTXt and compressed iTXt, or data starting with zlib, gzip or zstd header
+pub fn compressed_stream(chunk: &Chunk) -> Option<(&'static str, &[u8])> {
+    let data = chunk.data();
+    match &chunk.chunk_type().bytes() {
+        // keyword or profile name, separator and compression method
+        b"zTXt" | b"iCCP" => {
+            let separator = data.iter().position(|&b| b == 0)?;
+            Some(("zlib", data.get(separator + 2..)?))
+        }
+        b"iTXt" => {
+            // keyword, compression flag and method, language tag, translated keyword
+            let separator = data.iter().position(|&b| b == 0)?;
+            if *data.get(separator + 1)? != 1 {
+                return None;
+            }
+            let mut fields = data.get(separator + 3..)?.splitn(3, |&b| b == 0);
+            Some(("zlib", fields.nth(2)?))
+        }
+        _ => {
+            let format = magic(data)?;
+            ["zlib", "gzip", "zstd"]
+                .contains(&format)
+                .then_some((format, data))
+        }
+    }
+}
+
+/// Decompresses stream found by [`compressed_stream`], failing if output grows beyond `limit`
+pub fn decompress(format: &str, stream: &[u8], limit: usize) -> Result<Vec<u8>> {
+    match format {
+        "zlib" => inflate::zlib_decompress(stream, limit),
+        "gzip" => inflate::gzip_decompress(stream, limit),
+        "zstd" => inflate::zstd_decompress(stream, limit),
+        _ => bail!("{} decompression is not supported", format),
+    }
+}
+
+/// Payload hidden in ancillary chunk behind compression or pngme's own wrapping:
+/// compressed text or data, data protected by ECC or embedded file content.
+/// Returns `None` if chunk data is not wrapped in any known way
+pub fn decoded_payload(chunk: &Chunk) -> Option<Vec<u8>> {
+    // image data is compressed too, but never hides anything
+    if chunk.chunk_type().is_critical() {
+        return None;
+    }
+    if let Some((format, stream)) = compressed_stream(chunk) {
+        return decompress(format, stream, MAX_DECODED_SIZE).ok();
+    }
+
+    let data = chunk.data();
+    let (data, wrapped) = match ecc::unwrap(data) {
+        Ok(Some((payload, _))) => (payload, true),
+        _ => (data.to_vec(), false),
+    };
+    match Envelope::from_bytes(&data) {
+        Ok(Some(envelope)) => Some(envelope.content),
+        _ => wrapped.then_some(data),
+    }
+}
+
+/// Text part of uncompressed tEXt chunk (after keyword).
+/// Compressed text chunks are skipped since they always have high entropy
+fn text_payload(chunk: &Chunk) -> Option<&[u8]> {
+    if chunk.chunk_type().bytes() != *b"tEXt" {
+        return None;
+    }
+    let data = chunk.data();
+    let separator = data.iter().position(|&b| b == 0)?;
+    Some(&data[separator + 1..])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    #[cfg(feature = "write")]
+    use crate::hex;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_entropy() {
+        assert_eq!(entropy(&[]), 0.0);
+        assert_eq!(entropy(b"aaaa"), 0.0);
+        assert_eq!(entropy(b"abab"), 1.0);
+        let all_bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(entropy(&all_bytes), 8.0);
+    }
+
+    #[test]
+    fn test_chunk_stats() {
+        let stats = chunk_stats(b"hello\x00\x01\x02");
+        assert_eq!(stats.printable_ratio, 0.625);
+        assert_eq!(stats.magic, None);
+
+        let stats = chunk_stats(&[0x78, 0x9c, 0xcb, 0x48, 0xcd]);
+        assert_eq!(stats.magic, Some("zlib"));
+
+        let stats = chunk_stats(&[]);
+        assert_eq!(stats.entropy, 0.0);
+        assert_eq!(stats.printable_ratio, 0.0);
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(b"%PDF-1.7\n"), Some(("pdf", "pdf")));
+        assert_eq!(sniff(b"\x1f\x8b\x08\x00"), Some(("gzip", "gz")));
+        assert_eq!(sniff(b"\xff\xd8\xff\xe0"), Some(("jpeg", "jpg")));
+        assert_eq!(sniff(b"hello"), None);
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[0.0, 2.0, 4.0, 7.9, 8.0], 8.0), "▁▃▅██");
+        assert_eq!(sparkline(&[], 8.0), "");
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_decoded_payload() {
+        let mut ztxt = b"Comment\0\0".to_vec();
+        ztxt.extend(hex::decode("789ccb48cdc9c957c8402701680308b1").unwrap());
+        let decoded = decoded_payload(&chunk("zTXt", &ztxt)).unwrap();
+        assert_eq!(decoded, b"hello hello hello hello");
+        ztxt.splice(..7, *b"Profile");
+        let decoded = decoded_payload(&chunk("iCCP", &ztxt)).unwrap();
+        assert_eq!(decoded, b"hello hello hello hello");
+
+        let wrapped = ecc::wrap(b"secret", 0.5).unwrap();
+        let decoded = decoded_payload(&chunk("ruSt", &wrapped)).unwrap();
+        assert_eq!(decoded, b"secret");
+
+        assert!(decoded_payload(&chunk("tEXt", b"Comment\0plain")).is_none());
+        let zlib = hex::decode("789ccb48cdc9c957c8402701680308b1").unwrap();
+        assert!(decoded_payload(&chunk("IDAT", &zlib)).is_none());
+        assert!(decoded_payload(&chunk("ruSt", &zlib)).is_some());
+
+        let pdf = ecc::wrap(b"%PDF-1.7", 0.5).unwrap();
+        let findings = scan(&Png::from_chunks(vec![chunk("tIME", &pdf)]));
+        assert_eq!(findings[0].message, "decoded payload looks like pdf data");
+    }
+
+    #[test]
+    fn test_scan_clean_image() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("tEXt", b"Comment\0Just a plain picture of some dice"),
+            chunk("IDAT", &[1, 2, 3]),
+            chunk("IEND", &[]),
+        ]);
+        assert!(scan(&png).is_empty());
+    }
+
+    #[test]
+    fn test_scan_suspicious_image() {
+        let random_text: Vec<u8> = b"Comment\0"
+            .iter()
+            .copied()
+            .chain((0..200u8).map(|i| b'!' + i.wrapping_mul(37) % 90))
+            .collect();
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("tEXt", &random_text),
+            chunk("ruSt", &[0; 70000]),
+            chunk("IEND", &[]),
+            chunk("tIME", &[0; 7]),
+        ]);
+
+        let findings = scan(&png);
+        let summary: Vec<_> = findings
+            .iter()
+            .map(|f| (f.severity, f.chunk_index))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Severity::High, Some(4)),
+                (Severity::Medium, Some(2)),
+                (Severity::Medium, Some(2)),
+                (Severity::Low, Some(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scan_trailing_data() {
+        let image = Png::from_chunks(vec![chunk("IHDR", &[0; 13]), chunk("IEND", &[])]).as_bytes();
+        let mut bytes = image.clone();
+        bytes.extend_from_slice(&image);
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+
+        let findings = scan(&png);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, Severity::High);
+        assert_eq!(findings[0].chunk_index, None);
+        assert_eq!(
+            findings[0].message,
+            "data after IEND (45 bytes), looks like png data"
+        );
+    }
+
+    #[test]
+    fn test_scan_headers() {
+        let mut bytes = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("ruSt", &[0; 70000]),
+            chunk("IEND", &[]),
+            chunk("tIME", &[0; 7]),
+        ])
+        .as_bytes();
+        bytes.truncate(bytes.len() - 2);
+        let (headers, total) = crate::raw::read_headers(std::io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(total, bytes.len() as u64);
+        let types: Vec<_> = headers.iter().map(|h| h.chunk_type).collect();
+        assert_eq!(types, [*b"IHDR", *b"ruSt", *b"IEND", *b"tIME"]);
+        assert_eq!((headers[1].offset, headers[1].size), (33, 70012));
+        assert_eq!(headers[3].size, 17);
+
+        let findings: Vec<_> = scan_headers(&headers)
+            .into_iter()
+            .map(|f| (f.chunk_index, f.message))
+            .collect();
+        assert_eq!(
+            findings,
+            [
+                (Some(3), "chunk is truncated".to_string()),
+                (Some(3), "chunk placed after IEND".to_string()),
+                (Some(1), "non-standard chunk type".to_string()),
+                (
+                    Some(1),
+                    "unusually large ancillary chunk (70000 bytes)".to_string()
+                ),
+            ]
+        );
+    }
+}
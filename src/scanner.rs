@@ -0,0 +1,88 @@
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use anyhow::{bail, Context, Result};
+
+/// Result of passing payload through an external scanner
+#[derive(Debug, Eq, PartialEq)]
+pub enum Verdict {
+    Clean,
+    /// Payload was flagged, with first line of scanner output as a reason
+    Infected(String),
+}
+
+/// Pipes payload into stdin of shell command, so it never touches the disk.
+/// Follows exit code convention of clamscan/clamdscan: 0 means clean,
+/// 1 means infected and anything else is a scanner failure
+pub fn run(command: &str, payload: &[u8]) -> Result<Verdict> {
+    let mut child = shell(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run scanner '{}'", command))?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    let output = thread::scope(|s| -> Result<_> {
+        let writer = s.spawn(move || match stdin.write_all(payload) {
+            // scanner is allowed to stop reading once it has made up its mind
+            Err(err) if err.kind() == ErrorKind::BrokenPipe => Ok(()),
+            other => other,
+        });
+        let output = child.wait_with_output()?;
+        writer
+            .join()
+            .unwrap()
+            .context("Failed to pass payload to scanner")?;
+        Ok(output)
+    })?;
+
+    let reason = |bytes: &[u8]| {
+        String::from_utf8_lossy(bytes)
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or_default()
+            .to_string()
+    };
+    match output.status.code() {
+        Some(0) => Ok(Verdict::Clean),
+        Some(1) => Ok(Verdict::Infected(reason(&output.stdout))),
+        _ => bail!(
+            "Scanner '{}' failed ({}): {}",
+            command,
+            output.status,
+            reason(&output.stderr)
+        ),
+    }
+}
+
+/// Command running `command` through system shell
+pub fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verdicts() {
+        let payload = [b'x'; 100_000];
+        assert_eq!(run("cat > /dev/null", &payload).unwrap(), Verdict::Clean);
+        assert_eq!(
+            run("grep -q x && echo 'stream: Test FOUND' && exit 1", &payload).unwrap(),
+            Verdict::Infected("stream: Test FOUND".to_string())
+        );
+        assert!(run("echo broken >&2; exit 2", &payload).is_err());
+    }
+}
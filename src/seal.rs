@@ -0,0 +1,137 @@
+use anyhow::{bail, ensure, Context, Result};
+use ed25519_dalek::{Signature, VerifyingKey};
+#[cfg(feature = "write")]
+use ed25519_dalek::{Signer, SigningKey};
+use sha2::{Digest, Sha256};
+
+use crate::chunk::Chunk;
+use crate::png::Png;
+
+/// Private ancillary chunk holding digest of visible image, followed by
+/// Ed25519 signature of that digest when sealed with a key
+pub const SEAL_CHUNK: &str = "seAl";
+
+/// Prefix of every digest, so it can't be confused with digests made for other purposes
+const DOMAIN: &[u8] = b"pngme-seal-v1";
+const DIGEST_SIZE: usize = 32;
+const SIGNATURE_SIZE: usize = 64;
+
+/// What `verify` found out about a seal
+#[derive(Debug, Eq, PartialEq)]
+pub enum Sealed {
+    Unsigned,
+    /// Signature was there, but no key was given to check it
+    SignatureNotChecked,
+    Signed,
+}
+
+/// SHA-256 over type and data of IHDR, PLTE and IDAT chunks in file order,
+/// everything that decides how the image looks
+pub fn digest(png: &Png) -> [u8; DIGEST_SIZE] {
+    let mut hasher = Sha256::new();
+    hasher.update(DOMAIN);
+    for chunk in png.chunks().iter().filter(|c| is_visible(c)) {
+        hasher.update(chunk.length().to_be_bytes());
+        hasher.update(chunk.chunk_type().bytes());
+        hasher.update(chunk.data());
+    }
+    hasher.finalize().into()
+}
+
+/// Builds seal chunk for the image, signed if key is given
+#[cfg(feature = "write")]
+pub fn seal(png: &Png, key: Option<&SigningKey>) -> Result<Chunk> {
+    let digest = digest(png);
+    let mut data = digest.to_vec();
+    if let Some(key) = key {
+        data.extend_from_slice(&key.sign(&digest).to_bytes());
+    }
+    Ok(Chunk::new(SEAL_CHUNK.parse()?, data))
+}
+
+/// Recomputes digest of the image and compares it with the one in `seal`,
+/// checking signature as well when key is given
+pub fn verify(png: &Png, seal: &Chunk, key: Option<&VerifyingKey>) -> Result<Sealed> {
+    let data = seal.data();
+    ensure!(
+        data.len() == DIGEST_SIZE || data.len() == DIGEST_SIZE + SIGNATURE_SIZE,
+        "Malformed seal chunk"
+    );
+    let (digest, signature) = data.split_at(DIGEST_SIZE);
+    ensure!(
+        digest == self::digest(png),
+        "Image was modified after it was sealed"
+    );
+    match (key, signature.is_empty()) {
+        (None, true) => Ok(Sealed::Unsigned),
+        (None, false) => Ok(Sealed::SignatureNotChecked),
+        (Some(_), true) => bail!("Seal is not signed"),
+        (Some(key), false) => {
+            let signature = Signature::from_bytes(signature.try_into().unwrap());
+            key.verify_strict(digest, &signature)
+                .context("Invalid seal signature")?;
+            Ok(Sealed::Signed)
+        }
+    }
+}
+
+pub fn is_seal(chunk: &Chunk) -> bool {
+    chunk.chunk_type().to_string() == SEAL_CHUNK
+}
+
+fn is_visible(chunk: &Chunk) -> bool {
+    matches!(&chunk.chunk_type().bytes(), b"IHDR" | b"PLTE" | b"IDAT")
+}
+
+#[cfg(all(test, feature = "write"))]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use crate::signature;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn png() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", b"header"),
+            chunk("tEXt", b"Title\0Dice"),
+            chunk("IDAT", b"pixels"),
+            chunk("IEND", b""),
+        ])
+    }
+
+    #[test]
+    fn test_seal_unsigned() {
+        let mut png = png();
+        let seal = seal(&png, None).unwrap();
+        assert_eq!(verify(&png, &seal, None).unwrap(), Sealed::Unsigned);
+
+        // metadata may change, pixels may not
+        png.remove_chunk("tEXt").unwrap();
+        assert_eq!(verify(&png, &seal, None).unwrap(), Sealed::Unsigned);
+        png.insert_chunk(2, chunk("IDAT", b"more pixels"));
+        assert!(verify(&png, &seal, None).is_err());
+    }
+
+    #[test]
+    fn test_seal_signed() {
+        let png = png();
+        let key = signature::generate_key().unwrap();
+        let seal = seal(&png, Some(&key)).unwrap();
+        let public = key.verifying_key();
+        assert_eq!(verify(&png, &seal, Some(&public)).unwrap(), Sealed::Signed);
+        assert_eq!(
+            verify(&png, &seal, None).unwrap(),
+            Sealed::SignatureNotChecked
+        );
+
+        let other = signature::generate_key().unwrap().verifying_key();
+        assert!(verify(&png, &seal, Some(&other)).is_err());
+        let unsigned = super::seal(&png, None).unwrap();
+        assert!(verify(&png, &unsigned, Some(&public)).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use std::ops::Range;
+
+use anyhow::{Context, Result};
+use regex::bytes::{Regex, RegexBuilder};
+
+/// What `grep` looks for
+pub enum Pattern {
+    Bytes(Vec<u8>),
+    Regex(Regex),
+}
+
+impl Pattern {
+    /// Matches bytes rather than UTF-8 text, so `.` and `\xHH` work on binary data
+    pub fn regex(pattern: &str) -> Result<Pattern> {
+        let regex = RegexBuilder::new(pattern)
+            .unicode(false)
+            .build()
+            .context("Invalid regex")?;
+        Ok(Pattern::Regex(regex))
+    }
+}
+
+/// Occurrence of a pattern inside chunk data
+#[derive(Debug, Eq, PartialEq)]
+pub struct Match {
+    pub chunk_index: usize,
+    pub offset: usize,
+    pub length: usize,
+    /// Capture groups of regex, without the whole match
+    pub groups: Vec<Option<Range<usize>>>,
+}
+
+/// Offsets of all (possibly overlapping) occurrences of `pattern` in `data`
+pub fn find_all(data: &[u8], pattern: &[u8]) -> Vec<usize> {
+    if pattern.is_empty() || pattern.len() > data.len() {
+        return vec![];
+    }
+    data.windows(pattern.len())
+        .enumerate()
+        .filter(|(_, window)| *window == pattern)
+        .map(|(offset, _)| offset)
+        .collect()
+}
+
+/// Searches data of every chunk, in file order
+pub fn search<'a, I>(chunks: I, pattern: &Pattern) -> Vec<Match>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut matches = vec![];
+    for (chunk_index, data) in chunks.into_iter().enumerate() {
+        match pattern {
+            Pattern::Bytes(bytes) => {
+                matches.extend(find_all(data, bytes).into_iter().map(|offset| Match {
+                    chunk_index,
+                    offset,
+                    length: bytes.len(),
+                    groups: vec![],
+                }))
+            }
+            Pattern::Regex(regex) => matches.extend(regex.captures_iter(data).map(|captures| {
+                let whole = captures.get(0).unwrap();
+                Match {
+                    chunk_index,
+                    offset: whole.start(),
+                    length: whole.len(),
+                    groups: captures
+                        .iter()
+                        .skip(1)
+                        .map(|group| group.map(|group| group.range()))
+                        .collect(),
+                }
+            })),
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(matches: &[Match]) -> Vec<(usize, usize)> {
+        matches.iter().map(|m| (m.chunk_index, m.offset)).collect()
+    }
+
+    #[test]
+    fn test_find_all() {
+        assert_eq!(find_all(b"abcabc", b"bc"), vec![1, 4]);
+        assert_eq!(find_all(b"aaaa", b"aa"), vec![0, 1, 2]);
+        assert!(find_all(b"abc", b"").is_empty());
+        assert!(find_all(b"ab", b"abc").is_empty());
+    }
+
+    #[test]
+    fn test_search() {
+        let chunks: [&[u8]; 3] = [b"header", b"", b"secret key"];
+        let matches = search(chunks, &Pattern::Bytes(b"e".to_vec()));
+        assert_eq!(found(&matches), [(0, 1), (0, 4), (2, 1), (2, 4), (2, 8)]);
+        assert!(matches.iter().all(|m| m.length == 1));
+    }
+
+    #[test]
+    fn test_search_regex() {
+        let chunks: [&[u8]; 2] = [b"Comment\0mail bob@test.org", b"key=A1B2"];
+        let pattern = Pattern::regex("(\\w+)@(\\w+)\\.org|key=(\\w+)").unwrap();
+        let matches = search(chunks, &pattern);
+        assert_eq!(found(&matches), [(0, 13), (1, 0)]);
+        assert_eq!(matches[0].length, 12);
+        assert_eq!(matches[0].groups, vec![Some(13..16), Some(17..21), None]);
+        assert_eq!(matches[1].groups, vec![None, None, Some(4..8)]);
+    }
+
+    #[test]
+    fn test_search_regex_bytes() {
+        let chunks: [&[u8]; 1] = [b"\x89\xffabc\n"];
+        let matches = search(chunks, &Pattern::regex("(?i)\\xff.B").unwrap());
+        assert_eq!(found(&matches), [(0, 1)]);
+        assert_eq!(matches[0].length, 3);
+        assert!(Pattern::regex("(abc").is_err());
+    }
+}
@@ -0,0 +1,372 @@
+//! Read-only HTTP server checking uploaded images, with health and metrics endpoints
+//! for orchestration tooling. Every connection is handled on its own thread,
+//! so slow uploads don't hold up health checks
+
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{bail, ensure, Context, Result};
+use tracing::{info, warn};
+
+use crate::json::{self, json, Value};
+use crate::png::Png;
+
+/// Uploads larger than this are refused. Along with [`MAX_CONNECTIONS`] it bounds
+/// memory taken by buffered uploads, 256 MiB at most
+const MAX_BODY_SIZE: usize = 8 * 1024 * 1024;
+/// Request line and headers larger than this are refused
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+/// Slow clients are dropped so they don't keep their thread forever
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Connections over this are answered with 503 right away,
+/// so memory of buffered uploads stays bounded
+const MAX_CONNECTIONS: usize = 32;
+const PATHS: [&str; 3] = ["/healthz", "/metrics", "/verify"];
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+/// Request was refused for its size, answered with 413
+#[derive(Debug)]
+pub struct TooLarge(&'static str);
+
+impl Display for TooLarge {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is too large", self.0)
+    }
+}
+
+impl std::error::Error for TooLarge {}
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    fn text(status: u16, body: &str) -> Response {
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: format!("{}\n", body),
+        }
+    }
+
+    fn json(status: u16, value: Value) -> Response {
+        Response {
+            status,
+            content_type: "application/json",
+            body: json::pretty(&value) + "\n",
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Payload Too Large",
+            422 => "Unprocessable Entity",
+            503 => "Service Unavailable",
+            _ => "Error",
+        }
+    }
+}
+
+/// Counters exposed on `/metrics`
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Requests by path, unknown paths counted as `other`
+    requests: [u64; PATHS.len() + 1],
+    parse_failures: u64,
+    /// Sum and count of chunks in valid images, their ratio is the average
+    chunks_sum: u64,
+    images: u64,
+}
+
+impl Metrics {
+    fn count_request(&mut self, path: &str) {
+        let index = PATHS.iter().position(|p| *p == path).unwrap_or(PATHS.len());
+        self.requests[index] += 1;
+    }
+
+    /// Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        text += "# HELP pngme_requests_total HTTP requests handled, by path.\n";
+        text += "# TYPE pngme_requests_total counter\n";
+        for (path, count) in PATHS.iter().chain(&["other"]).zip(self.requests) {
+            text += &format!("pngme_requests_total{{path=\"{}\"}} {}\n", path, count);
+        }
+        text += "# HELP pngme_parse_failures_total Uploaded files that are not valid PNG.\n";
+        text += "# TYPE pngme_parse_failures_total counter\n";
+        text += &format!("pngme_parse_failures_total {}\n", self.parse_failures);
+        text += "# HELP pngme_image_chunks Chunks in valid uploaded images.\n";
+        text += "# TYPE pngme_image_chunks summary\n";
+        text += &format!("pngme_image_chunks_sum {}\n", self.chunks_sum);
+        text += &format!("pngme_image_chunks_count {}\n", self.images);
+        text
+    }
+}
+
+/// Listens on `address` and serves requests until the process is stopped
+pub fn run(address: &str) -> Result<()> {
+    let listener =
+        TcpListener::bind(address).with_context(|| format!("Failed to listen on {}", address))?;
+    eprintln!("Listening on http://{}", listener.local_addr()?);
+    let metrics = Arc::new(Mutex::new(Metrics::default()));
+    let connections = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                warn!("Failed to accept connection: {}", err);
+                continue;
+            }
+        };
+        if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            connections.fetch_sub(1, Ordering::SeqCst);
+            warn!("Too many connections, refusing one");
+            let _ = write_response(&stream, &Response::text(503, "Server is busy"));
+            continue;
+        }
+        let metrics = Arc::clone(&metrics);
+        let connections = Arc::clone(&connections);
+        thread::spawn(move || {
+            if let Err(err) = serve_connection(stream, &metrics) {
+                warn!("Failed to serve request: {:#}", err);
+            }
+            connections.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+fn serve_connection(stream: TcpStream, metrics: &Mutex<Metrics>) -> Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    // metrics are locked only once the whole request is read
+    let response = match read_request(&mut reader) {
+        Ok(request) => {
+            info!("{} {}", request.method, request.path);
+            handle(&request, &mut metrics.lock().unwrap())
+        }
+        Err(err) => {
+            let status = if err.is::<TooLarge>() { 413 } else { 400 };
+            Response::text(status, &format!("{:#}", err))
+        }
+    };
+    write_response(&stream, &response)
+}
+
+/// Reads HTTP/1.x request, body is taken by its Content-Length
+pub fn read_request(reader: &mut impl BufRead) -> Result<Request> {
+    let mut head = 0;
+    let mut line = String::new();
+    let mut read_line = |line: &mut String| -> Result<()> {
+        line.clear();
+        let n = reader
+            .by_ref()
+            .take((MAX_HEAD_SIZE - head) as u64)
+            .read_line(line)?;
+        head += n;
+        if !line.ends_with('\n') {
+            if head >= MAX_HEAD_SIZE {
+                return Err(TooLarge("Request head").into());
+            }
+            bail!("Request head is truncated");
+        }
+        Ok(())
+    };
+
+    read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        bail!("Malformed request line");
+    };
+    ensure!(
+        version.starts_with("HTTP/1."),
+        "Unsupported protocol {}",
+        version
+    );
+    let method = method.to_string();
+    let path = target.split('?').next().unwrap_or_default().to_string();
+
+    let mut length = 0;
+    loop {
+        read_line(&mut line)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().context("Invalid Content-Length")?;
+            }
+        }
+    }
+    if length > MAX_BODY_SIZE {
+        return Err(TooLarge("Request body").into());
+    }
+    // buffer grows as data arrives, so announcing a large body doesn't reserve memory
+    let mut body = vec![];
+    reader.take(length as u64).read_to_end(&mut body)?;
+    ensure!(body.len() == length, "Request body is truncated");
+    Ok(Request { method, path, body })
+}
+
+pub fn handle(request: &Request, metrics: &mut Metrics) -> Response {
+    metrics.count_request(&request.path);
+    let allowed = match request.path.as_str() {
+        "/verify" => "POST",
+        path if PATHS.contains(&path) => "GET",
+        _ => return Response::text(404, "Not found"),
+    };
+    if request.method != allowed {
+        return Response::text(405, &format!("Use {}", allowed));
+    }
+    match request.path.as_str() {
+        "/healthz" => Response::text(200, "ok"),
+        "/metrics" => Response {
+            status: 200,
+            content_type: "text/plain; version=0.0.4",
+            body: metrics.render(),
+        },
+        _ => verify(&request.body, metrics),
+    }
+}
+
+/// Parses uploaded image strictly and reports its chunks
+fn verify(body: &[u8], metrics: &mut Metrics) -> Response {
+    let png = match Png::try_from_strict(body) {
+        Ok(png) => png,
+        Err(err) => {
+            metrics.parse_failures += 1;
+            let value = json!({ "valid": false, "error": format!("{:#}", err) });
+            return Response::json(422, value);
+        }
+    };
+    metrics.images += 1;
+    metrics.chunks_sum += png.chunks().len() as u64;
+    let types: Vec<_> = png
+        .chunks()
+        .iter()
+        .map(|c| c.chunk_type().to_string())
+        .collect();
+    Response::json(
+        200,
+        json!({ "valid": true, "size": body.len(), "chunks": types }),
+    )
+}
+
+fn write_response(mut stream: &TcpStream, response: &Response) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.reason(),
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+
+    fn request(method: &str, path: &str, body: &[u8]) -> Request {
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            body: body.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_read_request() {
+        let raw = b"POST /verify?x=1 HTTP/1.1\r\nHost: a\r\nContent-Length: 3\r\n\r\nabcdef";
+        let request = read_request(&mut Cursor::new(&raw[..])).unwrap();
+        assert_eq!(
+            (request.method.as_str(), request.path.as_str()),
+            ("POST", "/verify")
+        );
+        assert_eq!(request.body, b"abc");
+
+        assert!(read_request(&mut Cursor::new(&b"GET /\r\n\r\n"[..])).is_err());
+        let truncated = b"POST / HTTP/1.1\r\nContent-Length: 9\r\n\r\nabc";
+        assert!(read_request(&mut Cursor::new(&truncated[..])).is_err());
+        let huge = format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", "a".repeat(MAX_HEAD_SIZE));
+        let err = read_request(&mut Cursor::new(huge.as_bytes()))
+            .err()
+            .unwrap();
+        assert!(err.is::<TooLarge>());
+        let huge = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_SIZE + 1
+        );
+        let err = read_request(&mut Cursor::new(huge.as_bytes()))
+            .err()
+            .unwrap();
+        assert!(err.is::<TooLarge>());
+        let err = read_request(&mut Cursor::new(&b"GET / HTTP/1.1\r\nX: a"[..]))
+            .err()
+            .unwrap();
+        assert!(!err.is::<TooLarge>());
+    }
+
+    #[test]
+    fn test_handle() {
+        let mut metrics = Metrics::default();
+        let chunks = ["IHDR", "IDAT", "IEND"]
+            .map(|t| Chunk::new(ChunkType::from_str(t).unwrap(), vec![]))
+            .to_vec();
+        let png = Png::from_chunks(chunks).as_bytes();
+
+        assert_eq!(
+            handle(&request("GET", "/healthz", b""), &mut metrics).status,
+            200
+        );
+        assert_eq!(
+            handle(&request("POST", "/verify", &png), &mut metrics).status,
+            200
+        );
+        let invalid = handle(&request("POST", "/verify", b"GIF89a"), &mut metrics);
+        assert_eq!(invalid.status, 422);
+        assert!(invalid.body.contains("\"valid\": false"));
+        assert_eq!(
+            handle(&request("GET", "/verify", b""), &mut metrics).status,
+            405
+        );
+        assert_eq!(
+            handle(&request("GET", "/admin", b""), &mut metrics).status,
+            404
+        );
+
+        let text = handle(&request("GET", "/metrics", b""), &mut metrics).body;
+        assert!(text.contains("pngme_requests_total{path=\"/verify\"} 3\n"));
+        assert!(text.contains("pngme_requests_total{path=\"other\"} 1\n"));
+        assert!(text.contains("pngme_parse_failures_total 1\n"));
+        assert!(text.contains("pngme_image_chunks_sum 3\n"));
+        assert!(text.contains("pngme_image_chunks_count 1\n"));
+    }
+}
@@ -0,0 +1,231 @@
+use anyhow::{anyhow, bail, ensure, Result};
+
+use crate::gf256;
+
+/// Marks chunk data that holds one share of a split message
+const MAGIC: &[u8; 4] = b"PSH1";
+const HEADER_SIZE: usize = 4 + 8 + 1 + 1 + 1 + 1;
+
+const MODE_SPLIT: u8 = 0;
+const MODE_SHAMIR: u8 = 1;
+
+/// Part of a message spread across several files.
+/// Shares of the same message have the same random `id`
+#[derive(Debug, Eq, PartialEq)]
+pub struct Share {
+    pub id: [u8; 8],
+    /// Plain split: consecutive pieces, all of them are needed to rebuild message.
+    /// Shamir: each share is a point on a secret polynomial, any `threshold` of them are enough
+    pub shamir: bool,
+    /// Position of piece for plain split, x coordinate (starting from 1) for Shamir
+    pub index: u8,
+    pub count: u8,
+    pub threshold: u8,
+    pub data: Vec<u8>,
+}
+
+/// Splits message into `count` shares. Without threshold message is cut into pieces,
+/// with threshold Shamir's secret sharing is used (each share is as long as the message)
+#[cfg(feature = "write")]
+pub fn split(message: &[u8], count: usize, threshold: Option<usize>) -> Result<Vec<Share>> {
+    ensure!(
+        (2..=255).contains(&count),
+        "Message can be split into 2 to 255 parts"
+    );
+    let mut id = [0; 8];
+    random_bytes(&mut id)?;
+
+    let Some(threshold) = threshold else {
+        let piece = message.len().div_ceil(count).max(1);
+        let mut pieces: Vec<&[u8]> = message.chunks(piece).collect();
+        pieces.resize(count, &[]);
+        return Ok(pieces
+            .into_iter()
+            .enumerate()
+            .map(|(index, data)| Share {
+                id,
+                shamir: false,
+                index: index as u8,
+                count: count as u8,
+                threshold: count as u8,
+                data: data.to_vec(),
+            })
+            .collect());
+    };
+
+    ensure!(
+        (2..=count).contains(&threshold),
+        "Threshold must be between 2 and number of files"
+    );
+    // coefficients of random polynomials, constant term of each is the message byte
+    let mut coefficients = vec![0; message.len() * (threshold - 1)];
+    random_bytes(&mut coefficients)?;
+
+    Ok((1..=count as u8)
+        .map(|x| {
+            let data = message
+                .iter()
+                .zip(coefficients.chunks(threshold - 1))
+                .map(|(&secret, coefs)| {
+                    // Horner's scheme for secret + c1*x + c2*x^2 + ...
+                    let y = coefs.iter().rev().fold(0, |acc, &c| gf256::mul(acc, x) ^ c);
+                    gf256::mul(y, x) ^ secret
+                })
+                .collect();
+            Share {
+                id,
+                shamir: true,
+                index: x,
+                count: count as u8,
+                threshold: threshold as u8,
+                data,
+            }
+        })
+        .collect())
+}
+
+/// Rebuilds message from shares, extra shares are ignored
+pub fn join(mut shares: Vec<Share>) -> Result<Vec<u8>> {
+    let first = shares.first().ok_or_else(|| anyhow!("No shares given"))?;
+    let (id, shamir, count, threshold) = (first.id, first.shamir, first.count, first.threshold);
+    ensure!(
+        shares
+            .iter()
+            .all(|s| s.id == id && s.shamir == shamir && s.count == count),
+        "Shares belong to different messages"
+    );
+
+    shares.sort_by_key(|s| s.index);
+    shares.dedup_by_key(|s| s.index);
+    ensure!(
+        shares.len() >= threshold as usize,
+        "Need {} distinct shares, only {} given",
+        threshold,
+        shares.len()
+    );
+
+    if !shamir {
+        return Ok(shares.into_iter().flat_map(|s| s.data).collect());
+    }
+
+    let shares = &shares[..threshold as usize];
+    let len = shares[0].data.len();
+    ensure!(
+        shares.iter().all(|s| s.data.len() == len),
+        "Shares have different lengths"
+    );
+
+    // Lagrange interpolation at x = 0, subtraction is xor in GF(256)
+    let weights: Vec<u8> = shares
+        .iter()
+        .map(|si| {
+            shares
+                .iter()
+                .filter(|sj| sj.index != si.index)
+                .fold(1, |acc, sj| {
+                    gf256::mul(acc, gf256::div(sj.index, sj.index ^ si.index))
+                })
+        })
+        .collect();
+
+    Ok((0..len)
+        .map(|i| {
+            shares
+                .iter()
+                .zip(&weights)
+                .fold(0, |acc, (s, &w)| acc ^ gf256::mul(s.data[i], w))
+        })
+        .collect())
+}
+
+impl Share {
+    #[cfg(feature = "write")]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mode = if self.shamir { MODE_SHAMIR } else { MODE_SPLIT };
+        MAGIC
+            .iter()
+            .chain(self.id.iter())
+            .chain([mode, self.index, self.count, self.threshold].iter())
+            .chain(self.data.iter())
+            .copied()
+            .collect()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Share> {
+        ensure!(
+            bytes.len() >= HEADER_SIZE && bytes.starts_with(MAGIC),
+            "Not a message share"
+        );
+        let shamir = match bytes[12] {
+            MODE_SPLIT => false,
+            MODE_SHAMIR => true,
+            other => bail!("Unknown share mode {}", other),
+        };
+        Ok(Share {
+            id: bytes[4..12].try_into().unwrap(),
+            shamir,
+            index: bytes[13],
+            count: bytes[14],
+            threshold: bytes[15],
+            data: bytes[HEADER_SIZE..].to_vec(),
+        })
+    }
+}
+
+#[cfg(feature = "write")]
+fn random_bytes(buf: &mut [u8]) -> Result<()> {
+    getrandom::getrandom(buf).map_err(|e| anyhow!("Failed to get random bytes: {}", e))
+}
+
+#[cfg(all(test, feature = "write"))]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &[u8] = b"Meet me at the usual place at noon";
+
+    #[test]
+    fn test_plain_split() {
+        let shares = split(MESSAGE, 3, None).unwrap();
+        assert_eq!(shares.len(), 3);
+        assert!(shares.iter().all(|s| s.data.len() < MESSAGE.len()));
+
+        let mut shares: Vec<Share> = shares
+            .iter()
+            .map(|s| Share::from_bytes(&s.to_bytes()).unwrap())
+            .collect();
+        shares.reverse();
+        assert_eq!(join(shares).unwrap(), MESSAGE);
+    }
+
+    #[test]
+    fn test_plain_split_needs_all_parts() {
+        let mut shares = split(MESSAGE, 3, None).unwrap();
+        shares.pop();
+        assert!(join(shares).is_err());
+    }
+
+    #[test]
+    fn test_shamir_any_threshold_shares() {
+        let shares = split(MESSAGE, 5, Some(3)).unwrap();
+        assert!(shares.iter().all(|s| s.data.len() == MESSAGE.len()));
+
+        let pick = |indices: &[usize]| -> Vec<Share> {
+            indices
+                .iter()
+                .map(|&i| Share::from_bytes(&shares[i].to_bytes()).unwrap())
+                .collect()
+        };
+        assert_eq!(join(pick(&[0, 1, 2])).unwrap(), MESSAGE);
+        assert_eq!(join(pick(&[4, 2, 0])).unwrap(), MESSAGE);
+        assert_eq!(join(pick(&[1, 3, 4, 0])).unwrap(), MESSAGE);
+        assert!(join(pick(&[1, 3])).is_err());
+    }
+
+    #[test]
+    fn test_mixed_messages() {
+        let mut shares = split(MESSAGE, 2, None).unwrap();
+        shares.pop();
+        shares.push(split(MESSAGE, 2, None).unwrap().pop().unwrap());
+        assert!(join(shares).is_err());
+    }
+}
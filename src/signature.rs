@@ -0,0 +1,192 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use ed25519_dalek::{Signature, VerifyingKey};
+#[cfg(feature = "write")]
+use ed25519_dalek::{Signer, SigningKey};
+
+#[cfg(feature = "write")]
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::hex;
+use crate::png::Png;
+
+/// Private ancillary chunk holding an Ed25519 signature.
+/// Layout: scope (1 byte), signed chunk type (4 bytes, zeros for critical scope), signature (64 bytes)
+pub const SIGNATURE_CHUNK: &str = "sgNt";
+
+/// Prefix mixed into every signed message, so signatures can't be reused in other contexts
+const DOMAIN: &[u8] = b"pngme-signature-v1";
+
+const SCOPE_CHUNK: u8 = 0;
+const SCOPE_CRITICAL: u8 = 1;
+
+/// What part of the image signature covers
+#[derive(Debug, Eq, PartialEq)]
+pub enum Scope {
+    /// Data of the first chunk with given type
+    Chunk([u8; 4]),
+    /// All critical chunks (type, data and CRC), in file order
+    Critical,
+}
+
+#[cfg(feature = "write")]
+pub fn generate_key() -> Result<SigningKey> {
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).map_err(|e| anyhow!("Failed to generate key: {}", e))?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// Reads secret key stored as 64 hex characters
+#[cfg(feature = "write")]
+pub fn read_signing_key(path: &Path) -> Result<SigningKey> {
+    Ok(SigningKey::from_bytes(&read_key_bytes(path)?))
+}
+
+/// Reads public key stored as 64 hex characters
+pub fn read_verifying_key(path: &Path) -> Result<VerifyingKey> {
+    Ok(VerifyingKey::from_bytes(&read_key_bytes(path)?)?)
+}
+
+/// Signs part of the image and returns chunk with signature
+#[cfg(feature = "write")]
+pub fn sign(png: &Png, scope: &Scope, key: &SigningKey) -> Result<Chunk> {
+    let signature = key.sign(&signed_message(png, scope)?);
+
+    let (scope_byte, target) = match scope {
+        Scope::Chunk(chunk_type) => (SCOPE_CHUNK, *chunk_type),
+        Scope::Critical => (SCOPE_CRITICAL, [0; 4]),
+    };
+    let data = [scope_byte]
+        .iter()
+        .chain(target.iter())
+        .chain(signature.to_bytes().iter())
+        .copied()
+        .collect();
+
+    Ok(Chunk::new(SIGNATURE_CHUNK.parse()?, data))
+}
+
+/// Verifies every signature chunk in the image against given key.
+/// Fails if there are no signatures or any of them is invalid
+pub fn verify(png: &Png, key: &VerifyingKey) -> Result<Vec<Scope>> {
+    let signature_type: ChunkType = SIGNATURE_CHUNK.parse()?;
+    let mut verified = vec![];
+
+    for chunk in png.chunks() {
+        if chunk.chunk_type() != &signature_type {
+            continue;
+        }
+        let data = chunk.data();
+        ensure!(data.len() == 1 + 4 + 64, "Malformed signature chunk");
+
+        let target: [u8; 4] = data[1..5].try_into().unwrap();
+        let scope = match data[0] {
+            SCOPE_CHUNK => Scope::Chunk(target),
+            SCOPE_CRITICAL => Scope::Critical,
+            other => bail!("Unknown signature scope {}", other),
+        };
+        let signature = Signature::from_bytes(data[5..].try_into().unwrap());
+
+        key.verify_strict(&signed_message(png, &scope)?, &signature)
+            .with_context(|| format!("Invalid signature over {}", scope))?;
+        verified.push(scope);
+    }
+
+    ensure!(!verified.is_empty(), "Image has no signatures");
+    Ok(verified)
+}
+
+fn signed_message(png: &Png, scope: &Scope) -> Result<Vec<u8>> {
+    let mut message = DOMAIN.to_vec();
+    match scope {
+        Scope::Chunk(chunk_type) => {
+            let chunk = png
+                .chunks()
+                .iter()
+                .find(|c| c.chunk_type().bytes() == *chunk_type)
+                .with_context(|| format!("No {} chunk found", scope))?;
+            message.push(SCOPE_CHUNK);
+            message.extend_from_slice(chunk_type);
+            message.extend_from_slice(chunk.data());
+        }
+        Scope::Critical => {
+            message.push(SCOPE_CRITICAL);
+            for chunk in png.chunks().iter().filter(|c| c.chunk_type().is_critical()) {
+                message.extend(chunk.as_bytes());
+            }
+        }
+    }
+    Ok(message)
+}
+
+fn read_key_bytes(path: &Path) -> Result<[u8; 32]> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read key {}", path.display()))?;
+    hex::decode(&content)?
+        .try_into()
+        .map_err(|_| anyhow!("Key in {} must be 32 bytes long", path.display()))
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scope::Chunk(chunk_type) => write!(f, "{} chunk", String::from_utf8_lossy(chunk_type)),
+            Scope::Critical => write!(f, "critical chunks"),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "write"))]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.into())
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", "header"),
+            chunk("ruSt", "secret message"),
+            chunk("IDAT", "pixels"),
+            chunk("IEND", ""),
+        ])
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let mut png = testing_png();
+        png.append_chunk(sign(&png, &Scope::Chunk(*b"ruSt"), &key).unwrap());
+        png.append_chunk(sign(&png, &Scope::Critical, &key).unwrap());
+
+        let verified = verify(&png, &key.verifying_key()).unwrap();
+        assert_eq!(verified, vec![Scope::Chunk(*b"ruSt"), Scope::Critical]);
+    }
+
+    #[test]
+    fn test_verify_tampered() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let png = testing_png();
+        let signature = sign(&png, &Scope::Chunk(*b"ruSt"), &key).unwrap();
+
+        let mut tampered = Png::from_chunks(vec![
+            chunk("IHDR", "header"),
+            chunk("ruSt", "forged message"),
+            chunk("IEND", ""),
+        ]);
+        tampered.append_chunk(signature);
+
+        assert!(verify(&tampered, &key.verifying_key()).is_err());
+    }
+
+    #[test]
+    fn test_verify_unsigned() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        assert!(verify(&testing_png(), &key.verifying_key()).is_err());
+    }
+}
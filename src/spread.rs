@@ -0,0 +1,318 @@
+#[cfg(feature = "write")]
+use std::collections::HashMap;
+
+#[cfg(feature = "write")]
+use anyhow::anyhow;
+use anyhow::{ensure, Context, Result};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+use crate::chunk::Chunk;
+#[cfg(feature = "write")]
+use crate::chunk_type::ChunkType;
+use crate::hmac;
+use crate::png::Png;
+
+/// Keeps fragment keys unrelated to other uses of the same password
+const DOMAIN: &[u8] = b"pngme spread fragments";
+/// Leading bytes of every fragment, telling its position to whoever knows the password
+const TAG_SIZE: usize = 8;
+/// Random nonce goes first in encrypted message, so messages never share keystream
+const NONCE_SIZE: usize = 12;
+/// Fragment data sizes, small enough to stay below entropy and size checks of `scan`
+#[cfg(feature = "write")]
+const MIN_FRAGMENT: usize = 16;
+#[cfg(feature = "write")]
+const MAX_FRAGMENT: usize = 48;
+/// Private chunks written by common editors (Fireworks, Android, ImageMagick, macOS),
+/// so fragments look like leftovers of the tools the image went through
+#[cfg(feature = "write")]
+const TYPES: [&str; 12] = [
+    "mkBF", "mkBS", "mkBT", "mkTS", "prVW", "npTc", "npLb", "npOl", "vpAg", "caNv", "orNT", "cpIp",
+];
+
+/// Splits message into many small chunks of plausible types. Message is encrypted
+/// with ChaCha20-Poly1305 under key derived from password and a random nonce,
+/// so fragments reveal nothing and gathering detects missing or altered ones
+#[cfg(feature = "write")]
+pub fn fragments(password: &str, message: &[u8]) -> Result<Vec<Chunk>> {
+    let key = hmac::hmac_sha256(password.as_bytes(), DOMAIN);
+    let data = encrypt(&message_key(&key), message)?;
+
+    let mut chunks = vec![];
+    let mut rest = data.as_slice();
+    while !rest.is_empty() {
+        let size = MIN_FRAGMENT + random_below(MAX_FRAGMENT - MIN_FRAGMENT + 1)?;
+        let (piece, tail) = rest.split_at(size.min(rest.len()));
+        let mut fragment = tag(&key, chunks.len() as u32).to_vec();
+        fragment.extend_from_slice(piece);
+        let chunk_type: ChunkType = TYPES[random_below(TYPES.len())?].parse()?;
+        chunks.push(Chunk::new(chunk_type, fragment));
+        rest = tail;
+    }
+    Ok(chunks)
+}
+
+/// Index chunk telling positions of fragments, to be inserted right before IEND
+/// once all fragments are in place. Positions are encrypted like the message,
+/// with a key and nonce of their own
+#[cfg(feature = "write")]
+pub fn index(png: &Png, password: &str) -> Result<Chunk> {
+    let key = hmac::hmac_sha256(password.as_bytes(), DOMAIN);
+    let tags: HashMap<[u8; TAG_SIZE], usize> = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .filter_map(|(i, c)| Some((c.data().get(..TAG_SIZE)?.try_into().ok()?, i)))
+        .collect();
+    let positions: Vec<u32> = (0..)
+        .map_while(|count| tags.get(&tag(&key, count)))
+        .map(|&i| i as u32)
+        .collect();
+    ensure!(!positions.is_empty(), "No fragments to index");
+
+    let mut data = (positions.len() as u32).to_be_bytes().to_vec();
+    data.extend(positions.iter().flat_map(|p| p.to_be_bytes()));
+    let mut index = index_tag(&key).to_vec();
+    index.extend(encrypt(&index_key(&key), &data)?);
+    let chunk_type: ChunkType = TYPES[random_below(TYPES.len())?].parse()?;
+    Ok(Chunk::new(chunk_type, index))
+}
+
+/// Reassembles message from fragments at positions listed in index chunk,
+/// `None` if there is no index for this password
+pub fn gather(png: &Png, password: &str) -> Result<Option<Vec<u8>>> {
+    let key = hmac::hmac_sha256(password.as_bytes(), DOMAIN);
+    let Some(pieces) = indexed(png, &key)? else {
+        return Ok(None);
+    };
+    let count = pieces.len();
+    let message = decrypt(&message_key(&key), &pieces.concat()).with_context(|| {
+        format!(
+            "Spread message is incomplete or was modified, {} fragments found",
+            count
+        )
+    })?;
+    Ok(Some(message))
+}
+
+/// Random position to insert a fragment at: after IHDR, before IEND and not between IDATs
+#[cfg(feature = "write")]
+pub fn random_slot(png: &Png) -> Result<usize> {
+    let is = |i: usize, t: &[u8; 4]| {
+        png.chunks()
+            .get(i)
+            .is_some_and(|c| &c.chunk_type().bytes() == t)
+    };
+    let end = (0..png.chunks().len())
+        .find(|&i| is(i, b"IEND"))
+        .unwrap_or(png.chunks().len());
+    let slots: Vec<_> = (1..=end)
+        .filter(|&i| !(is(i - 1, b"IDAT") && is(i, b"IDAT")))
+        .collect();
+    Ok(slots[random_below(slots.len())?])
+}
+
+/// Fragments at positions listed in index chunk, `None` if there is no index.
+/// Index is authenticated, so fragments that are not where it says were moved or removed
+fn indexed<'a>(png: &'a Png, key: &[u8]) -> Result<Option<Vec<&'a [u8]>>> {
+    let index_tag = index_tag(key);
+    let chunks = png.chunks();
+    let is_index = |c: &Chunk| c.data().starts_with(&index_tag);
+    // index goes right before IEND, anywhere else it is found by tag
+    let index = chunks
+        .len()
+        .checked_sub(2)
+        .map(|i| &chunks[i])
+        .filter(|c| is_index(c))
+        .or_else(|| chunks.iter().find(|c| is_index(c)));
+    let Some(index) = index else {
+        return Ok(None);
+    };
+
+    let data =
+        decrypt(&index_key(key), &index.data()[TAG_SIZE..]).context("Spread index was modified")?;
+    let (count, positions) = data
+        .split_at_checked(4)
+        .context("Spread index is malformed")?;
+    let count = u32::from_be_bytes(count.try_into().unwrap()) as usize;
+    let positions = positions.chunks_exact(4);
+    ensure!(positions.len() == count, "Spread index is malformed");
+    positions
+        .enumerate()
+        .map(|(i, position)| {
+            let position = u32::from_be_bytes(position.try_into().unwrap()) as usize;
+            chunks
+                .get(position)
+                .and_then(|chunk| chunk.data().split_at_checked(TAG_SIZE))
+                .filter(|(tag_bytes, _)| *tag_bytes == tag(key, i as u32))
+                .map(|(_, piece)| piece)
+                .context("Spread fragments were moved or removed after they were indexed")
+        })
+        .collect::<Result<_>>()
+        .map(Some)
+}
+
+fn index_tag(key: &[u8]) -> [u8; TAG_SIZE] {
+    hmac::hmac_sha256(key, b"index")[..TAG_SIZE]
+        .try_into()
+        .unwrap()
+}
+
+/// Index and message are encrypted with keys of their own, unrelated to tags
+fn index_key(key: &[u8]) -> [u8; hmac::TAG_SIZE] {
+    hmac::hmac_sha256(key, b"index key")
+}
+
+fn message_key(key: &[u8]) -> [u8; hmac::TAG_SIZE] {
+    hmac::hmac_sha256(key, b"message key")
+}
+
+fn tag(key: &[u8], index: u32) -> [u8; TAG_SIZE] {
+    let mut input = b"tag".to_vec();
+    input.extend_from_slice(&index.to_be_bytes());
+    hmac::hmac_sha256(key, &input)[..TAG_SIZE]
+        .try_into()
+        .unwrap()
+}
+
+/// ChaCha20-Poly1305 ciphertext of `data`, preceded by random nonce
+#[cfg(feature = "write")]
+fn encrypt(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0; NONCE_SIZE];
+    getrandom::getrandom(&mut nonce).map_err(|e| anyhow!("Failed to get random bytes: {}", e))?;
+    let encrypted = ChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(Nonce::from_slice(&nonce), data)
+        .map_err(|_| anyhow!("Failed to encrypt spread data"))?;
+    Ok([&nonce[..], &encrypted].concat())
+}
+
+/// Data encrypted by [`encrypt`], `None` if key is wrong or data was modified
+fn decrypt(key: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+    let (nonce, encrypted) = data.split_at_checked(NONCE_SIZE)?;
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(Nonce::from_slice(nonce), encrypted)
+        .ok()
+}
+
+#[cfg(feature = "write")]
+fn random_below(n: usize) -> Result<usize> {
+    let mut bytes = [0; 4];
+    getrandom::getrandom(&mut bytes).map_err(|e| anyhow!("Failed to get random bytes: {}", e))?;
+    Ok(u32::from_be_bytes(bytes) as usize % n)
+}
+
+#[cfg(all(test, feature = "write"))]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn png() -> Png {
+        let chunks = ["IHDR", "gAMA", "IDAT", "IDAT", "IEND"]
+            .map(|t| Chunk::new(ChunkType::from_str(t).unwrap(), b"data".to_vec()));
+        Png::from_chunks(chunks.into())
+    }
+
+    /// Fragments at random places and index before IEND, as spread-encode does
+    fn spread(png: &mut Png, fragments: Vec<Chunk>, password: &str) {
+        for fragment in fragments {
+            let slot = random_slot(png).unwrap();
+            png.insert_chunk(slot, fragment);
+        }
+        let end = png.chunks().len() - 1;
+        png.insert_chunk(end, index(png, password).unwrap());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let message = "meet me at the usual place ".repeat(10);
+        let fragments = fragments("s3cret", message.as_bytes()).unwrap();
+        assert!(fragments.len() >= 5);
+        for fragment in &fragments {
+            let size = fragment.data().len() - TAG_SIZE;
+            assert!(size <= MAX_FRAGMENT);
+            assert!(TYPES.contains(&fragment.chunk_type().to_string().as_str()));
+        }
+
+        let mut png = png();
+        spread(&mut png, fragments, "s3cret");
+        let types: Vec<_> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(types.first().unwrap(), "IHDR");
+        assert_eq!(types.last().unwrap(), "IEND");
+        let idat = types.iter().position(|t| t == "IDAT").unwrap();
+        assert_eq!(types[idat + 1], "IDAT");
+
+        let gathered = gather(&png, "s3cret").unwrap().unwrap();
+        assert_eq!(gathered, message.as_bytes());
+        assert_eq!(gather(&png, "wrong").unwrap(), None);
+    }
+
+    #[test]
+    fn test_fresh_nonce() {
+        let first = fragments("s3cret", &[7; 20]).unwrap();
+        let second = fragments("s3cret", &[7; 20]).unwrap();
+        // same tags, but nothing else in common
+        assert_eq!(first[0].data()[..TAG_SIZE], second[0].data()[..TAG_SIZE]);
+        assert_ne!(first[0].data()[TAG_SIZE..], second[0].data()[TAG_SIZE..]);
+
+        let mut png = png();
+        let mut tampered = first[0].data().to_vec();
+        *tampered.last_mut().unwrap() ^= 1;
+        let tampered = Chunk::new(first[0].chunk_type().clone(), tampered);
+        spread(
+            &mut png,
+            [vec![tampered], first[1..].to_vec()].concat(),
+            "s3cret",
+        );
+        assert!(gather(&png, "s3cret").is_err());
+    }
+
+    #[test]
+    fn test_index() {
+        assert!(index(&png(), "s3cret").is_err());
+        let mut png = png();
+        spread(&mut png, fragments("s3cret", &[7; 200]).unwrap(), "s3cret");
+        assert_eq!(gather(&png, "s3cret").unwrap().unwrap(), [7; 200]);
+        assert_eq!(gather(&png, "wrong").unwrap(), None);
+
+        // index is authenticated
+        let end = png.chunks().len() - 2;
+        let mut data = png.chunks()[end].data().to_vec();
+        *data.last_mut().unwrap() ^= 1;
+        let mut tampered = png.clone();
+        tampered.remove_chunk_at(end);
+        tampered.insert_chunk(end, Chunk::new(ChunkType::from_str("mkBF").unwrap(), data));
+        assert!(gather(&tampered, "s3cret").is_err());
+
+        // stale index after chunks are moved
+        png.insert_chunk(
+            1,
+            Chunk::new(ChunkType::from_str("tEXt").unwrap(), vec![0; 4]),
+        );
+        assert!(gather(&png, "s3cret").is_err());
+    }
+
+    #[test]
+    fn test_gather_incomplete() {
+        let mut png = png();
+        let fragments = fragments("s3cret", &[7; 100]).unwrap();
+        let count = fragments.len();
+        spread(
+            &mut png,
+            fragments.into_iter().take(count - 1).collect(),
+            "s3cret",
+        );
+        assert!(gather(&png, "s3cret").is_err());
+        // fragments alone, without index, are not found
+        let mut png = self::png();
+        for fragment in super::fragments("s3cret", &[7; 100]).unwrap() {
+            png.insert_chunk(1, fragment);
+        }
+        assert_eq!(gather(&png, "s3cret").unwrap(), None);
+    }
+}
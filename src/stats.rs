@@ -0,0 +1,146 @@
+use anyhow::{ensure, Result};
+
+use crate::png::Png;
+use crate::raw::{self, ChunkHeader};
+
+/// Size taken by chunks of one type
+#[derive(Debug, Eq, PartialEq)]
+pub struct TypeStats {
+    pub chunk_type: [u8; 4],
+    pub count: usize,
+    /// Whole chunks: length, type, data and CRC
+    pub bytes: usize,
+}
+
+/// Aggregate sizes of file and its chunks
+#[derive(Debug, Eq, PartialEq)]
+pub struct Stats {
+    pub total: usize,
+    pub chunks: usize,
+    /// Per chunk type, largest first
+    pub types: Vec<TypeStats>,
+    /// Bytes in chunks with ancillary bit set
+    pub ancillary: usize,
+    /// Index, type and size of the largest chunk
+    pub largest: Option<(usize, [u8; 4], usize)>,
+    /// Bytes following IEND, either chunks or any other data.
+    /// Whole file after signature if there is no IEND
+    pub after_iend: usize,
+}
+
+impl Stats {
+    /// Collects statistics from raw file, corrupted and truncated chunks are counted too
+    pub fn from_bytes(bytes: &[u8]) -> Result<Stats> {
+        ensure!(
+            bytes.starts_with(&Png::STANDARD_HEADER),
+            "Header is invalid"
+        );
+        let headers: Vec<_> = raw::raw_chunks(&bytes[8..])
+            .iter()
+            .map(|c| c.header())
+            .collect();
+        Ok(Stats::from_headers(&headers, bytes.len()))
+    }
+
+    /// Collects statistics from chunk headers of file with `total` bytes,
+    /// as read by [`raw::read_headers`]
+    pub fn from_headers(chunks: &[ChunkHeader], total: usize) -> Stats {
+        let mut types: Vec<TypeStats> = vec![];
+        let mut ancillary = 0;
+        let mut largest = None;
+        let mut end = 8;
+        let mut iend_end = None;
+        for (i, chunk) in chunks.iter().enumerate() {
+            let size = chunk.size as usize;
+            end += size;
+            match types.iter_mut().find(|t| t.chunk_type == chunk.chunk_type) {
+                Some(stats) => {
+                    stats.count += 1;
+                    stats.bytes += size;
+                }
+                None => types.push(TypeStats {
+                    chunk_type: chunk.chunk_type,
+                    count: 1,
+                    bytes: size,
+                }),
+            }
+            if chunk.chunk_type[0].is_ascii_lowercase() {
+                ancillary += size;
+            }
+            if largest.is_none_or(|(_, _, largest)| size > largest) {
+                largest = Some((i, chunk.chunk_type, size));
+            }
+            if &chunk.chunk_type == b"IEND" && iend_end.is_none() {
+                iend_end = Some(end);
+            }
+        }
+        types.sort_by_key(|t| std::cmp::Reverse(t.bytes));
+
+        Stats {
+            total,
+            chunks: chunks.len(),
+            types,
+            ancillary,
+            largest,
+            after_iend: total - iend_end.unwrap_or(8),
+        }
+    }
+}
+
+/// Share of total as percentage with one decimal, e.g. `12.5%`
+pub fn percent(part: usize, total: usize) -> String {
+    match total {
+        0 => "0.0%".to_string(),
+        _ => format!("{:.1}%", part as f64 * 100.0 / total as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+
+    fn chunk(chunk_type: &str, size: usize) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![0; size])
+    }
+
+    #[test]
+    fn test_stats() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", 13),
+            chunk("tEXt", 8),
+            chunk("IDAT", 100),
+            chunk("IDAT", 50),
+            chunk("IEND", 0),
+            chunk("ruSt", 3),
+        ]);
+        let mut bytes = png.as_bytes();
+        bytes.extend_from_slice(b"junk");
+        let stats = Stats::from_bytes(&bytes).unwrap();
+
+        assert_eq!(stats.total, 8 + 25 + 20 + 112 + 62 + 12 + 15 + 4);
+        assert_eq!(stats.chunks, 6);
+        assert_eq!(
+            stats.types[0],
+            TypeStats {
+                chunk_type: *b"IDAT",
+                count: 2,
+                bytes: 174,
+            }
+        );
+        assert_eq!(stats.types.len(), 5);
+        assert_eq!(stats.ancillary, 20 + 15);
+        assert_eq!(stats.largest, Some((2, *b"IDAT", 112)));
+        assert_eq!(stats.after_iend, 15 + 4);
+        assert!(Stats::from_bytes(b"GIF89a").is_err());
+    }
+
+    #[test]
+    fn test_percent() {
+        assert_eq!(percent(1, 8), "12.5%");
+        assert_eq!(percent(0, 0), "0.0%");
+    }
+}
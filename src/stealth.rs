@@ -0,0 +1,64 @@
+#[cfg(feature = "write")]
+use anyhow::{anyhow, Result};
+
+use crate::chunk_type::ChunkType;
+use crate::hmac;
+
+/// Keeps derived types unrelated to other uses of the same password
+const DOMAIN: &[u8] = b"pngme stealth chunk type";
+
+/// Derives chunk type from password, so payload doesn't sit in a well-known custom type
+pub fn chunk_type(password: &str) -> ChunkType {
+    let digest = hmac::hmac_sha256(password.as_bytes(), DOMAIN);
+    private_type([digest[0], digest[1], digest[2], digest[3]])
+}
+
+/// Picks random chunk type, for users who don't want to invent one
+#[cfg(feature = "write")]
+pub fn random_type() -> Result<ChunkType> {
+    let mut seed = [0; 4];
+    getrandom::getrandom(&mut seed).map_err(|e| anyhow!("Failed to get random bytes: {}", e))?;
+    Ok(private_type(seed))
+}
+
+/// Maps arbitrary bytes to letters of ancillary, private and safe to copy
+/// type (`xxXx` letter case), which makes decoders ignore the chunk and editors keep it
+fn private_type(seed: [u8; 4]) -> ChunkType {
+    let letter = |byte: u8| b'a' + byte % 26;
+    let bytes = [
+        letter(seed[0]),
+        letter(seed[1]),
+        letter(seed[2]).to_ascii_uppercase(),
+        letter(seed[3]),
+    ];
+    ChunkType::try_from(bytes).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derived_type() {
+        let chunk_type = chunk_type("correct horse");
+        assert_eq!(chunk_type, super::chunk_type("correct horse"));
+        assert_ne!(chunk_type, super::chunk_type("battery staple"));
+
+        assert!(chunk_type.is_valid());
+        assert!(!chunk_type.is_critical());
+        assert!(!chunk_type.is_public());
+        assert!(chunk_type.is_safe_to_copy());
+    }
+
+    #[test]
+    fn test_private_type() {
+        for seed in [[0; 4], [255; 4], [25, 26, 51, 52]] {
+            let chunk_type = private_type(seed);
+            assert!(chunk_type.is_valid());
+            assert!(!chunk_type.is_critical());
+            assert!(!chunk_type.is_public());
+            assert!(chunk_type.is_safe_to_copy());
+        }
+        assert_eq!(private_type([0, 1, 2, 3]).to_string(), "abCd");
+    }
+}
@@ -0,0 +1,340 @@
+use std::fs;
+#[cfg(feature = "write")]
+use std::fs::Permissions;
+#[cfg(feature = "write")]
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "write")]
+use std::time::SystemTime;
+
+#[cfg(feature = "write")]
+use anyhow::bail;
+use anyhow::{Context, Result};
+
+use crate::archive;
+
+/// Backend that commands read images and payloads from and write results to
+pub trait Storage {
+    fn read(&self, path: &Path) -> Result<Vec<u8>>;
+
+    #[cfg(feature = "write")]
+    fn write(&self, path: &Path, data: &[u8]) -> Result<()>;
+
+    #[cfg(feature = "write")]
+    fn exists(&self, path: &Path) -> bool;
+
+    /// Moves file, replacing existing one at `to`
+    #[cfg(feature = "write")]
+    fn rename(&self, from: &Path, to: &Path) -> Result<()>;
+
+    #[cfg(feature = "write")]
+    fn remove(&self, path: &Path) -> Result<()>;
+
+    /// Copies file, replacing existing one at `to`
+    #[cfg(feature = "write")]
+    fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.write(to, &self.read(from)?)
+    }
+
+    /// Makes sure written file is on disk, backends that can't tell do nothing
+    #[cfg(feature = "write")]
+    fn sync(&self, _path: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    /// Modification time of stored file, `None` if backend doesn't keep it
+    #[cfg(feature = "write")]
+    fn modified(&self, _path: &Path) -> Result<Option<SystemTime>> {
+        Ok(None)
+    }
+
+    /// Backends without timestamps silently ignore it
+    #[cfg(feature = "write")]
+    fn set_modified(&self, _path: &Path, _time: SystemTime) -> Result<()> {
+        Ok(())
+    }
+
+    /// Permissions of stored file, `None` if backend doesn't keep them
+    #[cfg(feature = "write")]
+    fn permissions(&self, _path: &Path) -> Result<Option<Permissions>> {
+        Ok(None)
+    }
+
+    #[cfg(feature = "write")]
+    fn set_permissions(&self, _path: &Path, _permissions: Permissions) -> Result<()> {
+        Ok(())
+    }
+
+    /// Replaces end of file from `offset` on with `new`, but only when it is `old`
+    /// exactly. `false` when backend can't do it, then the whole file has to be written
+    #[cfg(feature = "write")]
+    fn replace_tail(&self, _path: &Path, _offset: u64, _old: &[u8], _new: &[u8]) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Whether path is a local file, which trash and journal rely on
+    fn is_local(&self, _path: &Path) -> bool {
+        false
+    }
+}
+
+/// Files on local file system. Files inside zip and tar archives can be read
+/// as `ARCHIVE!ENTRY`, but not written
+pub struct LocalFs;
+
+impl Storage for LocalFs {
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        if let Some((archive, entry)) = in_archive(path) {
+            let bytes = fs::read(&archive)
+                .with_context(|| format!("Failed to read {}", archive.display()))?;
+            return archive::read_entry(&bytes, &entry)
+                .with_context(|| format!("Failed to read {}", path.display()));
+        }
+        fs::read(path).with_context(|| format!("Failed to read {}", path.display()))
+    }
+
+    #[cfg(feature = "write")]
+    fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        if let Some((archive, _)) = in_archive(path) {
+            bail!(
+                "Can't write {}, files inside {} are read-only",
+                path.display(),
+                archive.display()
+            );
+        }
+        fs::write(path, data).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    #[cfg(feature = "write")]
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    #[cfg(feature = "write")]
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        fs::rename(from, to)
+            .with_context(|| format!("Failed to move {} to {}", from.display(), to.display()))
+    }
+
+    #[cfg(feature = "write")]
+    fn remove(&self, path: &Path) -> Result<()> {
+        fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))
+    }
+
+    /// Real copy, not a hard link, so rewriting the end of the original in place
+    /// leaves its backup intact
+    #[cfg(feature = "write")]
+    fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        // a file linked to `to` elsewhere mustn't be overwritten along with it
+        match fs::remove_file(to) {
+            Err(err) if err.kind() != ErrorKind::NotFound => {
+                return Err(err).with_context(|| format!("Failed to remove {}", to.display()))
+            }
+            _ => {}
+        }
+        fs::copy(from, to)
+            .map(|_| ())
+            .with_context(|| format!("Failed to copy {} to {}", from.display(), to.display()))
+    }
+
+    #[cfg(feature = "write")]
+    fn sync(&self, path: &Path) -> Result<()> {
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.sync_all())
+            .with_context(|| format!("Failed to sync {}", path.display()))
+    }
+
+    #[cfg(feature = "write")]
+    fn modified(&self, path: &Path) -> Result<Option<SystemTime>> {
+        if in_archive(path).is_some() {
+            return Ok(None);
+        }
+        Ok(Some(fs::metadata(path)?.modified()?))
+    }
+
+    #[cfg(feature = "write")]
+    fn set_modified(&self, path: &Path, time: SystemTime) -> Result<()> {
+        fs::File::options()
+            .write(true)
+            .open(path)?
+            .set_modified(time)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "write")]
+    fn permissions(&self, path: &Path) -> Result<Option<Permissions>> {
+        if in_archive(path).is_some() {
+            return Ok(None);
+        }
+        Ok(Some(fs::metadata(path)?.permissions()))
+    }
+
+    #[cfg(feature = "write")]
+    fn set_permissions(&self, path: &Path, permissions: Permissions) -> Result<()> {
+        fs::set_permissions(path, permissions)
+            .with_context(|| format!("Failed to set permissions of {}", path.display()))
+    }
+
+    /// Everything but the first `old.len()` bytes is written past the old tail first,
+    /// so an interrupted write leaves a readable image with some trailing data
+    #[cfg(feature = "write")]
+    fn replace_tail(&self, path: &Path, offset: u64, old: &[u8], new: &[u8]) -> Result<bool> {
+        if in_archive(path).is_some() || new.len() < old.len() {
+            return Ok(false);
+        }
+        let mut file = fs::File::options()
+            .read(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        if file.metadata()?.len() != offset + old.len() as u64 {
+            return Ok(false);
+        }
+        let mut current = vec![0; old.len()];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut current)?;
+        if current != old {
+            return Ok(false);
+        }
+
+        let (head, rest) = new.split_at(old.len());
+        let written = file
+            .write_all(rest)
+            .and_then(|()| file.sync_data())
+            .and_then(|()| file.seek(SeekFrom::Start(offset)))
+            .and_then(|_| file.write_all(head))
+            .and_then(|()| file.sync_data());
+        written.with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(true)
+    }
+
+    fn is_local(&self, _path: &Path) -> bool {
+        true
+    }
+}
+
+/// Archive and entry name, when path is `ARCHIVE!ENTRY` rather than a plain file
+fn in_archive(path: &Path) -> Option<(PathBuf, String)> {
+    if path.exists() {
+        return None;
+    }
+    archive::split(path)
+}
+
+#[cfg(all(test, feature = "write"))]
+pub mod memory {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+
+    use anyhow::{Context, Result};
+
+    use super::Storage;
+
+    /// Files kept in memory, for running commands without touching the disk
+    #[derive(Default)]
+    pub struct MemoryStorage {
+        files: RefCell<HashMap<PathBuf, Vec<u8>>>,
+    }
+
+    impl Storage for MemoryStorage {
+        fn read(&self, path: &Path) -> Result<Vec<u8>> {
+            self.files
+                .borrow()
+                .get(path)
+                .cloned()
+                .with_context(|| format!("No such file: {}", path.display()))
+        }
+
+        #[cfg(feature = "write")]
+        fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+            self.files
+                .borrow_mut()
+                .insert(path.to_path_buf(), data.to_vec());
+            Ok(())
+        }
+
+        #[cfg(feature = "write")]
+        fn exists(&self, path: &Path) -> bool {
+            self.files.borrow().contains_key(path)
+        }
+
+        #[cfg(feature = "write")]
+        fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+            let data = self.read(from)?;
+            let mut files = self.files.borrow_mut();
+            files.remove(from);
+            files.insert(to.to_path_buf(), data);
+            Ok(())
+        }
+
+        #[cfg(feature = "write")]
+        fn remove(&self, path: &Path) -> Result<()> {
+            self.files
+                .borrow_mut()
+                .remove(path)
+                .map(|_| ())
+                .with_context(|| format!("No such file: {}", path.display()))
+        }
+    }
+}
+
+#[cfg(all(test, feature = "write"))]
+mod tests {
+    use super::memory::MemoryStorage;
+    use super::*;
+
+    #[test]
+    fn test_memory_storage() {
+        let storage = MemoryStorage::default();
+        let path = Path::new("dice.png");
+        assert!(!storage.exists(path));
+        assert!(storage.read(path).is_err());
+
+        storage.write(path, b"png").unwrap();
+        assert!(storage.exists(path));
+        assert_eq!(storage.read(path).unwrap(), b"png");
+        assert_eq!(storage.modified(path).unwrap(), None);
+        assert!(!storage.replace_tail(path, 0, b"png", b"png!").unwrap());
+    }
+
+    #[test]
+    fn test_replace_tail() {
+        let path = std::env::temp_dir().join(format!("pngme-tail-{}.png", std::process::id()));
+        fs::write(&path, b"head|end").unwrap();
+        assert!(!LocalFs.replace_tail(&path, 5, b"END", b"new|end").unwrap());
+        assert!(!LocalFs.replace_tail(&path, 4, b"end", b"new|end").unwrap());
+        assert_eq!(fs::read(&path).unwrap(), b"head|end");
+
+        assert!(LocalFs.replace_tail(&path, 5, b"end", b"new|end").unwrap());
+        assert_eq!(fs::read(&path).unwrap(), b"head|new|end");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_local_copy() {
+        let dir = std::env::temp_dir();
+        let from = dir.join(format!("pngme-copy-{}.png", std::process::id()));
+        let to = dir.join(format!("pngme-copy-{}.bak", std::process::id()));
+        let new = dir.join(format!("pngme-copy-{}.new", std::process::id()));
+        fs::write(&from, b"original").unwrap();
+        fs::write(&to, b"stale").unwrap();
+
+        LocalFs.copy(&from, &to).unwrap();
+        LocalFs.sync(&to).unwrap();
+        assert_eq!(fs::read(&to).unwrap(), b"original");
+        // replacing source by rename leaves the copy as it was
+        fs::write(&new, b"new").unwrap();
+        fs::rename(&new, &from).unwrap();
+        assert_eq!(fs::read(&to).unwrap(), b"original");
+        // and so does rewriting its end in place
+        LocalFs.copy(&from, &to).unwrap();
+        assert!(LocalFs.replace_tail(&from, 1, b"ew", b"ext").unwrap());
+        assert_eq!(fs::read(&from).unwrap(), b"next");
+        assert_eq!(fs::read(&to).unwrap(), b"new");
+        fs::remove_file(&from).unwrap();
+        fs::remove_file(&to).unwrap();
+    }
+}
@@ -0,0 +1,89 @@
+#[cfg(feature = "write")]
+use std::str::FromStr;
+
+#[cfg(feature = "write")]
+use anyhow::{Context, Result};
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+
+#[cfg(feature = "write")]
+use crate::chunk::Chunk;
+#[cfg(feature = "write")]
+use crate::chunk_type::ChunkType;
+#[cfg(feature = "write")]
+use crate::json::Fields;
+use crate::json::{json, Map, Value};
+use crate::png::Png;
+
+/// Describes every chunk of image, data is base64 encoded.
+/// CRCs are left out, they are computed again when image is built.
+/// Offsets of parsed chunks are informational and ignored by [`from_json`]
+pub fn to_json(png: &Png) -> Value {
+    let chunks = png
+        .chunks()
+        .iter()
+        .map(|chunk| {
+            let mut fields = Map::new();
+            fields.insert("type".into(), chunk.chunk_type().to_string().into());
+            fields.insert("data".into(), BASE64_STANDARD.encode(chunk.data()).into());
+            if let Some(offset) = chunk.offset() {
+                fields.insert("offset".into(), offset.into());
+            }
+            Value::Object(fields)
+        })
+        .collect::<Vec<_>>();
+    json!({ "chunks": chunks })
+}
+
+/// Builds image from description made by [`to_json`]
+#[cfg(feature = "write")]
+pub fn from_json(value: &Value) -> Result<Png> {
+    let chunks = value
+        .array_field("chunks")?
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let build = || -> Result<Chunk> {
+                let chunk_type = ChunkType::from_str(chunk.str_field("type")?)?;
+                let data = BASE64_STANDARD
+                    .decode(chunk.str_field("data")?.trim())
+                    .context("Invalid base64")?;
+                Ok(Chunk::new(chunk_type, data))
+            };
+            build().with_context(|| format!("Invalid chunk #{}", i))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Png::from_chunks(chunks))
+}
+
+#[cfg(all(test, feature = "write"))]
+mod tests {
+    use super::*;
+    use crate::json;
+
+    #[test]
+    fn test_roundtrip() {
+        let png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hi".to_vec()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ]);
+        let json = to_json(&png);
+        assert_eq!(
+            json.get("chunks").unwrap().as_array().unwrap()[1].to_string(),
+            r#"{"type":"ruSt","data":"aGk="}"#
+        );
+        let text = json::pretty(&json);
+        assert_eq!(
+            from_json(&json::parse(&text).unwrap()).unwrap().as_bytes(),
+            png.as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_from_json_invalid() {
+        let parse = |text: &str| from_json(&json::parse(text).unwrap());
+        assert!(parse(r#"{}"#).is_err());
+        assert!(parse(r#"{"chunks":[{"type":"ru","data":""}]}"#).is_err());
+        assert!(parse(r#"{"chunks":[{"type":"ruSt","data":"!"}]}"#).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::chunk_type::ChunkType;
+use crate::json::{json, Value};
+use crate::registry;
+use crate::stats::Stats;
+
+/// How often one chunk type appears across surveyed files
+#[derive(Debug, Eq, PartialEq)]
+pub struct TypeSurvey {
+    pub chunk_type: [u8; 4],
+    /// Files having at least one chunk of the type
+    pub files: usize,
+    pub count: usize,
+    /// Whole chunks: length, type, data and CRC
+    pub bytes: usize,
+}
+
+/// Chunk types found in many files at once
+#[derive(Debug, Default)]
+pub struct Survey {
+    pub files: usize,
+    /// Per chunk type, largest total first once [`Survey::finish`] is called
+    pub types: Vec<TypeSurvey>,
+    /// Files with chunk types outside of PNG and APNG specifications
+    pub nonstandard: Vec<(PathBuf, Vec<[u8; 4]>)>,
+    /// Files that are not PNG, with the reason
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+impl Survey {
+    /// Counts chunks of one file, files that fail to parse are only recorded as failed
+    pub fn add(&mut self, path: &Path, bytes: &[u8]) {
+        self.add_stats(path, Stats::from_bytes(bytes));
+    }
+
+    /// Counts chunks of one file from its statistics, e.g. from chunk headers alone
+    pub fn add_stats(&mut self, path: &Path, stats: Result<Stats>) {
+        let stats = match stats {
+            Ok(stats) => stats,
+            Err(err) => {
+                self.failed.push((path.to_path_buf(), format!("{:#}", err)));
+                return;
+            }
+        };
+        self.files += 1;
+        let mut nonstandard = vec![];
+        for t in stats.types {
+            match self.types.iter_mut().find(|s| s.chunk_type == t.chunk_type) {
+                Some(survey) => {
+                    survey.files += 1;
+                    survey.count += t.count;
+                    survey.bytes += t.bytes;
+                }
+                None => self.types.push(TypeSurvey {
+                    chunk_type: t.chunk_type,
+                    files: 1,
+                    count: t.count,
+                    bytes: t.bytes,
+                }),
+            }
+            if !is_standard(t.chunk_type) {
+                nonstandard.push(t.chunk_type);
+            }
+        }
+        if !nonstandard.is_empty() {
+            nonstandard.sort();
+            self.nonstandard.push((path.to_path_buf(), nonstandard));
+        }
+    }
+
+    pub fn finish(&mut self) {
+        self.types
+            .sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.chunk_type.cmp(&b.chunk_type)));
+    }
+
+    pub fn to_json(&self) -> Value {
+        let name = |t: &[u8; 4]| String::from_utf8_lossy(t).into_owned();
+        let types: Vec<_> = self
+            .types
+            .iter()
+            .map(|t| {
+                json!({
+                    "type": name(&t.chunk_type),
+                    "standard": is_standard(t.chunk_type),
+                    "files": t.files,
+                    "count": t.count,
+                    "bytes": t.bytes,
+                })
+            })
+            .collect();
+        let nonstandard: Vec<_> = self
+            .nonstandard
+            .iter()
+            .map(|(p, types)| {
+                let types: Vec<_> = types.iter().map(name).collect();
+                json!({ "path": p.display().to_string(), "types": types })
+            })
+            .collect();
+        let failed: Vec<_> = self
+            .failed
+            .iter()
+            .map(|(p, error)| json!({ "path": p.display().to_string(), "error": error }))
+            .collect();
+        json!({
+            "files": self.files,
+            "types": types,
+            "nonstandard": nonstandard,
+            "failed": failed,
+        })
+    }
+}
+
+/// Type is defined by PNG or APNG specification
+pub fn is_standard(chunk_type: [u8; 4]) -> bool {
+    ChunkType::try_from(chunk_type).is_ok_and(|t| registry::name_of(&t).is_some())
+}
+
+/// Paths of all `.png` files in directory and its subdirectories, sorted.
+/// Symbolic links to directories are not followed, so loops can't happen
+pub fn find_pngs(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in
+            fs::read_dir(&dir).with_context(|| format!("Failed to read {}", dir.display()))?
+        {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                dirs.push(path);
+            } else if path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("png"))
+                && path.is_file()
+            {
+                paths.push(path);
+            }
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::json::Fields;
+    use crate::png::Png;
+
+    fn png(types: &[&str]) -> Vec<u8> {
+        let chunks = types
+            .iter()
+            .map(|t| Chunk::new(ChunkType::from_str(t).unwrap(), vec![0; 4]))
+            .collect();
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    #[test]
+    fn test_survey() {
+        let mut survey = Survey::default();
+        survey.add(Path::new("a.png"), &png(&["IHDR", "IDAT", "IDAT", "IEND"]));
+        survey.add(Path::new("b.png"), &png(&["IHDR", "ruSt", "IDAT", "IEND"]));
+        survey.add(Path::new("c.png"), b"GIF89a");
+        survey.finish();
+
+        assert_eq!(survey.files, 2);
+        assert_eq!(
+            survey.types[0],
+            TypeSurvey {
+                chunk_type: *b"IDAT",
+                files: 2,
+                count: 3,
+                bytes: 48,
+            }
+        );
+        assert_eq!(survey.types.len(), 4);
+        assert_eq!(
+            survey.nonstandard,
+            [(PathBuf::from("b.png"), vec![*b"ruSt"])]
+        );
+        assert_eq!(survey.failed.len(), 1);
+
+        let json = survey.to_json();
+        assert_eq!(json.get("files").and_then(Value::as_u64), Some(2));
+        let rust = &json.get("types").and_then(Value::as_array).unwrap()[3];
+        assert_eq!(rust.str_field("type").unwrap(), "ruSt");
+        assert_eq!(rust.get("standard"), Some(&Value::Bool(false)));
+    }
+}
@@ -0,0 +1,119 @@
+use std::env;
+use std::io::{self, IsTerminal};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Color {
+    Red,
+    Yellow,
+    Blue,
+    Green,
+}
+
+impl Color {
+    fn code(self) -> &'static str {
+        match self {
+            Color::Red => "31",
+            Color::Green => "32",
+            Color::Yellow => "33",
+            Color::Blue => "34",
+        }
+    }
+}
+
+/// Colors are used only in terminal, unless disabled by flag or `NO_COLOR`
+pub fn use_color(no_color: bool) -> bool {
+    let no_color_env = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    !no_color && !no_color_env && io::stdout().is_terminal()
+}
+
+/// Text table with columns aligned by content width
+pub struct Table {
+    headers: Vec<&'static str>,
+    /// Columns aligned to the right, e.g. numbers
+    right: Vec<usize>,
+    rows: Vec<(Vec<String>, Option<Color>)>,
+}
+
+impl Table {
+    pub fn new(headers: Vec<&'static str>) -> Table {
+        Table {
+            headers,
+            right: vec![],
+            rows: vec![],
+        }
+    }
+
+    pub fn align_right(mut self, column: usize) -> Table {
+        self.right.push(column);
+        self
+    }
+
+    /// Adds row, whole row is colored if color is set
+    pub fn row(&mut self, cells: Vec<String>, color: Option<Color>) {
+        self.rows.push((cells, color));
+    }
+
+    pub fn render(&self, color: bool) -> String {
+        let mut widths: Vec<_> = self.headers.iter().map(|h| h.chars().count()).collect();
+        for (cells, _) in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(cells) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let headers: Vec<_> = self.headers.iter().map(|h| h.to_string()).collect();
+        let mut lines = vec![self.line(&headers, &widths)];
+        for (cells, row_color) in &self.rows {
+            let line = self.line(cells, &widths);
+            lines.push(match (color, row_color) {
+                (true, Some(c)) => format!("\x1b[{}m{}\x1b[0m", c.code(), line),
+                _ => line,
+            });
+        }
+        lines.join("\n") + "\n"
+    }
+
+    fn line(&self, cells: &[String], widths: &[usize]) -> String {
+        let cells: Vec<_> = cells
+            .iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(i, (cell, &width))| match self.right.contains(&i) {
+                true => format!("{:>width$}", cell),
+                false => format!("{:<width$}", cell),
+            })
+            .collect();
+        cells.join("  ").trim_end().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Table {
+        let mut table = Table::new(vec!["#", "TYPE", "SIZE"]).align_right(2);
+        table.row(
+            vec!["0".into(), "IHDR".into(), "13".into()],
+            Some(Color::Blue),
+        );
+        table.row(vec!["1".into(), "ruSt".into(), "1024".into()], None);
+        table
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            table().render(false),
+            "#  TYPE  SIZE\n0  IHDR    13\n1  ruSt  1024\n"
+        );
+    }
+
+    #[test]
+    fn test_render_color() {
+        let rendered = table().render(true);
+        let lines: Vec<_> = rendered.lines().collect();
+        assert_eq!(lines[1], "\x1b[34m0  IHDR    13\x1b[0m");
+        assert_eq!(lines[2], "1  ruSt  1024");
+    }
+}
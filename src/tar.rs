@@ -0,0 +1,50 @@
+use ::tar::{Builder, Header};
+use anyhow::{Context, Result};
+
+/// Builds uncompressed ustar archive with regular files
+pub fn archive(entries: &[(&str, &[u8])], mtime: u64) -> Result<Vec<u8>> {
+    let mut builder = Builder::new(vec![]);
+    for (name, content) in entries {
+        let mut header = Header::new_ustar();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        builder
+            .append_data(&mut header, name, *content)
+            .with_context(|| format!("Failed to add {} to archive", name))?;
+    }
+    builder.into_inner().context("Failed to finish archive")
+}
+
+#[cfg(test)]
+mod tests {
+    use ::tar::Archive;
+
+    use super::*;
+
+    #[test]
+    fn test_archive() {
+        let tar = archive(&[("report.txt", b"hello"), ("empty", b"")], 1_700_000_000).unwrap();
+        let mut archive = Archive::new(tar.as_slice());
+        let entries: Vec<_> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let mut content = vec![];
+                std::io::Read::read_to_end(&mut entry, &mut content).unwrap();
+                let header = entry.header();
+                assert_eq!(header.mtime().unwrap(), 1_700_000_000);
+                assert_eq!(header.mode().unwrap(), 0o644);
+                (entry.path().unwrap().display().to_string(), content)
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("report.txt".to_string(), b"hello".to_vec()),
+                ("empty".to_string(), vec![])
+            ]
+        );
+    }
+}
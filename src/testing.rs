@@ -0,0 +1,242 @@
+//! Helpers for integration tests of code using pngme: deterministic images
+//! built from chunk specs and chunk-level comparison with readable diffs.
+//! Available with the `testing` feature
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::str::FromStr;
+
+use anyhow::{bail, ensure, Result};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+
+pub const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// Builds image of given size with IHDR, IDAT of black 8-bit RGBA pixels and IEND,
+/// added chunks go between IDAT and IEND in order they are given.
+/// Same specs always give byte-identical output
+#[derive(Clone, Debug)]
+pub struct TestPng {
+    width: u32,
+    height: u32,
+    chunks: Vec<Chunk>,
+}
+
+impl TestPng {
+    pub fn new(width: u32, height: u32) -> TestPng {
+        TestPng {
+            width,
+            height,
+            chunks: Vec::new(),
+        }
+    }
+
+    /// Adds chunk of `chunk_type`, e.g. "ruSt"
+    ///
+    /// # Panics
+    /// If `chunk_type` is not a valid chunk type
+    pub fn chunk(mut self, chunk_type: &str, data: impl Into<Vec<u8>>) -> TestPng {
+        let chunk_type = ChunkType::from_str(chunk_type).expect("invalid chunk type");
+        self.chunks.push(Chunk::new(chunk_type, data.into()));
+        self
+    }
+
+    /// Adds `tEXt` chunk with Latin-1 `text` under `keyword`
+    pub fn text(self, keyword: &str, text: &str) -> TestPng {
+        let mut data = Vec::from(keyword.as_bytes());
+        data.push(0);
+        data.extend_from_slice(text.as_bytes());
+        self.chunk("tEXt", data)
+    }
+
+    pub fn to_chunks(&self) -> Vec<Chunk> {
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&self.width.to_be_bytes());
+        ihdr.extend_from_slice(&self.height.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+        let row = 1 + self.width as usize * 4;
+        let pixels = alloc::vec![0; row * self.height as usize];
+
+        let mut chunks = Vec::with_capacity(self.chunks.len() + 3);
+        chunks.push(Chunk::new(ChunkType::try_from(*b"IHDR").unwrap(), ihdr));
+        chunks.push(Chunk::new(
+            ChunkType::try_from(*b"IDAT").unwrap(),
+            zlib_stored(&pixels),
+        ));
+        chunks.extend(self.chunks.iter().cloned());
+        chunks.push(Chunk::new(
+            ChunkType::try_from(*b"IEND").unwrap(),
+            Vec::new(),
+        ));
+        chunks
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        SIGNATURE
+            .iter()
+            .copied()
+            .chain(self.to_chunks().iter().flat_map(|c| c.bytes_iter()))
+            .collect()
+    }
+}
+
+/// zlib stream of uncompressed deflate blocks, valid for any decoder
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::from([0x78, 0x01]);
+    let mut blocks = data.chunks(u16::MAX as usize).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    out.extend_from_slice(&(b << 16 | a).to_be_bytes());
+    out
+}
+
+/// Chunks of PNG file, failing on bad signature, corrupted or truncated chunk
+pub fn parse_chunks(bytes: &[u8]) -> Result<Vec<Chunk>> {
+    ensure!(bytes.starts_with(&SIGNATURE), "Missing PNG signature");
+    let mut chunks = Vec::new();
+    let mut offset = SIGNATURE.len();
+    while offset < bytes.len() {
+        let Some((chunk, size)) = Chunk::parse(&bytes[offset..])? else {
+            bail!("Truncated chunk at offset {}", offset);
+        };
+        chunks.push(chunk);
+        offset += size;
+    }
+    Ok(chunks)
+}
+
+/// Line per chunk that differs, marked with `-` when only in `expected`,
+/// `+` when only in `actual` and `~` with first differing data byte
+/// when chunk of the same type has other data. `None` if chunks are equal
+pub fn diff_chunks(actual: &[Chunk], expected: &[Chunk]) -> Option<String> {
+    let mut diff = String::new();
+    for i in 0..actual.len().max(expected.len()) {
+        let line = match (actual.get(i), expected.get(i)) {
+            (Some(a), Some(e)) if a == e => continue,
+            (Some(a), Some(e)) if a.chunk_type() == e.chunk_type() => {
+                let at = a
+                    .data()
+                    .iter()
+                    .zip(e.data())
+                    .position(|(a, e)| a != e)
+                    .unwrap_or(a.data().len().min(e.data().len()));
+                format!(
+                    "~ {} {}: {} bytes, expected {}, first difference at byte {}",
+                    i,
+                    a.chunk_type(),
+                    a.data().len(),
+                    e.data().len(),
+                    at
+                )
+            }
+            (a, e) => {
+                let mut line = String::new();
+                if let Some(e) = e {
+                    writeln!(
+                        line,
+                        "- {} {} ({} bytes)",
+                        i,
+                        e.chunk_type(),
+                        e.data().len()
+                    )
+                    .ok();
+                }
+                if let Some(a) = a {
+                    write!(
+                        line,
+                        "+ {} {} ({} bytes)",
+                        i,
+                        a.chunk_type(),
+                        a.data().len()
+                    )
+                    .ok();
+                }
+                String::from(line.trim_end())
+            }
+        };
+        writeln!(diff, "{}", line).ok();
+    }
+    Some(diff).filter(|d| !d.is_empty())
+}
+
+/// Panics with chunk diff unless both files hold the same chunks.
+/// Bytes outside chunks (signature, data after IEND) aren't compared
+#[track_caller]
+pub fn assert_chunks_eq(actual: &[u8], expected: &[u8]) {
+    let parse = |bytes, name| match parse_chunks(bytes) {
+        Ok(chunks) => chunks,
+        Err(err) => panic!("{} is not a valid PNG: {}", name, err),
+    };
+    let (actual, expected) = (parse(actual, "actual"), parse(expected, "expected"));
+    if let Some(diff) = diff_chunks(&actual, &expected) {
+        panic!("chunks differ (- expected, + actual):\n{}", diff);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let png = TestPng::new(3, 2).text("Title", "dice").chunk("ruSt", "hi");
+        let bytes = png.to_bytes();
+        assert_eq!(bytes, png.clone().to_bytes());
+        let chunks = parse_chunks(&bytes).unwrap();
+        let types: Vec<_> = chunks.iter().map(|c| c.chunk_type().bytes()).collect();
+        assert_eq!(types, [*b"IHDR", *b"IDAT", *b"tEXt", *b"ruSt", *b"IEND"]);
+        assert_eq!(chunks[2].data(), b"Title\0dice");
+        assert_eq!(chunks, png.to_chunks());
+        assert!(parse_chunks(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_zlib_stored() {
+        assert_eq!(zlib_stored(b""), [0x78, 1, 1, 0, 0, 0xff, 0xff, 0, 0, 0, 1]);
+        let data = [7; 70000];
+        let stream = zlib_stored(&data);
+        assert_eq!(stream.len(), 2 + 5 * 2 + data.len() + 4);
+        assert_eq!(stream[2], 0);
+        assert_eq!(stream[2 + 5 + 65535], 1);
+    }
+
+    #[test]
+    fn test_diff() {
+        let base = TestPng::new(1, 1).chunk("ruSt", "abc");
+        let same = base.to_bytes();
+        assert_chunks_eq(&same, &base.to_bytes());
+        assert_eq!(diff_chunks(&base.to_chunks(), &base.to_chunks()), None);
+
+        let changed = TestPng::new(1, 1).chunk("ruSt", "abd").chunk("teSt", "");
+        let diff = diff_chunks(&changed.to_chunks(), &base.to_chunks()).unwrap();
+        assert_eq!(
+            diff,
+            "~ 2 ruSt: 3 bytes, expected 3, first difference at byte 2\n\
+             - 3 IEND (0 bytes)\n+ 3 teSt (0 bytes)\n\
+             + 4 IEND (0 bytes)\n"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "chunks differ")]
+    fn test_assert_fails() {
+        let png = TestPng::new(1, 1);
+        assert_chunks_eq(&png.to_bytes(), &png.text("a", "b").to_bytes());
+    }
+}
@@ -0,0 +1,510 @@
+use std::str::FromStr;
+
+#[cfg(feature = "write")]
+use anyhow::anyhow;
+use anyhow::{bail, ensure, Context, Error, Result};
+
+use crate::chunk::Chunk;
+#[cfg(feature = "write")]
+use crate::deflate;
+use crate::inflate;
+use crate::json::{json, Value};
+
+/// Compressed text is not expected to be larger, limit protects from zip bombs
+const MAX_TEXT_SIZE: usize = 16 * 1024 * 1024;
+
+/// Keywords registered in PNG specification, paired with names used in `meta` options
+pub const STANDARD_KEYWORDS: &[(&str, &str)] = &[
+    ("title", "Title"),
+    ("author", "Author"),
+    ("description", "Description"),
+    ("copyright", "Copyright"),
+    ("creation-time", "Creation Time"),
+    ("software", "Software"),
+    ("disclaimer", "Disclaimer"),
+    ("warning", "Warning"),
+    ("source", "Source"),
+    ("comment", "Comment"),
+];
+
+/// Keyword and text of a text chunk. Both are Latin-1 in `tEXt` and `zTXt`,
+/// while `iTXt` has UTF-8 text along with a few more fields
+#[derive(Debug, Eq, PartialEq)]
+pub struct TextEntry {
+    pub keyword: String,
+    pub text: String,
+    /// Whether text is zlib-compressed, as in `zTXt` or compressed `iTXt`
+    pub compressed: bool,
+    /// Set for `iTXt` chunks
+    pub international: Option<International>,
+}
+
+/// Fields that only `iTXt` chunk has
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct International {
+    /// Language tag like `en-US`, empty if unknown
+    pub language: String,
+    /// Keyword translated into that language
+    pub translated_keyword: String,
+}
+
+/// Chunk type that text is stored in
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TextFormat {
+    /// `tEXt`
+    Plain,
+    /// `zTXt`
+    Compressed,
+    /// `iTXt`, compressed or not
+    International { compressed: bool },
+}
+
+impl TextFormat {
+    pub fn chunk_type(self) -> [u8; 4] {
+        match self {
+            TextFormat::Plain => *b"tEXt",
+            TextFormat::Compressed => *b"zTXt",
+            TextFormat::International { .. } => *b"iTXt",
+        }
+    }
+}
+
+impl FromStr for TextFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(TextFormat::Plain),
+            "ztxt" => Ok(TextFormat::Compressed),
+            "itxt" => Ok(TextFormat::International { compressed: false }),
+            "itxt-compressed" => Ok(TextFormat::International { compressed: true }),
+            _ => bail!(
+                "Unknown text format '{}', expected text, ztxt, itxt or itxt-compressed",
+                s
+            ),
+        }
+    }
+}
+
+impl TextEntry {
+    #[cfg(feature = "write")]
+    pub fn new(keyword: &str, text: &str) -> Result<TextEntry> {
+        validate_keyword(keyword)?;
+        latin1(text).with_context(|| format!("Text of {} keyword", keyword))?;
+        Ok(TextEntry {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+            compressed: false,
+            international: None,
+        })
+    }
+
+    /// Entry of `iTXt` chunk, text can be any Unicode
+    #[cfg(feature = "write")]
+    pub fn international(
+        keyword: &str,
+        text: &str,
+        international: International,
+    ) -> Result<TextEntry> {
+        validate_keyword(keyword)?;
+        validate_language(&international.language)?;
+        Ok(TextEntry {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+            compressed: false,
+            international: Some(international),
+        })
+    }
+
+    /// Parses `tEXt`, `zTXt` or `iTXt` chunk
+    pub fn from_chunk(chunk: &Chunk) -> Result<TextEntry> {
+        match &chunk.chunk_type().bytes() {
+            b"tEXt" => TextEntry::from_data(chunk.data()),
+            b"zTXt" => TextEntry::from_ztxt(chunk.data()),
+            b"iTXt" => TextEntry::from_itxt(chunk.data()),
+            _ => bail!("{} is not a text chunk", chunk.chunk_type()),
+        }
+    }
+
+    /// Parses data of `tEXt` chunk
+    pub fn from_data(data: &[u8]) -> Result<TextEntry> {
+        let separator = data
+            .iter()
+            .position(|&b| b == 0)
+            .context("Text chunk has no keyword separator")?;
+        let keyword: String = data[..separator].iter().map(|&b| b as char).collect();
+        validate_keyword(&keyword)?;
+        let text = data[separator + 1..].iter().map(|&b| b as char).collect();
+        Ok(TextEntry {
+            keyword,
+            text,
+            compressed: false,
+            international: None,
+        })
+    }
+
+    /// Parses data of `zTXt` chunk: keyword, null separator,
+    /// compression method and compressed Latin-1 text
+    pub fn from_ztxt(data: &[u8]) -> Result<TextEntry> {
+        let separator = data
+            .iter()
+            .position(|&b| b == 0)
+            .context("zTXt chunk has no keyword separator")?;
+        let keyword: String = data[..separator].iter().map(|&b| b as char).collect();
+        validate_keyword(&keyword)?;
+        let method = *data.get(separator + 1).context("zTXt chunk is truncated")?;
+        ensure!(method == 0, "Unknown zTXt compression method {}", method);
+        let text = inflate::zlib_decompress(&data[separator + 2..], MAX_TEXT_SIZE)?;
+        Ok(TextEntry {
+            keyword,
+            text: text.iter().map(|&b| b as char).collect(),
+            compressed: true,
+            international: None,
+        })
+    }
+
+    /// Parses data of `iTXt` chunk: keyword, compression flag and method,
+    /// language tag, translated keyword and text, with null separators
+    pub fn from_itxt(data: &[u8]) -> Result<TextEntry> {
+        let mut fields = data.splitn(2, |&b| b == 0);
+        let keyword: String = fields.next().unwrap().iter().map(|&b| b as char).collect();
+        validate_keyword(&keyword)?;
+        let rest = fields
+            .next()
+            .context("iTXt chunk has no keyword separator")?;
+        let [flag, method, rest @ ..] = rest else {
+            bail!("iTXt chunk is truncated");
+        };
+        let mut fields = rest.splitn(3, |&b| b == 0);
+        let language = fields.next().unwrap();
+        let translated_keyword = fields.next().context("iTXt chunk is truncated")?;
+        let text = fields.next().context("iTXt chunk is truncated")?;
+
+        let compressed = match (flag, method) {
+            (0, _) => false,
+            (1, 0) => true,
+            (1, _) => bail!("Unknown iTXt compression method {}", method),
+            _ => bail!("Invalid iTXt compression flag {}", flag),
+        };
+        let text = match compressed {
+            true => inflate::zlib_decompress(text, MAX_TEXT_SIZE)?,
+            false => text.to_vec(),
+        };
+        let international = International {
+            language: String::from_utf8(language.to_vec()).context("Invalid iTXt language")?,
+            translated_keyword: String::from_utf8(translated_keyword.to_vec())
+                .context("iTXt translated keyword is not valid UTF-8")?,
+        };
+        validate_language(&international.language)?;
+        Ok(TextEntry {
+            keyword,
+            text: String::from_utf8(text).context("iTXt text is not valid UTF-8")?,
+            compressed,
+            international: Some(international),
+        })
+    }
+
+    pub fn format(&self) -> TextFormat {
+        match (&self.international, self.compressed) {
+            (Some(_), compressed) => TextFormat::International { compressed },
+            (None, true) => TextFormat::Compressed,
+            (None, false) => TextFormat::Plain,
+        }
+    }
+
+    /// Type of chunk that entry is stored in
+    pub fn chunk_type(&self) -> [u8; 4] {
+        self.format().chunk_type()
+    }
+
+    /// Same entry stored in another format. Language and translated keyword
+    /// are lost when converting from `iTXt`, whose text then must be Latin-1
+    #[cfg(feature = "write")]
+    pub fn convert(self, format: TextFormat) -> Result<TextEntry> {
+        if let TextFormat::International { compressed } = format {
+            return Ok(TextEntry {
+                compressed,
+                international: Some(self.international.unwrap_or_default()),
+                ..self
+            });
+        }
+        latin1(&self.text).with_context(|| format!("Text of {} keyword", self.keyword))?;
+        Ok(TextEntry {
+            compressed: format == TextFormat::Compressed,
+            international: None,
+            ..self
+        })
+    }
+
+    #[cfg(feature = "write")]
+    pub fn to_data(&self) -> Vec<u8> {
+        let mut data = latin1(&self.keyword).unwrap();
+        data.push(0);
+        let text = match &self.international {
+            Some(international) => {
+                data.extend([self.compressed as u8, 0]);
+                data.extend(international.language.as_bytes());
+                data.push(0);
+                data.extend(international.translated_keyword.as_bytes());
+                data.push(0);
+                self.text.as_bytes().to_vec()
+            }
+            None => {
+                if self.compressed {
+                    data.push(0);
+                }
+                latin1(&self.text).unwrap()
+            }
+        };
+        match self.compressed {
+            true => data.extend(deflate::zlib_compress(&text)),
+            false => data.extend(text),
+        }
+        data
+    }
+
+    /// Language tag of `iTXt` entry, empty for other chunks or unknown language
+    pub fn language(&self) -> &str {
+        self.international
+            .as_ref()
+            .map_or("", |i| i.language.as_str())
+    }
+
+    /// Whether entry is in language `tag` or its variant, so `de` matches `de-CH`.
+    /// Tags are compared ignoring case
+    pub fn matches_language(&self, tag: &str) -> bool {
+        let language = self.language().as_bytes();
+        language.len() >= tag.len()
+            && language[..tag.len()].eq_ignore_ascii_case(tag.as_bytes())
+            && matches!(language.get(tag.len()), None | Some(b'-'))
+    }
+
+    pub fn to_json(&self) -> Value {
+        let international = self.international.clone().unwrap_or_default();
+        json!({
+            "keyword": self.keyword,
+            "format": String::from_utf8_lossy(&self.chunk_type()),
+            "compressed": self.compressed,
+            "language": international.language,
+            "translated-keyword": international.translated_keyword,
+            "text": self.text,
+        })
+    }
+
+    /// Keyword along with language and translated keyword if there are any
+    pub fn label(&self) -> String {
+        let details: Vec<_> = self
+            .international
+            .iter()
+            .flat_map(|i| [&i.language, &i.translated_keyword])
+            .filter(|s| !s.is_empty())
+            .map(String::as_str)
+            .collect();
+        match details.is_empty() {
+            true => self.keyword.clone(),
+            false => format!("{} ({})", self.keyword, details.join(", ")),
+        }
+    }
+}
+
+/// Resolves `meta` option name (e.g. `creation-time`) or keyword itself
+pub fn resolve_keyword(name: &str) -> Result<String> {
+    let standard = STANDARD_KEYWORDS
+        .iter()
+        .find(|(option, keyword)| option.eq_ignore_ascii_case(name) || *keyword == name);
+    match standard {
+        Some((_, keyword)) => Ok(keyword.to_string()),
+        None => {
+            validate_keyword(name)?;
+            Ok(name.to_string())
+        }
+    }
+}
+
+/// Keywords are 1-79 printable Latin-1 characters,
+/// without leading, trailing or consecutive spaces
+pub fn validate_keyword(keyword: &str) -> Result<()> {
+    let length = keyword.chars().count();
+    ensure!(
+        (1..=79).contains(&length),
+        "Keyword must be 1-79 characters long, got {}",
+        length
+    );
+    ensure!(
+        !keyword.starts_with(' ') && !keyword.ends_with(' ') && !keyword.contains("  "),
+        "Keyword '{}' can't have leading, trailing or consecutive spaces",
+        keyword
+    );
+    for c in keyword.chars() {
+        let printable = matches!(c as u32, 32..=126 | 161..=255);
+        if !printable {
+            bail!("Keyword '{}' contains invalid character {:?}", keyword, c);
+        }
+    }
+    Ok(())
+}
+
+/// Language tag (RFC 3066): hyphen-separated words of 1-8 ASCII letters or digits.
+/// Empty tag means unknown language
+pub fn validate_language(tag: &str) -> Result<()> {
+    let valid = tag.is_empty()
+        || tag.split('-').all(|word| {
+            (1..=8).contains(&word.len()) && word.chars().all(|c| c.is_ascii_alphanumeric())
+        });
+    ensure!(valid, "Invalid language tag '{}'", tag);
+    Ok(())
+}
+
+/// Texts of all valid `tEXt`, `zTXt` and `iTXt` chunks, in file order
+pub fn entries(chunks: &[Chunk]) -> Vec<TextEntry> {
+    chunks
+        .iter()
+        .filter_map(|c| TextEntry::from_chunk(c).ok())
+        .collect()
+}
+
+#[cfg(feature = "write")]
+fn latin1(s: &str) -> Result<Vec<u8>> {
+    s.chars()
+        .map(|c| u8::try_from(c).map_err(|_| anyhow!("{:?} is not a Latin-1 character", c)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::Fields;
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_entry_roundtrip() {
+        let entry = TextEntry::new("Author", "Zoë").unwrap();
+        let data = entry.to_data();
+        assert_eq!(data, b"Author\0Zo\xeb");
+        assert_eq!(TextEntry::from_data(&data).unwrap(), entry);
+        assert!(TextEntry::from_data(b"no separator").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_itxt_roundtrip() {
+        for compressed in [false, true] {
+            let international = International {
+                language: "ja-JP".to_string(),
+                translated_keyword: "作者".to_string(),
+            };
+            let mut entry =
+                TextEntry::international("Author", "雪".repeat(50).as_str(), international)
+                    .unwrap();
+            entry.compressed = compressed;
+            let data = entry.to_data();
+            assert!(data.starts_with(b"Author\0"));
+            assert_eq!(TextEntry::from_itxt(&data).unwrap(), entry);
+            assert_eq!(entry.label(), "Author (ja-JP, 作者)");
+        }
+        let data = b"Title\0\0\0\0\0Hello";
+        let entry = TextEntry::from_itxt(data).unwrap();
+        assert_eq!(entry.text, "Hello");
+        assert_eq!(entry.label(), "Title");
+        assert_eq!(entry.to_data(), data);
+        assert!(TextEntry::from_itxt(b"Title\0\x01\x00\0\0not zlib").is_err());
+        assert!(TextEntry::from_itxt(b"Title\0\0\0en").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_matches_language() {
+        let entry = |language: &str| International {
+            language: language.to_string(),
+            translated_keyword: String::new(),
+        };
+        let swiss = TextEntry::international("Title", "Grüezi", entry("de-CH")).unwrap();
+        assert!(swiss.matches_language("de"));
+        assert!(swiss.matches_language("DE-ch"));
+        assert!(!swiss.matches_language("de-DE"));
+        assert!(!swiss.matches_language("d"));
+        let english = TextEntry::international("Title", "Hi", entry("en")).unwrap();
+        assert!(!english.matches_language("en-US"));
+        assert!(!TextEntry::new("Title", "Hi")
+            .unwrap()
+            .matches_language("en"));
+
+        let json = swiss.to_json();
+        assert_eq!(json.str_field("format").unwrap(), "iTXt");
+        assert_eq!(json.str_field("language").unwrap(), "de-CH");
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_ztxt_roundtrip() {
+        let entry = TextEntry::new("Comment", &"Zoë ".repeat(100))
+            .unwrap()
+            .convert(TextFormat::Compressed)
+            .unwrap();
+        let data = entry.to_data();
+        assert_eq!(&data[..9], b"Comment\0\0");
+        assert!(data.len() < 100);
+        assert_eq!(TextEntry::from_ztxt(&data).unwrap(), entry);
+        assert!(TextEntry::from_ztxt(b"Comment\0\x01x").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_convert() {
+        let entry = TextEntry::new("Title", "Zoë").unwrap();
+        let itxt = entry
+            .convert(TextFormat::International { compressed: true })
+            .unwrap();
+        assert_eq!(&itxt.chunk_type(), b"iTXt");
+        assert_eq!(TextEntry::from_itxt(&itxt.to_data()).unwrap(), itxt);
+        let text = itxt.convert(TextFormat::Plain).unwrap();
+        assert_eq!(text, TextEntry::new("Title", "Zoë").unwrap());
+
+        let snow = TextEntry::international("Title", "雪", International::default()).unwrap();
+        assert!(snow.convert(TextFormat::Compressed).is_err());
+        assert_eq!(
+            "zTXt".parse::<TextFormat>().unwrap(),
+            TextFormat::Compressed
+        );
+        assert!("xTXt".parse::<TextFormat>().is_err());
+    }
+
+    #[test]
+    fn test_language_validation() {
+        assert!(validate_language("").is_ok());
+        assert!(validate_language("en-US").is_ok());
+        assert!(validate_language("x-klingon").is_ok());
+        assert!(validate_language("en_US").is_err());
+        assert!(validate_language("english-language").is_ok());
+        assert!(validate_language("englishes-us").is_err());
+        assert!(validate_language("en--us").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_keyword_validation() {
+        assert!(validate_keyword("Creation Time").is_ok());
+        assert!(validate_keyword("Légende").is_ok());
+        for keyword in [
+            "",
+            " Title",
+            "Title ",
+            "Two  spaces",
+            "Tab\there",
+            "Emoji 😀",
+        ] {
+            assert!(validate_keyword(keyword).is_err(), "{}", keyword);
+        }
+        assert!(validate_keyword(&"k".repeat(79)).is_ok());
+        assert!(validate_keyword(&"k".repeat(80)).is_err());
+        assert!(TextEntry::new("Title", "雪").is_err());
+    }
+
+    #[test]
+    fn test_resolve_keyword() {
+        assert_eq!(resolve_keyword("creation-time").unwrap(), "Creation Time");
+        assert_eq!(resolve_keyword("Author").unwrap(), "Author");
+        assert_eq!(resolve_keyword("Camera Model").unwrap(), "Camera Model");
+        assert!(resolve_keyword(" bad").is_err());
+    }
+}
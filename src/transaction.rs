@@ -0,0 +1,174 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use tracing::{debug, warn};
+
+use crate::storage::Storage;
+
+/// Writes several files all at once: every file is staged next to its target first,
+/// and staged files are moved in place only after all of them were written.
+/// If anything fails, already replaced files are restored from backups
+pub struct Transaction<'a> {
+    storage: &'a dyn Storage,
+    /// Target paths together with paths of their staged content
+    staged: Vec<(PathBuf, PathBuf)>,
+}
+
+impl<'a> Transaction<'a> {
+    pub fn new(storage: &'a dyn Storage) -> Self {
+        Transaction {
+            storage,
+            staged: vec![],
+        }
+    }
+
+    pub fn stage(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        let staged = sibling(path, "staged");
+        self.storage.write(&staged, data)?;
+        self.storage.sync(&staged)?;
+        debug!("Staged {} as {}", path.display(), staged.display());
+        self.staged.push((path.to_path_buf(), staged));
+        Ok(())
+    }
+
+    pub fn commit(mut self) -> Result<()> {
+        let mut replaced = vec![];
+        for (target, staged) in &self.staged {
+            match self.replace(target, staged) {
+                Ok(backup) => replaced.push((target, backup)),
+                Err(err) => {
+                    warn!("Failed to replace {}, rolling back", target.display());
+                    for (target, backup) in replaced.into_iter().rev() {
+                        // best effort, there is nothing else to do if rollback fails
+                        let _ = match backup {
+                            Some(backup) => self.storage.rename(&backup, target),
+                            None => self.storage.remove(target),
+                        };
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        for backup in replaced.into_iter().filter_map(|(_, backup)| backup) {
+            self.storage.remove(&backup)?;
+        }
+        self.staged.clear();
+        Ok(())
+    }
+
+    /// Moves staged file in place of target, returns backup of replaced target.
+    /// Target is backed up by a copy, so it never goes missing while being replaced
+    fn replace(&self, target: &Path, staged: &Path) -> Result<Option<PathBuf>> {
+        let backup = if self.storage.exists(target) {
+            let backup = sibling(target, "backup");
+            self.storage.copy(target, &backup)?;
+            Some(backup)
+        } else {
+            None
+        };
+
+        if let Err(err) = self.storage.rename(staged, target) {
+            if let Some(backup) = &backup {
+                let _ = self.storage.remove(backup);
+            }
+            return Err(err);
+        }
+        Ok(backup)
+    }
+}
+
+impl Drop for Transaction<'_> {
+    /// Cleans up staged files of transaction that was not committed
+    fn drop(&mut self) {
+        for (_, staged) in &self.staged {
+            let _ = self.storage.remove(staged);
+        }
+    }
+}
+
+/// Hidden file next to `path`, e.g. `dir/.dice.png.pngme-staged`
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.pngme-{}", name, suffix))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::bail;
+
+    use super::*;
+    use crate::storage::memory::MemoryStorage;
+
+    /// Memory storage that refuses to replace one file
+    struct Failing {
+        inner: MemoryStorage,
+        path: PathBuf,
+    }
+
+    impl Storage for Failing {
+        fn read(&self, path: &Path) -> Result<Vec<u8>> {
+            self.inner.read(path)
+        }
+
+        fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+            self.inner.write(path, data)
+        }
+
+        fn exists(&self, path: &Path) -> bool {
+            self.inner.exists(path)
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+            if to == self.path {
+                bail!("Disk is full");
+            }
+            self.inner.rename(from, to)
+        }
+
+        fn remove(&self, path: &Path) -> Result<()> {
+            self.inner.remove(path)
+        }
+    }
+
+    fn storage(fail_on: &str) -> Failing {
+        let storage = Failing {
+            inner: MemoryStorage::default(),
+            path: PathBuf::from(fail_on),
+        };
+        storage.write(Path::new("a.png"), b"old a").unwrap();
+        storage.write(Path::new("b.png"), b"old b").unwrap();
+        storage
+    }
+
+    fn write_all(storage: &dyn Storage) -> Result<()> {
+        let mut transaction = Transaction::new(storage);
+        transaction.stage(Path::new("a.png"), b"new a")?;
+        transaction.stage(Path::new("b.png"), b"new b")?;
+        transaction.stage(Path::new("c.png"), b"new c")?;
+        transaction.commit()
+    }
+
+    #[test]
+    fn test_commit() {
+        let storage = storage("");
+        write_all(&storage).unwrap();
+
+        assert_eq!(storage.read(Path::new("a.png")).unwrap(), b"new a");
+        assert_eq!(storage.read(Path::new("c.png")).unwrap(), b"new c");
+        assert!(!storage.exists(Path::new(".a.png.pngme-backup")));
+        assert!(!storage.exists(Path::new(".a.png.pngme-staged")));
+    }
+
+    #[test]
+    fn test_rollback() {
+        let storage = storage("c.png");
+        assert!(write_all(&storage).is_err());
+
+        assert_eq!(storage.read(Path::new("a.png")).unwrap(), b"old a");
+        assert_eq!(storage.read(Path::new("b.png")).unwrap(), b"old b");
+        assert!(!storage.exists(Path::new("c.png")));
+        assert!(!storage.exists(Path::new(".b.png.pngme-backup")));
+        assert!(!storage.exists(Path::new(".c.png.pngme-staged")));
+    }
+}
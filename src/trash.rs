@@ -0,0 +1,128 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{bail, Context, Result};
+
+use crate::date;
+
+/// Environment variable with directory used instead of OS trash
+pub const TRASH_DIR_VAR: &str = "PNGME_TRASH_DIR";
+
+/// Puts copy of the file into trash, so it can be restored after file is overwritten.
+/// Uses directory from `PNGME_TRASH_DIR` if set, otherwise OS trash
+/// (freedesktop.org trash on Linux, `~/.Trash` on macOS).
+/// Returns path of the trashed copy
+pub fn trash(path: &Path) -> Result<PathBuf> {
+    let path =
+        fs::canonicalize(path).with_context(|| format!("Failed to resolve {}", path.display()))?;
+
+    if let Some(dir) = env::var_os(TRASH_DIR_VAR) {
+        return copy_into(&path, Path::new(&dir));
+    }
+
+    let home = env::var_os("HOME").map(PathBuf::from);
+    if cfg!(target_os = "macos") {
+        let home = home.context("HOME is not set")?;
+        copy_into(&path, &home.join(".Trash"))
+    } else if cfg!(unix) {
+        let data_home = match env::var_os("XDG_DATA_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => home.context("HOME is not set")?.join(".local/share"),
+        };
+        freedesktop_trash(&path, &data_home.join("Trash"))
+    } else {
+        bail!(
+            "OS trash is not supported on this platform, set {} to a trash directory",
+            TRASH_DIR_VAR
+        )
+    }
+}
+
+/// Trash layout from freedesktop.org spec: file goes into `files/`
+/// and its original location with deletion date into `info/<name>.trashinfo`
+fn freedesktop_trash(path: &Path, trash_dir: &Path) -> Result<PathBuf> {
+    let trashed = copy_into(path, &trash_dir.join("files"))?;
+    let info_dir = trash_dir.join("info");
+    fs::create_dir_all(&info_dir)?;
+
+    let mut info_name = trashed.file_name().unwrap().to_os_string();
+    info_name.push(".trashinfo");
+    let info = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(&path.to_string_lossy()),
+        date::format_timestamp(SystemTime::now())
+    );
+    fs::write(info_dir.join(info_name), info)?;
+    Ok(trashed)
+}
+
+fn copy_into(path: &Path, dir: &Path) -> Result<PathBuf> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create trash directory {}", dir.display()))?;
+    let target = unique_name(path, dir);
+    fs::copy(path, &target).with_context(|| format!("Failed to trash {}", path.display()))?;
+    Ok(target)
+}
+
+/// Picks name that doesn't exist in directory yet: `a.png`, `a (2).png`, `a (3).png`...
+fn unique_name(path: &Path, dir: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut candidate = dir.join(format!("{}{}", stem, ext));
+    let mut n = 2;
+    while candidate.exists() {
+        candidate = dir.join(format!("{} ({}){}", stem, n, ext));
+        n += 1;
+    }
+    candidate
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"-_.~/".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percent_encode() {
+        assert_eq!(
+            percent_encode("/home/me/my pic.png"),
+            "/home/me/my%20pic.png"
+        );
+    }
+
+    #[test]
+    fn test_freedesktop_trash() {
+        let dir = env::temp_dir().join("pngme-trash-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("image.png");
+        fs::write(&file, b"original").unwrap();
+
+        let trash_dir = dir.join("Trash");
+        let first = freedesktop_trash(&file, &trash_dir).unwrap();
+        let second = freedesktop_trash(&file, &trash_dir).unwrap();
+
+        assert_eq!(first, trash_dir.join("files/image.png"));
+        assert_eq!(second, trash_dir.join("files/image (2).png"));
+        assert_eq!(fs::read(&second).unwrap(), b"original");
+        let info = fs::read_to_string(trash_dir.join("info/image (2).png.trashinfo")).unwrap();
+        assert!(info.starts_with("[Trash Info]\nPath=/"));
+    }
+}
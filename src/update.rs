@@ -0,0 +1,165 @@
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{bail, ensure, Context, Result};
+use ed25519_dalek::{Signature, VerifyingKey};
+
+use crate::hex;
+
+const RELEASES_URL: &str = "https://github.com/funbiscuit/pngme/releases/latest/download";
+
+/// Release signing key, hex encoded. It is pinned at build time,
+/// builds without it can't update themselves
+const RELEASE_KEY: Option<&str> = option_env!("PNGME_RELEASE_KEY");
+
+/// Prefix of signed message, signature covers version and binary together
+/// so an older signed binary can't be served as a newer one
+const DOMAIN: &[u8] = b"pngme-release-v1";
+
+/// Binaries are way smaller, limit protects from filling the disk
+const MAX_BINARY_SIZE: u64 = 64 * 1024 * 1024;
+
+pub struct Release {
+    pub version: String,
+    binary: Vec<u8>,
+}
+
+/// Returns latest release if it is newer than running binary
+pub fn check() -> Result<Option<String>> {
+    let latest = fetch_text(&format!("{}/version.txt", RELEASES_URL))?;
+    let latest = latest.trim().to_string();
+    Ok(is_newer(&latest, env!("CARGO_PKG_VERSION")).then_some(latest))
+}
+
+/// Downloads release for current platform and verifies its signature
+pub fn download(version: &str) -> Result<Release> {
+    let key = release_key()?;
+    let asset = asset_name();
+    let binary = fetch_bytes(&format!("{}/{}", RELEASES_URL, asset))?;
+    let signature = fetch_text(&format!("{}/{}.sig", RELEASES_URL, asset))?;
+
+    let signature: [u8; 64] = hex::decode(&signature)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Release signature must be 64 bytes long"))?;
+    key.verify_strict(
+        &signed_message(version, &binary),
+        &Signature::from_bytes(&signature),
+    )
+    .context("Release signature is invalid, refusing to update")?;
+
+    Ok(Release {
+        version: version.to_string(),
+        binary,
+    })
+}
+
+/// Replaces running executable with the release. New binary is written next to
+/// the current one and renamed over it, so an interrupted update leaves a working binary
+pub fn install(release: &Release) -> Result<()> {
+    let current = env::current_exe()?.canonicalize()?;
+    let staged = current.with_extension("new");
+    fs::write(&staged, &release.binary)
+        .with_context(|| format!("Failed to write {}", staged.display()))?;
+    make_executable(&staged)?;
+
+    if cfg!(windows) {
+        // running executable can't be overwritten on Windows, but it can be renamed
+        let old = current.with_extension("old");
+        let _ = fs::remove_file(&old);
+        fs::rename(&current, &old)?;
+    }
+    fs::rename(&staged, &current)
+        .with_context(|| format!("Failed to replace {}", current.display()))
+}
+
+fn release_key() -> Result<VerifyingKey> {
+    let Some(key) = RELEASE_KEY else {
+        bail!("This build has no pinned release key, self-update is unavailable");
+    };
+    let key: [u8; 32] = hex::decode(key)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Pinned release key must be 32 bytes long"))?;
+    Ok(VerifyingKey::from_bytes(&key)?)
+}
+
+fn signed_message(version: &str, binary: &[u8]) -> Vec<u8> {
+    [DOMAIN, version.as_bytes(), b"\n", binary].concat()
+}
+
+fn asset_name() -> String {
+    format!(
+        "pngme-{}-{}{}",
+        env::consts::ARCH,
+        env::consts::OS,
+        env::consts::EXE_SUFFIX
+    )
+}
+
+/// Compares dotted numeric versions, e.g. `0.10.0` is newer than `0.9.3`
+fn is_newer(candidate: &str, current: &str) -> bool {
+    let parse = |v: &str| -> Vec<u64> {
+        v.trim_start_matches('v')
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    parse(candidate) > parse(current)
+}
+
+fn fetch_text(url: &str) -> Result<String> {
+    Ok(ureq::get(url)
+        .call()
+        .with_context(|| format!("Failed to fetch {}", url))?
+        .into_string()?)
+}
+
+fn fetch_bytes(url: &str) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    ureq::get(url)
+        .call()
+        .with_context(|| format!("Failed to fetch {}", url))?
+        .into_reader()
+        .take(MAX_BINARY_SIZE + 1)
+        .read_to_end(&mut bytes)?;
+    ensure!(
+        bytes.len() as u64 <= MAX_BINARY_SIZE,
+        "Release binary is too large"
+    );
+    Ok(bytes)
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("0.2.0", "0.1.0"));
+        assert!(is_newer("v0.10.0", "0.9.3"));
+        assert!(is_newer("1.0.0.1", "1.0.0"));
+        assert!(!is_newer("0.1.0", "0.1.0"));
+        assert!(!is_newer("0.0.9", "0.1.0"));
+    }
+
+    #[test]
+    fn test_signed_message_binds_version() {
+        assert_ne!(
+            signed_message("0.2.0", b"binary"),
+            signed_message("0.1.0", b"binary")
+        );
+    }
+}
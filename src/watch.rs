@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+/// Events coming within this time are handled together,
+/// so file that is still being written is processed once
+const SETTLE: Duration = Duration::from_millis(300);
+
+/// Calls `handle` for every PNG added to or modified in `dir`, runs until watching fails
+pub fn watch(dir: &Path, mut handle: impl FnMut(&Path)) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("Failed to start watcher")?;
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("Failed to watch {}", dir.display()))?;
+
+    // modification time of files after they were handled,
+    // so changes made by the handler itself don't trigger it again
+    let mut handled: HashMap<PathBuf, SystemTime> = HashMap::new();
+    loop {
+        let event = rx.recv().context("Watcher stopped")??;
+        let mut paths = changed_pngs(event);
+        while let Ok(event) = rx.recv_timeout(SETTLE) {
+            paths.extend(changed_pngs(event?));
+        }
+        paths.sort();
+        paths.dedup();
+
+        for path in paths {
+            // file could be removed or renamed in the meantime
+            let Ok(modified) = fs::metadata(&path).and_then(|m| m.modified()) else {
+                continue;
+            };
+            if handled.get(&path) == Some(&modified) {
+                continue;
+            }
+            handle(&path);
+            if let Ok(modified) = fs::metadata(&path).and_then(|m| m.modified()) {
+                handled.insert(path, modified);
+            }
+        }
+    }
+}
+
+fn changed_pngs(event: Event) -> Vec<PathBuf> {
+    match event.kind {
+        EventKind::Create(_) | EventKind::Modify(_) => {
+            event.paths.into_iter().filter(|p| is_png(p)).collect()
+        }
+        _ => vec![],
+    }
+}
+
+fn is_png(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("png"))
+}
+
+/// Command line of operation for a file: `{}` is replaced with its path,
+/// without placeholder path goes right after command name
+pub fn operation_args(operation: &str, path: &Path) -> Vec<String> {
+    let path = path.display().to_string();
+    let mut args: Vec<_> = operation
+        .split_whitespace()
+        .map(|arg| arg.replace("{}", &path))
+        .collect();
+    if !operation.contains("{}") {
+        args.insert(args.len().min(1), path);
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{CreateKind, ModifyKind, RemoveKind};
+
+    use super::*;
+
+    #[test]
+    fn test_operation_args() {
+        let path = Path::new("drop/dice.png");
+        assert_eq!(
+            operation_args("decode ruSt", path),
+            ["decode", "drop/dice.png", "ruSt"]
+        );
+        assert_eq!(
+            operation_args("dpi get {}", path),
+            ["dpi", "get", "drop/dice.png"]
+        );
+    }
+
+    #[test]
+    fn test_changed_pngs() {
+        let event = |kind| {
+            Event::new(kind)
+                .add_path("a.png".into())
+                .add_path("b.PNG".into())
+                .add_path("notes.txt".into())
+        };
+        assert_eq!(
+            changed_pngs(event(EventKind::Create(CreateKind::File))),
+            [PathBuf::from("a.png"), PathBuf::from("b.PNG")]
+        );
+        assert_eq!(
+            changed_pngs(event(EventKind::Modify(ModifyKind::Any))).len(),
+            2
+        );
+        assert!(changed_pngs(event(EventKind::Remove(RemoveKind::File))).is_empty());
+    }
+}
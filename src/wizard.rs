@@ -0,0 +1,371 @@
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::args::{EncodeArgs, Message, WriteOptions};
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::registry;
+
+/// Interactively asks for everything `encode` needs, validating each answer
+/// and asking again until it is valid
+pub fn ask_encode<R: BufRead, W: Write>(input: &mut R, output: &mut W) -> Result<EncodeArgs> {
+    writeln!(output, "This wizard hides a message inside a PNG file.")?;
+    writeln!(
+        output,
+        "Encryption is not supported yet, message is stored as is."
+    )?;
+
+    let file_path = ask(input, output, "Carrier PNG file", |answer| {
+        let bytes = std::fs::read(answer)?;
+        Png::try_from(bytes.as_slice())?;
+        Ok(PathBuf::from(answer))
+    })?;
+    let chunk_type = ask(
+        input,
+        output,
+        "Chunk type (4-character code like ruSt or a name like text)",
+        registry::resolve,
+    )?;
+    let message = ask(input, output, "Message", |answer| {
+        if answer.is_empty() {
+            bail!("Message can't be empty");
+        }
+        Ok(Message::Text(answer.to_string()))
+    })?;
+    let output_path = ask(
+        input,
+        output,
+        "Output file (leave empty to modify carrier in place)",
+        |answer| Ok((!answer.is_empty()).then(|| PathBuf::from(answer))),
+    )?;
+
+    Ok(EncodeArgs {
+        file_path,
+        chunk_type: Some(chunk_type),
+        message,
+        input_encoding: None,
+        output: output_path,
+        hmac_key: None,
+        ecc: None,
+        obfuscate: None,
+        expires: None,
+        filter: None,
+        #[cfg(feature = "age")]
+        recipients: vec![],
+        provenance: None,
+        max_growth: None,
+        mode: None,
+        write: WriteOptions::default(),
+    })
+}
+
+/// What case of chunk type letters tells decoders about the chunk
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TypeProperties {
+    pub critical: bool,
+    pub public: bool,
+    pub safe_to_copy: bool,
+}
+
+impl TypeProperties {
+    /// Letters with case set by properties, third one is always uppercase
+    fn apply(self, letters: [u8; 4]) -> [u8; 4] {
+        let case = |letter: u8, upper: bool| match upper {
+            true => letter.to_ascii_uppercase(),
+            false => letter.to_ascii_lowercase(),
+        };
+        [
+            case(letters[0], self.critical),
+            case(letters[1], self.public),
+            letters[2].to_ascii_uppercase(),
+            case(letters[3], !self.safe_to_copy),
+        ]
+    }
+}
+
+/// Interactively asks how chunk should be treated and what it is about,
+/// then proposes types with letter case matching the answers
+pub fn ask_chunk_type<R: BufRead, W: Write>(input: &mut R, output: &mut W) -> Result<ChunkType> {
+    writeln!(
+        output,
+        "This helper designs a chunk type. Case of its letters tells programs how to treat it."
+    )?;
+    let properties = TypeProperties {
+        critical: ask_yes_no(
+            input,
+            output,
+            "Should programs that don't know the chunk refuse to show the image? \
+             Almost never wanted",
+            false,
+        )?,
+        public: ask_yes_no(
+            input,
+            output,
+            "Is the type registered in PNG specification? Private types are for own use",
+            false,
+        )?,
+        safe_to_copy: ask_yes_no(
+            input,
+            output,
+            "Can editors keep the chunk when they change pixels?",
+            true,
+        )?,
+    };
+    let word = ask(
+        input,
+        output,
+        "Word to name the chunk after, like rust or secret",
+        |answer| {
+            let letters: Vec<u8> = answer.bytes().filter(u8::is_ascii_alphabetic).collect();
+            ensure!(
+                !letters.is_empty(),
+                "Word must have at least one ASCII letter"
+            );
+            Ok(letters)
+        },
+    )?;
+
+    let proposals = propose(&word, properties);
+    for (i, chunk_type) in proposals.iter().enumerate() {
+        writeln!(output, "  {}) {}", i + 1, chunk_type)?;
+    }
+    let chunk_type = ask(
+        input,
+        output,
+        "Pick a number or type other 4 letters",
+        |answer| match answer.parse::<usize>() {
+            Ok(number) => proposals
+                .get(number.wrapping_sub(1))
+                .copied()
+                .context("No proposal with this number"),
+            Err(_) => {
+                let letters: [u8; 4] = answer
+                    .as_bytes()
+                    .try_into()
+                    .context("Type must be 4 letters long")?;
+                ensure!(
+                    letters.iter().all(u8::is_ascii_alphabetic),
+                    "Type must consist of ASCII letters"
+                );
+                let letters = properties.apply(letters);
+                ensure!(
+                    !registry::clashes_with_known(&letters),
+                    "{} is too close to a standard type",
+                    String::from_utf8_lossy(&letters)
+                );
+                ChunkType::try_from(letters)
+            }
+        },
+    )?;
+    Ok(chunk_type)
+}
+
+/// Up to 3 types made of letters of `word` (padded with `x`), case set by properties,
+/// leaving out ones that spell standard types
+fn propose(word: &[u8], properties: TypeProperties) -> Vec<ChunkType> {
+    let mut letters = word.to_vec();
+    letters.resize(letters.len().max(4), b'x');
+    let consonants: Vec<u8> = letters[1..]
+        .iter()
+        .copied()
+        .filter(|c| !b"aeiouAEIOU".contains(c))
+        .collect();
+    let n = letters.len();
+    let candidates = [
+        [letters[0], letters[1], letters[2], letters[3]],
+        [letters[0], letters[1], letters[n - 2], letters[n - 1]],
+        [
+            letters[0],
+            *consonants.first().unwrap_or(&b'x'),
+            *consonants.get(1).unwrap_or(&b'x'),
+            *consonants.get(2).unwrap_or(&b'x'),
+        ],
+        [letters[0], letters[1], letters[2], b'x'],
+    ];
+    let mut proposals: Vec<ChunkType> = vec![];
+    for candidate in candidates {
+        let letters = properties.apply(candidate);
+        if registry::clashes_with_known(&letters) {
+            continue;
+        }
+        let Ok(chunk_type) = ChunkType::try_from(letters) else {
+            continue;
+        };
+        if !proposals.contains(&chunk_type) && proposals.len() < 3 {
+            proposals.push(chunk_type);
+        }
+    }
+    proposals
+}
+
+fn ask_yes_no<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    question: &str,
+    default: bool,
+) -> Result<bool> {
+    let hint = if default { "Y/n" } else { "y/N" };
+    ask(
+        input,
+        output,
+        &format!("{} [{}]", question, hint),
+        |answer| match answer.trim().to_ascii_lowercase().as_str() {
+            "" => Ok(default),
+            "y" | "yes" => Ok(true),
+            "n" | "no" => Ok(false),
+            _ => bail!("Answer y or n"),
+        },
+    )
+}
+
+/// Builds non-interactive command line equivalent to given arguments
+pub fn equivalent_command(args: &EncodeArgs) -> String {
+    let mut parts = vec![
+        "pngme".to_string(),
+        "encode".to_string(),
+        quote(&args.file_path.to_string_lossy()),
+        match &args.chunk_type {
+            Some(chunk_type) => quote(&chunk_type.to_string()),
+            None => "--random-type".to_string(),
+        },
+        match &args.message {
+            Message::Text(text) => quote(text),
+            Message::File(path) => format!("--message-file {}", quote(&path.to_string_lossy())),
+            Message::Stdin => "-".to_string(),
+            #[cfg(feature = "clipboard")]
+            Message::Clipboard => "--from-clipboard".to_string(),
+        },
+    ];
+    if let Some(output) = &args.output {
+        parts.push(quote(&output.to_string_lossy()));
+    }
+    parts.join(" ")
+}
+
+fn ask<R, W, T, F>(input: &mut R, output: &mut W, prompt: &str, validate: F) -> Result<T>
+where
+    R: BufRead,
+    W: Write,
+    F: Fn(&str) -> Result<T>,
+{
+    loop {
+        write!(output, "{}: ", prompt)?;
+        output.flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            bail!("Wizard aborted");
+        }
+        match validate(line.trim_end_matches(['\r', '\n'])) {
+            Ok(value) => return Ok(value),
+            Err(err) => writeln!(output, "Invalid value: {}", err)?,
+        }
+    }
+}
+
+/// Quotes argument for POSIX shell if it contains anything but safe characters
+fn quote(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./".contains(c));
+    if safe {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_ask_retries_invalid_answers() {
+        let mut input = Cursor::new("Ru1t\nnot-a-chunk\ntext\n");
+        let mut output = Vec::new();
+
+        let chunk_type = ask(&mut input, &mut output, "Type", registry::resolve).unwrap();
+
+        assert_eq!(chunk_type.to_string(), "tEXt");
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.matches("Invalid value").count(), 2);
+    }
+
+    #[test]
+    fn test_ask_aborts_on_eof() {
+        let mut input = Cursor::new("");
+        let mut output = Vec::new();
+
+        assert!(ask(&mut input, &mut output, "Type", registry::resolve).is_err());
+    }
+
+    #[test]
+    fn test_ask_chunk_type() {
+        let mut input = Cursor::new("\nmaybe\nn\ny\nrust\n1\n");
+        let mut output = Vec::new();
+        let chunk_type = ask_chunk_type(&mut input, &mut output).unwrap();
+        assert_eq!(chunk_type.to_string(), "ruSt");
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("  1) ruSt"));
+
+        let mut input = Cursor::new("y\nn\nn\ntext\ntext\nsEcR\n");
+        let mut output = Vec::new();
+        let chunk_type = ask_chunk_type(&mut input, &mut output).unwrap();
+        assert_eq!(chunk_type.to_string(), "SeCR");
+        assert!(String::from_utf8(output).unwrap().contains("too close"));
+    }
+
+    #[test]
+    fn test_propose() {
+        let private = TypeProperties {
+            critical: false,
+            public: false,
+            safe_to_copy: true,
+        };
+        let names: Vec<_> = propose(b"secret", private)
+            .iter()
+            .map(ChunkType::to_string)
+            .collect();
+        assert_eq!(names, ["seCr", "seEt", "scRt"]);
+        let names: Vec<_> = propose(b"a", private)
+            .iter()
+            .map(ChunkType::to_string)
+            .collect();
+        assert_eq!(names, ["axXx"]);
+        assert!(propose(b"time", private)
+            .iter()
+            .all(|t| t.to_string() != "tiMe"));
+    }
+
+    #[test]
+    fn test_equivalent_command() {
+        let args = EncodeArgs {
+            file_path: "dice.png".into(),
+            chunk_type: Some("ruSt".parse().unwrap()),
+            message: Message::Text("it's a secret".to_string()),
+            input_encoding: None,
+            output: Some("out.png".into()),
+            hmac_key: None,
+            ecc: None,
+            obfuscate: None,
+            expires: None,
+            filter: None,
+            #[cfg(feature = "age")]
+            recipients: vec![],
+            provenance: None,
+            max_growth: None,
+            mode: None,
+            write: WriteOptions::default(),
+        };
+
+        assert_eq!(
+            equivalent_command(&args),
+            r"pngme encode dice.png ruSt 'it'\''s a secret' out.png"
+        );
+    }
+}